@@ -0,0 +1,78 @@
+// An IndexedDB store for Walloc's offline cache, with the methods
+// set_offline_cache expects. Bytes and records live in separate object
+// stores, so records() never reads asset bytes.
+
+const BYTES = 'bytes';
+const RECORDS = 'records';
+
+function request(req) {
+  return new Promise((resolve, reject) => {
+    req.onsuccess = () => resolve(req.result);
+    req.onerror = () => reject(req.error);
+  });
+}
+
+function done(tx) {
+  return new Promise((resolve, reject) => {
+    tx.oncomplete = () => resolve();
+    tx.onerror = () => reject(tx.error);
+    tx.onabort = () => reject(tx.error);
+  });
+}
+
+export async function createIndexedDbStore(name = 'walloc-offline-cache') {
+  const open = indexedDB.open(name, 1);
+  open.onupgradeneeded = () => {
+    open.result.createObjectStore(BYTES);
+    open.result.createObjectStore(RECORDS, { keyPath: 'key' });
+  };
+  const db = await request(open);
+
+  return {
+    async get(key) {
+      const tx = db.transaction([BYTES, RECORDS], 'readonly');
+      const [bytes, record] = await Promise.all([
+        request(tx.objectStore(BYTES).get(key)),
+        request(tx.objectStore(RECORDS).get(key)),
+      ]);
+      return bytes && record ? { bytes, record } : undefined;
+    },
+
+    async put(key, { bytes, record }) {
+      const tx = db.transaction([BYTES, RECORDS], 'readwrite');
+      tx.objectStore(BYTES).put(bytes, key);
+      tx.objectStore(RECORDS).put(record);
+      await done(tx);
+    },
+
+    async delete(key) {
+      const tx = db.transaction([BYTES, RECORDS], 'readwrite');
+      tx.objectStore(BYTES).delete(key);
+      tx.objectStore(RECORDS).delete(key);
+      await done(tx);
+    },
+
+    async touch(key, time) {
+      const tx = db.transaction(RECORDS, 'readwrite');
+      const records = tx.objectStore(RECORDS);
+      const record = await request(records.get(key));
+      if (record) {
+        record.lastUsed = time;
+        records.put(record);
+      }
+      await done(tx);
+    },
+
+    async records() {
+      const tx = db.transaction(RECORDS, 'readonly');
+      return request(tx.objectStore(RECORDS).getAll());
+    },
+
+    async clear() {
+      const tx = db.transaction([BYTES, RECORDS], 'readwrite');
+      tx.objectStore(BYTES).clear();
+      tx.objectStore(RECORDS).clear();
+      await done(tx);
+    },
+  };
+}
@@ -71,17 +71,17 @@ function runInitTest() {
   // Simulate loading all the scene data into the main buffer, in one big chunk.
   const persistentDataSize = 74 * MB;
   const persistentDataSizeMB = (persistentDataSize / MB).toFixed(2);
-  const largeOffset = allocator.allocate_tiered(persistentDataSize, TIER.SCENE);
+  const largeHandle = allocator.allocate_tiered(persistentDataSize, TIER.SCENE);
   log(
-    `Total persistent data allocated: ${persistentDataSizeMB}MB, offset: ${largeOffset}`
+    `Total persistent data allocated: ${persistentDataSizeMB}MB, handle: ${largeHandle}`
   );
 
   // Initialize render resources
   log('Renderer starting up for 1080p resolution...');
   const renderSize = 3 * MB;
-  const renderOffset = allocator.allocate_tiered(renderSize, TIER.RENDER);
+  const renderHandle = allocator.allocate_tiered(renderSize, TIER.RENDER);
   log(
-    `Allocated ${renderSize / MB}MB in RENDER tier at offset ${renderOffset}`
+    `Allocated ${renderSize / MB}MB in RENDER tier at offset ${allocator.resolve_handle(renderHandle)}`
   );
 
   // Store the persistent data size globally for use in frame function
@@ -182,13 +182,13 @@ function runFrameTest() {
     const mediumTexture = 2048 * 2048 * 4; // 16MB
     const largeTexture = 4096 * 4096 * 4; // 64MB
 
-    const smallOffset = allocator.allocate_tiered(smallTexture, TIER.SCENE);
-    const mediumOffset = allocator.allocate_tiered(mediumTexture, TIER.SCENE);
-    const largeOffset = allocator.allocate_tiered(largeTexture, TIER.SCENE);
+    const smallHandle = allocator.allocate_tiered(smallTexture, TIER.SCENE);
+    const mediumHandle = allocator.allocate_tiered(mediumTexture, TIER.SCENE);
+    const largeHandle = allocator.allocate_tiered(largeTexture, TIER.SCENE);
 
     if (shouldLog) {
       log(
-        `Allocated 3 new textures in SCENE tier: offsets ${smallOffset}, ${mediumOffset}, ${largeOffset}`
+        `Allocated 3 new textures in SCENE tier: ${smallHandle}, ${mediumHandle}, ${largeHandle}`
       );
       log('Memory stats after new allocations:');
       logMemoryStats(allocator.memory_stats());
@@ -231,23 +231,25 @@ async function runAssetTests() {
       log(
         `Scene tier: ${(stats.tiers[1].used / 1024).toFixed(2)} KB used, ` +
           `${(stats.tiers[1].highWaterMark / 1024).toFixed(2)} KB high, ` +
-          `${(stats.tiers[1].memorySaved / 1024).toFixed(2)} KB saved`
+          `${(stats.tiers[1].memorySaved / 1024).toFixed(2)} KB saved, ` +
+          `${stats.assetCache.assets} assets cached (${(stats.assetCache.used / 1024).toFixed(2)} KB)`
       );
     }
   }
 
   try {
     // Test setup
-    allocator.set_base_url('https://jsonplaceholder.typicode.com/');
+    // Fixtures are served next to this page, no public network needed
+    allocator.set_base_url(new URL('fixtures/', window.location.href).href);
     log('Starting asset tests...');
     logAssetMemStats();
 
     // Test 1: Load assets
     log('\n1. Loading assets:');
-    const offset1 = await allocator.load_asset('todos/1', 1);
-    const offset2 = await allocator.load_asset('todos/2', 1);
-    const offset3 = await allocator.load_asset('todos/3', 1);
-    log(`Assets loaded at offsets: ${offset1}, ${offset2}, ${offset3}`);
+    const handle1 = await allocator.load_asset('todos/1', 1);
+    const handle2 = await allocator.load_asset('todos/2', 1);
+    const handle3 = await allocator.load_asset('todos/3', 1);
+    log(`Assets loaded: ${handle1}, ${handle2}, ${handle3}`);
     logAssetMemStats();
 
     // Test 2: Verify content
@@ -263,6 +265,12 @@ async function runAssetTests() {
     allocator.evict_asset('todos/2');
     log('Asset 2 evicted');
     logAssetMemStats();
+    log(
+      `[${allocator.is_handle_valid(handle1) ? 'PASS' : 'FAIL'}] Other assets keep their handles`
+    );
+    log(
+      `[${allocator.asset_handle('todos/3').offset === handle3.offset ? 'PASS' : 'FAIL'}] Other assets stay in place`
+    );
 
     // Verify eviction worked properly
     try {
@@ -283,9 +291,17 @@ async function runAssetTests() {
 
     // Test 4: Load more and evict all
     log('\n4. Load more and reset:');
-    for (let i = 4; i <= 6; i++) {
-      await allocator.load_asset(`todos/${i}`, 1);
-    }
+    const batch = await allocator.load_assets(
+      [['todos/4', 1], ['todos/5', 1], ['todos/4', 1], ['todos/6', 1]],
+      2
+    );
+    const failed = batch.filter((result) => result.error);
+    log(
+      `[${failed.length === 0 ? 'PASS' : 'FAIL'}] Batch loaded ${batch.length - failed.length} of ${batch.length}`
+    );
+    log(
+      `[${batch[0].handle?.offset === batch[2].handle?.offset ? 'PASS' : 'FAIL'}] Duplicate request shares one load`
+    );
     log('Added 3 more assets');
     logAssetMemStats();
 
@@ -299,18 +315,24 @@ async function runAssetTests() {
     log('All assets evicted');
     logAssetMemStats();
 
-    // Test 5: Recovery and larger asset
-    log('\n5. Recovery test with larger asset:');
-    await allocator.load_asset('comments', 1);
-    log('Loaded larger asset (comments)');
+    // Test 5: Recovery with a streamed asset
+    log('\n5. Recovery test with a streamed asset:');
+    let progressEvents = 0;
+    await allocator.load_asset('comments', 1, (received, total) => {
+      progressEvents++;
+      if (received === total) {
+        log(`Streamed ${received} of ${total} bytes in ${progressEvents} chunks`);
+      }
+    });
+    log('Loaded streamed asset (comments)');
     logAssetMemStats();
 
     try {
       const commentsData = allocator.get_asset('comments');
       const comments = JSON.parse(new TextDecoder().decode(commentsData));
       log(
-        `[PASS] Comments loaded: ${comments.length} items, ` +
-          `${(commentsData.length / 1024).toFixed(2)} KB`
+        `[${comments.length === 5 && progressEvents > 0 ? 'PASS' : 'FAIL'}] Comments loaded: ` +
+          `${comments.length} items, ${commentsData.length} bytes`
       );
     } catch (error) {
       log(`[FAIL] Failed to verify comments: ${error.message}`);
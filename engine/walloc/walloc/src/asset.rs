@@ -0,0 +1,536 @@
+// Asset types and their decoders.
+//
+// load_asset decodes every asset before it is placed. inspect() works out
+// the decoded format and size from the raw bytes, so the destination block
+// can be allocated first, and decode_into() then writes the decoded bytes
+// straight into that block. Textures and meshes go to the render tier, every
+// other type to the asset tier.
+//
+// Mesh files use a small binary layout of their own, little endian:
+//
+//   magic "WMSH" | u32 version (1) | u32 vertex_count | u32 vertex_stride
+//   | u32 index_count | u32 index_size (0, 2 or 4) | vertices | indices
+//
+// Decoded meshes keep the vertices first, with the indices following at the
+// next 4-byte boundary.
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum AssetType {
+    Image = 0,       // Encoded image, stored as fetched
+    Json = 1,        // UTF-8 JSON
+    Mesh = 2,        // WMSH binary mesh
+    Texture = 3,     // PNG, decoded to RGBA8
+    Shader = 4,      // WGSL source
+    Audio = 5,       // WAV, decoded to its PCM samples
+    Text = 6,        // UTF-8 text
+    WasmModule = 7,  // WebAssembly module binary
+}
+
+impl AssetType {
+    pub fn from_u8(value: u8) -> Option<AssetType> {
+        match value {
+            0 => Some(AssetType::Image),
+            1 => Some(AssetType::Json),
+            2 => Some(AssetType::Mesh),
+            3 => Some(AssetType::Texture),
+            4 => Some(AssetType::Shader),
+            5 => Some(AssetType::Audio),
+            6 => Some(AssetType::Text),
+            7 => Some(AssetType::WasmModule),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AssetType::Image => "image",
+            AssetType::Json => "json",
+            AssetType::Mesh => "mesh",
+            AssetType::Texture => "texture",
+            AssetType::Shader => "shader",
+            AssetType::Audio => "audio",
+            AssetType::Text => "text",
+            AssetType::WasmModule => "wasm",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<AssetType> {
+        (0..=7).filter_map(AssetType::from_u8).find(|asset_type| asset_type.name() == name)
+    }
+
+    // Whether the decoded data is meant for the GPU and belongs in the render tier
+    pub fn is_render_data(self) -> bool {
+        matches!(self, AssetType::Mesh | AssetType::Texture)
+    }
+}
+
+// What an asset's bytes look like once decoded
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AssetFormat {
+    Encoded,  // Stored as fetched
+    Json,
+    Text,
+    Wgsl,
+    Wasm,
+    Rgba8 { width: u32, height: u32 },
+    Mesh { vertex_count: u32, vertex_stride: u32, index_count: u32, index_size: u32 },
+    Pcm { sample_rate: u32, channels: u16, bits_per_sample: u16, float: bool },
+}
+
+impl AssetFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            AssetFormat::Encoded => "encoded",
+            AssetFormat::Json => "json",
+            AssetFormat::Text => "text",
+            AssetFormat::Wgsl => "wgsl",
+            AssetFormat::Wasm => "wasm",
+            AssetFormat::Rgba8 { .. } => "rgba8",
+            AssetFormat::Mesh { .. } => "mesh",
+            AssetFormat::Pcm { .. } => "pcm",
+        }
+    }
+}
+
+const MESH_MAGIC: &[u8; 4] = b"WMSH";
+const MESH_VERSION: u32 = 1;
+const MESH_HEADER_SIZE: usize = 24;
+const WASM_MAGIC: &[u8; 4] = b"\0asm";
+const UTF8_BOM: &[u8; 3] = b"\xEF\xBB\xBF";
+
+// Decoded format and size of an asset
+pub(crate) fn inspect(asset_type: AssetType, raw: &[u8]) -> Result<(AssetFormat, usize), String> {
+    match asset_type {
+        AssetType::Image => Ok((AssetFormat::Encoded, raw.len())),
+        AssetType::Json => Ok((AssetFormat::Json, utf8_body(raw)?.len())),
+        AssetType::Text => Ok((AssetFormat::Text, utf8_body(raw)?.len())),
+        AssetType::Shader => Ok((AssetFormat::Wgsl, utf8_body(raw)?.len())),
+        AssetType::WasmModule => {
+            if raw.len() < 8 || &raw[..4] != WASM_MAGIC {
+                return Err("Not a WebAssembly module".to_string());
+            }
+            Ok((AssetFormat::Wasm, raw.len()))
+        },
+        AssetType::Texture => {
+            let reader = png::Decoder::new(raw)
+                .read_info()
+                .map_err(|e| format!("Invalid PNG texture: {}", e))?;
+            let (width, height) = (reader.info().width, reader.info().height);
+            let size = (width as usize)
+                .checked_mul(height as usize)
+                .and_then(|pixels| pixels.checked_mul(4))
+                .ok_or_else(|| format!("Texture of {}x{} is too large", width, height))?;
+            Ok((AssetFormat::Rgba8 { width, height }, size))
+        },
+        AssetType::Mesh => {
+            let mesh = parse_mesh(raw)?;
+            Ok((mesh.format, mesh.decoded_size))
+        },
+        AssetType::Audio => {
+            let wav = parse_wav(raw)?;
+            Ok((wav.format, wav.samples.len()))
+        },
+    }
+}
+
+// Whether decoding leaves the fetched bytes as they are, so a streamed asset
+// can stay where it was received
+pub(crate) fn is_stored_as_fetched(format: &AssetFormat, raw_size: usize, size: usize) -> bool {
+    let unchanged = matches!(
+        format,
+        AssetFormat::Encoded | AssetFormat::Json | AssetFormat::Text | AssetFormat::Wgsl | AssetFormat::Wasm
+    );
+    // Text decodes to the same bytes unless it had a byte order mark
+    unchanged && raw_size == size
+}
+
+// Write the decoded asset into `out`, which is exactly as long as inspect() said
+pub(crate) fn decode_into(asset_type: AssetType, raw: &[u8], out: &mut [u8]) -> Result<(), String> {
+    match asset_type {
+        AssetType::Image | AssetType::WasmModule => out.copy_from_slice(raw),
+        AssetType::Json | AssetType::Text | AssetType::Shader => out.copy_from_slice(utf8_body(raw)?),
+        AssetType::Texture => decode_png(raw, out)?,
+        AssetType::Mesh => {
+            let mesh = parse_mesh(raw)?;
+            out.fill(0);
+            out[..mesh.vertices.len()].copy_from_slice(mesh.vertices);
+            out[mesh.index_offset..].copy_from_slice(mesh.indices);
+        },
+        AssetType::Audio => out.copy_from_slice(parse_wav(raw)?.samples),
+    }
+    Ok(())
+}
+
+// Text without its byte order mark, as long as it is valid UTF-8
+fn utf8_body(raw: &[u8]) -> Result<&[u8], String> {
+    let body = raw.strip_prefix(UTF8_BOM.as_slice()).unwrap_or(raw);
+    std::str::from_utf8(body).map_err(|e| format!("Invalid UTF-8: {}", e))?;
+    Ok(body)
+}
+
+fn read_u16(raw: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(raw.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn read_u32(raw: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(raw.get(at..at + 4)?.try_into().ok()?))
+}
+
+// === Texture ===
+
+// Decode a PNG of any color type and bit depth to 8-bit RGBA
+fn decode_png(raw: &[u8], out: &mut [u8]) -> Result<(), String> {
+    let mut decoder = png::Decoder::new(raw);
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(|e| format!("Invalid PNG texture: {}", e))?;
+
+    let (color_type, _) = reader.output_color_type();
+    if color_type == png::ColorType::Rgba {
+        reader.next_frame(out).map_err(|e| format!("Invalid PNG texture: {}", e))?;
+        return Ok(());
+    }
+
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    reader.next_frame(&mut buffer).map_err(|e| format!("Invalid PNG texture: {}", e))?;
+
+    let channels = color_type.samples();
+    for (pixel, rgba) in buffer.chunks_exact(channels).zip(out.chunks_exact_mut(4)) {
+        match pixel {
+            [gray] => rgba.copy_from_slice(&[*gray, *gray, *gray, 255]),
+            [gray, alpha] => rgba.copy_from_slice(&[*gray, *gray, *gray, *alpha]),
+            [r, g, b] => rgba.copy_from_slice(&[*r, *g, *b, 255]),
+            _ => return Err(format!("Unsupported PNG color type {:?}", color_type)),
+        }
+    }
+    Ok(())
+}
+
+// === Mesh ===
+
+struct MeshFile<'a> {
+    format: AssetFormat,
+    vertices: &'a [u8],
+    indices: &'a [u8],
+    index_offset: usize,  // Where the indices start once decoded
+    decoded_size: usize,
+}
+
+fn parse_mesh(raw: &[u8]) -> Result<MeshFile<'_>, String> {
+    if raw.len() < MESH_HEADER_SIZE || &raw[..4] != MESH_MAGIC {
+        return Err("Not a WMSH mesh".to_string());
+    }
+    let field = |index: usize| read_u32(raw, 4 + index * 4).unwrap_or(0);
+    let (version, vertex_count, vertex_stride, index_count, index_size) =
+        (field(0), field(1), field(2), field(3), field(4));
+
+    if version != MESH_VERSION {
+        return Err(format!("Unsupported WMSH version {}", version));
+    }
+    if !matches!(index_size, 0 | 2 | 4) || (index_size == 0 && index_count > 0) {
+        return Err(format!("Invalid WMSH index size {}", index_size));
+    }
+
+    let vertex_bytes = (vertex_count as usize)
+        .checked_mul(vertex_stride as usize)
+        .ok_or("WMSH vertex data is too large")?;
+    let index_bytes = (index_count as usize)
+        .checked_mul(index_size as usize)
+        .ok_or("WMSH index data is too large")?;
+    let expected = MESH_HEADER_SIZE
+        .checked_add(vertex_bytes)
+        .and_then(|size| size.checked_add(index_bytes))
+        .ok_or("WMSH mesh is too large")?;
+    if raw.len() != expected {
+        return Err(format!("WMSH mesh should be {} bytes, got {}", expected, raw.len()));
+    }
+
+    let vertices = &raw[MESH_HEADER_SIZE..MESH_HEADER_SIZE + vertex_bytes];
+    let indices = &raw[MESH_HEADER_SIZE + vertex_bytes..];
+    let index_offset = vertex_bytes.next_multiple_of(4);
+
+    Ok(MeshFile {
+        format: AssetFormat::Mesh {
+            vertex_count,
+            vertex_stride,
+            index_count,
+            index_size,
+        },
+        vertices,
+        indices,
+        index_offset,
+        decoded_size: index_offset + index_bytes,
+    })
+}
+
+// === Audio ===
+
+struct WavFile<'a> {
+    format: AssetFormat,
+    samples: &'a [u8],
+}
+
+// PCM or IEEE float WAV
+fn parse_wav(raw: &[u8]) -> Result<WavFile<'_>, String> {
+    if raw.len() < 12 || &raw[..4] != b"RIFF" || &raw[8..12] != b"WAVE" {
+        return Err("Not a WAV file".to_string());
+    }
+
+    let mut format = None;
+    let mut samples = None;
+    let mut at = 12;
+    while at + 8 <= raw.len() {
+        let id = &raw[at..at + 4];
+        let size = read_u32(raw, at + 4).unwrap_or(0) as usize;
+        let truncated = || format!("WAV chunk '{}' is truncated", String::from_utf8_lossy(id));
+        let end = (at + 8).checked_add(size).ok_or_else(truncated)?;
+        let body = raw.get(at + 8..end).ok_or_else(truncated)?;
+
+        match id {
+            b"fmt " => {
+                let (encoding, channels, sample_rate, bits_per_sample) = (
+                    read_u16(body, 0),
+                    read_u16(body, 2),
+                    read_u32(body, 4),
+                    read_u16(body, 14),
+                );
+                let (Some(encoding), Some(channels), Some(sample_rate), Some(bits_per_sample)) =
+                    (encoding, channels, sample_rate, bits_per_sample)
+                else {
+                    return Err("WAV format chunk is too short".to_string());
+                };
+                // 1 is integer PCM, 3 IEEE float
+                if encoding != 1 && encoding != 3 {
+                    return Err(format!("Unsupported WAV encoding {}", encoding));
+                }
+                format = Some(AssetFormat::Pcm {
+                    sample_rate,
+                    channels,
+                    bits_per_sample,
+                    float: encoding == 3,
+                });
+            },
+            b"data" => samples = Some(body),
+            _ => {},
+        }
+
+        // Chunks are padded to an even size
+        at = match end.checked_add(size % 2) {
+            Some(next) => next,
+            None => break,
+        };
+    }
+
+    match (format, samples) {
+        (Some(format), Some(samples)) => Ok(WavFile { format, samples }),
+        (None, _) => Err("WAV file has no format chunk".to_string()),
+        (_, None) => Err("WAV file has no data chunk".to_string()),
+    }
+}
+
+// What load_asset recorded about a resident asset
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AssetInfo {
+    pub asset_type: AssetType,
+    pub format: AssetFormat,
+    pub encoding: super::AssetEncoding,
+    pub compressed_size: usize,  // Bytes as fetched
+    pub raw_size: usize,  // Bytes once decompressed
+    pub size: usize,      // Bytes once decoded
+    pub tier: super::Tier,
+    pub hash: Option<[u8; 32]>,  // SHA-256 of the fetched bytes, if the manifest or a pack verified it
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn png(color_type: png::ColorType, width: u32, height: u32, pixels: &[u8], palette: Option<&[u8]>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        let mut encoder = png::Encoder::new(&mut bytes, width, height);
+        encoder.set_color(color_type);
+        encoder.set_depth(png::BitDepth::Eight);
+        if let Some(palette) = palette {
+            encoder.set_palette(palette.to_vec());
+        }
+        encoder.write_header().unwrap().write_image_data(pixels).unwrap();
+        bytes
+    }
+
+    fn decode(asset_type: AssetType, raw: &[u8]) -> Result<(AssetFormat, Vec<u8>), String> {
+        let (format, size) = inspect(asset_type, raw)?;
+        let mut out = vec![0xAA; size];
+        decode_into(asset_type, raw, &mut out)?;
+        Ok((format, out))
+    }
+
+    fn mesh(vertex_count: u32, vertex_stride: u32, index_count: u32, index_size: u32) -> Vec<u8> {
+        let mut raw = MESH_MAGIC.to_vec();
+        for field in [MESH_VERSION, vertex_count, vertex_stride, index_count, index_size] {
+            raw.extend_from_slice(&field.to_le_bytes());
+        }
+        raw.extend((0..vertex_count * vertex_stride).map(|byte| byte as u8 + 1));
+        raw.extend((0..index_count * index_size).map(|byte| 0xF0 | byte as u8));
+        raw
+    }
+
+    fn chunk(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut chunk = id.to_vec();
+        chunk.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        chunk.extend_from_slice(body);
+        if body.len() % 2 == 1 {
+            chunk.push(0);
+        }
+        chunk
+    }
+
+    fn wav(chunks: &[Vec<u8>]) -> Vec<u8> {
+        let body: Vec<u8> = chunks.concat();
+        let mut raw = b"RIFF".to_vec();
+        raw.extend_from_slice(&(body.len() as u32 + 4).to_le_bytes());
+        raw.extend_from_slice(b"WAVE");
+        raw.extend(body);
+        raw
+    }
+
+    fn fmt(encoding: u16, channels: u16, sample_rate: u32, bits_per_sample: u16) -> Vec<u8> {
+        let mut body = Vec::new();
+        body.extend_from_slice(&encoding.to_le_bytes());
+        body.extend_from_slice(&channels.to_le_bytes());
+        body.extend_from_slice(&sample_rate.to_le_bytes());
+        body.extend_from_slice(&(sample_rate * 4).to_le_bytes());
+        body.extend_from_slice(&4u16.to_le_bytes());
+        body.extend_from_slice(&bits_per_sample.to_le_bytes());
+        chunk(b"fmt ", &body)
+    }
+
+    // Two pixels of `color_type` decoded as a texture
+    fn rgba(color_type: png::ColorType, pixels: &[u8], palette: Option<&[u8]>) -> Vec<u8> {
+        let raw = png(color_type, 2, 1, pixels, palette);
+        let (format, out) = decode(AssetType::Texture, &raw).unwrap();
+        assert_eq!(format, AssetFormat::Rgba8 { width: 2, height: 1 });
+        out
+    }
+
+    #[test]
+    fn png_color_types_decode_to_rgba8() {
+        use png::ColorType::*;
+        assert_eq!(rgba(Grayscale, &[10, 200], None), [10, 10, 10, 255, 200, 200, 200, 255]);
+        assert_eq!(rgba(GrayscaleAlpha, &[10, 1, 200, 2], None), [10, 10, 10, 1, 200, 200, 200, 2]);
+        assert_eq!(rgba(Rgb, &[1, 2, 3, 4, 5, 6], None), [1, 2, 3, 255, 4, 5, 6, 255]);
+        assert_eq!(rgba(Rgba, &[1, 2, 3, 4, 5, 6, 7, 8], None), [1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(rgba(Indexed, &[1, 0], Some(&[9, 8, 7, 4, 5, 6])), [4, 5, 6, 255, 9, 8, 7, 255]);
+    }
+
+    #[test]
+    fn broken_pngs_are_rejected() {
+        let raw = png(png::ColorType::Rgb, 2, 2, &[7; 12], None);
+        assert!(inspect(AssetType::Texture, b"not a png").is_err());
+        assert!(inspect(AssetType::Texture, &raw[..20]).is_err());
+
+        // The header is intact but the image data is cut short
+        let (_, size) = inspect(AssetType::Texture, &raw[..raw.len() - 20]).unwrap();
+        assert!(decode_into(AssetType::Texture, &raw[..raw.len() - 20], &mut vec![0; size]).is_err());
+    }
+
+    #[test]
+    fn mesh_indices_start_on_a_4_byte_boundary() {
+        let raw = mesh(3, 3, 3, 2);
+        let (format, out) = decode(AssetType::Mesh, &raw).unwrap();
+        assert_eq!(
+            format,
+            AssetFormat::Mesh { vertex_count: 3, vertex_stride: 3, index_count: 3, index_size: 2 }
+        );
+        // 9 vertex bytes, padded to 12, then 6 index bytes
+        assert_eq!(out.len(), 18);
+        assert_eq!(&out[..9], &raw[MESH_HEADER_SIZE..MESH_HEADER_SIZE + 9]);
+        assert_eq!(&out[9..12], &[0, 0, 0]);
+        assert_eq!(&out[12..], &raw[MESH_HEADER_SIZE + 9..]);
+
+        let (_, out) = decode(AssetType::Mesh, &mesh(2, 8, 0, 0)).unwrap();
+        assert_eq!(out.len(), 16);
+    }
+
+    #[test]
+    fn malformed_meshes_are_rejected() {
+        let mut wrong_version = mesh(1, 4, 0, 0);
+        wrong_version[4] = 2;
+        let mut wrong_magic = mesh(1, 4, 0, 0);
+        wrong_magic[0] = b'X';
+        let mut too_long = mesh(1, 4, 0, 0);
+        too_long.push(0);
+        let huge = mesh(0, 0, 0, 0)[..MESH_HEADER_SIZE]
+            .iter()
+            .copied()
+            .enumerate()
+            .map(|(at, byte)| if (8..16).contains(&at) { 0xFF } else { byte })
+            .collect::<Vec<_>>();
+
+        for raw in [
+            wrong_version,
+            wrong_magic,
+            too_long,
+            huge,
+            mesh(1, 4, 2, 3),
+            mesh(1, 4, 2, 0),
+            mesh(1, 4, 0, 0)[..MESH_HEADER_SIZE + 2].to_vec(),
+            b"WMSH".to_vec(),
+        ] {
+            assert!(inspect(AssetType::Mesh, &raw).is_err(), "{:?}", raw);
+        }
+    }
+
+    #[test]
+    fn wav_chunks_are_walked_to_the_samples() {
+        let raw = wav(&[
+            chunk(b"LIST", b"odd"),
+            fmt(1, 2, 44100, 16),
+            chunk(b"data", &[1, 2, 3, 4, 5, 6, 7, 8]),
+        ]);
+        let (format, out) = decode(AssetType::Audio, &raw).unwrap();
+        assert_eq!(
+            format,
+            AssetFormat::Pcm { sample_rate: 44100, channels: 2, bits_per_sample: 16, float: false }
+        );
+        assert_eq!(out, [1, 2, 3, 4, 5, 6, 7, 8]);
+
+        let raw = wav(&[fmt(3, 1, 48000, 32), chunk(b"data", &[0; 4])]);
+        assert!(matches!(inspect(AssetType::Audio, &raw), Ok((AssetFormat::Pcm { float: true, .. }, 4))));
+    }
+
+    #[test]
+    fn malformed_wavs_are_rejected() {
+        let data = chunk(b"data", &[0; 4]);
+        let error = |raw: Vec<u8>| inspect(AssetType::Audio, &raw).unwrap_err();
+
+        assert!(error(b"RIFF\0\0\0\0AVI ".to_vec()).contains("Not a WAV"));
+        assert!(error(wav(std::slice::from_ref(&data))).contains("no format chunk"));
+        assert!(error(wav(&[fmt(1, 1, 8000, 8)])).contains("no data chunk"));
+        assert!(error(wav(&[fmt(2, 1, 8000, 8), data.clone()])).contains("encoding 2"));
+        assert!(error(wav(&[chunk(b"fmt ", &[1, 0, 1, 0]), data.clone()])).contains("too short"));
+
+        // A chunk claiming more bytes than are left, up to the largest u32
+        for size in [100, u32::MAX] {
+            let mut raw = wav(&[fmt(1, 1, 8000, 8), data.clone()]);
+            let at = raw.len() - data.len() + 4;
+            raw[at..at + 4].copy_from_slice(&size.to_le_bytes());
+            assert!(error(raw).contains("'data' is truncated"));
+        }
+    }
+
+    #[test]
+    fn wasm_modules_need_the_magic() {
+        let module = b"\0asm\x01\0\0\0".to_vec();
+        assert_eq!(decode(AssetType::WasmModule, &module).unwrap(), (AssetFormat::Wasm, module.clone()));
+        assert!(inspect(AssetType::WasmModule, b"\0asm").is_err());
+        assert!(inspect(AssetType::WasmModule, b"\0ASM\x01\0\0\0").is_err());
+    }
+
+    #[test]
+    fn text_loses_its_byte_order_mark() {
+        let raw = b"\xEF\xBB\xBF{}";
+        let (format, out) = decode(AssetType::Json, raw).unwrap();
+        assert_eq!((format, out.as_slice()), (AssetFormat::Json, b"{}".as_slice()));
+        assert!(!is_stored_as_fetched(&format, raw.len(), out.len()));
+        assert!(inspect(AssetType::Text, b"\xFF\xFE").is_err());
+    }
+}
@@ -0,0 +1,460 @@
+// Loading many assets at once.
+//
+// Every load of an asset that is not resident registers itself as in flight.
+// A load of the same path that starts before it finishes does not fetch or
+// allocate anything, it waits for the first one and gets its result.
+// load_assets runs a list of loads with at most a given number in flight.
+//
+// Fetches also queue for one of the allocator's load slots. A slot that
+// opens up goes to the waiting load of the highest priority, and among
+// equal priorities to the one that asked first.
+//
+// There is no executor here. The futures are polled by whatever awaits the
+// batch, the browser's microtask queue or a native runtime.
+
+use std::collections::{BinaryHeap, HashMap};
+use std::cmp::Ordering;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+use super::{AllocHandle, Priority, WallocError};
+
+// Browsers open about six connections per host
+pub const DEFAULT_MAX_CONCURRENT_LOADS: usize = 6;
+
+pub(crate) type SharedResult = Result<AllocHandle, WallocError>;
+
+// One load in progress, shared by every load of its path
+#[derive(Default)]
+pub(crate) struct InFlight {
+    result: Mutex<Option<SharedResult>>,
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl InFlight {
+    fn complete(&self, result: SharedResult) {
+        if let Ok(mut slot) = self.result.lock() {
+            slot.get_or_insert(result);
+        }
+        if let Ok(mut wakers) = self.wakers.lock() {
+            for waker in wakers.drain(..) {
+                waker.wake();
+            }
+        }
+    }
+
+    // Resolves with the result of the load that fetched
+    pub async fn wait(&self) -> SharedResult {
+        std::future::poll_fn(|cx: &mut Context<'_>| {
+            // The waker is registered before the result is checked, so a
+            // completion in between still wakes this load
+            if let Ok(mut wakers) = self.wakers.lock()
+                && !wakers.iter().any(|waker| waker.will_wake(cx.waker()))
+            {
+                wakers.push(cx.waker().clone());
+            }
+            match self.result.lock() {
+                Ok(slot) => match slot.as_ref() {
+                    Some(result) => Poll::Ready(result.clone()),
+                    None => Poll::Pending,
+                },
+                Err(_) => Poll::Ready(Err(WallocError::Lock("an in-flight load"))),
+            }
+        })
+        .await
+    }
+}
+
+// Loads in flight by path, shared by every clone of an allocator
+pub(crate) type InFlightLoads = Arc<Mutex<HashMap<String, Arc<InFlight>>>>;
+
+pub(crate) enum Flight {
+    Leader(FlightGuard),    // Nothing is loading the path, the caller fetches it
+    Follower(Arc<InFlight>), // Another load is, the caller waits for it
+}
+
+// Become the load of `path` or join the one in flight
+pub(crate) fn join(loads: &InFlightLoads, path: &str) -> Flight {
+    let flight = Arc::new(InFlight::default());
+    if let Ok(mut in_flight) = loads.lock() {
+        if let Some(leader) = in_flight.get(path) {
+            return Flight::Follower(Arc::clone(leader));
+        }
+        in_flight.insert(path.to_string(), Arc::clone(&flight));
+    }
+
+    Flight::Leader(FlightGuard {
+        loads: Arc::clone(loads),
+        path: path.to_string(),
+        flight,
+        completed: false,
+    })
+}
+
+// Held by the load that fetches. Its result is handed to the followers and
+// the path leaves the in-flight set, also if the load is dropped halfway.
+pub(crate) struct FlightGuard {
+    loads: InFlightLoads,
+    path: String,
+    flight: Arc<InFlight>,
+    completed: bool,
+}
+
+impl FlightGuard {
+    pub fn complete(mut self, result: SharedResult) {
+        self.finish(result);
+    }
+
+    fn finish(&mut self, result: SharedResult) {
+        if self.completed {
+            return;
+        }
+        self.completed = true;
+        if let Ok(mut in_flight) = self.loads.lock()
+            && in_flight.get(&self.path).is_some_and(|flight| Arc::ptr_eq(flight, &self.flight))
+        {
+            in_flight.remove(&self.path);
+        }
+        self.flight.complete(result);
+    }
+}
+
+impl Drop for FlightGuard {
+    fn drop(&mut self) {
+        let cancelled = Err(WallocError::Cancelled { path: self.path.clone() });
+        self.finish(cancelled);
+    }
+}
+
+pub(crate) type BoxedLoad<T> = Pin<Box<dyn Future<Output = T>>>;
+
+// Run `loads` with at most `limit` of them in flight, starting the next as
+// soon as one finishes. Results come back in the order of `loads`.
+pub(crate) async fn join_limited<T>(loads: Vec<BoxedLoad<T>>, limit: usize) -> Vec<T> {
+    let limit = limit.max(1);
+    let mut results: Vec<Option<T>> = loads.iter().map(|_| None).collect();
+    let mut queued = loads.into_iter().enumerate();
+    let mut running: Vec<(usize, BoxedLoad<T>)> = Vec::with_capacity(limit);
+
+    std::future::poll_fn(|cx: &mut Context<'_>| {
+        loop {
+            while running.len() < limit {
+                match queued.next() {
+                    Some(load) => running.push(load),
+                    None => break,
+                }
+            }
+            if running.is_empty() {
+                return Poll::Ready(());
+            }
+
+            let before = running.len();
+            running.retain_mut(|(index, load)| match load.as_mut().poll(cx) {
+                Poll::Ready(result) => {
+                    results[*index] = Some(result);
+                    false
+                },
+                Poll::Pending => true,
+            });
+
+            // Slots opened up, start the next loads right away
+            if running.len() == before {
+                return Poll::Pending;
+            }
+        }
+    })
+    .await;
+
+    results.into_iter().flatten().collect()
+}
+
+// === Load queue ===
+
+// Load slots shared by every clone of an allocator
+#[derive(Clone)]
+pub(crate) struct LoadQueue {
+    state: Arc<Mutex<QueueState>>,
+}
+
+struct QueueState {
+    limit: usize,
+    running: usize,
+    waiting: BinaryHeap<Waiting>,
+    next_order: u64,
+}
+
+// Whether a waiting load got its slot, and how to tell it
+#[derive(Default)]
+struct Ticket {
+    granted: bool,
+    abandoned: bool,  // The load was dropped while it waited
+    waker: Option<Waker>,
+}
+
+struct Waiting {
+    priority: Priority,
+    order: u64,
+    ticket: Arc<Mutex<Ticket>>,
+}
+
+// Highest priority first, then lowest order
+impl Ord for Waiting {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.priority.cmp(&other.priority).then_with(|| other.order.cmp(&self.order))
+    }
+}
+
+impl PartialOrd for Waiting {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Waiting {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Waiting {}
+
+impl LoadQueue {
+    pub fn new(limit: usize) -> Self {
+        LoadQueue {
+            state: Arc::new(Mutex::new(QueueState {
+                limit: limit.max(1),
+                running: 0,
+                waiting: BinaryHeap::new(),
+                next_order: 0,
+            })),
+        }
+    }
+
+    // Loads that may fetch at once. Raising it starts waiting loads right away.
+    pub fn set_limit(&self, limit: usize) {
+        if let Ok(mut state) = self.state.lock() {
+            state.limit = limit.max(1);
+            state.grant();
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.state.lock().map_or(DEFAULT_MAX_CONCURRENT_LOADS, |state| state.limit)
+    }
+
+    // Loads waiting for a slot, not counting those dropped while they waited
+    pub fn queued(&self) -> usize {
+        self.state.lock().map_or(0, |state| {
+            state
+                .waiting
+                .iter()
+                .filter(|waiting| !waiting.ticket.lock().is_ok_and(|ticket| ticket.abandoned))
+                .count()
+        })
+    }
+
+    // Resolves once a slot is free for a load of `priority`. The slot is
+    // held until the permit is dropped.
+    pub async fn acquire(&self, priority: Priority) -> LoadPermit {
+        let ticket = Arc::new(Mutex::new(Ticket::default()));
+        if let Ok(mut state) = self.state.lock() {
+            let order = state.next_order;
+            state.next_order += 1;
+            state.waiting.push(Waiting { priority, order, ticket: Arc::clone(&ticket) });
+            state.grant();
+        } else {
+            // Without the queue every load runs right away
+            return LoadPermit { queue: None };
+        }
+
+        let mut waiter = Waiter { queue: self, ticket, done: false };
+        std::future::poll_fn(|cx: &mut Context<'_>| match waiter.ticket.lock() {
+            Ok(mut ticket) if !ticket.granted => {
+                ticket.waker = Some(cx.waker().clone());
+                Poll::Pending
+            },
+            _ => Poll::Ready(()),
+        })
+        .await;
+        waiter.done = true;
+        LoadPermit { queue: Some(self.clone()) }
+    }
+
+    fn release(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.running = state.running.saturating_sub(1);
+            state.grant();
+        }
+    }
+}
+
+impl QueueState {
+    // Hand free slots to the waiting loads that come first
+    fn grant(&mut self) {
+        while self.running < self.limit {
+            let next = match self.waiting.pop() {
+                Some(next) => next,
+                None => return,
+            };
+            if let Ok(mut ticket) = next.ticket.lock() {
+                if ticket.abandoned {
+                    continue;
+                }
+                ticket.granted = true;
+                if let Some(waker) = ticket.waker.take() {
+                    waker.wake();
+                }
+            }
+            self.running += 1;
+        }
+    }
+}
+
+// A load waiting in the queue. Dropped before its slot was taken, it gives
+// the slot back or leaves the queue.
+struct Waiter<'a> {
+    queue: &'a LoadQueue,
+    ticket: Arc<Mutex<Ticket>>,
+    done: bool,
+}
+
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        if self.done {
+            return;
+        }
+        let granted = match self.ticket.lock() {
+            Ok(mut ticket) => {
+                ticket.abandoned = true;
+                ticket.granted
+            },
+            Err(_) => false,
+        };
+        if granted {
+            self.queue.release();
+        }
+    }
+}
+
+// A load slot, free again once dropped
+pub(crate) struct LoadPermit {
+    queue: Option<LoadQueue>,
+}
+
+impl Drop for LoadPermit {
+    fn drop(&mut self) {
+        if let Some(queue) = self.queue.take() {
+            queue.release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_executor::block_on;
+
+    use super::*;
+
+    type Acquire<'a> = Pin<Box<dyn Future<Output = LoadPermit> + 'a>>;
+
+    fn acquire(queue: &LoadQueue, priority: Priority) -> Acquire<'_> {
+        Box::pin(queue.acquire(priority))
+    }
+
+    // Poll once, without anything to wake
+    fn poll(future: &mut Acquire<'_>) -> Option<LoadPermit> {
+        match future.as_mut().poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(permit) => Some(permit),
+            Poll::Pending => None,
+        }
+    }
+
+    // Names of the waiting loads in the order they get the one slot
+    fn grant_order(queue: &LoadQueue, held: LoadPermit, mut waiting: Vec<(&'static str, Acquire<'_>)>) -> Vec<&'static str> {
+        for (_, future) in &mut waiting {
+            assert!(poll(future).is_none());
+        }
+        let mut order = Vec::new();
+        let mut held = held;
+        while !waiting.is_empty() {
+            drop(held);
+            let granted: Vec<_> = waiting.iter_mut().map(|(_, future)| poll(future)).collect();
+            assert_eq!(granted.iter().flatten().count(), 1);
+            let index = granted.iter().position(Option::is_some).unwrap();
+            held = granted.into_iter().flatten().next().unwrap();
+            order.push(waiting.remove(index).0);
+        }
+        assert_eq!(queue.queued(), 0);
+        order
+    }
+
+    #[test]
+    fn slots_go_to_the_highest_priority_first() {
+        let queue = LoadQueue::new(1);
+        let held = block_on(queue.acquire(Priority::Low));
+        let waiting = vec![
+            ("low", acquire(&queue, Priority::Low)),
+            ("critical", acquire(&queue, Priority::Critical)),
+            ("normal", acquire(&queue, Priority::Normal)),
+            ("high", acquire(&queue, Priority::High)),
+        ];
+        assert_eq!(grant_order(&queue, held, waiting), ["critical", "high", "normal", "low"]);
+    }
+
+    #[test]
+    fn equal_priorities_are_served_in_order() {
+        let queue = LoadQueue::new(1);
+        let held = block_on(queue.acquire(Priority::Normal));
+        let waiting = vec![
+            ("first", acquire(&queue, Priority::Normal)),
+            ("second", acquire(&queue, Priority::Normal)),
+            ("third", acquire(&queue, Priority::Normal)),
+        ];
+        assert_eq!(grant_order(&queue, held, waiting), ["first", "second", "third"]);
+    }
+
+    #[test]
+    fn cancelled_loads_give_their_slot_back() {
+        let queue = LoadQueue::new(1);
+        let held = block_on(queue.acquire(Priority::Normal));
+        let mut cancelled = acquire(&queue, Priority::High);
+        let mut next = acquire(&queue, Priority::Normal);
+        assert!(poll(&mut cancelled).is_none());
+        assert!(poll(&mut next).is_none());
+        assert_eq!(queue.queued(), 2);
+
+        // Dropped while waiting, it leaves the queue
+        drop(cancelled);
+        assert_eq!(queue.queued(), 1);
+        drop(held);
+        let held = poll(&mut next).unwrap();
+
+        // Dropped after its slot was granted but before it took it
+        let mut granted = acquire(&queue, Priority::Normal);
+        assert!(poll(&mut granted).is_none());
+        drop(held);
+        drop(granted);
+        assert!(poll(&mut acquire(&queue, Priority::Low)).is_some());
+    }
+
+    #[test]
+    fn raising_the_limit_starts_waiting_loads() {
+        let queue = LoadQueue::new(1);
+        let _held = block_on(queue.acquire(Priority::Normal));
+        let mut first = acquire(&queue, Priority::Normal);
+        let mut second = acquire(&queue, Priority::Normal);
+        assert!(poll(&mut first).is_none());
+        assert!(poll(&mut second).is_none());
+
+        queue.set_limit(3);
+        assert_eq!(queue.limit(), 3);
+        assert!(poll(&mut first).is_some());
+        assert!(poll(&mut second).is_some());
+        assert_eq!(queue.queued(), 0);
+
+        queue.set_limit(0);
+        assert_eq!(queue.limit(), 1);
+    }
+}
@@ -0,0 +1,240 @@
+// layerw-pack: builds and inspects Layer-W asset packs and manifests.
+//
+//   layerw-pack build <pack> <file or directory>... [--type <ext>=<type>]...
+//   layerw-pack manifest <json> <file or directory>... [--strict]
+//   layerw-pack list <pack>
+//   layerw-pack verify <pack>
+//
+// Files inside a directory are packed under their path relative to it, with
+// '/' separators, loose files under their file name. The asset type comes
+// from the extension unless --type maps it to another, skipping a compression
+// suffix such as .gz first. Manifests list every file the same way, and every
+// entry of the packs among them, with the encoding of compressed ones.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+
+use walloc::{AssetEncoding, AssetManifest, AssetType, ManifestEntry, PackBuilder, PackIndex, PACK_ALIGNMENT, content_hash, hash_hex};
+
+const USAGE: &str = "usage:
+  layerw-pack build <pack> <file or directory>... [--type <ext>=<type>]...
+  layerw-pack manifest <json> <file or directory>... [--strict]
+  layerw-pack list <pack>
+  layerw-pack verify <pack>
+
+types: image, json, mesh, texture, shader, audio, text, wasm";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("build") if args.len() >= 3 => build(&args[1], &args[2..]),
+        Some("manifest") if args.len() >= 3 => manifest(&args[1], &args[2..]),
+        Some("list") if args.len() == 2 => list(&args[1]),
+        Some("verify") if args.len() == 2 => verify(&args[1]),
+        _ => Err(USAGE.to_string()),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        },
+    }
+}
+
+// Type of a file by its extension, the one before a compression suffix if it has one
+fn type_for(path: &Path, overrides: &HashMap<String, AssetType>) -> AssetType {
+    let compressed = path.to_str().and_then(AssetEncoding::from_extension).is_some();
+    let path = match path.file_stem() {
+        Some(stem) if compressed => Path::new(stem),
+        _ => path,
+    };
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    if let Some(asset_type) = overrides.get(&extension) {
+        return *asset_type;
+    }
+    match extension.as_str() {
+        "json" => AssetType::Json,
+        "wmsh" => AssetType::Mesh,
+        "png" => AssetType::Texture,
+        "wgsl" => AssetType::Shader,
+        "wav" => AssetType::Audio,
+        "txt" | "csv" | "md" => AssetType::Text,
+        "wasm" => AssetType::WasmModule,
+        _ => AssetType::Image,
+    }
+}
+
+// Every file below `dir`, sorted so packs build the same way every time
+fn collect_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    let entries = std::fs::read_dir(dir).map_err(|e| format!("Failed to read {}: {}", dir.display(), e))?;
+    let mut paths: Vec<PathBuf> = entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect();
+    paths.sort();
+    for path in paths {
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn pack_path(file: &Path, root: &Path) -> Result<String, String> {
+    let relative = file.strip_prefix(root).unwrap_or(file);
+    let parts: Option<Vec<&str>> = relative.components().map(|component| component.as_os_str().to_str()).collect();
+    parts
+        .map(|parts| parts.join("/"))
+        .ok_or_else(|| format!("Path is not UTF-8: {}", file.display()))
+}
+
+// (pack path, file) of every file below the inputs
+fn gather(inputs: &[PathBuf]) -> Result<Vec<(String, PathBuf)>, String> {
+    let mut gathered = Vec::new();
+    for input in inputs {
+        let (root, files) = if input.is_dir() {
+            let mut files = Vec::new();
+            collect_files(input, &mut files)?;
+            (input.clone(), files)
+        } else {
+            (input.parent().map(Path::to_path_buf).unwrap_or_default(), vec![input.clone()])
+        };
+
+        for file in files {
+            gathered.push((pack_path(&file, &root)?, file));
+        }
+    }
+    Ok(gathered)
+}
+
+fn build(output: &str, args: &[String]) -> Result<(), String> {
+    let mut inputs = Vec::new();
+    let mut overrides = HashMap::new();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg != "--type" {
+            inputs.push(PathBuf::from(arg));
+            continue;
+        }
+        let mapping = args.next().ok_or("--type needs <ext>=<type>")?;
+        let (extension, name) = mapping.split_once('=').ok_or_else(|| format!("Invalid type mapping '{}'", mapping))?;
+        let asset_type = AssetType::from_name(name).ok_or_else(|| format!("Unknown asset type '{}'", name))?;
+        overrides.insert(extension.trim_start_matches('.').to_ascii_lowercase(), asset_type);
+    }
+
+    let mut builder = PackBuilder::new();
+    for (path, file) in gather(&inputs)? {
+        let bytes = std::fs::read(&file).map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+        builder.add(&path, type_for(&file, &overrides), bytes);
+    }
+
+    let pack = builder.build()?;
+    std::fs::write(output, &pack).map_err(|e| format!("Failed to write {}: {}", output, e))?;
+    println!("Wrote {}: {} entries, {} bytes", output, builder.len(), pack.len());
+    Ok(())
+}
+
+fn manifest(output: &str, args: &[String]) -> Result<(), String> {
+    let strict = args.iter().any(|arg| arg == "--strict");
+    let inputs: Vec<PathBuf> = args.iter().filter(|arg| *arg != "--strict").map(PathBuf::from).collect();
+
+    let mut manifest = AssetManifest::new().with_strict(strict);
+    for (path, file) in gather(&inputs)? {
+        let bytes = std::fs::read(&file).map_err(|e| format!("Failed to read {}: {}", file.display(), e))?;
+
+        // Entries of a pack are listed too, they are checked when loaded on their own
+        if let Ok(index) = PackIndex::parse(&bytes) {
+            for entry in &index.entries {
+                manifest.insert(&entry.path, manifest_entry(&entry.path, entry.size as usize, entry.hash));
+            }
+        }
+        manifest.insert(&path, manifest_entry(&path, bytes.len(), content_hash(&bytes)));
+    }
+
+    std::fs::write(output, manifest.to_json()).map_err(|e| format!("Failed to write {}: {}", output, e))?;
+    println!("Wrote {}: {} assets", output, manifest.len());
+    Ok(())
+}
+
+// Manifest entry of a file, with the encoding its compression suffix names
+fn manifest_entry(path: &str, size: usize, hash: [u8; 32]) -> ManifestEntry {
+    let entry = ManifestEntry::new(size, hash);
+    match AssetEncoding::from_extension(path) {
+        Some(encoding) => entry.with_encoding(encoding),
+        None => entry,
+    }
+}
+
+fn read_pack(path: &str) -> Result<(Vec<u8>, PackIndex), String> {
+    let bytes = std::fs::read(path).map_err(|e| format!("Failed to read {}: {}", path, e))?;
+    let index = PackIndex::parse(&bytes).map_err(|e| format!("{}: {}", path, e))?;
+    if index.payloads(&bytes).is_none() {
+        return Err(format!("{}: truncated to {} of {} bytes", path, bytes.len(), index.payload_offset + index.payload_size));
+    }
+    Ok((bytes, index))
+}
+
+fn list(path: &str) -> Result<(), String> {
+    let (bytes, index) = read_pack(path)?;
+    println!(
+        "{}: {} entries, {} bytes (index {}, payloads {} aligned to {})",
+        path, index.len(), bytes.len(), index.payload_offset, index.payload_size, PACK_ALIGNMENT
+    );
+    for entry in &index.entries {
+        println!(
+            "{:>10} {:>10}  {:<8} {}  {}",
+            entry.offset, entry.size, entry.asset_type.name(), hash_hex(&entry.hash), entry.path
+        );
+    }
+    Ok(())
+}
+
+fn verify(path: &str) -> Result<(), String> {
+    let (bytes, index) = read_pack(path)?;
+    let payloads = index.payloads(&bytes).ok_or_else(|| format!("{}: truncated", path))?;
+
+    let corrupt: Vec<&str> = index.entries
+        .iter()
+        .filter(|entry| !index.payload(entry, payloads).is_some_and(|payload| entry.verify(payload)))
+        .map(|entry| entry.path.as_str())
+        .collect();
+    if !corrupt.is_empty() {
+        return Err(format!("{}: {} entries do not match their hash: {}", path, corrupt.len(), corrupt.join(", ")));
+    }
+
+    println!("{}: all {} entries match their hash", path, index.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn types_skip_compression_suffixes() {
+        let overrides = HashMap::from([("bin".to_string(), AssetType::Mesh)]);
+        let type_of = |path: &str| type_for(Path::new(path), &overrides);
+
+        assert_eq!(type_of("hero.png"), AssetType::Texture);
+        assert_eq!(type_of("textures/hero.png.gz"), AssetType::Texture);
+        assert_eq!(type_of("level.json.zst"), AssetType::Json);
+        assert_eq!(type_of("ship.bin.br"), AssetType::Mesh);
+        assert_eq!(type_of("notes.TXT"), AssetType::Text);
+        assert_eq!(type_of("archive.gz"), AssetType::Image);
+        assert_eq!(type_of("logo.webp"), AssetType::Image);
+    }
+
+    #[test]
+    fn manifest_entries_record_their_encoding() {
+        let hash = content_hash(b"abc");
+        assert_eq!(manifest_entry("a.txt.gz", 3, hash).encoding, Some(AssetEncoding::Gzip));
+        assert_eq!(manifest_entry("dir.gz/a.txt", 3, hash).encoding, None);
+        assert_eq!(manifest_entry("a.txt", 3, hash), ManifestEntry::new(3, hash));
+    }
+}
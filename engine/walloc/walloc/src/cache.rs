@@ -0,0 +1,465 @@
+// Budgeted cache of loaded assets.
+//
+// Every asset gets its own block in its tier and stays where it was loaded
+// until it is evicted, so its handle stays valid for as long as it is
+// resident. When a load would take the cache past its byte budget, the
+// eviction policy picks resident assets to free first. Pinned assets are
+// never evicted automatically.
+//
+// Access times are ticks of a logical clock that advances on every load and
+// get_asset, which keeps eviction order deterministic across platforms.
+
+use std::collections::HashMap;
+
+use super::{AllocHandle, AssetEncoding, AssetFormat, AssetType, PackEntry, PackIndex, WallocError};
+
+#[derive(Clone, Debug)]
+pub(crate) struct AssetMetadata {
+    pub asset_type: AssetType,
+    pub format: AssetFormat,
+    pub encoding: AssetEncoding,
+    pub compressed_size: usize,  // Bytes as fetched
+    pub raw_size: usize,    // Bytes once decompressed
+    pub size: usize,        // Bytes once decoded, what the budget counts
+    pub block_size: usize,  // What the tier handed out, needed to free the block
+    pub handle: AllocHandle,
+    pub loaded_at: u64,
+    pub last_access: u64,
+    pub access_count: u64,
+    pub pinned: bool,
+    pub pack: Option<String>,  // Loaded pack whose block holds the asset
+    pub hash: Option<[u8; 32]>,  // SHA-256 of the fetched bytes, once verified
+}
+
+impl AssetMetadata {
+    // Bytes the asset counts against the budget, its pack counts for it
+    fn charged_size(&self) -> usize {
+        if self.pack.is_some() { 0 } else { self.size }
+    }
+}
+
+// A pack loaded whole, one block holding every entry's payload
+#[derive(Clone, Debug)]
+pub(crate) struct PackMetadata {
+    pub handle: AllocHandle,
+    pub block_size: usize,
+    pub entries: usize,
+}
+
+// What an eviction policy gets to see of a resident asset
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AssetUsage<'a> {
+    pub path: &'a str,
+    pub size: usize,
+    pub loaded_at: u64,
+    pub last_access: u64,
+    pub access_count: u64,
+}
+
+// Decides which resident asset goes when a load would exceed the budget
+pub trait EvictionPolicy: Send {
+    fn name(&self) -> &'static str;
+
+    // Index of the candidate to evict next, None to evict nothing more.
+    // Candidates never include pinned assets or entries of loaded packs.
+    fn choose(&self, candidates: &[AssetUsage<'_>]) -> Option<usize>;
+}
+
+// Least recently used goes first
+pub struct LruPolicy;
+
+impl EvictionPolicy for LruPolicy {
+    fn name(&self) -> &'static str {
+        "lru"
+    }
+
+    fn choose(&self, candidates: &[AssetUsage<'_>]) -> Option<usize> {
+        (0..candidates.len()).min_by_key(|&i| candidates[i].last_access)
+    }
+}
+
+// Least frequently used goes first, the least recently used of those on a tie
+pub struct LfuPolicy;
+
+impl EvictionPolicy for LfuPolicy {
+    fn name(&self) -> &'static str {
+        "lfu"
+    }
+
+    fn choose(&self, candidates: &[AssetUsage<'_>]) -> Option<usize> {
+        (0..candidates.len()).min_by_key(|&i| (candidates[i].access_count, candidates[i].last_access))
+    }
+}
+
+// Nothing is evicted automatically, loads past the budget fail until
+// evict_asset makes room
+pub struct PinnedPolicy;
+
+impl EvictionPolicy for PinnedPolicy {
+    fn name(&self) -> &'static str {
+        "pinned"
+    }
+
+    fn choose(&self, _candidates: &[AssetUsage<'_>]) -> Option<usize> {
+        None
+    }
+}
+
+// Built-in policy by name: "lru", "lfu" or "pinned"
+pub fn eviction_policy(name: &str) -> Option<Box<dyn EvictionPolicy>> {
+    match name {
+        "lru" => Some(Box::new(LruPolicy)),
+        "lfu" => Some(Box::new(LfuPolicy)),
+        "pinned" => Some(Box::new(PinnedPolicy)),
+        _ => None,
+    }
+}
+
+// Snapshot of the asset cache
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct AssetCacheStats {
+    pub budget: usize,
+    pub used: usize,     // Bytes of resident assets
+    pub assets: usize,   // Resident assets
+    pub pinned: usize,
+    pub packs: usize,    // Packs loaded whole
+    pub compressed: usize,          // Resident assets that arrived compressed
+    pub compressed_bytes: usize,    // Their bytes as fetched
+    pub uncompressed_bytes: usize,  // Their bytes once decompressed
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+pub(crate) struct AssetCache {
+    entries: HashMap<String, AssetMetadata>,
+    packs: HashMap<String, PackMetadata>,
+    packed: HashMap<String, (String, PackEntry)>,  // Entry path to its pack and table of contents entry
+    policy: Box<dyn EvictionPolicy>,
+    budget: usize,
+    used: usize,
+    clock: u64,
+
+    hits: u64,
+    misses: u64,
+    evictions: u64,
+}
+
+impl AssetCache {
+    pub fn new(budget: usize) -> Self {
+        AssetCache {
+            entries: HashMap::new(),
+            packs: HashMap::new(),
+            packed: HashMap::new(),
+            policy: Box::new(LruPolicy),
+            budget,
+            used: 0,
+            clock: 0,
+            hits: 0,
+            misses: 0,
+            evictions: 0,
+        }
+    }
+
+    fn tick(&mut self) -> u64 {
+        self.clock += 1;
+        self.clock
+    }
+
+    // Look an asset up and record the access
+    pub fn get(&mut self, path: &str) -> Option<&AssetMetadata> {
+        let now = self.tick();
+        match self.entries.get_mut(path) {
+            Some(metadata) => {
+                metadata.last_access = now;
+                metadata.access_count += 1;
+                self.hits += 1;
+                Some(metadata)
+            },
+            None => {
+                self.misses += 1;
+                None
+            },
+        }
+    }
+
+    // Look an asset up without counting it as an access
+    pub fn peek(&self, path: &str) -> Option<&AssetMetadata> {
+        self.entries.get(path)
+    }
+
+    // Path of a resident asset by the verified hash of its content
+    pub fn find_by_hash(&self, hash: &[u8; 32]) -> Option<&str> {
+        self.entries
+            .iter()
+            .find(|(_, metadata)| metadata.hash.as_ref() == Some(hash))
+            .map(|(path, _)| path.as_str())
+    }
+
+    // Add a freshly loaded asset, counting the load as its first access
+    pub fn insert(&mut self, path: String, mut metadata: AssetMetadata) {
+        let now = self.tick();
+        metadata.loaded_at = now;
+        metadata.last_access = now;
+        metadata.access_count = 1;
+        self.used += metadata.charged_size();
+        if let Some(old) = self.entries.insert(path, metadata) {
+            self.used -= old.charged_size();
+        }
+    }
+
+    pub fn remove(&mut self, path: &str) -> Option<AssetMetadata> {
+        let metadata = self.entries.remove(path)?;
+        self.used -= metadata.charged_size();
+        Some(metadata)
+    }
+
+    // Drop every asset and pack whose handle `keep` rejects, e.g. those whose
+    // memory a tier reset recycled
+    pub fn retain(&mut self, mut keep: impl FnMut(&AllocHandle) -> bool) {
+        let used = &mut self.used;
+        self.entries.retain(|_, metadata| {
+            let kept = keep(&metadata.handle);
+            if !kept {
+                *used -= metadata.charged_size();
+            }
+            kept
+        });
+        self.packs.retain(|_, pack| {
+            let kept = keep(&pack.handle);
+            if !kept {
+                *used -= pack.handle.size();
+            }
+            kept
+        });
+    }
+
+    // === Packs ===
+
+    // Remember where the entries of a pack live, for lazy loads
+    pub fn index_pack(&mut self, pack_path: &str, index: &PackIndex) {
+        for entry in &index.entries {
+            let mut entry = entry.clone();
+            entry.offset += index.payload_offset;
+            self.packed.insert(entry.path.clone(), (pack_path.to_string(), entry));
+        }
+    }
+
+    // Pack holding an asset and its entry, with the offset from the start of the pack
+    pub fn packed_entry(&self, path: &str) -> Option<(String, PackEntry)> {
+        self.packed.get(path).cloned()
+    }
+
+    pub fn pack(&self, pack_path: &str) -> Option<&PackMetadata> {
+        self.packs.get(pack_path)
+    }
+
+    // Add a pack loaded whole along with its entries, which replace any asset
+    // of the same path. The replaced assets are handed back to be freed.
+    pub fn insert_pack(&mut self, pack_path: &str, pack: PackMetadata, entries: Vec<(String, AssetMetadata)>) -> Vec<AssetMetadata> {
+        let replaced = entries
+            .into_iter()
+            .filter_map(|(path, mut metadata)| {
+                metadata.pack = Some(pack_path.to_string());
+                let old = self.remove(&path);
+                self.insert(path, metadata);
+                old
+            })
+            .filter(|old| old.pack.is_none())
+            .collect();
+
+        self.used += pack.handle.size();
+        if let Some(old) = self.packs.insert(pack_path.to_string(), pack) {
+            self.used -= old.handle.size();
+        }
+        replaced
+    }
+
+    // Remove a loaded pack and every entry still pointing into it
+    pub fn remove_pack(&mut self, pack_path: &str) -> Option<PackMetadata> {
+        let pack = self.packs.remove(pack_path)?;
+        self.used -= pack.handle.size();
+        self.entries.retain(|_, metadata| metadata.pack.as_deref() != Some(pack_path));
+        Some(pack)
+    }
+
+    // Remove the assets the policy picks until `size` more bytes fit in the
+    // budget and hand them back so their blocks can be freed. Nothing is
+    // removed if that is impossible.
+    pub fn make_room(&mut self, size: usize) -> Result<Vec<AssetMetadata>, WallocError> {
+        let full = WallocError::CacheFull {
+            size,
+            used: self.used,
+            budget: self.budget,
+            policy: self.policy.name(),
+        };
+        if size > self.budget {
+            return Err(full);
+        }
+
+        let mut candidates: Vec<AssetUsage<'_>> = self.entries
+            .iter()
+            .filter(|(_, metadata)| !metadata.pinned && metadata.pack.is_none())
+            .map(|(path, metadata)| AssetUsage {
+                path,
+                size: metadata.size,
+                loaded_at: metadata.loaded_at,
+                last_access: metadata.last_access,
+                access_count: metadata.access_count,
+            })
+            .collect();
+
+        let mut used = self.used;
+        let mut chosen = Vec::new();
+        while used + size > self.budget {
+            let index = match self.policy.choose(&candidates) {
+                Some(index) if index < candidates.len() => index,
+                _ => return Err(full),
+            };
+            let victim = candidates.swap_remove(index);
+            used -= victim.size;
+            chosen.push(victim.path.to_string());
+        }
+
+        let victims: Vec<AssetMetadata> = chosen.iter().filter_map(|path| self.remove(path)).collect();
+        self.evictions += victims.len() as u64;
+        Ok(victims)
+    }
+
+    // Whether the asset exists
+    pub fn set_pinned(&mut self, path: &str, pinned: bool) -> bool {
+        match self.entries.get_mut(path) {
+            Some(metadata) => {
+                metadata.pinned = pinned;
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+    }
+
+    pub fn set_policy(&mut self, policy: Box<dyn EvictionPolicy>) {
+        self.policy = policy;
+    }
+
+    pub fn policy_name(&self) -> &'static str {
+        self.policy.name()
+    }
+
+    pub fn stats(&self) -> AssetCacheStats {
+        let compressed = self.entries.values().filter(|metadata| metadata.encoding.is_compressed());
+        AssetCacheStats {
+            budget: self.budget,
+            used: self.used,
+            assets: self.entries.len(),
+            pinned: self.entries.values().filter(|metadata| metadata.pinned).count(),
+            packs: self.packs.len(),
+            compressed: compressed.clone().count(),
+            compressed_bytes: compressed.clone().map(|metadata| metadata.compressed_size).sum(),
+            uncompressed_bytes: compressed.map(|metadata| metadata.raw_size).sum(),
+            hits: self.hits,
+            misses: self.misses,
+            evictions: self.evictions,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Tier;
+
+    fn metadata(size: usize) -> AssetMetadata {
+        AssetMetadata {
+            asset_type: AssetType::Text,
+            format: AssetFormat::Text,
+            encoding: AssetEncoding::Identity,
+            compressed_size: size,
+            raw_size: size,
+            size,
+            block_size: size,
+            handle: AllocHandle::new(Tier::SCENE, 0, 0, size, 0, 0),
+            loaded_at: 0,
+            last_access: 0,
+            access_count: 0,
+            pinned: false,
+            pack: None,
+            hash: None,
+        }
+    }
+
+    // A full cache of a, b and c at 100 bytes each, loaded in that order.
+    // b is then read twice and c once.
+    fn cache(policy: Box<dyn EvictionPolicy>) -> AssetCache {
+        let mut cache = AssetCache::new(300);
+        cache.set_policy(policy);
+        for path in ["a", "b", "c"] {
+            cache.insert(path.to_string(), metadata(100));
+        }
+        cache.get("b");
+        cache.get("b");
+        cache.get("c");
+        cache
+    }
+
+    fn resident(cache: &AssetCache) -> Vec<&str> {
+        let mut paths: Vec<&str> = ["a", "b", "c"].into_iter().filter(|path| cache.peek(path).is_some()).collect();
+        paths.sort_unstable();
+        paths
+    }
+
+    #[test]
+    fn lru_evicts_least_recently_used_first() {
+        let mut cache = cache(Box::new(LruPolicy));
+        assert_eq!(cache.make_room(100).unwrap().len(), 1);
+        assert_eq!(resident(&cache), ["b", "c"]);
+
+        assert_eq!(cache.make_room(200).unwrap().len(), 1);
+        assert_eq!(resident(&cache), ["c"]);
+        assert_eq!(cache.stats().evictions, 2);
+    }
+
+    #[test]
+    fn lfu_evicts_least_frequently_used_first() {
+        let mut cache = cache(Box::new(LfuPolicy));
+        assert_eq!(cache.make_room(200).unwrap().len(), 2);
+        assert_eq!(resident(&cache), ["b"]);
+    }
+
+    #[test]
+    fn lfu_breaks_ties_by_recency() {
+        let mut cache = cache(Box::new(LfuPolicy));
+        cache.get("a");
+        // a and c were both used twice, c longer ago
+        assert_eq!(cache.make_room(100).unwrap().len(), 1);
+        assert_eq!(resident(&cache), ["a", "b"]);
+    }
+
+    #[test]
+    fn pinned_assets_are_skipped() {
+        let mut cache = cache(Box::new(LruPolicy));
+        assert!(cache.set_pinned("a", true));
+        assert_eq!(cache.make_room(100).unwrap().len(), 1);
+        assert_eq!(resident(&cache), ["a", "c"]);
+    }
+
+    #[test]
+    fn pinned_policy_never_evicts() {
+        let mut cache = cache(Box::new(PinnedPolicy));
+        assert!(matches!(cache.make_room(100), Err(WallocError::CacheFull { size: 100, used: 300, budget: 300, policy: "pinned" })));
+        assert_eq!(resident(&cache), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn nothing_is_evicted_unless_enough_room_can_be_made() {
+        let mut cache = cache(Box::new(LruPolicy));
+        cache.set_pinned("b", true);
+        assert!(cache.make_room(300).is_err());
+        assert!(cache.make_room(400).is_err());
+        assert_eq!(resident(&cache), ["a", "b", "c"]);
+        assert_eq!(cache.stats().evictions, 0);
+    }
+}
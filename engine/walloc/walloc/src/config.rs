@@ -0,0 +1,379 @@
+// Tier layout for the tiered allocator.
+//
+// A TieredAllocatorConfig lists the tiers in order. Each tier gets its own
+// initial size, alignment, allocation strategy, growth policy and a name for
+// stats and diagnostics, along with the memory pressure thresholds it warns at
+// and what happens once it runs out. The default layout is the original
+// Render/Scene/Entity split (50/30/20) of the initial heap.
+
+use wasm_bindgen::prelude::*;
+
+use super::{ExhaustionPolicy, PressureThresholds, StrategyKind, Tier, PAGE_SIZE};
+
+// Largest number of tiers a layout can have, Tier is a u8 index
+pub const MAX_TIERS: usize = 255;
+
+// How a tier grows once its segments are full
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum GrowthPolicy {
+    #[default]
+    Exact,          // Just enough pages for the allocation that failed
+    Chunk(usize),   // At least this many bytes at a time
+    Double,         // At least the tier's current capacity, doubling it
+    Never,          // The tier stays at its initial size
+}
+
+impl GrowthPolicy {
+    // Named policies, Chunk needs a size and has no name
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "exact" => Some(GrowthPolicy::Exact),
+            "double" => Some(GrowthPolicy::Double),
+            "never" => Some(GrowthPolicy::Never),
+            _ => None,
+        }
+    }
+
+    // Bytes to grow a tier of `capacity` bytes by so `size_needed` fits, None if it may not grow
+    pub fn grow_size(&self, size_needed: usize, capacity: usize) -> Option<usize> {
+        match *self {
+            GrowthPolicy::Exact => Some(size_needed),
+            GrowthPolicy::Chunk(chunk) => Some(size_needed.max(chunk)),
+            GrowthPolicy::Double => Some(size_needed.max(capacity)),
+            GrowthPolicy::Never => None,
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct TierConfig {
+    pub name: String,
+    pub initial_size: usize,   // Rounded down to the alignment
+    pub alignment: usize,      // Power of two, at most PAGE_SIZE
+    pub strategy: StrategyKind,
+    pub growth: GrowthPolicy,
+    pub pressure: PressureThresholds,
+    pub exhaustion: ExhaustionPolicy,
+}
+
+impl TierConfig {
+    pub fn new(name: &str, initial_size: usize, alignment: usize) -> Self {
+        TierConfig {
+            name: name.to_string(),
+            initial_size,
+            alignment,
+            strategy: StrategyKind::Bump,
+            growth: GrowthPolicy::Exact,
+            pressure: PressureThresholds::default(),
+            exhaustion: ExhaustionPolicy::default(),
+        }
+    }
+
+    pub fn with_strategy(mut self, strategy: StrategyKind) -> Self {
+        self.strategy = strategy;
+        self
+    }
+
+    pub fn with_growth(mut self, growth: GrowthPolicy) -> Self {
+        self.growth = growth;
+        self
+    }
+
+    pub fn with_pressure(mut self, pressure: PressureThresholds) -> Self {
+        self.pressure = pressure;
+        self
+    }
+
+    pub fn with_exhaustion(mut self, exhaustion: ExhaustionPolicy) -> Self {
+        self.exhaustion = exhaustion;
+        self
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct TieredAllocatorConfig {
+    pub tiers: Vec<TierConfig>,
+    pub asset_tier: Tier,   // Where load_asset places downloaded data
+    pub render_tier: Tier,  // Where decoded textures and meshes go instead
+}
+
+impl TieredAllocatorConfig {
+    pub fn new() -> Self {
+        TieredAllocatorConfig {
+            tiers: Vec::new(),
+            asset_tier: Tier::new(0),
+            render_tier: Tier::new(0),
+        }
+    }
+
+    // The original layout: Render 50%, Scene 30%, Entity 20% of `total_size` bytes
+    pub fn split(total_size: usize) -> Self {
+        TieredAllocatorConfig::new()
+            // 128-byte alignment for GPU warp access
+            .with_tier(TierConfig::new("render", (total_size * 50) / 100, 128))
+            // 64-byte alignment for cache lines
+            .with_tier(TierConfig::new("scene", (total_size * 30) / 100, 64))
+            // 8-byte alignment for short lived objects
+            .with_tier(TierConfig::new("entity", (total_size * 20) / 100, 8))
+            .with_asset_tier(Tier::SCENE)
+            .with_render_tier(Tier::RENDER)
+    }
+
+    pub fn with_tier(mut self, tier: TierConfig) -> Self {
+        self.tiers.push(tier);
+        self
+    }
+
+    pub fn with_asset_tier(mut self, tier: Tier) -> Self {
+        self.asset_tier = tier;
+        self
+    }
+
+    pub fn with_render_tier(mut self, tier: Tier) -> Self {
+        self.render_tier = tier;
+        self
+    }
+
+    // Tier with the given name
+    pub fn tier_by_name(&self, name: &str) -> Option<Tier> {
+        self.tiers
+            .iter()
+            .position(|tier| tier.name == name)
+            .map(|index| Tier::new(index as u8))
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.tiers.is_empty() {
+            return Err("A tier layout needs at least one tier".to_string());
+        }
+        if self.tiers.len() > MAX_TIERS {
+            return Err(format!("A tier layout can have at most {} tiers", MAX_TIERS));
+        }
+        if self.asset_tier.index() >= self.tiers.len() {
+            return Err(format!("Asset tier {} is not part of the layout", self.asset_tier.index()));
+        }
+        if self.render_tier.index() >= self.tiers.len() {
+            return Err(format!("Render tier {} is not part of the layout", self.render_tier.index()));
+        }
+
+        for (index, tier) in self.tiers.iter().enumerate() {
+            if !tier.alignment.is_power_of_two() || tier.alignment > PAGE_SIZE {
+                return Err(format!(
+                    "Tier '{}' alignment {} must be a power of two no larger than {}",
+                    tier.name, tier.alignment, PAGE_SIZE
+                ));
+            }
+            if self.tiers[..index].iter().any(|other| other.name == tier.name) {
+                return Err(format!("Tier name '{}' is used twice", tier.name));
+            }
+            tier.pressure.validate().map_err(|e| format!("Tier '{}': {}", tier.name, e))?;
+        }
+
+        if self.initial_size().is_none() {
+            return Err("The tier layout is larger than the address space".to_string());
+        }
+
+        Ok(())
+    }
+
+    // Bytes the initial tiers take up, including padding between tiers. None
+    // if that does not fit in a usize.
+    pub fn initial_size(&self) -> Option<usize> {
+        self.tiers.iter().try_fold(0usize, |end, tier| {
+            end.checked_next_multiple_of(tier.alignment)?
+                .checked_add(tier.initial_size - tier.initial_size % tier.alignment)
+        })
+    }
+}
+
+impl Default for TieredAllocatorConfig {
+    // The original split of a 1MB heap
+    fn default() -> Self {
+        TieredAllocatorConfig::split(super::INITIAL_HEAP_PAGES * PAGE_SIZE)
+    }
+}
+
+// === JS config objects ===
+//
+// {
+//   tiers: [
+//     { name: "render", size: 524288, alignment: 128, strategy: "bump", growth: "exact" },
+//     { name: "ui", size: 262144, alignment: 64, strategy: "tlsf", growth: { chunk: 65536 },
+//       pressure: { warning: 196608, critical: 245760 }, exhaustion: "fail" },
+//   ],
+//   assetTier: "ui",        // name or index, defaults to the first tier
+//   renderTier: "render",   // textures and meshes, defaults to the first tier
+// }
+//
+// strategy is one of "bump", "free-list", "buddy" or "tlsf" (default "bump"),
+// growth one of "exact", "double", "never" or { chunk: bytes } (default "exact").
+// pressure thresholds are bytes in use, either may be left out, and exhaustion
+// is "reset" or "fail" (default "reset").
+impl TieredAllocatorConfig {
+    pub fn from_js(value: &JsValue) -> Result<Self, JsValue> {
+        let tiers = js_sys::Reflect::get(value, &JsValue::from_str("tiers"))?;
+        if !js_sys::Array::is_array(&tiers) {
+            return Err(JsValue::from_str("Config needs a 'tiers' array"));
+        }
+
+        let mut config = TieredAllocatorConfig::new();
+        for tier in js_sys::Array::from(&tiers).iter() {
+            config.tiers.push(tier_from_js(&tier)?);
+        }
+
+        if let Some(tier) = config.tier_from_js(value, "assetTier")? {
+            config.asset_tier = tier;
+        }
+        if let Some(tier) = config.tier_from_js(value, "renderTier")? {
+            config.render_tier = tier;
+        }
+
+        config.validate().map_err(|e| JsValue::from_str(&e))?;
+        Ok(config)
+    }
+
+    // A tier given by name or index in `field`, if it is set
+    fn tier_from_js(&self, value: &JsValue, field: &str) -> Result<Option<Tier>, JsValue> {
+        let tier = js_sys::Reflect::get(value, &JsValue::from_str(field))?;
+        if let Some(name) = tier.as_string() {
+            let tier = self
+                .tier_by_name(&name)
+                .ok_or_else(|| JsValue::from_str(&format!("Unknown {} '{}'", field, name)))?;
+            Ok(Some(tier))
+        } else {
+            Ok(tier.as_f64().map(|index| Tier::new(index as u8)))
+        }
+    }
+}
+
+fn tier_from_js(value: &JsValue) -> Result<TierConfig, JsValue> {
+    let field = |name: &str| js_sys::Reflect::get(value, &JsValue::from_str(name));
+
+    let name = field("name")?
+        .as_string()
+        .ok_or_else(|| JsValue::from_str("Every tier needs a 'name'"))?;
+    let size = field("size")?
+        .as_f64()
+        .ok_or_else(|| JsValue::from_str(&format!("Tier '{}' needs a 'size' in bytes", name)))?;
+    let alignment = field("alignment")?.as_f64().unwrap_or(8.0);
+
+    let strategy = match field("strategy")?.as_string() {
+        Some(strategy) => StrategyKind::from_name(&strategy).ok_or_else(|| {
+            JsValue::from_str(&format!("Tier '{}' has an unknown strategy '{}'", name, strategy))
+        })?,
+        None => StrategyKind::Bump,
+    };
+
+    let growth = field("growth")?;
+    let growth = if let Some(growth) = growth.as_string() {
+        GrowthPolicy::from_name(&growth).ok_or_else(|| {
+            JsValue::from_str(&format!("Tier '{}' has an unknown growth policy '{}'", name, growth))
+        })?
+    } else if growth.is_object() {
+        let chunk = js_sys::Reflect::get(&growth, &JsValue::from_str("chunk"))?
+            .as_f64()
+            .ok_or_else(|| JsValue::from_str(&format!("Tier '{}' growth needs a 'chunk' size", name)))?;
+        GrowthPolicy::Chunk(chunk as usize)
+    } else {
+        GrowthPolicy::Exact
+    };
+
+    let pressure = field("pressure")?;
+    let pressure = if pressure.is_object() {
+        let threshold = |level: &str| -> Result<Option<usize>, JsValue> {
+            Ok(js_sys::Reflect::get(&pressure, &JsValue::from_str(level))?.as_f64().map(|bytes| bytes as usize))
+        };
+        PressureThresholds { warning: threshold("warning")?, critical: threshold("critical")? }
+    } else {
+        PressureThresholds::default()
+    };
+
+    let exhaustion = match field("exhaustion")?.as_string() {
+        Some(exhaustion) => ExhaustionPolicy::from_name(&exhaustion).ok_or_else(|| {
+            JsValue::from_str(&format!("Tier '{}' has an unknown exhaustion policy '{}'", name, exhaustion))
+        })?,
+        None => ExhaustionPolicy::default(),
+    };
+
+    Ok(TierConfig::new(&name, size as usize, alignment as usize)
+        .with_strategy(strategy)
+        .with_growth(growth)
+        .with_pressure(pressure)
+        .with_exhaustion(exhaustion))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{NativeMemory, TieredAllocator};
+
+    fn two_tiers() -> TieredAllocatorConfig {
+        TieredAllocatorConfig::new()
+            .with_tier(TierConfig::new("ui", 100, 8))
+            .with_tier(TierConfig::new("gpu", 256, 128).with_growth(GrowthPolicy::Never))
+            .with_render_tier(Tier::new(1))
+    }
+
+    #[test]
+    fn split_keeps_the_original_layout() {
+        let config = TieredAllocatorConfig::split(1000);
+        let layout: Vec<_> = config.tiers.iter().map(|tier| (tier.name.as_str(), tier.initial_size, tier.alignment)).collect();
+        assert_eq!(layout, vec![("render", 500, 128), ("scene", 300, 64), ("entity", 200, 8)]);
+        assert_eq!(config.asset_tier, Tier::SCENE);
+        assert_eq!(config.render_tier, Tier::RENDER);
+        assert_eq!(config.tier_by_name("entity"), Some(Tier::ENTITY));
+        assert_eq!(config.tier_by_name("audio"), None);
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn initial_size_pads_each_tier_to_its_alignment() {
+        // 100 rounds down to 96, then the gpu tier starts at 128
+        assert_eq!(two_tiers().initial_size(), Some(384));
+
+        let huge = TieredAllocatorConfig::new()
+            .with_tier(TierConfig::new("a", usize::MAX - 8, 8))
+            .with_tier(TierConfig::new("b", 64, 64));
+        assert_eq!(huge.initial_size(), None);
+        assert!(huge.validate().is_err());
+    }
+
+    #[test]
+    fn validate_rejects_broken_layouts() {
+        let error = |config: TieredAllocatorConfig| config.validate().unwrap_err();
+
+        assert!(error(TieredAllocatorConfig::new()).contains("at least one tier"));
+        assert!(error(two_tiers().with_asset_tier(Tier::new(2))).contains("Asset tier 2"));
+        assert!(error(two_tiers().with_render_tier(Tier::new(5))).contains("Render tier 5"));
+        assert!(error(two_tiers().with_tier(TierConfig::new("odd", 64, 24))).contains("alignment 24"));
+        assert!(error(two_tiers().with_tier(TierConfig::new("wide", 64, 2 * PAGE_SIZE))).contains("power of two"));
+        assert!(error(two_tiers().with_tier(TierConfig::new("ui", 64, 8))).contains("used twice"));
+
+        let many = (0..=MAX_TIERS).fold(TieredAllocatorConfig::new(), |config, index| {
+            config.with_tier(TierConfig::new(&format!("tier{}", index), 0, 8))
+        });
+        assert!(error(many).contains("at most 255"));
+    }
+
+    #[test]
+    fn growth_policies() {
+        assert_eq!(GrowthPolicy::from_name("double"), Some(GrowthPolicy::Double));
+        assert_eq!(GrowthPolicy::from_name("never"), Some(GrowthPolicy::Never));
+        assert_eq!(GrowthPolicy::from_name("chunk"), None);
+
+        assert_eq!(GrowthPolicy::Exact.grow_size(100, 4096), Some(100));
+        assert_eq!(GrowthPolicy::Chunk(1024).grow_size(100, 4096), Some(1024));
+        assert_eq!(GrowthPolicy::Double.grow_size(100, 4096), Some(4096));
+        assert_eq!(GrowthPolicy::Never.grow_size(100, 4096), None);
+    }
+
+    #[test]
+    fn allocator_follows_the_layout() {
+        let allocator = TieredAllocator::with_config(Box::new(NativeMemory::new(16)), two_tiers()).unwrap();
+        assert_eq!(allocator.tier_by_name("gpu"), Some(Tier::new(1)));
+        assert_eq!(allocator.tier_stats(Tier::new(0)).capacity, 96);
+        assert_eq!(allocator.tier_stats(Tier::new(1)).capacity, 256);
+
+        assert!(TieredAllocator::with_config(Box::new(NativeMemory::new(16)), TieredAllocatorConfig::new()).is_err());
+    }
+}
@@ -0,0 +1,99 @@
+// Compressed asset payloads.
+//
+// An asset can arrive gzip, zstd or brotli compressed. Its encoding comes
+// from the manifest if it says, else from the file extension (.gz, .zst,
+// .br), else from the Content-Encoding the server sent. Browsers undo
+// Content-Encoding on their own, so there it only matters natively.
+//
+// Hashes and sizes in the manifest and in packs are those of the bytes as
+// shipped, compressed or not. The decoders read the compressed bytes where
+// they were staged and write straight into a block of the destination tier.
+
+use std::io::Read;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Default)]
+pub enum AssetEncoding {
+    #[default]
+    Identity,
+    Gzip,
+    Zstd,
+    Brotli,
+}
+
+// Brotli has no header to read the decompressed size from
+const BROTLI_SIZE_FACTOR: usize = 4;
+
+// Buffer of the brotli decoder, its input is already in memory
+const BROTLI_BUFFER_SIZE: usize = 4096;
+
+impl AssetEncoding {
+    // Names as in the manifest and the Content-Encoding header
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "identity" | "none" => Some(AssetEncoding::Identity),
+            "gzip" | "x-gzip" => Some(AssetEncoding::Gzip),
+            "zstd" => Some(AssetEncoding::Zstd),
+            "br" | "brotli" => Some(AssetEncoding::Brotli),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            AssetEncoding::Identity => "identity",
+            AssetEncoding::Gzip => "gzip",
+            AssetEncoding::Zstd => "zstd",
+            AssetEncoding::Brotli => "br",
+        }
+    }
+
+    // Encoding the last extension of `path` stands for, None if it stands for none
+    pub fn from_extension(path: &str) -> Option<Self> {
+        let name = path.rsplit('/').next().unwrap_or(path);
+        match name.rsplit_once('.')?.1.to_ascii_lowercase().as_str() {
+            "gz" | "gzip" => Some(AssetEncoding::Gzip),
+            "zst" | "zstd" => Some(AssetEncoding::Zstd),
+            "br" => Some(AssetEncoding::Brotli),
+            _ => None,
+        }
+    }
+
+    pub fn is_compressed(self) -> bool {
+        self != AssetEncoding::Identity
+    }
+
+    // Likely decompressed size of `bytes`, from the gzip trailer or the zstd
+    // frame header. A guess for brotli, and for gzip over 4GB.
+    pub fn size_hint(self, bytes: &[u8]) -> usize {
+        let guess = bytes.len().saturating_mul(BROTLI_SIZE_FACTOR);
+        match self {
+            AssetEncoding::Identity => bytes.len(),
+            AssetEncoding::Gzip => match bytes.len().checked_sub(4).and_then(|start| bytes.get(start..)) {
+                Some(trailer) if bytes.len() >= 18 => {
+                    u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]) as usize
+                },
+                _ => guess,
+            },
+            AssetEncoding::Zstd => {
+                let mut frame = ruzstd::decoding::FrameDecoder::new();
+                match frame.init(bytes) {
+                    Ok(()) if frame.content_size() > 0 => frame.content_size() as usize,
+                    _ => guess,
+                }
+            },
+            AssetEncoding::Brotli => guess,
+        }
+    }
+
+    // Reader of the decompressed bytes
+    pub fn decoder<'a>(self, bytes: &'a [u8]) -> Result<Box<dyn Read + 'a>, String> {
+        match self {
+            AssetEncoding::Identity => Ok(Box::new(bytes)),
+            AssetEncoding::Gzip => Ok(Box::new(flate2::read::MultiGzDecoder::new(bytes))),
+            AssetEncoding::Zstd => ruzstd::decoding::StreamingDecoder::new(bytes)
+                .map(|decoder| Box::new(decoder) as Box<dyn Read + 'a>)
+                .map_err(|e| format!("Invalid zstd frame: {}", e)),
+            AssetEncoding::Brotli => Ok(Box::new(brotli_decompressor::Decompressor::new(bytes, BROTLI_BUFFER_SIZE))),
+        }
+    }
+}
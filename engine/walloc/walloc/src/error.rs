@@ -0,0 +1,146 @@
+// Errors of asset loading and the allocator's other fallible calls.
+//
+// Rust callers match on the variant, JS callers on the `code` of the Error
+// object each variant turns into. Errors are Clone, so a load that waited on
+// another one gets the same error.
+
+use std::time::Duration;
+
+use wasm_bindgen::prelude::*;
+
+use super::IntegrityError;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum WallocError {
+    // A bad argument or configuration from the caller
+    InvalidArgument(String),
+    // A lock was poisoned by a panic while it was held
+    Lock(&'static str),
+    // No such asset, pack or tier
+    NotFound { path: String },
+    // The server answered with a status that is not a success
+    Http { url: String, status: u16 },
+    // The request or read failed before there was an answer
+    Fetch { url: String, message: String },
+    Timeout { path: String, after: Duration },
+    Integrity(IntegrityError),
+    // The bytes arrived but are not what their type or format says
+    Decode { path: String, message: String },
+    OutOfMemory { size: usize },
+    // The eviction policy cannot free enough of the cache budget
+    CacheFull { size: usize, used: usize, budget: usize, policy: &'static str },
+    // A tier reset recycled memory that was still in use
+    Recycled { path: String },
+    // The load that others waited on was dropped before it finished
+    Cancelled { path: String },
+}
+
+impl WallocError {
+    // Stable name of the kind of failure, the `code` field in JS
+    pub fn code(&self) -> &'static str {
+        match self {
+            WallocError::InvalidArgument(_) => "invalid_argument",
+            WallocError::Lock(_) => "lock",
+            WallocError::NotFound { .. } => "not_found",
+            WallocError::Http { .. } => "http",
+            WallocError::Fetch { .. } => "fetch",
+            WallocError::Timeout { .. } => "timeout",
+            WallocError::Integrity(_) => "integrity",
+            WallocError::Decode { .. } => "decode",
+            WallocError::OutOfMemory { .. } => "out_of_memory",
+            WallocError::CacheFull { .. } => "cache_full",
+            WallocError::Recycled { .. } => "recycled",
+            WallocError::Cancelled { .. } => "cancelled",
+        }
+    }
+
+    // HTTP status, for Http errors
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            WallocError::Http { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    // Asset path or URL the error is about, if any
+    pub fn path(&self) -> Option<&str> {
+        match self {
+            WallocError::NotFound { path }
+            | WallocError::Timeout { path, .. }
+            | WallocError::Decode { path, .. }
+            | WallocError::Recycled { path }
+            | WallocError::Cancelled { path } => Some(path),
+            WallocError::Http { url, .. } | WallocError::Fetch { url, .. } => Some(url),
+            WallocError::Integrity(error) => Some(error.path()),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for WallocError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            WallocError::InvalidArgument(message) => write!(f, "{}", message),
+            WallocError::Lock(what) => write!(f, "Failed to lock {}", what),
+            WallocError::NotFound { path } => write!(f, "Not found: {}", path),
+            WallocError::Http { url, status } => write!(f, "HTTP error: {} for {}", status, url),
+            WallocError::Fetch { url, message } => write!(f, "Failed to fetch {}: {}", url, message),
+            WallocError::Timeout { path, after } => {
+                write!(f, "Fetching {} timed out after {} ms", path, after.as_millis())
+            },
+            WallocError::Integrity(error) => write!(f, "{}", error),
+            WallocError::Decode { path, message } => write!(f, "Failed to decode {}: {}", path, message),
+            WallocError::OutOfMemory { size } => write!(f, "Failed to allocate {} bytes", size),
+            WallocError::CacheFull { size, budget, .. } if size > budget => {
+                write!(f, "Asset of {} bytes is larger than the cache budget of {} bytes", size, budget)
+            },
+            WallocError::CacheFull { size, used, budget, policy } => write!(
+                f,
+                "Asset cache cannot make room for {} bytes ({} of {} bytes used, policy '{}')",
+                size, used, budget, policy
+            ),
+            WallocError::Recycled { path } => write!(f, "The memory of {} was recycled by a tier reset", path),
+            WallocError::Cancelled { path } => write!(f, "Loading {} was cancelled", path),
+        }
+    }
+}
+
+impl std::error::Error for WallocError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WallocError::Integrity(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<IntegrityError> for WallocError {
+    fn from(error: IntegrityError) -> Self {
+        WallocError::Integrity(error)
+    }
+}
+
+// A JS Error named "WallocError" with `code` set, and `path` and `status`
+// where they apply. Integrity failures stay IntegrityErrors, with a code.
+impl From<WallocError> for JsValue {
+    fn from(error: WallocError) -> JsValue {
+        let js_error: js_sys::Error = match &error {
+            WallocError::Integrity(integrity) => JsValue::from(integrity.clone()).unchecked_into(),
+            _ => {
+                let js_error = js_sys::Error::new(&error.to_string());
+                js_error.set_name("WallocError");
+                js_error
+            },
+        };
+
+        let set = |key: &str, value: JsValue| js_sys::Reflect::set(&js_error, &JsValue::from_str(key), &value);
+        let _ = set("code", JsValue::from_str(error.code()));
+        if let Some(path) = error.path() {
+            let _ = set("path", JsValue::from_str(path));
+        }
+        if let Some(status) = error.status() {
+            let _ = set("status", JsValue::from_f64(status as f64));
+        }
+        js_error.into()
+    }
+}
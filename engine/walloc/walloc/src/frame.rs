@@ -0,0 +1,372 @@
+// Frame-ventilated transient memory.
+//
+// A FrameArena cycles through two or three equally sized buffers, one per frame
+// in flight. begin_frame() hands the oldest buffer to the new frame, so with
+// double buffering the memory frame N allocated is recycled as soon as frame
+// N + 2 starts (N + 3 with triple buffering). Nothing is freed individually,
+// the previous contents are simply overwritten by the frames that follow.
+//
+// Every allocation remembers the arena and frame it was made in. Reads go
+// through the arena, which refuses allocations of other arenas and those whose
+// buffer has already been ventilated, and in debug builds reports the stale
+// access.
+//
+// The buffers come straight from the memory source, so resetting or compacting
+// a tier never touches them. Once the arena is dropped they are handed to the
+// Render tier as a new segment rather than lost.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use wasm_bindgen::prelude::*;
+
+use super::{Arena, MemorySource, Segment, StrategyKind, Tier};
+
+pub const FRAME_ALIGNMENT: usize = 128; // Same alignment as the Render tier
+pub const MIN_FRAME_BUFFERS: usize = 2;
+pub const MAX_FRAME_BUFFERS: usize = 3;
+
+// Ids of frame arenas, so an allocation is only ever read through its own
+static NEXT_ARENA_ID: AtomicU64 = AtomicU64::new(1);
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct FrameAllocation {
+    ptr: *mut u8,
+    size: usize,
+    frame: u64,
+    arena: u64,  // Id of the arena it came from
+}
+
+impl FrameAllocation {
+    pub fn ptr(&self) -> *mut u8 {
+        self.ptr
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    // Frame generation the allocation was made in
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+}
+
+#[wasm_bindgen]
+impl FrameAllocation {
+    #[wasm_bindgen(getter, js_name = size)]
+    pub fn js_size(&self) -> usize {
+        self.size
+    }
+
+    #[wasm_bindgen(getter, js_name = frame)]
+    pub fn js_frame(&self) -> f64 {
+        self.frame as f64
+    }
+}
+
+pub struct FrameArena {
+    id: u64,
+    buffers: Vec<Segment>,
+    frame_size: usize,
+    reserved: usize,  // Bytes taken from the memory source, from the first buffer on
+    _memory: Arc<Mutex<Box<dyn MemorySource>>>,  // Keeps the buffers mapped while the arena lives
+    render: Arc<Mutex<Arena>>,  // Takes the buffers over when the arena is dropped
+    frame: u64,       // Generation of the current (or last) frame, starts at 1
+    in_frame: bool,
+
+    peak_frame_usage: usize,    // Most bytes any single frame has used
+    stale_reads: AtomicUsize,   // Reads of ventilated memory that were refused
+}
+
+// The buffers are owned exclusively by this arena
+unsafe impl Send for FrameArena {}
+
+impl FrameArena {
+    // `base` must point at `reserved` bytes of `memory`, at least
+    // `frame_size * buffer_count`, taken for this arena alone
+    pub(crate) fn new(
+        base: *mut u8,
+        frame_size: usize,
+        buffer_count: usize,
+        reserved: usize,
+        memory: Arc<Mutex<Box<dyn MemorySource>>>,
+        render: Arc<Mutex<Arena>>,
+    ) -> Self {
+        let buffers = (0..buffer_count)
+            .map(|index| {
+                let buffer = unsafe { base.add(index * frame_size) };
+                Segment::new(buffer, frame_size, Tier::RENDER, StrategyKind::Bump)
+            })
+            .collect();
+
+        FrameArena {
+            id: NEXT_ARENA_ID.fetch_add(1, Ordering::Relaxed),
+            buffers,
+            frame_size,
+            reserved,
+            _memory: memory,
+            render,
+            frame: 0,
+            in_frame: false,
+            peak_frame_usage: 0,
+            stale_reads: AtomicUsize::new(0),
+        }
+    }
+
+    fn current_buffer(&self) -> &Segment {
+        &self.buffers[(self.frame as usize) % self.buffers.len()]
+    }
+
+    fn current_buffer_mut(&mut self) -> &mut Segment {
+        let index = (self.frame as usize) % self.buffers.len();
+        &mut self.buffers[index]
+    }
+
+    // Start a new frame. The buffer it takes over belonged to the frame
+    // `buffer_count` frames ago, which is ventilated here.
+    pub fn begin_frame(&mut self) -> u64 {
+        if self.in_frame {
+            self.end_frame();
+        }
+
+        self.frame += 1;
+        self.current_buffer_mut().allocator.reset();
+        self.in_frame = true;
+        self.frame
+    }
+
+    // Finish the current frame. Its allocations stay readable until the
+    // buffer comes around again.
+    pub fn end_frame(&mut self) {
+        if !self.in_frame {
+            return;
+        }
+
+        self.peak_frame_usage = self.peak_frame_usage.max(self.frame_usage());
+        self.in_frame = false;
+    }
+
+    // Bump allocate scratch memory for the current frame. Returns None outside
+    // of begin_frame/end_frame or when the frame buffer is full.
+    pub fn allocate(&mut self, size: usize) -> Option<FrameAllocation> {
+        if !self.in_frame {
+            return None;
+        }
+
+        let aligned_size = size.next_multiple_of(FRAME_ALIGNMENT);
+        let frame = self.frame;
+        let buffer = self.current_buffer_mut();
+        let (offset, _) = buffer.allocator.allocate(aligned_size, FRAME_ALIGNMENT)?;
+
+        Some(FrameAllocation {
+            ptr: unsafe { buffer.base.add(offset) },
+            size,
+            frame,
+            arena: self.id,
+        })
+    }
+
+    // Whether an allocation of this arena has not had its buffer handed to a newer frame yet
+    pub fn is_live(&self, allocation: &FrameAllocation) -> bool {
+        allocation.arena == self.id
+            && allocation.frame <= self.frame
+            && allocation.frame + (self.buffers.len() as u64) > self.frame
+    }
+
+    fn check_live(&self, allocation: &FrameAllocation) -> bool {
+        if allocation.arena != self.id {
+            return false;
+        }
+        if self.is_live(allocation) {
+            return true;
+        }
+
+        self.stale_reads.fetch_add(1, Ordering::Relaxed);
+
+        #[cfg(debug_assertions)]
+        {
+            super::console_warn(&format!(
+                "Stale frame memory read: {} bytes at {:p} from frame {} accessed during frame {} ({} frame buffers)",
+                allocation.size, allocation.ptr, allocation.frame, self.frame, self.buffers.len()
+            ));
+        }
+
+        false
+    }
+
+    // Read an allocation, refusing memory that has already been ventilated
+    pub fn get(&self, allocation: &FrameAllocation) -> Option<&[u8]> {
+        if !self.check_live(allocation) {
+            return None;
+        }
+
+        Some(unsafe { std::slice::from_raw_parts(allocation.ptr, allocation.size) })
+    }
+
+    // Write access to an allocation, refusing memory that has already been ventilated
+    pub fn get_mut(&mut self, allocation: &FrameAllocation) -> Option<&mut [u8]> {
+        if !self.check_live(allocation) {
+            return None;
+        }
+
+        Some(unsafe { std::slice::from_raw_parts_mut(allocation.ptr, allocation.size) })
+    }
+
+    pub fn current_frame(&self) -> u64 {
+        self.frame
+    }
+
+    pub fn buffer_count(&self) -> usize {
+        self.buffers.len()
+    }
+
+    // Bytes used by the current frame
+    pub fn frame_usage(&self) -> usize {
+        self.current_buffer().allocator.used()
+    }
+
+    // Bytes available to each frame
+    pub fn frame_capacity(&self) -> usize {
+        self.frame_size
+    }
+
+    pub fn peak_frame_usage(&self) -> usize {
+        self.peak_frame_usage.max(self.frame_usage())
+    }
+
+    pub fn stale_reads(&self) -> usize {
+        self.stale_reads.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for FrameArena {
+    // The memory source never shrinks, so the buffers become Render tier memory
+    fn drop(&mut self) {
+        let base = self.buffers[0].base;
+        if let Ok(mut render) = self.render.lock() {
+            render.add_segment(base, self.reserved);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{NativeMemory, Tier, TieredAllocator, PAGE_SIZE};
+
+    fn allocator() -> TieredAllocator {
+        TieredAllocator::new(Box::new(NativeMemory::new(64)), 16)
+    }
+
+    // Allocation of the first frame and whether it can still be read in each frame after it
+    fn live_frames(buffer_count: usize) -> Vec<bool> {
+        let mut allocator = allocator();
+        let mut frames = allocator.create_frame_arena(1024, buffer_count).unwrap();
+        frames.begin_frame();
+        let allocation = frames.allocate(64).unwrap();
+        frames.end_frame();
+
+        (0..3)
+            .map(|_| {
+                frames.begin_frame();
+                frames.get(&allocation).is_some()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn double_buffering_recycles_at_n_plus_2() {
+        assert_eq!(live_frames(2), vec![true, false, false]);
+    }
+
+    #[test]
+    fn triple_buffering_recycles_at_n_plus_3() {
+        assert_eq!(live_frames(3), vec![true, true, false]);
+    }
+
+    #[test]
+    fn stale_reads_are_refused_and_counted() {
+        let mut allocator = allocator();
+        let mut frames = allocator.create_frame_arena(1024, 2).unwrap();
+        assert_eq!(frames.begin_frame(), 1);
+        let allocation = frames.allocate(16).unwrap();
+        frames.get_mut(&allocation).unwrap().fill(1);
+        frames.begin_frame();
+        frames.begin_frame();
+
+        assert!(!frames.is_live(&allocation));
+        assert!(frames.get(&allocation).is_none());
+        assert!(frames.get_mut(&allocation).is_none());
+        assert_eq!(frames.stale_reads(), 2);
+    }
+
+    #[test]
+    fn allocations_of_other_arenas_are_refused() {
+        let mut allocator = allocator();
+        let mut frames = allocator.create_frame_arena(1024, 2).unwrap();
+        let mut other = allocator.create_frame_arena(1024, 2).unwrap();
+        frames.begin_frame();
+        other.begin_frame();
+
+        let allocation = other.allocate(16).unwrap();
+        assert!(!frames.is_live(&allocation));
+        assert!(frames.get(&allocation).is_none());
+        assert_eq!(frames.stale_reads(), 0);
+        assert!(other.get(&allocation).is_some());
+    }
+
+    #[test]
+    fn allocations_need_a_frame_and_room() {
+        let mut allocator = allocator();
+        let mut frames = allocator.create_frame_arena(1000, 2).unwrap();
+        assert_eq!(frames.frame_capacity(), 1024);
+        assert!(frames.allocate(16).is_none());
+
+        frames.begin_frame();
+        assert!(frames.allocate(1000).is_some());
+        assert!(frames.allocate(1).is_none());
+        assert_eq!(frames.frame_usage(), 1024);
+        frames.end_frame();
+        assert!(frames.allocate(16).is_none());
+
+        frames.begin_frame();
+        assert_eq!(frames.frame_usage(), 0);
+        assert_eq!(frames.peak_frame_usage(), 1024);
+    }
+
+    #[test]
+    fn invalid_layouts_are_rejected() {
+        let mut allocator = allocator();
+        assert!(allocator.create_frame_arena(1024, 1).is_none());
+        assert!(allocator.create_frame_arena(1024, 4).is_none());
+        assert!(allocator.create_frame_arena(0, 2).is_none());
+        assert!(allocator.create_frame_arena(usize::MAX, 2).is_none());
+        assert!(allocator.create_frame_arena(usize::MAX / 2, 3).is_none());
+    }
+
+    #[test]
+    fn frames_outlive_the_allocator() {
+        let mut allocator = allocator();
+        let mut frames = allocator.create_frame_arena(1024, 2).unwrap();
+        frames.begin_frame();
+        let allocation = frames.allocate(16).unwrap();
+        drop(allocator);
+
+        frames.get_mut(&allocation).unwrap().fill(3);
+        assert!(frames.get(&allocation).unwrap().iter().all(|&byte| byte == 3));
+    }
+
+    #[test]
+    fn dropped_arena_gives_its_memory_to_the_render_tier() {
+        let mut allocator = allocator();
+        let capacity = allocator.tier_stats(Tier::RENDER).capacity;
+        let frames = allocator.create_frame_arena(PAGE_SIZE, 2).unwrap();
+        assert_eq!(allocator.tier_stats(Tier::RENDER).capacity, capacity);
+
+        drop(frames);
+        assert_eq!(allocator.tier_stats(Tier::RENDER).capacity, capacity + 2 * PAGE_SIZE);
+        assert_eq!(allocator.tier_segments(Tier::RENDER), 2);
+        assert!(!allocator.allocate(2 * PAGE_SIZE - 1024, Tier::RENDER).is_null());
+    }
+}
@@ -0,0 +1,274 @@
+// Guard mode, built with the "guard" feature.
+//
+// Every block a tier hands out gets canary bytes in front of and behind what
+// was asked for, so an overflow out of one block lands in its own canaries
+// before it reaches the neighbour. The front guard is a whole multiple of the
+// tier's alignment, which keeps the memory handed out aligned:
+//
+//   [front guard][requested bytes][GUARD_SIZE rear guard][slack]
+//
+// Canaries are checked when a block is freed and by validate(), which walks
+// every live block of a tier. validate() also reports the damaged blocks that
+// were freed since it last ran. Freed blocks are poisoned, and so is memory
+// that a reset, compaction or popped marker recycles, so reads through stale
+// pointers stand out.
+
+use std::collections::HashMap;
+
+use super::AllocHandle;
+
+// Bytes of the rear guard, and the least the front guard has
+pub const GUARD_SIZE: usize = 16;
+
+// What guards are filled with
+pub const CANARY_BYTE: u8 = 0xFD;
+
+// What recycled memory is filled with
+pub const POISON_BYTE: u8 = 0xDD;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum GuardSide {
+    Front,  // Something wrote before the block, e.g. the block below overflowed
+    Rear,   // The block overflowed
+}
+
+impl GuardSide {
+    pub fn name(&self) -> &'static str {
+        match self {
+            GuardSide::Front => "front",
+            GuardSide::Rear => "rear",
+        }
+    }
+}
+
+// A guard that no longer holds its canaries
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct GuardCorruption {
+    pub handle: AllocHandle,    // The block whose guard was overwritten
+    pub tag: Option<String>,    // Its allocation tag
+    pub side: GuardSide,
+    pub corrupted: usize,       // Guard bytes that changed
+    pub freed: bool,            // Found when the block was freed, its handle no longer resolves
+}
+
+// Front guard of a tier with `alignment`
+pub(crate) fn front_size(alignment: usize) -> usize {
+    GUARD_SIZE.next_multiple_of(alignment)
+}
+
+// Bytes a block needs to hold `size` bytes and its guards, None if that overflows
+pub(crate) fn guarded_size(size: usize, alignment: usize) -> Option<usize> {
+    front_size(alignment).checked_add(size)?.checked_add(GUARD_SIZE)
+}
+
+// Fill both guards of the block at `block`
+//
+// # Safety
+// `block` has to point at a live block of at least `guarded_size(size, alignment)` bytes.
+pub(crate) unsafe fn write_canaries(block: *mut u8, size: usize, alignment: usize) {
+    let front = front_size(alignment);
+    unsafe {
+        std::ptr::write_bytes(block, CANARY_BYTE, front);
+        std::ptr::write_bytes(block.add(front + size), CANARY_BYTE, GUARD_SIZE);
+    }
+}
+
+// Changed bytes in the front and rear guard of the block at `block`
+//
+// # Safety
+// Same as write_canaries.
+pub(crate) unsafe fn check_canaries(block: *const u8, size: usize, alignment: usize) -> (usize, usize) {
+    let front = front_size(alignment);
+    let changed = |bytes: &[u8]| bytes.iter().filter(|&&byte| byte != CANARY_BYTE).count();
+    unsafe {
+        (
+            changed(std::slice::from_raw_parts(block, front)),
+            changed(std::slice::from_raw_parts(block.add(front + size), GUARD_SIZE)),
+        )
+    }
+}
+
+// Requested size of every live block, by the position of the block's start
+#[derive(Default)]
+pub(crate) struct GuardTracker {
+    live: HashMap<usize, (usize, usize)>,  // Position to requested size and block size
+    damaged_frees: Vec<GuardCorruption>,   // Found by free, until validate() hands them out
+}
+
+impl GuardTracker {
+    pub fn allocated(&mut self, position: usize, size: usize, block_size: usize) {
+        self.live.insert(position, (size, block_size));
+    }
+
+    // Requested size and block size of the live block at `position`
+    pub fn live(&self, position: usize) -> Option<(usize, usize)> {
+        self.live.get(&position).copied()
+    }
+
+    // The block at `position` is no longer live
+    pub fn freed(&mut self, position: usize) {
+        self.live.remove(&position);
+    }
+
+    // Forget every block that reaches past `preserved` bytes
+    pub fn truncate(&mut self, preserved: usize) {
+        self.live.retain(|&position, (_, block_size)| position + *block_size <= preserved);
+    }
+
+    pub fn clear(&mut self) {
+        self.live.clear();
+    }
+
+    pub fn damaged_free(&mut self, corruption: GuardCorruption) {
+        self.damaged_frees.push(corruption);
+    }
+
+    pub fn take_damaged_frees(&mut self) -> Vec<GuardCorruption> {
+        std::mem::take(&mut self.damaged_frees)
+    }
+
+    // Every live block as (position, requested size), lowest first
+    pub fn blocks(&self) -> Vec<(usize, usize)> {
+        let mut blocks: Vec<(usize, usize)> = self.live.iter().map(|(&position, &(size, _))| (position, size)).collect();
+        blocks.sort_unstable();
+        blocks
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::strategy::MIN_BLOCK_SIZE;
+    use crate::{NativeMemory, Tier, TieredAllocator};
+
+    const SIZE: usize = 100;
+
+    fn allocator() -> TieredAllocator {
+        TieredAllocator::new(Box::new(NativeMemory::new(64)), 16)
+    }
+
+    fn filled(allocator: &mut TieredAllocator, byte: u8) -> *mut u8 {
+        let ptr = allocator.allocate(SIZE, Tier::SCENE);
+        assert!(!ptr.is_null());
+        unsafe { std::ptr::write_bytes(ptr, byte, SIZE) };
+        ptr
+    }
+
+    fn bytes<'a>(ptr: *mut u8, len: usize) -> &'a [u8] {
+        unsafe { std::slice::from_raw_parts(ptr, len) }
+    }
+
+    #[test]
+    fn rear_overflow_is_reported() {
+        let mut allocator = allocator();
+        let ptr = allocator.allocate_tagged(SIZE, Tier::SCENE, "overflow");
+        unsafe { std::ptr::write_bytes(ptr, 1, SIZE + 3) };
+
+        let corrupted = allocator.validate();
+        assert_eq!(corrupted.len(), 1);
+        assert_eq!(corrupted[0].side, GuardSide::Rear);
+        assert_eq!(corrupted[0].corrupted, 3);
+        assert_eq!(corrupted[0].tag.as_deref(), Some("overflow"));
+        assert_eq!(corrupted[0].handle.size(), SIZE);
+        assert_eq!(allocator.resolve_handle(&corrupted[0].handle), Some(ptr));
+        assert!(!corrupted[0].freed);
+    }
+
+    #[test]
+    fn front_underflow_is_reported() {
+        let mut allocator = allocator();
+        filled(&mut allocator, 1);
+        let ptr = filled(&mut allocator, 2);
+        unsafe { ptr.sub(1).write(2) };
+
+        let corrupted = allocator.validate_tier(Tier::SCENE);
+        assert_eq!(corrupted.len(), 1);
+        assert_eq!(corrupted[0].side, GuardSide::Front);
+        assert_eq!(corrupted[0].corrupted, 1);
+        assert_eq!(allocator.resolve_handle(&corrupted[0].handle), Some(ptr));
+    }
+
+    #[test]
+    fn clean_free_reports_nothing() {
+        let mut allocator = allocator();
+        let (mut owner, ptr) = allocator.allocate_with_owner(SIZE, Tier::SCENE).unwrap();
+        unsafe { std::ptr::write_bytes(ptr, 1, SIZE) };
+        owner.allocate(SIZE).unwrap();
+
+        // Below the other block, so the strategy links it into its free list
+        assert!(owner.free(0));
+        assert!(allocator.validate().is_empty());
+
+        // Everything past the strategy's free list header is poisoned
+        assert!(bytes(ptr, SIZE + GUARD_SIZE)[MIN_BLOCK_SIZE..].iter().all(|&byte| byte == POISON_BYTE));
+    }
+
+    #[test]
+    fn damaged_free_is_reported_once() {
+        let mut allocator = allocator();
+        let (mut owner, ptr) = allocator.allocate_with_owner(SIZE, Tier::SCENE).unwrap();
+        unsafe { std::ptr::write_bytes(ptr, 1, SIZE + 1) };
+        assert!(owner.free(0));
+
+        let corrupted = allocator.validate();
+        assert_eq!(corrupted.len(), 1);
+        assert_eq!((corrupted[0].side, corrupted[0].corrupted), (GuardSide::Rear, 1));
+        assert!(corrupted[0].freed);
+        assert_eq!(allocator.resolve_handle(&corrupted[0].handle), None);
+        assert!(allocator.validate().is_empty());
+    }
+
+    #[test]
+    fn reset_poisons_the_tier() {
+        let mut allocator = allocator();
+        let ptr = filled(&mut allocator, 1);
+        allocator.reset_tier(Tier::SCENE);
+
+        assert!(bytes(ptr, SIZE + GUARD_SIZE).iter().all(|&byte| byte == POISON_BYTE));
+        assert!(allocator.validate().is_empty());
+    }
+
+    #[test]
+    fn compaction_poisons_what_it_recycles() {
+        let mut allocator = allocator();
+        let kept = filled(&mut allocator, 1);
+        let preserved = allocator.tier_stats(Tier::SCENE).used;
+        let recycled = filled(&mut allocator, 2);
+        assert!(allocator.fast_compact_tier(Tier::SCENE, preserved));
+
+        assert!(bytes(kept, SIZE).iter().all(|&byte| byte == 1));
+        assert!(bytes(recycled, SIZE + GUARD_SIZE).iter().all(|&byte| byte == POISON_BYTE));
+        assert!(allocator.validate().is_empty());
+    }
+
+    #[test]
+    fn guards_keep_blocks_aligned() {
+        let mut allocator = allocator();
+        for tier in [Tier::RENDER, Tier::SCENE, Tier::ENTITY] {
+            let alignment = allocator.lock_arena(tier).unwrap().alignment();
+            let ptr = allocator.allocate(SIZE, tier);
+            assert_eq!(ptr as usize % alignment, 0);
+        }
+    }
+
+    #[test]
+    fn guarded_sizes_that_overflow_fail() {
+        assert_eq!(guarded_size(SIZE, 128), Some(128 + SIZE + GUARD_SIZE));
+        assert_eq!(guarded_size(usize::MAX - GUARD_SIZE, 8), None);
+
+        let mut allocator = allocator();
+        assert!(allocator.allocate(usize::MAX - 8, Tier::SCENE).is_null());
+        assert!(allocator.validate().is_empty());
+    }
+
+    #[test]
+    fn growth_leaves_room_for_the_guards() {
+        let mut allocator = allocator();
+        let size = 4 * crate::PAGE_SIZE;
+        assert!(size > allocator.tier_stats(Tier::ENTITY).capacity);
+        let ptr = allocator.allocate(size, Tier::ENTITY);
+        assert!(!ptr.is_null());
+        unsafe { std::ptr::write_bytes(ptr, 1, size) };
+        assert!(allocator.validate().is_empty());
+    }
+}
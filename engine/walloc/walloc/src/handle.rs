@@ -0,0 +1,90 @@
+// Generational allocation handles.
+//
+// A handle names an allocation by where it lives (tier, segment and offset
+// inside the segment) plus the tier generation it was made in. Every reset or
+// compaction that recycles memory bumps the tier generation, so resolving a
+// handle to recycled memory fails instead of handing back someone else's bytes.
+// Each block also gets an epoch when it is allocated, so freeing or evicting
+// the block invalidates its handles even if the same spot is handed out again.
+
+use wasm_bindgen::prelude::*;
+
+use super::Tier;
+
+#[wasm_bindgen]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AllocHandle {
+    tier: Tier,
+    segment: usize,
+    offset: usize,
+    size: usize,
+    generation: u32,
+    epoch: u32,  // Epoch of the block the handle points into
+}
+
+impl AllocHandle {
+    pub(crate) fn new(tier: Tier, segment: usize, offset: usize, size: usize, generation: u32, epoch: u32) -> Self {
+        AllocHandle {
+            tier,
+            segment,
+            offset,
+            size,
+            generation,
+            epoch,
+        }
+    }
+
+    pub(crate) fn epoch(&self) -> u32 {
+        self.epoch
+    }
+
+    // Handle to `size` bytes at `offset` inside this allocation
+    pub(crate) fn sub_handle(&self, offset: usize, size: usize) -> AllocHandle {
+        AllocHandle {
+            offset: self.offset + offset,
+            size,
+            ..*self
+        }
+    }
+
+    pub fn tier_kind(&self) -> Tier {
+        self.tier
+    }
+}
+
+#[wasm_bindgen]
+impl AllocHandle {
+    #[wasm_bindgen(getter)]
+    pub fn tier(&self) -> u8 {
+        self.tier.id()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn segment(&self) -> usize {
+        self.segment
+    }
+
+    // Offset inside the segment, not inside linear memory
+    #[wasm_bindgen(getter)]
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    #[wasm_bindgen(js_name = toString)]
+    pub fn to_js_string(&self) -> String {
+        format!(
+            "tier {} [segment {} + {}, {} bytes, generation {}]",
+            self.tier.index(), self.segment, self.offset, self.size, self.generation
+        )
+    }
+}
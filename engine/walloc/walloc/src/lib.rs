@@ -1,994 +1,2877 @@
 use wasm_bindgen::prelude::*;
-use std::sync::{Arc, Mutex, atomic::{AtomicUsize, Ordering}};
-use reqwest::Client;
+use std::collections::{BTreeMap, HashMap};
+use std::time::Duration;
+use std::sync::{Arc, Mutex, Weak, atomic::{AtomicUsize, Ordering}};
 use wasm_bindgen_futures::{future_to_promise};
-use std::collections::HashMap;
 use js_sys::Promise;
 
+mod memory;
+mod frame;
+mod handle;
+mod strategy;
+mod config;
+mod typed;
+mod marker;
+mod pool;
+mod cache;
+mod source;
+mod asset;
+mod pack;
+mod manifest;
+mod batch;
+mod error;
+mod retry;
+mod request;
+mod offline;
+mod encoding;
+mod view;
+mod tag;
+mod pressure;
+#[cfg(feature = "guard")]
+mod guard;
+#[cfg(feature = "trace")]
+mod trace;
+
+pub use handle::AllocHandle;
+pub use marker::Marker;
+pub use pool::{Pool, PoolHandle, PoolStats, MAX_POOL_SLOTS};
+pub use asset::{AssetType, AssetFormat, AssetInfo};
+pub use pack::{PackBuilder, PackIndex, PackEntry, PACK_MAGIC, PACK_VERSION, PACK_ALIGNMENT, content_hash, hash_hex, hash_from_hex};
+pub use manifest::{AssetManifest, ManifestEntry, IntegrityError};
+pub use batch::DEFAULT_MAX_CONCURRENT_LOADS;
+pub use error::WallocError;
+pub use retry::RetryPolicy;
+pub use request::{AssetRequest, Priority};
+pub use encoding::AssetEncoding;
+pub use view::{ViewKind, SoaLayout, SoaField, SOA_ALIGNMENT};
+pub use tag::TagStats;
+#[cfg(feature = "guard")]
+pub use guard::{GuardCorruption, GuardSide, GUARD_SIZE, CANARY_BYTE, POISON_BYTE};
+pub use pressure::{PressureLevel, PressureThresholds, PressureEvent, PressureCallback, ExhaustionPolicy};
+#[cfg(feature = "trace")]
+pub use trace::{TraceLog, TraceEvent, TraceOp, DEFAULT_TRACE_CAPACITY};
+pub use offline::{CacheStore, StoreFuture, CacheRecord, CachedAsset, OfflineCache, OfflineCacheStats, CachedSource, InMemoryStore, DEFAULT_OFFLINE_CACHE_LIMIT};
+#[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+pub use offline::DirectoryStore;
+#[cfg(target_arch = "wasm32")]
+pub use offline::JsCacheStore;
+pub use source::{AssetSource, AssetStream, FetchFuture, StreamFuture, ChunkFuture, ConditionalFuture, LoadProgress, Credentials, FetchOptions, Validators, Revalidation, HttpSource, InMemorySource, FallbackSource};
+#[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+pub use source::FileSource;
+pub use cache::{EvictionPolicy, LruPolicy, LfuPolicy, PinnedPolicy, AssetUsage, AssetCacheStats, eviction_policy};
+pub use typed::{Pod, ArenaBox, ArenaSlice, ArenaVec};
+pub use config::{TieredAllocatorConfig, TierConfig, GrowthPolicy, MAX_TIERS};
+pub use strategy::{AllocStrategy, StrategyKind, BumpStrategy, FreeListStrategy, BuddyStrategy, TlsfStrategy};
+pub use frame::{FrameArena, FrameAllocation, FRAME_ALIGNMENT};
+pub use memory::{MemorySource, PAGE_SIZE, MAX_PAGES, default_source};
+#[cfg(target_arch = "wasm32")]
+pub use memory::WasmMemory;
+#[cfg(not(target_arch = "wasm32"))]
+pub use memory::NativeMemory;
+use strategy::MIN_BLOCK_SIZE;
+use pool::PoolCounters;
+use cache::{AssetCache, AssetMetadata, PackMetadata};
+use batch::{BoxedLoad, Flight, InFlightLoads, LoadQueue};
+use retry::Deadline;
+use tag::TagTracker;
+use pressure::PressureHandlers;
+#[cfg(feature = "guard")]
+use guard::GuardTracker;
+
+// Pages reserved for the tiers when a Walloc is created (1MB)
+const INITIAL_HEAP_PAGES: usize = 16;
+
+// Staging space for a streaming load whose length is not known up front
+const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+// Most staging a Content-Length reserves up front, beyond it the block grows
+// as the data actually arrives
+const STREAM_RESERVE_LIMIT: usize = 16 * STREAM_CHUNK_SIZE;
+
+// A streamed asset keeps its staging block unless over 1/8 of it is unused
+const STAGING_SLACK_DIVISOR: usize = 8;
+
+// Read from a decompressor whose block is full, to see if it has more
+const DECOMPRESS_PROBE_SIZE: usize = 4096;
+
+// Tags of the blocks asset loads allocate, assets themselves are tagged
+// "assets/<type>"
+const STAGING_TAG: &str = "assets/staging";
+const PACK_TAG: &str = "assets/pack";
+
 #[wasm_bindgen]
 pub struct Walloc {
     strategy: TieredAllocator,
     memory_base: *mut u8,
-    memory_size: usize,
+    frames: Option<FrameArena>,  // Scratch memory for the renderer, once created
+    #[cfg(target_arch = "wasm32")]
+    pressure_handler: Option<usize>,  // Id of the JS pressure callback's handler
 }
 
-#[repr(C)]
-struct BlockHeader {
-    size: usize,
-    next: *mut BlockHeader,
-    is_free: bool,
-    tier: u8,
-}
-
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
-pub enum Tier {
-    Render = 0,   // Top tier: Mesh data, render targets (frequent reallocation, cache-aligned)
-    Scene = 1,    // Middle tier: Scene data, gameplay systems (medium lifecycle)
-    Entity = 2,   // Bottom tier: Actors, particles, effects (short lifecycle)
-}
+// Index of a tier in the allocator's layout. The default layout has the three
+// tiers below, a TieredAllocatorConfig can lay out up to 255 of its own.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct Tier(u8);
 
 impl Tier {
-    fn from_u8(value: u8) -> Option<Tier> {
-        match value {
-            0 => Some(Tier::Render),
-            1 => Some(Tier::Scene),
-            2 => Some(Tier::Entity),
-            _ => None,
-        }
+    pub const RENDER: Tier = Tier(0);   // Top tier: Mesh data, render targets (frequent reallocation, cache-aligned)
+    pub const SCENE: Tier = Tier(1);    // Middle tier: Scene data, gameplay systems (medium lifecycle)
+    pub const ENTITY: Tier = Tier(2);   // Bottom tier: Actors, particles, effects (short lifecycle)
+
+    pub const fn new(index: u8) -> Tier {
+        Tier(index)
+    }
+
+    pub fn index(self) -> usize {
+        self.0 as usize
+    }
+
+    pub fn id(self) -> u8 {
+        self.0
     }
 }
 
-pub struct Arena {
+// One contiguous region of a tier. Segments are never moved or replaced, so
+// anything allocated in them stays put when the tier grows.
+struct Segment {
     base: *mut u8,
     size: usize,
-    current_offset: AtomicUsize,
+    allocator: Box<dyn AllocStrategy>,
+}
+
+pub struct Arena {
+    segments: Vec<Segment>,
+    // Keeps the segments mapped for as long as any owner can reach the arena,
+    // even once the allocator itself is gone
+    memory: Arc<Mutex<Box<dyn MemorySource>>>,
     tier: Tier,
+    name: String,
+    alignment: usize,
+    strategy: StrategyKind,  // Used for every segment of the tier
+    growth: GrowthPolicy,
 
     high_water_mark: AtomicUsize,  // Track the highest allocation point
     total_allocated: AtomicUsize,  // Track total bytes allocated, even when recycled
+
+    // Bumped whenever memory is recycled
+    generation: u32,
+
+    // Every live block by where it starts, with its size and the epoch its
+    // handles carry. Freeing or recycling a block kills its handles, whatever
+    // generation they were made in.
+    blocks: BTreeMap<usize, (usize, u32)>,
+    next_epoch: u32,
+
+    // Pushed markers, innermost last. Allocations stay above the innermost one.
+    markers: Vec<Marker>,
+    next_marker_id: u64,
+
+    tags: TagTracker,  // Tags of live tagged blocks
+    #[cfg(feature = "trace")]
+    trace: Option<Arc<TraceLog>>,
+    #[cfg(feature = "guard")]
+    guards: GuardTracker,  // Requested size of every live block, to find its canaries
+
+    pressure: PressureThresholds,
+    pressure_level: PressureLevel,  // Level the handlers last heard of
+    exhaustion: ExhaustionPolicy,
+}
+
+// An arena exclusively owns its region of memory and is only ever reached
+// through a Mutex, so it can move between threads.
+unsafe impl Send for Arena {}
+
+// Snapshot of a tier's allocator
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct ArenaStats {
+    pub strategy: StrategyKind,
+    pub used: usize,
+    pub capacity: usize,
+    pub high_water_mark: usize,
+    pub total_allocated: usize,
+    pub free_bytes: usize,          // Freed bytes below the highest allocation, waiting for reuse
+    pub largest_free_block: usize,  // Largest allocation that fits without growing
+    pub fragmentation: f32,         // 0.0 when free memory is contiguous, towards 1.0 as it splinters
+}
+
+// Block of the asset tier a streaming load copies its chunks into
+struct Staging {
+    handle: AllocHandle,  // Covers the whole reserved capacity
+    block_size: usize,
+    len: usize,           // Bytes received so far
+    tier: Tier,
+}
+
+// How an asset arrived, before it was decompressed
+#[derive(Clone, Copy)]
+struct Shipped {
+    encoding: AssetEncoding,
+    size: usize,  // Bytes as fetched
+}
+
+impl Shipped {
+    fn identity(size: usize) -> Self {
+        Shipped { encoding: AssetEncoding::Identity, size }
+    }
 }
 
+// Owns a set of allocations in one tier and gives them back when dropped
 pub struct MemoryOwner {
     arena: Arc<Mutex<Arena>>,
-    allocations: Vec<(usize, usize)>,
+    allocations: Vec<(AllocHandle, usize)>, // (handle, block size)
+    tag: Option<Arc<str>>,  // Allocation tag of every block it allocates
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
-pub enum AssetType {
-    Image = 0,
-    Json = 1,
+pub struct TieredAllocator {
+    arenas: Vec<Arc<Mutex<Arena>>>,  // One per tier, in layout order
+    asset_tier: Tier,
+    render_tier: Tier,
+
+    memory: Arc<Mutex<Box<dyn MemorySource>>>,
+
+    assets: Arc<Mutex<AssetCache>>,
+    asset_source: Arc<Mutex<Arc<dyn AssetSource>>>,
+    manifest: Arc<Mutex<AssetManifest>>,  // Empty until one is installed, which checks nothing
+    loading: InFlightLoads,  // Asset loads waiting on their fetch, by path
+    queue: LoadQueue,  // Slots for fetching, handed out by priority
+    offline_cache: Arc<Mutex<Option<Arc<OfflineCache>>>>,  // Under the asset source, if installed
+    retry: Arc<Mutex<RetryPolicy>>,
+
+    pools: Arc<Mutex<Vec<Weak<PoolCounters>>>>,  // Every pool created, for memory_stats
+
+    tag: Arc<Mutex<Option<Arc<str>>>>,  // Given to allocations that name no tag of their own
+    tag_names: Arc<Mutex<HashMap<String, Arc<str>>>>,  // Every tag used, shared between blocks
+    #[cfg(feature = "trace")]
+    trace: Arc<TraceLog>,
+
+    pressure: Arc<PressureHandlers>,
 }
 
-#[derive(Clone)]
-struct AssetMetadata {
-    asset_type: AssetType,
-    size: usize,
-    offset: usize,
+// Console logging that stays silent outside the browser
+fn console_log(message: &str) {
+    #[cfg(target_arch = "wasm32")]
+    {
+        web_sys::console::log_1(&message.into());
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let _ = message;
+    }
 }
 
-pub struct TieredAllocator {
-    render_arena: Arc<Mutex<Arena>>,
-    scene_arena: Arc<Mutex<Arena>>,
-    entity_arena: Arc<Mutex<Arena>>,
+// { tag, tier, liveBytes, liveAllocations, totalBytes, totalAllocations }
+fn tag_stats_object(stats: &TagStats) -> JsValue {
+    let obj = js_sys::Object::new();
+    let set = |key: &str, value: JsValue| {
+        let _ = js_sys::Reflect::set(&obj, &JsValue::from_str(key), &value);
+    };
+    set("tag", JsValue::from_str(&stats.tag));
+    set("tier", JsValue::from_f64(stats.tier.index() as f64));
+    set("liveBytes", JsValue::from_f64(stats.live_bytes as f64));
+    set("liveAllocations", JsValue::from_f64(stats.live_allocations as f64));
+    set("totalBytes", JsValue::from_f64(stats.total_bytes as f64));
+    set("totalAllocations", JsValue::from_f64(stats.total_allocations as f64));
+    obj.into()
+}
 
-    assets: Arc<Mutex<HashMap<String, AssetMetadata>>>,
-    base_url: Arc<Mutex<String>>,
-    http_client: Client,
+// { tier, tierName, level, previous, used, capacity, requested, exhausted }
+#[cfg(target_arch = "wasm32")]
+fn pressure_event_object(event: &PressureEvent, tier_name: &str) -> JsValue {
+    let obj = js_sys::Object::new();
+    let set = |key: &str, value: JsValue| {
+        let _ = js_sys::Reflect::set(&obj, &JsValue::from_str(key), &value);
+    };
+    set("tier", JsValue::from_f64(event.tier.index() as f64));
+    set("tierName", JsValue::from_str(tier_name));
+    set("level", JsValue::from_str(event.level.name()));
+    set("previous", JsValue::from_str(event.previous.name()));
+    set("used", JsValue::from_f64(event.used as f64));
+    set("capacity", JsValue::from_f64(event.capacity as f64));
+    set("requested", JsValue::from_f64(event.requested as f64));
+    set("exhausted", JsValue::from_bool(event.exhausted));
+    obj.into()
 }
 
-// Arena implementation for tiered allocation
-impl Arena {
-    pub fn new(base: *mut u8, size: usize, tier: Tier) -> Self {
+// JS memory pressure callback
+#[cfg(target_arch = "wasm32")]
+struct JsPressureHandler(js_sys::Function);
+
+// The browser build has one thread, so the callback never leaves it
+#[cfg(target_arch = "wasm32")]
+unsafe impl Send for JsPressureHandler {}
+#[cfg(target_arch = "wasm32")]
+unsafe impl Sync for JsPressureHandler {}
+
+// Tag of the block holding an asset of `asset_type`
+fn asset_tag(asset_type: AssetType) -> String {
+    format!("assets/{}", asset_type.name())
+}
+
+// View type named by JS, bytes unless it says otherwise
+fn view_kind(name: Option<&str>) -> Result<ViewKind, JsValue> {
+    match name {
+        None => Ok(ViewKind::Uint8),
+        Some(name) => ViewKind::from_name(name).ok_or_else(|| JsValue::from_str(&format!("Unknown view type '{}'", name))),
+    }
+}
+
+// Console warning for debug diagnostics, silent outside the browser like
+// console_log. Native callers count what they report instead (stale frame
+// reads, guard damage returned by validate()).
+#[cfg(any(debug_assertions, feature = "guard"))]
+fn console_warn(message: &str) {
+    #[cfg(target_arch = "wasm32")]
+    {
+        web_sys::console::warn_1(&message.into());
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        let _ = message;
+    }
+}
+
+impl Segment {
+    fn new(base: *mut u8, size: usize, tier: Tier, strategy: StrategyKind) -> Self {
         Self {
             base,
             size,
-            current_offset: AtomicUsize::new(0),
+            allocator: strategy.build(tier, base, size),
+        }
+    }
+
+    fn contains(&self, ptr: *mut u8) -> bool {
+        let end = unsafe { self.base.add(self.size) };
+        ptr >= self.base && ptr < end
+    }
+}
+
+// Arena implementation for tiered allocation
+impl Arena {
+    pub fn new(memory: Arc<Mutex<Box<dyn MemorySource>>>, base: *mut u8, size: usize, tier: Tier, config: &TierConfig) -> Self {
+        Self {
+            segments: vec![Segment::new(base, size, tier, config.strategy)],
+            memory,
             tier,
+            name: config.name.clone(),
+            alignment: config.alignment,
+            strategy: config.strategy,
+            growth: config.growth,
             high_water_mark: AtomicUsize::new(0),
             total_allocated: AtomicUsize::new(0),
+            generation: 0,
+            blocks: BTreeMap::new(),
+            next_epoch: 0,
+            markers: Vec::new(),
+            next_marker_id: 0,
+            tags: TagTracker::default(),
+            #[cfg(feature = "trace")]
+            trace: None,
+            #[cfg(feature = "guard")]
+            guards: GuardTracker::default(),
+            pressure: config.pressure,
+            pressure_level: PressureLevel::Normal,
+            exhaustion: config.exhaustion,
+        }
+    }
+
+    // Log every operation on the tier to `trace`
+    #[cfg(feature = "trace")]
+    pub fn set_trace(&mut self, trace: Arc<TraceLog>) {
+        self.trace = Some(trace);
+    }
+
+    #[cfg(feature = "trace")]
+    fn trace(&self, op: TraceOp, position: Option<usize>, size: usize, tag: Option<&Arc<str>>) {
+        if let Some(trace) = &self.trace {
+            trace.record(op, self.tier, position, size, self.usage(), tag);
         }
     }
+
+    // Add a freshly grown region to the tier. Existing segments are untouched,
+    // so earlier allocations and their (segment, offset) locations stay valid.
+    pub fn add_segment(&mut self, base: *mut u8, size: usize) -> usize {
+        self.segments.push(Segment::new(base, size, self.tier, self.strategy));
+        #[cfg(feature = "trace")]
+        self.trace(TraceOp::Grow, Some(self.capacity() - size), size, None);
+        self.segments.len() - 1
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn strategy(&self) -> StrategyKind {
+        self.strategy
+    }
     
-    // Bump allocation - very fast track total allocated memory and high water mark
-    pub fn allocate(&self, size: usize) -> Option<(*mut u8, usize)> {
-        // Align size to appropriate boundary based on tier
-        let aligned_size = match self.tier {
-            Tier::Render => (size + 127) & !127,  // 128-byte alignment for GPU warp access
-            Tier::Scene => (size + 63) & !63,     // 64-byte alignment for cache lines
-            Tier::Entity => (size + 7) & !7,      // 8-byte alignment for other tiers
-        };
+    // Allocate through the tier's strategy, tracking total allocated memory and high water mark
+    pub fn allocate(&mut self, size: usize) -> Option<(*mut u8, usize)> {
+        self.allocate_tagged(size, None)
+    }
+
+    // Allocate a block that remembers `tag` while it is live
+    pub fn allocate_tagged(&mut self, size: usize, tag: Option<&Arc<str>>) -> Option<(*mut u8, usize)> {
+        let aligned_size = self.block_size(size)?;
+        let front = self.front_guard();
+        let alignment = self.alignment;
+        let floor = self.markers.last().map_or(0, |marker| marker.position());
         
-        // Atomic compare-and-swap to reserve space
-        let mut current_offset = self.current_offset.load(Ordering::Relaxed);
-        loop {
-            // Check if we have enough space
-            if current_offset + aligned_size > self.size {
-                return None; // Not enough space
-            }
-            
-            // Try to advance the offset
-            let new_offset = current_offset + aligned_size;
-            match self.current_offset.compare_exchange(
-                current_offset, 
-                new_offset,
-                Ordering::SeqCst,
-                Ordering::Relaxed
-            ) {
-                Ok(_) => {
-                    // Success! Update the high water mark if needed
-                    let hwm = self.high_water_mark.load(Ordering::Relaxed);
-                    if new_offset > hwm {
-                        self.high_water_mark.store(new_offset, Ordering::Relaxed);
-                    }
-                    
-                    // Update total allocated bytes
-                    self.total_allocated.fetch_add(aligned_size, Ordering::Relaxed);
-                    
-                    // Return pointer to the allocated memory
-                    let ptr = unsafe { self.base.add(current_offset) };
-                    return Some((ptr, aligned_size));
+        // First segment with room wins, so space recycled in older segments is reused
+        let mut start = 0;
+        for segment in &mut self.segments {
+            // Only memory above the innermost marker may be handed out
+            let local_floor = floor.saturating_sub(start);
+            start += segment.size;
+            let allocation = if local_floor == 0 {
+                segment.allocator.allocate(aligned_size, alignment)
+            } else if local_floor < segment.size {
+                segment.allocator.allocate_from(aligned_size, alignment, local_floor)
+            } else {
+                None
+            };
+
+            if let Some((offset, block_size)) = allocation {
+                let block = unsafe { segment.base.add(offset) };
+                let ptr = unsafe { block.add(front) };
+                let position = start - segment.size + offset;
+                #[cfg(feature = "guard")]
+                {
+                    unsafe { guard::write_canaries(block, size, alignment) };
+                    self.guards.allocated(position, size, block_size);
                 }
-                Err(actual) => {
-                    // Try again with the updated offset
-                    current_offset = actual;
+                self.blocks.insert(position, (block_size, self.next_epoch));
+                self.next_epoch = self.next_epoch.wrapping_add(1);
+
+                // Success! Update the high water mark if needed
+                let usage = self.usage();
+                self.high_water_mark.fetch_max(usage, Ordering::Relaxed);
+                
+                // Update total allocated bytes
+                self.total_allocated.fetch_add(block_size, Ordering::Relaxed);
+
+                if let Some(tag) = tag {
+                    self.tags.allocated(position, block_size, tag);
                 }
+                #[cfg(feature = "trace")]
+                self.trace(TraceOp::Alloc, Some(position), block_size, tag);
+                
+                return Some((ptr, block_size));
+            }
+        }
+
+        None // Not enough space in any segment
+    }
+
+    // Alignment of every block in the tier
+    pub fn alignment(&self) -> usize {
+        self.alignment
+    }
+    
+    // Align size to the tier's boundary
+    fn align_size(&self, size: usize) -> Option<usize> {
+        size.max(MIN_BLOCK_SIZE).checked_next_multiple_of(self.alignment)
+    }
+
+    // Bytes of the block an allocation of `size` takes, guards included
+    fn block_size(&self, size: usize) -> Option<usize> {
+        #[cfg(not(feature = "guard"))]
+        return self.align_size(size);
+        #[cfg(feature = "guard")]
+        return self.align_size(guard::guarded_size(size, self.alignment)?);
+    }
+
+    // Bytes between the start of a block and the memory handed out
+    fn front_guard(&self) -> usize {
+        #[cfg(feature = "guard")]
+        {
+            guard::front_size(self.alignment)
+        }
+        #[cfg(not(feature = "guard"))]
+        {
+            0
+        }
+    }
+
+    // Give a block back to the tier's strategy.
+    // Returns false if the block is not (or no longer) allocated here.
+    pub fn free(&mut self, ptr: *mut u8, block_size: usize) -> bool {
+        let (segment_index, offset) = match self.locate(ptr) {
+            Some((segment_index, offset)) => match offset.checked_sub(self.front_guard()) {
+                Some(offset) => (segment_index, offset),
+                None => return false,
+            },
+            None => return false,
+        };
+
+        let position = self.segment_start(segment_index) + offset;
+        if !self.blocks.contains_key(&position) {
+            return false;
+        }
+
+        // Canaries are checked and the block poisoned before the strategy
+        // writes any free list header into it
+        #[cfg(feature = "guard")]
+        let damage = match self.guards.live(position) {
+            Some((size, guarded_block)) => {
+                let block = unsafe { self.segments[segment_index].base.add(offset) };
+                let (front, rear) = unsafe { guard::check_canaries(block, size, self.alignment) };
+                unsafe { std::ptr::write_bytes(block, guard::POISON_BYTE, guarded_block) };
+                let epoch = self.blocks.get(&position).map_or(0, |&(_, epoch)| epoch);
+                let handle = AllocHandle::new(self.tier, segment_index, offset + self.front_guard(), size, self.generation, epoch);
+                [(GuardSide::Front, front), (GuardSide::Rear, rear)]
+                    .into_iter()
+                    .filter(|&(_, bytes)| bytes > 0)
+                    .map(|(side, bytes)| GuardCorruption {
+                        handle,
+                        tag: self.tags.tag_at(position).map(|tag| tag.to_string()),
+                        side,
+                        corrupted: bytes,
+                        freed: true,
+                    })
+                    .collect()
+            },
+            None => Vec::new(),
+        };
+
+        if !self.segments[segment_index].allocator.free(offset, block_size) {
+            return false;
+        }
+        self.blocks.remove(&position);
+        #[cfg(feature = "guard")]
+        {
+            self.guards.freed(position);
+            for corruption in damage {
+                console_warn(&format!(
+                    "Freed a block of {} bytes at {} in tier '{}' with {} {} guard bytes overwritten (tag {})",
+                    corruption.handle.size(), position, self.name, corruption.corrupted, corruption.side.name(),
+                    corruption.tag.as_deref().unwrap_or("none")
+                ));
+                self.guards.damaged_free(corruption);
             }
         }
+        #[cfg(feature = "trace")]
+        self.trace(TraceOp::Free, Some(position), block_size, self.tags.tag_at(position));
+        self.tags.freed(position);
+        true
+    }
+
+    // Tag of the live block a handle names
+    pub fn tag_of(&self, handle: &AllocHandle) -> Option<Arc<str>> {
+        let position = self.block_position(handle)?;
+        self.tags.tag_at(position).cloned()
+    }
+
+    // Give the live block a handle names another tag
+    pub fn retag(&mut self, handle: &AllocHandle, tag: &Arc<str>) {
+        if let Some(position) = self.block_position(handle) {
+            self.tags.retag(position, tag);
+        }
+    }
+
+    // Where the block a handle points into starts, segments laid end to end
+    fn block_position(&self, handle: &AllocHandle) -> Option<usize> {
+        if handle.segment() >= self.segments.len() {
+            return None;
+        }
+        (self.segment_start(handle.segment()) + handle.offset()).checked_sub(self.front_guard())
+    }
+
+    // What every tag holds in the tier
+    pub fn tag_stats(&self) -> Vec<TagStats> {
+        self.tags.stats(self.tier)
+    }
+
+    pub fn set_pressure(&mut self, pressure: PressureThresholds) {
+        self.pressure = pressure;
+    }
+
+    pub fn pressure(&self) -> PressureThresholds {
+        self.pressure
+    }
+
+    pub fn pressure_level(&self) -> PressureLevel {
+        self.pressure.level(self.usage())
+    }
+
+    // Event for the handlers if the level moved since they last heard of it
+    pub fn update_pressure(&mut self) -> Option<PressureEvent> {
+        let used = self.usage();
+        let level = self.pressure.level(used);
+        if level == self.pressure_level {
+            return None;
+        }
+        let previous = std::mem::replace(&mut self.pressure_level, level);
+        Some(PressureEvent {
+            tier: self.tier,
+            level,
+            previous,
+            used,
+            capacity: self.capacity(),
+            requested: 0,
+            exhausted: false,
+        })
+    }
+
+    pub fn set_exhaustion(&mut self, exhaustion: ExhaustionPolicy) {
+        self.exhaustion = exhaustion;
+    }
+
+    pub fn exhaustion(&self) -> ExhaustionPolicy {
+        self.exhaustion
+    }
+
+    // Freed bytes below the highest allocation, waiting to be reused
+    pub fn free_bytes(&self) -> usize {
+        self.segments
+            .iter()
+            .map(|segment| segment.allocator.extent() - segment.allocator.used())
+            .sum()
     }
     
     // Reset the entire arena - very efficient way to free everything at once
-    pub fn reset(&self) {
-        self.current_offset.store(0, Ordering::SeqCst);
+    pub fn reset(&mut self) {
+        for segment in &mut self.segments {
+            #[cfg(feature = "guard")]
+            unsafe { std::ptr::write_bytes(segment.base, guard::POISON_BYTE, segment.allocator.extent()) };
+            segment.allocator.reset();
+        }
+        self.markers.clear();
+        self.tags.clear();
+        self.blocks.clear();
+        #[cfg(feature = "guard")]
+        self.guards.clear();
+        self.recycle();
+        #[cfg(feature = "trace")]
+        self.trace(TraceOp::Reset, None, 0, None);
+    }
+
+    // Start a new generation once recycled blocks are gone from `blocks`
+    fn recycle(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    // Start of a segment when the segments are laid end to end
+    fn segment_start(&self, segment: usize) -> usize {
+        self.segments[..segment].iter().map(|segment| segment.size).sum()
+    }
+
+    // Live block holding `position`, as (start, size, epoch)
+    fn block_at(&self, position: usize) -> Option<(usize, usize, u32)> {
+        let (&start, &(block_size, epoch)) = self.blocks.range(..=position).next_back()?;
+        (position < start + block_size).then_some((start, block_size, epoch))
+    }
+
+    // Handle for `size` bytes at `ptr`, inside a block allocated in the current generation
+    pub fn handle_for(&self, ptr: *mut u8, size: usize) -> Option<AllocHandle> {
+        let (segment, offset) = self.locate(ptr)?;
+        let (_, _, epoch) = self.block_at(self.segment_start(segment) + offset)?;
+        Some(AllocHandle::new(self.tier, segment, offset, size, self.generation, epoch))
+    }
+
+    // Whether a handle still names memory that has not been freed or recycled
+    pub fn is_handle_valid(&self, handle: &AllocHandle) -> bool {
+        if handle.tier_kind() != self.tier
+            || self.resolve(handle.segment(), handle.offset(), handle.size()).is_none()
+        {
+            return false;
+        }
+
+        // The block it was made for has to still be live, not a later one in its
+        // place. Recycling drops every block it reaches, so that is all older
+        // generations need too.
+        let position = self.segment_start(handle.segment()) + handle.offset();
+        let end = position + handle.size();
+        matches!(
+            self.block_at(position),
+            Some((start, block_size, epoch)) if epoch == handle.epoch() && end <= start + block_size
+        )
+    }
+
+    // Pointer for a handle, or None once its memory has been recycled
+    pub fn resolve_handle(&self, handle: &AllocHandle) -> Option<*mut u8> {
+        if !self.is_handle_valid(handle) {
+            return None;
+        }
+        self.resolve(handle.segment(), handle.offset(), handle.size())
     }
     
     // Check if a pointer belongs to this arena
     pub fn contains(&self, ptr: *mut u8) -> bool {
-        let end = unsafe { self.base.add(self.size) };
-        ptr >= self.base && ptr < end
+        self.segments.iter().any(|segment| segment.contains(ptr))
+    }
+
+    // Find the (segment, offset) location of a pointer in this arena
+    pub fn locate(&self, ptr: *mut u8) -> Option<(usize, usize)> {
+        self.segments
+            .iter()
+            .position(|segment| segment.contains(ptr))
+            .map(|index| (index, (ptr as usize) - (self.segments[index].base as usize)))
+    }
+
+    // Turn a (segment, offset) location back into a pointer, if it is still in bounds
+    pub fn resolve(&self, segment: usize, offset: usize, size: usize) -> Option<*mut u8> {
+        let segment = self.segments.get(segment)?;
+        if offset.checked_add(size)? > segment.size {
+            return None;
+        }
+        Some(unsafe { segment.base.add(offset) })
     }
     
-    // Get current usage
+    // Get current usage - bytes held by live blocks
     pub fn usage(&self) -> usize {
-        self.current_offset.load(Ordering::Relaxed)
+        self.segments.iter().map(|segment| segment.allocator.used()).sum()
     }
     
     // Get capacity
     pub fn capacity(&self) -> usize {
-        self.size
+        self.segments.iter().map(|segment| segment.size).sum()
+    }
+
+    pub fn segment_count(&self) -> usize {
+        self.segments.len()
+    }
+
+    // End of the allocated region when the segments are laid end to end
+    fn logical_end(&self) -> usize {
+        let mut start = 0;
+        let mut end = 0;
+        for segment in &self.segments {
+            let extent = segment.allocator.extent();
+            if extent > 0 {
+                end = start + extent;
+            }
+            start += segment.size;
+        }
+        end
+    }
+
+    // Mark the first `bytes` of the tier (segments laid end to end) as allocated
+    fn set_logical_end(&mut self, bytes: usize) {
+        let mut start = 0;
+        for segment in &mut self.segments {
+            segment.allocator.reserve(bytes.saturating_sub(start).min(segment.size));
+            start += segment.size;
+        }
+        self.high_water_mark.fetch_max(self.usage(), Ordering::Relaxed);
+    }
+
+    // Recycle every allocation that reaches past `preserved` bytes
+    fn truncate(&mut self, preserved: usize) {
+        let mut start = 0;
+        for segment in &mut self.segments {
+            let keep = preserved.saturating_sub(start);
+            #[cfg(feature = "guard")]
+            let extent = segment.allocator.extent();
+            segment.allocator.truncate(keep);
+            // Poisoned once the strategy is done with any free list kept in there
+            #[cfg(feature = "guard")]
+            if extent > keep {
+                unsafe { std::ptr::write_bytes(segment.base.add(keep), guard::POISON_BYTE, extent - keep) };
+            }
+            start += segment.size;
+        }
+        self.blocks.retain(|&position, (block_size, _)| position + *block_size <= preserved);
+        #[cfg(feature = "guard")]
+        self.guards.truncate(preserved);
     }
 
-    // Fast compact operation that preserves the first 'preserve_bytes' of memory
-    // Note: This will return false if preserve_bytes > current_offset.
-    // The TieredAllocator::fast_compact_tier handles the case of growing
-    // memory when needed before calling this method.
-    pub fn fast_compact(&self, preserve_bytes: usize) -> bool {
-        // Ensure we don't preserve more than our current offset
-        let current = self.current_offset.load(Ordering::Relaxed);
-        if preserve_bytes > current {
+    // Fast compact operation that preserves the first 'preserve_bytes' of memory,
+    // counting segments in the order they were added.
+    // Note: This will return false if preserve_bytes is past the end of the
+    // allocated region. The TieredAllocator::fast_compact_tier handles the case
+    // of growing memory when needed before calling this method.
+    pub fn fast_compact(&mut self, preserve_bytes: usize) -> bool {
+        // Ensure we don't preserve more than we've allocated
+        if preserve_bytes > self.logical_end() {
             return false; // Can't preserve more than we've allocated
         }
         
-        // Simple atomic store to update the allocation pointer
-        // This effectively "recycles" all memory after the preserved section
-        self.current_offset.store(preserve_bytes, Ordering::SeqCst);
+        // Every allocation that reaches past the preserved section is recycled
+        self.truncate(preserve_bytes);
+        self.markers.retain(|marker| marker.position() <= preserve_bytes);
+        self.tags.truncate(preserve_bytes);
+        self.recycle();
+        #[cfg(feature = "trace")]
+        self.trace(TraceOp::Compact, None, preserve_bytes, None);
         
         true
     }
 
-    pub fn get_stats(&self) -> (usize, usize, usize, usize) {
-        (
-            self.usage(),
-            self.capacity(),
-            self.high_water_mark.load(Ordering::Relaxed),
-            self.total_allocated.load(Ordering::Relaxed)
-        )
-    }
-}
+    // === Stack markers ===
 
-// TieredAllocator implementation
-impl TieredAllocator {
-    pub fn new(memory_base: *mut u8, memory_size: usize) -> Self {
-        // Calculate sizes for each arena
-        // Render tier: 50% of memory, Scene tier: 30%, Entity tier: 20%
-        let render_size = (memory_size * 50) / 100;
-        let scene_size = (memory_size * 30) / 100;
-        let entity_size = (memory_size * 20) / 100;
-        
-        // Create arenas
-        let render_base = memory_base;
-        let scene_base = unsafe { render_base.add(render_size) };
-        let entity_base = unsafe { scene_base.add(scene_size) };
-        
-        let render_arena = Arena::new(render_base, render_size, Tier::Render);
-        let scene_arena = Arena::new(scene_base, scene_size, Tier::Scene);
-        let entity_arena = Arena::new(entity_base, entity_size, Tier::Entity);
-        
-        TieredAllocator {
-            render_arena: Arc::new(Mutex::new(render_arena)),
-            scene_arena: Arc::new(Mutex::new(scene_arena)),
-            entity_arena: Arc::new(Mutex::new(entity_arena)),
+    // Remember the current end of the tier. Everything allocated until the
+    // marker is popped lands above it.
+    pub fn push_marker(&mut self, name: &str) -> Marker {
+        let marker = Marker::new(self.tier, self.next_marker_id, self.markers.len(), self.logical_end(), name);
+        self.next_marker_id += 1;
+        self.markers.push(marker.clone());
+        marker
+    }
 
-            assets: Arc::new(Mutex::new(HashMap::new())),
-            base_url: Arc::new(Mutex::new(String::new())),
-            http_client: Client::new(),
+    // Recycle everything allocated since `marker` was pushed and pop it.
+    // Only the innermost marker can be popped.
+    pub fn pop_to_marker(&mut self, marker: &Marker) -> Result<(), String> {
+        if marker.tier_kind() != self.tier {
+            return Err(format!("Marker '{}' belongs to tier {}, not {}", marker.name(), marker.tier(), self.tier.index()));
         }
-    }
 
-    // Fast compact for a specific tier with intelligent growing
-    pub fn fast_compact_tier(&mut self, tier: Tier, preserve_bytes: usize) -> bool {
-        // Get current allocation and capacity for the specified tier
-        let (current_offset, capacity) = match tier {
-            Tier::Render => {
-                if let Ok(arena) = self.render_arena.lock() {
-                    (arena.current_offset.load(Ordering::Relaxed), arena.capacity())
-                } else {
-                    return false;
-                }
-            },
-            Tier::Scene => {
-                if let Ok(arena) = self.scene_arena.lock() {
-                    (arena.current_offset.load(Ordering::Relaxed), arena.capacity())
-                } else {
-                    return false;
-                }
-            },
-            Tier::Entity => {
-                if let Ok(arena) = self.entity_arena.lock() {
-                    (arena.current_offset.load(Ordering::Relaxed), arena.capacity())
-                } else {
-                    return false;
-                }
-            },
+        let top = match self.markers.last() {
+            Some(top) => top,
+            None => return Err(format!("Marker '{}' is no longer on tier '{}'", marker.name(), self.name)),
         };
-        
-        // If we need more space than currently allocated
-        if preserve_bytes > current_offset {
-            // Check if the requested size exceeds our capacity
-            if preserve_bytes > capacity {
-                // We need to grow the heap, but first check if it's feasible
-                
-                // Get total WebAssembly memory size (can't exceed 4GB in wasm32)
-                let total_current_pages = core::arch::wasm32::memory_size(0);
-                let max_pages = 65536; // Max 4GB (65536 pages * 64KB per page)
-                
-                // Calculate how many more pages we need
-                let additional_bytes_needed = preserve_bytes - current_offset;
-                let additional_pages_needed = (additional_bytes_needed + 65535) / 65536;
-                
-                // Check if growing would exceed the 4GB limit
-                if total_current_pages + additional_pages_needed > max_pages {
-                    #[cfg(target_arch = "wasm32")]
-                    {
-                        web_sys::console::log_1(&format!(
-                            "Cannot grow memory - would exceed 4GB limit. Current pages: {}, needed: {}, max: {}",
-                            total_current_pages, additional_pages_needed, max_pages
-                        ).into());
-                    }
-                    return false;
-                }
-                
-                // Try to grow the heap
-                #[cfg(target_arch = "wasm32")]
-                {   
-                    web_sys::console::log_1(&format!(
-                        "Growing heap for tier {:?} compact - current: {}, preserve: {}, growing by: {} pages",
-                        tier, current_offset, preserve_bytes, additional_pages_needed
-                    ).into());
-                }
-                
-                // Create temporary storage to hold data we want to preserve
-                let preserve_data = if current_offset > 0 {
-                    // Get a reference to the arena to copy data from
-                    let arena_ref = match tier {
-                        Tier::Render => self.render_arena.clone(),
-                        Tier::Scene => self.scene_arena.clone(),
-                        Tier::Entity => self.entity_arena.clone(),
-                    };
-                    
-                    // Copy the data we want to preserve
-                    if let Ok(arena) = arena_ref.lock() {
-                        // Only copy what's currently allocated (not what we'll grow to)
-                        let bytes_to_copy = current_offset.min(preserve_bytes);
-                        let mut data = Vec::with_capacity(bytes_to_copy);
-                        unsafe {
-                            std::ptr::copy_nonoverlapping(
-                                arena.base,
-                                data.as_mut_ptr(),
-                                bytes_to_copy
-                            );
-                            data.set_len(bytes_to_copy);
-                        }
-                        Some(data)
-                    } else {
-                        None
-                    }
-                } else {
-                    None
-                };
-                
-                // Grow the heap
-                let new_mem = self.grow_heap(additional_bytes_needed, tier);
-                if new_mem.is_null() {
-                    #[cfg(target_arch = "wasm32")]
-                    {   
-                        web_sys::console::log_1(&JsValue::from_str("Failed to grow memory for compact operation"));
-                    }
-                    return false;
-                }
-                
-                // Copy preserved data to the new arena if needed
-                if let Some(data) = preserve_data {
-                    match tier {
-                        Tier::Render => {
-                            if let Ok(arena) = self.render_arena.lock() {
-                                unsafe {
-                                    std::ptr::copy_nonoverlapping(
-                                        data.as_ptr(),
-                                        arena.base,
-                                        data.len()
-                                    );
-                                }
-                                // Set the current offset to include our preserved data
-                                arena.current_offset.store(data.len(), Ordering::SeqCst);
-                            }
-                        },
-                        Tier::Scene => {
-                            if let Ok(arena) = self.scene_arena.lock() {
-                                unsafe {
-                                    std::ptr::copy_nonoverlapping(
-                                        data.as_ptr(),
-                                        arena.base,
-                                        data.len()
-                                    );
-                                }
-                                arena.current_offset.store(data.len(), Ordering::SeqCst);
-                            }
-                        },
-                        Tier::Entity => {
-                            if let Ok(arena) = self.entity_arena.lock() {
-                                unsafe {
-                                    std::ptr::copy_nonoverlapping(
-                                        data.as_ptr(),
-                                        arena.base,
-                                        data.len()
-                                    );
-                                }
-                                arena.current_offset.store(data.len(), Ordering::SeqCst);
-                            }
-                        },
-                    }
-                }
-                
-                // Now ensure the offset is correctly set to preserve_bytes
-                match tier {
-                    Tier::Render => {
-                        if let Ok(arena) = self.render_arena.lock() {
-                            arena.current_offset.store(preserve_bytes, Ordering::SeqCst);
-                        }
-                    },
-                    Tier::Scene => {
-                        if let Ok(arena) = self.scene_arena.lock() {
-                            arena.current_offset.store(preserve_bytes, Ordering::SeqCst);
-                        }
-                    },
-                    Tier::Entity => {
-                        if let Ok(arena) = self.entity_arena.lock() {
-                            arena.current_offset.store(preserve_bytes, Ordering::SeqCst);
-                        }
-                    },
-                }
-                
-                return true; // Successfully grew and preserved
+        if top.id() != marker.id() {
+            return Err(if self.markers.iter().any(|pushed| pushed.id() == marker.id()) {
+                format!("Marker '{}' must be popped before '{}'", top.name(), marker.name())
             } else {
-                // We have enough capacity, just need to allocate up to preserve_bytes
-                match tier {
-                    Tier::Render => {
-                        if let Ok(arena) = self.render_arena.lock() {
-                            // Set the current offset to preserve_bytes
-                            arena.current_offset.store(preserve_bytes, Ordering::SeqCst);
-                            return true;
-                        }
-                    },
-                    Tier::Scene => {
-                        if let Ok(arena) = self.scene_arena.lock() {
-                            arena.current_offset.store(preserve_bytes, Ordering::SeqCst);
-                            return true;
-                        }
-                    },
-                    Tier::Entity => {
-                        if let Ok(arena) = self.entity_arena.lock() {
-                            arena.current_offset.store(preserve_bytes, Ordering::SeqCst);
-                            return true;
-                        }
-                    },
-                }
-            }
-        } else {
-            // Current allocation is sufficient, proceed with normal compact
-            match tier {
-                Tier::Render => {
-                    if let Ok(arena) = self.render_arena.lock() {
-                        return arena.fast_compact(preserve_bytes);
-                    }
-                },
-                Tier::Scene => {
-                    if let Ok(arena) = self.scene_arena.lock() {
-                        return arena.fast_compact(preserve_bytes);
-                    }
-                },
-                Tier::Entity => {
-                    if let Ok(arena) = self.entity_arena.lock() {
-                        return arena.fast_compact(preserve_bytes);
-                    }
-                },
-            }
+                format!("Marker '{}' is no longer on tier '{}'", marker.name(), self.name)
+            });
         }
-        
-        false
+
+        self.truncate(marker.position());
+        self.markers.pop();
+        self.tags.truncate(marker.position());
+        self.recycle();
+        #[cfg(feature = "trace")]
+        self.trace(TraceOp::Compact, None, marker.position(), None);
+
+        Ok(())
     }
 
-    // Grow heap for a specific tier - exact allocation, no overhead
-    pub fn grow_heap(&mut self, size_needed: usize, tier: Tier) -> *mut u8 {
-        // Calculate how many WebAssembly pages we need (64KiB per page)
-        let pages_needed = (size_needed + 65535) / 65536;
-        
-        // Try to grow memory
-        let old_pages = core::arch::wasm32::memory_grow(0, pages_needed);
-        if old_pages == usize::MAX {
-            // Failed to grow memory - log failure
-            return std::ptr::null_mut();
+    // Number of markers currently pushed
+    pub fn marker_depth(&self) -> usize {
+        self.markers.len()
+    }
+
+    // === Guards ===
+
+    // Pointer to a position, segments laid end to end
+    #[cfg(feature = "guard")]
+    fn pointer_at(&self, position: usize) -> Option<*mut u8> {
+        let mut start = 0;
+        for segment in &self.segments {
+            if position < start + segment.size {
+                return Some(unsafe { segment.base.add(position - start) });
+            }
+            start += segment.size;
         }
-        
-        // We successfully grew the memory
-        let new_block_size = pages_needed * 65536;
-        
-        // Calculate the base address for the new memory
-        let new_memory_base = (old_pages * 65536) as *mut u8;
-        
-        // Create a new arena for the specific tier
-        let new_arena = Arena::new(new_memory_base, new_block_size, tier);
-        
-        // Based on the tier, update or replace the corresponding arena
-        match tier {
-            Tier::Render => {
-                if let Ok(mut old_arena) = self.render_arena.lock() {
-                    *old_arena = new_arena;
-                }
-            },
-            Tier::Scene => {
-                if let Ok(mut old_arena) = self.scene_arena.lock() {
-                    *old_arena = new_arena;
-                }
-            },
-            Tier::Entity => {
-                if let Ok(mut old_arena) = self.entity_arena.lock() {
-                    *old_arena = new_arena;
-                }
-            },
-        }
-        
-        // Return a non-null pointer to indicate success
-        // The actual allocation will happen in the caller
-        new_memory_base
-    }
-    
-    pub fn allocate_with_owner(&mut self, size: usize, tier: Tier) -> Option<(MemoryOwner, *mut u8)> {
-        let arena = match tier {
-            Tier::Render => &self.render_arena,
-            Tier::Scene => &self.scene_arena,
-            Tier::Entity => &self.entity_arena,
-        };
-        
-        // Try to allocate from the selected arena
-        if let Ok(arena_lock) = arena.lock() {
-            if let Some((ptr, alloc_size)) = arena_lock.allocate(size) {
-                // Create a memory owner for this allocation
-                let offset = (ptr as usize) - (arena_lock.base as usize);
-                let owner = MemoryOwner {
-                    arena: Arc::clone(arena),
-                    allocations: vec![(offset, alloc_size)],
-                };
-                
-                return Some((owner, ptr));
-            }
-        }
-        
-        // If the arena allocation failed, try to grow the heap
-        // First grow the heap
-        let ptr = self.grow_heap(size, tier);
-        
-        // If growth failed, return None
-        if ptr.is_null() {
-            return None;
-        }
-        
-        // Try allocation again with the newly expanded arena
-        let arena = match tier {
-            Tier::Render => &self.render_arena,
-            Tier::Scene => &self.scene_arena,
-            Tier::Entity => &self.entity_arena,
-        };
-        
-        // Try to allocate from the selected arena after growing
-        if let Ok(arena_lock) = arena.lock() {
-            if let Some((new_ptr, alloc_size)) = arena_lock.allocate(size) {
-                // Create a memory owner for this allocation
-                let offset = (new_ptr as usize) - (arena_lock.base as usize);
-                let owner = MemoryOwner {
-                    arena: Arc::clone(arena),
-                    allocations: vec![(offset, alloc_size)],
-                };
-                
-                return Some((owner, new_ptr));
-            }
-        }
-        
-        // If allocation still fails after growing, return None, we're out of memory.
         None
     }
-    
-    pub fn allocate(&mut self, size: usize, tier: Tier) -> *mut u8 {
-        // First attempt: try to allocate from the selected arena
-        let arena = match tier {
-            Tier::Render => &self.render_arena,
-            Tier::Scene => &self.scene_arena,
-            Tier::Entity => &self.entity_arena,
-        };
-        
-        if let Ok(arena_lock) = arena.lock() {
-            if let Some((ptr, _)) = arena_lock.allocate(size) {
-                return ptr; // Allocation succeeded
-            }
-        }
-        
-        // First attempt failed - try to grow the heap
-        let ptr = self.grow_heap(size, tier);
-        
-        // If growth succeeded, try allocation again
-        if !ptr.is_null() {
-            let arena = match tier {
-                Tier::Render => &self.render_arena,
-                Tier::Scene => &self.scene_arena,
-                Tier::Entity => &self.entity_arena,
+
+    // Blocks freed with overwritten canaries since the last call, then every
+    // live block whose canaries are overwritten, lowest first
+    #[cfg(feature = "guard")]
+    pub fn validate(&mut self) -> Vec<GuardCorruption> {
+        let front_guard = self.front_guard();
+        let mut corrupted = self.guards.take_damaged_frees();
+        for (position, size) in self.guards.blocks() {
+            let block = match self.pointer_at(position) {
+                Some(block) => block,
+                None => continue,
             };
-            
-            if let Ok(arena_lock) = arena.lock() {
-                if let Some((new_ptr, _)) = arena_lock.allocate(size) {
-                    return new_ptr;
-                }
-            }
-        } else {
-            // Growth failed - try recycling and then allocating
-            
-            // Get current stats for this tier to determine how much we're using
-            let (current_usage, _, _, _) = match tier {
-                Tier::Render => {
-                    if let Ok(arena) = self.render_arena.lock() {
-                        arena.get_stats()
-                    } else {
-                        (0, 0, 0, 0)
-                    }
-                },
-                Tier::Scene => {
-                    if let Ok(arena) = self.scene_arena.lock() {
-                        arena.get_stats()
-                    } else {
-                        (0, 0, 0, 0)
-                    }
-                },
-                Tier::Entity => {
-                    if let Ok(arena) = self.entity_arena.lock() {
-                        arena.get_stats()
-                    } else {
-                        (0, 0, 0, 0)
-                    }
-                },
+            let (front, rear) = unsafe { guard::check_canaries(block, size, self.alignment) };
+            let handle = match self.handle_for(unsafe { block.add(front_guard) }, size) {
+                Some(handle) => handle,
+                None => continue,
             };
-            
-            // If we're using enough memory that recycling might help
-            if current_usage > size {
-                web_sys::console::log_1(&format!(
-                    "Growth failed, attempting to reset tier {:?} completely to make space",
-                    tier
-                ).into());
-                
-                // Reset this tier completely - clearer than preserving 0 bytes
-                self.reset_tier(tier);
-                
-                // Try allocation again after resetting
-                let arena = match tier {
-                    Tier::Render => &self.render_arena,
-                    Tier::Scene => &self.scene_arena,
-                    Tier::Entity => &self.entity_arena,
-                };
-                
-                if let Ok(arena_lock) = arena.lock() {
-                    if let Some((new_ptr, _)) = arena_lock.allocate(size) {
-                        return new_ptr; // Allocation succeeded after resetting
-                    }
+            let tag = self.tags.tag_at(position).map(|tag| tag.to_string());
+            for (side, bytes) in [(GuardSide::Front, front), (GuardSide::Rear, rear)] {
+                if bytes > 0 {
+                    corrupted.push(GuardCorruption { handle, tag: tag.clone(), side, corrupted: bytes, freed: false });
                 }
             }
         }
-        
-        // If all attempts fail, return null
-        std::ptr::null_mut()
+        corrupted
     }
-    
-    // Check if pointer is in any arena
-    pub fn is_ptr_in_arena(&self, ptr: *mut u8) -> bool {
-        if let Ok(arena) = self.render_arena.lock() {
-            if arena.contains(ptr) {
-                return true;
-            }
+
+    pub fn get_stats(&self) -> ArenaStats {
+        let available: usize = self.segments.iter().map(|segment| segment.allocator.available()).sum();
+        let largest_free_block = self.segments
+            .iter()
+            .map(|segment| segment.allocator.largest_available())
+            .max()
+            .unwrap_or(0);
+
+        // Segments are never contiguous, so fragmentation is measured inside
+        // each segment and weighted by what it has available
+        let largest_per_segment: usize = self.segments
+            .iter()
+            .map(|segment| segment.allocator.largest_available())
+            .sum();
+
+        ArenaStats {
+            strategy: self.strategy,
+            used: self.usage(),
+            capacity: self.capacity(),
+            high_water_mark: self.high_water_mark.load(Ordering::Relaxed),
+            total_allocated: self.total_allocated.load(Ordering::Relaxed),
+            free_bytes: self.free_bytes(),
+            largest_free_block,
+            fragmentation: if available == 0 {
+                0.0
+            } else {
+                1.0 - (largest_per_segment as f32 / available as f32)
+            },
         }
-        
-        if let Ok(arena) = self.scene_arena.lock() {
-            if arena.contains(ptr) {
-                return true;
-            }
+    }
+}
+
+// An arena only hands out memory under its Mutex, and every block on the free
+// list belongs to it
+unsafe impl Send for MemoryOwner {}
+
+impl MemoryOwner {
+    pub fn tier(&self) -> Tier {
+        match self.arena.lock() {
+            Ok(arena) => arena.tier,
+            Err(poisoned) => poisoned.into_inner().tier,
         }
-        
-        if let Ok(arena) = self.entity_arena.lock() {
-            if arena.contains(ptr) {
-                return true;
-            }
+    }
+
+    // Allocate another block in the same tier. Owners don't grow the heap,
+    // use TieredAllocator::allocate_with_owner for that.
+    pub fn allocate(&mut self, size: usize) -> Option<usize> {
+        let mut arena = self.arena.lock().ok()?;
+        let (ptr, block_size) = arena.allocate_tagged(size, self.tag.as_ref())?;
+        let handle = arena.handle_for(ptr, size)?;
+        drop(arena);
+
+        self.allocations.push((handle, block_size));
+        Some(self.allocations.len() - 1)
+    }
+
+    pub fn len(&self) -> usize {
+        self.allocations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.allocations.is_empty()
+    }
+
+    pub fn handle(&self, index: usize) -> Option<AllocHandle> {
+        self.allocations.get(index).map(|(handle, _)| *handle)
+    }
+
+    // Requested bytes across every allocation
+    pub fn total_size(&self) -> usize {
+        self.allocations.iter().map(|(handle, _)| handle.size()).sum()
+    }
+
+    fn resolve(&self, index: usize) -> Option<(*mut u8, usize)> {
+        let (handle, _) = self.allocations.get(index)?;
+        let ptr = self.arena.lock().ok()?.resolve_handle(handle)?;
+        Some((ptr, handle.size()))
+    }
+
+    // Call `f` with an allocation's pointer and size while the tier is locked,
+    // so no reset or compaction can recycle it before `f` returns
+    pub(crate) fn locked<R>(&self, index: usize, f: impl FnOnce(*mut u8, usize) -> R) -> Option<R> {
+        let (handle, _) = self.allocations.get(index)?;
+        let arena = self.arena.lock().ok()?;
+        let ptr = arena.resolve_handle(handle)?;
+        let result = f(ptr, handle.size());
+        drop(arena);
+        Some(result)
+    }
+
+    // Read an allocation. None once the tier has been reset underneath the
+    // owner. The tier stays locked while `f` runs, so `f` must not allocate
+    // in it or reset it.
+    pub fn with_slice<R>(&self, index: usize, f: impl FnOnce(&[u8]) -> R) -> Option<R> {
+        self.locked(index, |ptr, size| f(unsafe { std::slice::from_raw_parts(ptr, size) }))
+    }
+
+    // Write an allocation, like with_slice
+    pub fn with_slice_mut<R>(&mut self, index: usize, f: impl FnOnce(&mut [u8]) -> R) -> Option<R> {
+        self.locked(index, |ptr, size| f(unsafe { std::slice::from_raw_parts_mut(ptr, size) }))
+    }
+
+    /// Read access - None once the tier has been reset underneath the owner
+    ///
+    /// # Safety
+    /// The tier must not be reset or compacted while the slice is alive.
+    pub unsafe fn slice(&self, index: usize) -> Option<&[u8]> {
+        let (ptr, size) = self.resolve(index)?;
+        Some(unsafe { std::slice::from_raw_parts(ptr, size) })
+    }
+
+    /// Write access - None once the tier has been reset underneath the owner
+    ///
+    /// # Safety
+    /// Same as slice.
+    pub unsafe fn slice_mut(&mut self, index: usize) -> Option<&mut [u8]> {
+        let (ptr, size) = self.resolve(index)?;
+        Some(unsafe { std::slice::from_raw_parts_mut(ptr, size) })
+    }
+
+    // Give one allocation back early. Later indices shift down by one.
+    pub fn free(&mut self, index: usize) -> bool {
+        if index >= self.allocations.len() {
+            return false;
         }
-        
-        false
+        let (handle, block_size) = self.allocations.remove(index);
+        self.release(&handle, block_size)
     }
-    
-    // Reset a specific tier
-    pub fn reset_tier(&mut self, tier: Tier) {
-        match tier {
-            Tier::Render => {
-                if let Ok(arena) = self.render_arena.lock() {
-                    arena.reset();
-                }
-            },
-            Tier::Scene => {
-                if let Ok(arena) = self.scene_arena.lock() {
-                    arena.reset();
-                }
-            },
-            Tier::Entity => {
-                if let Ok(arena) = self.entity_arena.lock() {
-                    arena.reset();
-                }
-            },
+
+    // Take over every allocation of another owner of the same tier
+    pub fn merge(&mut self, mut other: MemoryOwner) -> Result<(), MemoryOwner> {
+        if !Arc::ptr_eq(&self.arena, &other.arena) {
+            return Err(other);
         }
+        self.allocations.append(&mut other.allocations);
+        Ok(())
     }
-    
-    pub fn tier_stats(&self, tier: Tier) -> (usize, usize, usize, usize) {
-        match tier {
-            Tier::Render => {
-                if let Ok(arena) = self.render_arena.lock() {
-                    arena.get_stats()
-                } else {
-                    (0, 0, 0, 0)
-                }
-            },
-            Tier::Scene => {
-                if let Ok(arena) = self.scene_arena.lock() {
-                    arena.get_stats()
-                } else {
-                    (0, 0, 0, 0)
-                }
-            },
-            Tier::Entity => {
-                if let Ok(arena) = self.entity_arena.lock() {
-                    arena.get_stats()
-                } else {
-                    (0, 0, 0, 0)
-                }
-            },
+
+    // Move one allocation into its own owner, e.g. to hand it to another system
+    pub fn split_off(&mut self, index: usize) -> Option<MemoryOwner> {
+        if index >= self.allocations.len() {
+            return None;
+        }
+        Some(MemoryOwner {
+            arena: Arc::clone(&self.arena),
+            allocations: vec![self.allocations.remove(index)],
+            tag: self.tag.clone(),
+        })
+    }
+
+    // Blocks recycled by a reset or compaction are skipped, they already went back
+    fn release(&self, handle: &AllocHandle, block_size: usize) -> bool {
+        let mut arena = match self.arena.lock() {
+            Ok(arena) => arena,
+            Err(_) => return false,
+        };
+
+        match arena.resolve_handle(handle) {
+            Some(ptr) => arena.free(ptr, block_size),
+            None => false,
+        }
+    }
+}
+
+impl Drop for MemoryOwner {
+    fn drop(&mut self) {
+        for (handle, block_size) in std::mem::take(&mut self.allocations) {
+            self.release(&handle, block_size);
         }
     }
+}
+
+// Grow one tier so `size_needed` bytes fit, by what its growth policy asks for.
+// Shared by the allocator and containers that grow on their own (ArenaVec).
+fn grow_tier(arena: &Mutex<Arena>, size_needed: usize) -> *mut u8 {
+    // Room for the whole block, guards included
+    let (grow_size, memory) = match arena.lock() {
+        Ok(arena) => (
+            arena.block_size(size_needed).and_then(|size| arena.growth.grow_size(size, arena.capacity())),
+            Arc::clone(&arena.memory),
+        ),
+        Err(_) => return std::ptr::null_mut(),
+    };
+    let grow_size = match grow_size {
+        Some(size) => size,
+        None => return std::ptr::null_mut(), // The tier may not grow
+    };
+
+    // Calculate how many pages we need (64KiB per page)
+    let pages_needed = grow_size.div_ceil(PAGE_SIZE);
     
-    // Check if a pointer is valid
-    pub fn is_ptr_valid(&self, ptr: *mut u8) -> bool {
-        self.is_ptr_in_arena(ptr)
+    // Try to grow memory
+    let grown = match memory.lock() {
+        Ok(mut memory) => memory.grow(pages_needed),
+        Err(_) => None,
+    };
+    
+    // Base address for the new memory
+    let new_memory_base = match grown {
+        Some(base) => base,
+        None => return std::ptr::null_mut(), // Failed to grow memory
+    };
+    
+    // We successfully grew the memory
+    let new_block_size = pages_needed * PAGE_SIZE;
+    
+    // Append the new memory to the tier, leaving existing allocations in place
+    if let Ok(mut arena) = arena.lock() {
+        arena.add_segment(new_memory_base, new_block_size);
     }
+    
+    // Return a non-null pointer to indicate success
+    // The actual allocation will happen in the caller
+    new_memory_base
+}
 
-    pub fn set_base_url(&self, url: String) {
-        if let Ok(mut base_url) = self.base_url.lock() {
-            *base_url = url;
+// TieredAllocator implementation
+impl TieredAllocator {
+    // Reserve `initial_pages` from the memory source and split them between the
+    // Render (50%), Scene (30%) and Entity (20%) tiers
+    pub fn new(memory: Box<dyn MemorySource>, initial_pages: usize) -> Self {
+        match Self::with_config(memory, TieredAllocatorConfig::split(initial_pages * PAGE_SIZE)) {
+            Ok(allocator) => allocator,
+            Err(_) => unreachable!("the default split is always a valid layout"),
         }
     }
 
-    pub async fn load_asset(&mut self, path: String, asset_type: u8) -> Result<usize, JsValue> {
-        let asset_type = match asset_type {
-            0 => AssetType::Image,
-            1 => AssetType::Json,
-            _ => return Err(JsValue::from_str("Invalid asset type: must be 0 (Image) or 1 (Json)")),
-        };
+    // Lay the tiers of `config` out back to back at the start of the memory source
+    pub fn with_config(mut source: Box<dyn MemorySource>, config: TieredAllocatorConfig) -> Result<Self, String> {
+        config.validate()?;
 
-        // Get the base URL from the mutex
-        let full_url = {
-            let base_url = match self.base_url.lock() {
-                Ok(guard) => guard.clone(),
-                Err(_) => return Err(JsValue::from_str("Failed to lock base_url")),
-            };
-            format!("{}{}", base_url, path)
+        let initial_size = config.initial_size().ok_or("The tier layout is larger than the address space")?;
+        let (memory_base, reserved) = match source.grow(initial_size.div_ceil(PAGE_SIZE)) {
+            Some(base) => (base, true),
+            None => {
+                // Every tier starts out empty and grows on demand
+                console_log(&format!("Failed to reserve {} bytes for the tiers", initial_size));
+                (source.base(), false)
+            },
         };
-        
-        web_sys::console::log_1(&format!("Loading asset from: {}", full_url).into());
+        let memory = Arc::new(Mutex::new(source));
 
-        // Fetch the asset
-        let response = match self.http_client.get(&full_url).send().await {
-            Ok(resp) => resp,
-            Err(e) => return Err(JsValue::from_str(&format!("Failed to fetch: {}", e))),
-        };
-        
-        if !response.status().is_success() {
-            return Err(JsValue::from_str(&format!(
-                "HTTP error: {} for {}", 
-                response.status(), 
-                full_url
-            )));
-        }
-
-        // Get the bytes
-        let bytes = match response.bytes().await {
-            Ok(b) => b,
-            Err(e) => return Err(JsValue::from_str(&format!("Failed to get bytes: {}", e))),
-        };
+        // Each tier starts on its own alignment and is sized in whole aligned blocks
+        let mut arenas = Vec::with_capacity(config.tiers.len());
+        let mut offset: usize = 0;
+        for (index, tier_config) in config.tiers.iter().enumerate() {
+            offset = offset.next_multiple_of(tier_config.alignment);
+            let base = memory_base.wrapping_add(offset);
+            let size = if reserved {
+                tier_config.initial_size - tier_config.initial_size % tier_config.alignment
+            } else {
+                0
+            };
+            offset += size;
 
-        let data_size = bytes.len();
+            let arena = Arena::new(Arc::clone(&memory), base, size, Tier::new(index as u8), tier_config);
+            arenas.push(Arc::new(Mutex::new(arena)));
+        }
 
-        // Allocate memory in the Scene tier
-        let ptr = self.allocate(data_size, Tier::Scene);
-        
-        if ptr.is_null() {
-            return Err(JsValue::from_str("Failed to allocate memory for asset"));
+        #[cfg(feature = "trace")]
+        let trace = Arc::new(TraceLog::default());
+        #[cfg(feature = "trace")]
+        for arena in &arenas {
+            if let Ok(mut arena) = arena.lock() {
+                arena.set_trace(Arc::clone(&trace));
+            }
         }
+
+        // Assets may fill the asset tier's initial size before they are evicted
+        let asset_budget = config.tiers[config.asset_tier.index()].initial_size;
         
-        // Calculate offset from memory base
-        let memory_base = self.get_memory_base(Tier::Scene);
-        let offset = (ptr as usize) - (memory_base as usize);
-        
-        // Copy bytes into memory
-        unsafe {
-            std::ptr::copy_nonoverlapping(bytes.as_ptr(), ptr, data_size);
-        }
+        Ok(TieredAllocator {
+            arenas,
+            asset_tier: config.asset_tier,
+            render_tier: config.render_tier,
 
-        // Save metadata
-        if let Ok(mut assets) = self.assets.lock() {
-            assets.insert(
-                path.clone(),
-                AssetMetadata {
-                    asset_type,
-                    size: data_size,
-                    offset,
-                },
-            );
-        } else {
-            return Err(JsValue::from_str("Failed to acquire assets lock"));
-        }
+            memory,
 
-        Ok(offset)
+            assets: Arc::new(Mutex::new(AssetCache::new(asset_budget))),
+            asset_source: Arc::new(Mutex::new(Arc::new(HttpSource::new("")))),
+            manifest: Arc::new(Mutex::new(AssetManifest::new())),
+            loading: Arc::new(Mutex::new(HashMap::new())),
+            queue: LoadQueue::new(DEFAULT_MAX_CONCURRENT_LOADS),
+            offline_cache: Arc::new(Mutex::new(None)),
+            retry: Arc::new(Mutex::new(RetryPolicy::default())),
+
+            pools: Arc::new(Mutex::new(Vec::new())),
+
+            tag: Arc::new(Mutex::new(None)),
+            tag_names: Arc::new(Mutex::new(HashMap::new())),
+            #[cfg(feature = "trace")]
+            trace,
+
+            pressure: Arc::new(PressureHandlers::default()),
+        })
     }
 
-    // Get memory base pointer for a specific tier
-    fn get_memory_base(&self, tier: Tier) -> *mut u8 {
-        match tier {
-            Tier::Render => {
-                if let Ok(arena) = self.render_arena.lock() {
-                    arena.base
-                } else {
-                    std::ptr::null_mut()
-                }
-            },
-            Tier::Scene => {
-                if let Ok(arena) = self.scene_arena.lock() {
-                    arena.base
-                } else {
-                    std::ptr::null_mut()
-                }
-            },
-            Tier::Entity => {
-                if let Ok(arena) = self.entity_arena.lock() {
-                    arena.base
-                } else {
-                    std::ptr::null_mut()
-                }
-            },
-        }
+    fn arena(&self, tier: Tier) -> Option<&Arc<Mutex<Arena>>> {
+        self.arenas.get(tier.index())
     }
 
-    pub async fn test_fetch_json(&self) -> Result<JsValue, JsValue> {
-        web_sys::console::log_1(&"Testing JSON fetch".into());
-        
-        let test_url = "https://jsonplaceholder.typicode.com/todos/1";
-        
-        let response = match self.http_client.get(test_url).send().await {
-            Ok(resp) => resp,
-            Err(e) => return Err(JsValue::from_str(&format!("Failed to fetch: {}", e))),
-        };
-        
-        if !response.status().is_success() {
-            return Err(JsValue::from_str(&format!("HTTP error: {}", response.status())));
-        }
-        
-        let text = match response.text().await {
-            Ok(t) => t,
-            Err(e) => return Err(JsValue::from_str(&format!("Failed to get text: {}", e))),
-        };
-        
-        web_sys::console::log_1(&format!("Received JSON: {}", text).into());
-        
-        Ok(JsValue::from_str(&text))
+    fn lock_arena(&self, tier: Tier) -> Option<std::sync::MutexGuard<'_, Arena>> {
+        self.arena(tier)?.lock().ok()
     }
 
-    pub fn evict_asset(&mut self, path: &str) -> Result<(), JsValue> {
-        // First, get information about the target asset
-        let target_metadata = {
-            let assets_lock = match self.assets.lock() {
-                Ok(lock) => lock,
-                Err(_) => return Err(JsValue::from_str("Failed to acquire assets lock")),
-            };
-            
-            // Check if the asset exists
-            match assets_lock.get(path) {
-                Some(meta) => meta.clone(),
-                None => return Err(JsValue::from_str(&format!("Asset not found: {}", path))),
-            }
+    // Number of tiers in the layout
+    pub fn tier_count(&self) -> usize {
+        self.arenas.len()
+    }
+
+    pub fn tiers(&self) -> impl Iterator<Item = Tier> + use<> {
+        (0..self.arenas.len()).map(|index| Tier::new(index as u8))
+    }
+
+    // Tier for a JS facing tier number, if the layout has it
+    pub fn tier(&self, index: u8) -> Option<Tier> {
+        let tier = Tier::new(index);
+        self.arena(tier).map(|_| tier)
+    }
+
+    pub fn tier_by_name(&self, name: &str) -> Option<Tier> {
+        self.tiers().find(|&tier| self.tier_name(tier).as_deref() == Some(name))
+    }
+
+    pub fn tier_name(&self, tier: Tier) -> Option<String> {
+        Some(self.arena(tier)?.lock().ok()?.name().to_string())
+    }
+
+    // Tier that load_asset places downloaded data in
+    pub fn asset_tier(&self) -> Tier {
+        self.asset_tier
+    }
+
+    // Tier that textures, meshes and other GPU data go to
+    pub fn render_tier(&self) -> Tier {
+        self.render_tier
+    }
+
+    // Fast compact for a specific tier with intelligent growing
+    pub fn fast_compact_tier(&mut self, tier: Tier, preserve_bytes: usize) -> bool {
+        // Get current allocation end and capacity for the specified tier
+        let (current_end, capacity) = match self.lock_arena(tier) {
+            Some(arena) => (arena.logical_end(), arena.capacity()),
+            None => return false,
         };
         
-        // Get a temporary buffer for the content we want to preserve
-        let (preserve_buffer, preserve_map) = {
-            let assets_lock = match self.assets.lock() {
-                Ok(lock) => lock,
-                Err(_) => return Err(JsValue::from_str("Failed to acquire assets lock")),
+        // Current allocation is sufficient, proceed with normal compact
+        if preserve_bytes <= current_end {
+            let compacted = match self.lock_arena(tier) {
+                Some(mut arena) => arena.fast_compact(preserve_bytes),
+                None => false,
             };
-            
-            let mut preserve_buffer = Vec::new();
-            let mut preserve_map = HashMap::new();
-            
-            // Get memory base for Scene tier
-            let memory_base = self.get_memory_base(Tier::Scene);
-            
-            // Copy all assets except the one being evicted
-            for (asset_path, metadata) in assets_lock.iter() {
-                if asset_path != path {
-                    // Record the current position in our buffer
-                    let new_offset = preserve_buffer.len();
-                    
-                    // Read the asset's bytes
-                    unsafe {
-                        let src_ptr = memory_base.add(metadata.offset);
-                        let src_data = std::slice::from_raw_parts(src_ptr, metadata.size);
-                        
-                        // Append to our buffer
-                        preserve_buffer.extend_from_slice(src_data);
-                        
-                        // Add to our map with updated offset
-                        preserve_map.insert(asset_path.clone(), AssetMetadata {
-                            asset_type: metadata.asset_type,
-                            size: metadata.size,
-                            offset: new_offset,
-                        });
-                    }
-                }
-            }
-            
-            (preserve_buffer, preserve_map)
-        };
-        
-        // Now reset the entire scene tier
-        self.reset_tier(Tier::Scene);
+            self.check_pressure(tier);
+            return compacted;
+        }
         
-        // If we have assets to preserve, reallocate and copy them back
-        if !preserve_buffer.is_empty() {
-            // Allocate new memory for the preserved data
-            let buffer_size = preserve_buffer.len();
-            let ptr = self.allocate(buffer_size, Tier::Scene);
-            
-            if ptr.is_null() {
-                return Err(JsValue::from_str("Failed to allocate memory for preserved assets"));
-            }
-            
-            // Calculate offset
-            let memory_base = self.get_memory_base(Tier::Scene);
-            let offset = (ptr as usize) - (memory_base as usize);
+        // We need more space than currently allocated - grow first if the tier is too small
+        if preserve_bytes > capacity {
+            // Get total memory size (can't exceed 4GB in wasm32)
+            let (total_current_pages, max_pages) = match self.memory.lock() {
+                Ok(memory) => (memory.pages(), memory.max_pages()),
+                Err(_) => return false,
+            };
             
-            // Copy the preserved data back to WebAssembly memory
-            unsafe {
-                std::ptr::copy_nonoverlapping(
-                    preserve_buffer.as_ptr(),
-                    ptr,
-                    buffer_size
-                );
-            }
+            // Calculate how many more pages we need
+            let additional_bytes_needed = preserve_bytes - capacity;
+            let additional_pages_needed = additional_bytes_needed.div_ceil(PAGE_SIZE);
             
-            // Update the offsets in our preserve map
-            let mut updated_preserve_map = HashMap::new();
-            for (asset_path, metadata) in preserve_map {
-                updated_preserve_map.insert(asset_path, AssetMetadata {
-                    asset_type: metadata.asset_type,
-                    size: metadata.size,
-                    offset: offset + metadata.offset,
-                });
+            // Check if growing would exceed the 4GB limit
+            if total_current_pages + additional_pages_needed > max_pages {
+                console_log(&format!(
+                    "Cannot grow memory - would exceed 4GB limit. Current pages: {}, needed: {}, max: {}",
+                    total_current_pages, additional_pages_needed, max_pages
+                ));
+                return false;
             }
             
-            // Update the assets HashMap with the preserved assets (removing the target)
-            let mut assets_lock = match self.assets.lock() {
-                Ok(lock) => lock,
-                Err(_) => return Err(JsValue::from_str("Failed to acquire assets lock")),
-            };
+            console_log(&format!(
+                "Growing heap for tier {:?} compact - current: {}, preserve: {}, growing by: {} pages",
+                tier, current_end, preserve_bytes, additional_pages_needed
+            ));
             
-            // Clear and repopulate with preserved assets
-            assets_lock.clear();
-            for (asset_path, metadata) in updated_preserve_map {
-                assets_lock.insert(asset_path, metadata);
+            // Growing adds a segment, so the data we preserve never has to move
+            if self.grow_heap(additional_bytes_needed, tier).is_null() {
+                console_log("Failed to grow memory for compact operation");
+                return false;
             }
-        } else {
-            // If no assets to preserve, just clear the HashMap
-            let mut assets_lock = match self.assets.lock() {
-                Ok(lock) => lock,
-                Err(_) => return Err(JsValue::from_str("Failed to acquire assets lock")),
-            };
-            assets_lock.clear();
         }
         
-        web_sys::console::log_1(&format!(
-            "Evicted asset: {} and freed {} bytes",
-            path, target_metadata.size
-        ).into());
-        
-        Ok(())
-    }
-    
-    pub fn get_asset(&self, path: &str) -> Result<js_sys::Uint8Array, JsValue> {
-        // Get the assets lock
-        let assets_lock = match self.assets.lock() {
-            Ok(lock) => lock,
-            Err(_) => return Err(JsValue::from_str("Failed to acquire assets lock")),
-        };
-        
-        // Get the metadata
-        let metadata = match assets_lock.get(path) {
-            Some(meta) => meta.clone(), // Clone to avoid lifetime issues
-            None => return Err(JsValue::from_str(&format!("Asset not found: {}", path))),
-        };
-        
-        // Drop assets lock before accessing memory
-        drop(assets_lock);
-        
-        let memory_base = self.get_memory_base(Tier::Scene);
-        
-        unsafe {
-            let ptr = memory_base.add(metadata.offset);
-            let mem_slice = std::slice::from_raw_parts(ptr, metadata.size);
-            Ok(js_sys::Uint8Array::from(mem_slice))
+        // Mark everything up to preserve_bytes as allocated
+        match self.lock_arena(tier) {
+            Some(mut arena) => arena.set_logical_end(preserve_bytes),
+            None => return false,
         }
+        self.check_pressure(tier);
+        true
     }
-}
 
-impl Clone for TieredAllocator {
-    fn clone(&self) -> Self {
-        TieredAllocator {
-            render_arena: Arc::clone(&self.render_arena),
-            scene_arena: Arc::clone(&self.scene_arena),
-            entity_arena: Arc::clone(&self.entity_arena),
-            assets: Arc::clone(&self.assets),
-            base_url: Arc::clone(&self.base_url),
-            http_client: self.http_client.clone(),
+    // Grow heap for a specific tier by what its growth policy asks for.
+    // The new memory becomes another segment of the tier.
+    pub fn grow_heap(&mut self, size_needed: usize, tier: Tier) -> *mut u8 {
+        match self.arena(tier) {
+            Some(arena) => grow_tier(arena, size_needed),
+            None => std::ptr::null_mut(),
         }
     }
-}
-
-#[wasm_bindgen]
-impl Walloc {
-    pub fn new() -> Self {
-        let memory_base = core::arch::wasm32::memory_size(0) as *mut u8;
-        let memory_size = (core::arch::wasm32::memory_size(0) * 65536) as usize;
 
-        let strategy = TieredAllocator::new(memory_base, memory_size);
-        
-        Walloc {
-            strategy,
-            memory_base,
-            memory_size,
+    // Reserve a double or triple buffered frame arena with `frame_size` bytes
+    // per frame. Its memory comes straight from the memory source rather than
+    // a tier, so reset_tier and fast_compact_tier never touch it. Dropping the
+    // arena hands its memory to the Render tier.
+    pub fn create_frame_arena(&mut self, frame_size: usize, buffer_count: usize) -> Option<FrameArena> {
+        if !(frame::MIN_FRAME_BUFFERS..=frame::MAX_FRAME_BUFFERS).contains(&buffer_count) || frame_size == 0 {
+            return None;
+        }
+
+        let frame_size = frame_size.checked_next_multiple_of(FRAME_ALIGNMENT)?;
+        let pages_needed = frame_size.checked_mul(buffer_count)?.div_ceil(PAGE_SIZE);
+        let render = Arc::clone(self.arena(self.render_tier)?);
+        let base = self.memory.lock().ok()?.grow(pages_needed)?;
+
+        Some(FrameArena::new(base, frame_size, buffer_count, pages_needed * PAGE_SIZE, Arc::clone(&self.memory), render))
+    }
+
+    // Single allocation attempt in the tier as it is now
+    fn try_allocate_tagged(&self, size: usize, tier: Tier, tag: Option<&Arc<str>>) -> Option<(*mut u8, usize)> {
+        match self.lock_arena(tier) {
+            Some(mut arena) => arena.allocate_tagged(size, tag),
+            None => None,
+        }
+    }
+
+    // === Allocation tags ===
+
+    // Tag every allocation that names no tag of its own with `tag`, until it
+    // is changed. Shared by every clone of the allocator.
+    pub fn set_allocation_tag(&self, tag: Option<&str>) {
+        let tag = tag.map(|tag| self.intern_tag(tag));
+        if let Ok(mut current) = self.tag.lock() {
+            *current = tag;
+        }
+    }
+
+    pub fn allocation_tag(&self) -> Option<String> {
+        self.tag.lock().ok()?.as_deref().map(str::to_string)
+    }
+
+    fn current_tag(&self) -> Option<Arc<str>> {
+        self.tag.lock().ok().and_then(|tag| tag.clone())
+    }
+
+    // One shared copy of each tag name
+    fn intern_tag(&self, tag: &str) -> Arc<str> {
+        match self.tag_names.lock() {
+            Ok(mut names) => Arc::clone(names.entry(tag.to_string()).or_insert_with(|| Arc::from(tag))),
+            Err(_) => Arc::from(tag),
+        }
+    }
+
+    // Allocate like allocate(), tagging the block with `tag`
+    pub fn allocate_tagged(&mut self, size: usize, tier: Tier, tag: &str) -> *mut u8 {
+        let tag = self.intern_tag(tag);
+        self.allocate_as(size, tier, Some(&tag))
+    }
+
+    pub fn allocate_handle_tagged(&mut self, size: usize, tier: Tier, tag: &str) -> Option<AllocHandle> {
+        let ptr = self.allocate_tagged(size, tier, tag);
+        if ptr.is_null() {
+            return None;
+        }
+        self.lock_arena(tier)?.handle_for(ptr, size)
+    }
+
+    // Tag of the live block a handle names
+    pub fn tag_of(&self, handle: &AllocHandle) -> Option<String> {
+        self.lock_arena(handle.tier_kind())?.tag_of(handle).map(|tag| tag.to_string())
+    }
+
+    // What every tag holds in a tier, most live bytes first
+    pub fn tag_stats(&self, tier: Tier) -> Vec<TagStats> {
+        match self.lock_arena(tier) {
+            Some(arena) => arena.tag_stats(),
+            None => Vec::new(),
+        }
+    }
+
+    // === Tracing ===
+
+    // Log of every tier's operations
+    #[cfg(feature = "trace")]
+    pub fn trace(&self) -> &TraceLog {
+        &self.trace
+    }
+
+    #[cfg(feature = "trace")]
+    pub fn trace_json(&self) -> String {
+        self.trace.to_json()
+    }
+
+    // The trace in Chrome Trace Event format, for chrome://tracing or Perfetto
+    #[cfg(feature = "trace")]
+    pub fn chrome_trace(&self) -> String {
+        let names: Vec<String> = self.tiers()
+            .map(|tier| self.tier_name(tier).unwrap_or_default())
+            .collect();
+        self.trace.to_chrome_trace(&names)
+    }
+
+    // === Guards ===
+
+    // Every live block, in every tier, whose canaries were overwritten
+    #[cfg(feature = "guard")]
+    pub fn validate(&self) -> Vec<GuardCorruption> {
+        self.tiers().flat_map(|tier| self.validate_tier(tier)).collect()
+    }
+
+    #[cfg(feature = "guard")]
+    pub fn validate_tier(&self, tier: Tier) -> Vec<GuardCorruption> {
+        match self.lock_arena(tier) {
+            Some(mut arena) => arena.validate(),
+            None => Vec::new(),
+        }
+    }
+    
+    // === Memory pressure ===
+
+    // Call `callback` whenever a tier's pressure level changes or it runs out
+    // of space. The id removes it again.
+    pub fn on_pressure<F>(&self, callback: F) -> usize
+    where
+        F: Fn(&mut TieredAllocator, &PressureEvent) + Send + Sync + 'static,
+    {
+        self.pressure.add(Arc::new(callback))
+    }
+
+    pub fn remove_pressure_handler(&self, id: usize) -> bool {
+        self.pressure.remove(id)
+    }
+
+    pub fn set_pressure_thresholds(&mut self, tier: Tier, thresholds: PressureThresholds) -> Result<(), String> {
+        thresholds.validate()?;
+        match self.lock_arena(tier) {
+            Some(mut arena) => arena.set_pressure(thresholds),
+            None => return Err(format!("Unknown tier {}", tier.id())),
+        }
+        self.check_pressure(tier);
+        Ok(())
+    }
+
+    pub fn pressure_thresholds(&self, tier: Tier) -> PressureThresholds {
+        match self.lock_arena(tier) {
+            Some(arena) => arena.pressure(),
+            None => PressureThresholds::default(),
+        }
+    }
+
+    // Where a tier's usage stands against its thresholds
+    pub fn pressure_level(&self, tier: Tier) -> PressureLevel {
+        match self.lock_arena(tier) {
+            Some(arena) => arena.pressure_level(),
+            None => PressureLevel::Normal,
+        }
+    }
+
+    pub fn set_exhaustion_policy(&self, tier: Tier, policy: ExhaustionPolicy) {
+        if let Some(mut arena) = self.lock_arena(tier) {
+            arena.set_exhaustion(policy);
+        }
+    }
+
+    pub fn exhaustion_policy(&self, tier: Tier) -> ExhaustionPolicy {
+        match self.lock_arena(tier) {
+            Some(arena) => arena.exhaustion(),
+            None => ExhaustionPolicy::default(),
+        }
+    }
+
+    // Tell the handlers if the tier's level moved
+    fn check_pressure(&mut self, tier: Tier) {
+        if self.pressure.is_dispatching() {
+            return;
+        }
+        let event = match self.lock_arena(tier) {
+            Some(mut arena) => arena.update_pressure(),
+            None => None,
+        };
+        if let Some(event) = event {
+            self.dispatch_pressure(&event);
+        }
+    }
+
+    // Tell the handlers the tier is out of space for `requested` bytes
+    fn exhausted(&mut self, tier: Tier, requested: usize) {
+        let event = match self.lock_arena(tier) {
+            Some(arena) => PressureEvent {
+                tier,
+                level: PressureLevel::Critical,
+                previous: arena.pressure_level(),
+                used: arena.usage(),
+                capacity: arena.capacity(),
+                requested,
+                exhausted: true,
+            },
+            None => return,
+        };
+        self.dispatch_pressure(&event);
+    }
+
+    fn dispatch_pressure(&mut self, event: &PressureEvent) {
+        let pressure = Arc::clone(&self.pressure);
+        let dispatch = match pressure.begin_dispatch() {
+            Some(dispatch) => dispatch,
+            None => return,
+        };
+        for callback in &dispatch.callbacks {
+            callback(self, event);
+        }
+        drop(dispatch);
+
+        // Whatever the handlers freed or allocated, in any tier
+        for tier in self.tiers() {
+            self.check_pressure(tier);
+        }
+    }
+    
+    pub fn allocate_with_owner(&mut self, size: usize, tier: Tier) -> Option<(MemoryOwner, *mut u8)> {
+        // If allocation still fails after growing and the pressure handlers, return None, we're out of memory.
+        let tag = self.current_tag();
+        let (ptr, alloc_size) = self.allocate_block(size, tier, tag.as_ref())?;
+        
+        // Create a memory owner for this allocation
+        let arena = self.arena(tier)?;
+        let handle = arena.lock().ok()?.handle_for(ptr, size)?;
+        let owner = MemoryOwner {
+            arena: Arc::clone(arena),
+            allocations: vec![(handle, alloc_size)],
+            tag,
+        };
+        
+        Some((owner, ptr))
+    }
+    
+    pub fn allocate(&mut self, size: usize, tier: Tier) -> *mut u8 {
+        self.allocate_as(size, tier, None)
+    }
+
+    // Allocate in `tier`, growing it once if it is full. If it cannot grow the
+    // pressure handlers get a chance to make room before a last attempt.
+    fn allocate_block(&mut self, size: usize, tier: Tier, tag: Option<&Arc<str>>) -> Option<(*mut u8, usize)> {
+        let mut allocation = self.try_allocate_tagged(size, tier, tag);
+        if allocation.is_none() && !self.grow_heap(size, tier).is_null() {
+            allocation = self.try_allocate_tagged(size, tier, tag);
+        }
+        if allocation.is_none() {
+            self.exhausted(tier, size);
+            allocation = self.try_allocate_tagged(size, tier, tag);
+        }
+
+        if allocation.is_some() {
+            self.check_pressure(tier);
+        }
+        allocation
+    }
+
+    // Allocate with `tag`, or the current allocation tag if None
+    fn allocate_as(&mut self, size: usize, tier: Tier, tag: Option<&Arc<str>>) -> *mut u8 {
+        let tag = match tag {
+            Some(tag) => Some(Arc::clone(tag)),
+            None => self.current_tag(),
+        };
+        let tag = tag.as_ref();
+
+        if let Some((ptr, _)) = self.allocate_block(size, tier, tag) {
+            return ptr;
+        }
+
+        // Still out of space after the handlers, the tier's exhaustion policy decides
+        let (exhaustion, current_usage) = match self.lock_arena(tier) {
+            Some(arena) => (arena.exhaustion(), arena.usage()),
+            None => return std::ptr::null_mut(),
+        };
+        
+        // Resetting only helps if we're using enough memory that recycling might
+        if exhaustion == ExhaustionPolicy::Reset && current_usage > size {
+            console_log(&format!(
+                "Growth failed, resetting tier {:?} completely to make space",
+                tier
+            ));
+            
+            // Reset this tier completely - clearer than preserving 0 bytes
+            self.reset_tier(tier);
+            
+            // Try allocation again after resetting
+            if let Some((ptr, _)) = self.try_allocate_tagged(size, tier, tag) {
+                self.check_pressure(tier);
+                return ptr; // Allocation succeeded after resetting
+            }
+        }
+        
+        // If all attempts fail, return null
+        std::ptr::null_mut()
+    }
+    
+    // === Typed allocation ===
+
+    // Move a value into a tier. It is dropped when the box is, unless the tier
+    // was reset first.
+    pub fn alloc_value<T>(&mut self, value: T, tier: Tier) -> Option<ArenaBox<T>> {
+        let owner = self.allocate_typed::<T>(std::mem::size_of::<T>(), tier)?;
+        ArenaBox::new(owner, value)
+    }
+
+    // Zeroed slice of `len` Pod values
+    pub fn alloc_slice<T: Pod>(&mut self, len: usize, tier: Tier) -> Option<ArenaSlice<T>> {
+        let owner = self.allocate_typed::<T>(len.checked_mul(std::mem::size_of::<T>())?, tier)?;
+        ArenaSlice::new_zeroed(owner, len)
+    }
+
+    // Slice holding a copy of `values`
+    pub fn alloc_slice_copy<T: Pod>(&mut self, values: &[T], tier: Tier) -> Option<ArenaSlice<T>> {
+        let mut slice = self.alloc_slice::<T>(values.len(), tier)?;
+        slice.with_mut(|slice| slice.copy_from_slice(values))?;
+        Some(slice)
+    }
+
+    // Growable vector of Pod values with room for `capacity` values up front
+    pub fn alloc_vec<T: Pod>(&mut self, capacity: usize, tier: Tier) -> Option<ArenaVec<T>> {
+        let owner = MemoryOwner {
+            arena: Arc::clone(self.arena(tier)?),
+            allocations: Vec::new(),
+            tag: self.current_tag(),
+        };
+        let mut vec = ArenaVec::new(owner);
+        if capacity > 0 && !vec.reserve(capacity) {
+            return None;
+        }
+        Some(vec)
+    }
+
+    // Pool of `capacity` fixed size slots for T, carved out of a tier in one
+    // block. Meant for the Entity tier's particles, projectiles and actors.
+    pub fn create_pool<T>(&mut self, capacity: usize, tier: Tier) -> Option<Pool<T>> {
+        // Slots are counted in u32, which is all a wasm32 usize can ask for anyway
+        if capacity == 0 || u32::try_from(capacity).is_err() {
+            return None;
+        }
+        let owner = self.allocate_typed::<pool::Slot<T>>(capacity.checked_mul(std::mem::size_of::<pool::Slot<T>>())?, tier)?;
+        let pool = Pool::new(owner, capacity);
+        if let Ok(mut pools) = self.pools.lock() {
+            pools.retain(|counters| counters.strong_count() > 0);
+            pools.push(Arc::downgrade(pool.counters()));
+        }
+        Some(pool)
+    }
+
+    // Stats of every pool that is still alive, in the order they were created
+    pub fn pool_stats(&self) -> Vec<PoolStats> {
+        match self.pools.lock() {
+            Ok(pools) => pools
+                .iter()
+                .filter_map(|counters| counters.upgrade())
+                .map(|counters| counters.stats())
+                .collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    // Owner of one block that fits `bytes` of T-aligned data
+    fn allocate_typed<T>(&mut self, bytes: usize, tier: Tier) -> Option<MemoryOwner> {
+        let alignment = self.lock_arena(tier)?.alignment();
+        let (owner, _) = self.allocate_with_owner(typed::padded_size::<T>(bytes, alignment)?, tier)?;
+        Some(owner)
+    }
+
+    // Allocate and return a generational handle instead of a raw pointer
+    pub fn allocate_handle(&mut self, size: usize, tier: Tier) -> Option<AllocHandle> {
+        let ptr = self.allocate(size, tier);
+        if ptr.is_null() {
+            return None;
+        }
+        self.lock_arena(tier)?.handle_for(ptr, size)
+    }
+
+    // Pointer for a handle, or None once its block was freed, evicted or recycled
+    pub fn resolve_handle(&self, handle: &AllocHandle) -> Option<*mut u8> {
+        self.lock_arena(handle.tier_kind())?.resolve_handle(handle)
+    }
+
+    pub fn is_handle_valid(&self, handle: &AllocHandle) -> bool {
+        match self.lock_arena(handle.tier_kind()) {
+            Some(arena) => arena.is_handle_valid(handle),
+            None => false,
+        }
+    }
+
+    // Check if pointer is in any segment of any arena
+    pub fn is_ptr_in_arena(&self, ptr: *mut u8) -> bool {
+        self.tiers().any(|tier| match self.lock_arena(tier) {
+            Some(arena) => arena.contains(ptr),
+            None => false,
+        })
+    }
+    
+    // Push a named rollback point onto a tier
+    pub fn push_marker(&mut self, tier: Tier, name: &str) -> Option<Marker> {
+        Some(self.lock_arena(tier)?.push_marker(name))
+    }
+
+    // Roll the marker's tier back to where it was when the marker was pushed
+    pub fn pop_to_marker(&mut self, marker: &Marker) -> Result<(), String> {
+        match self.lock_arena(marker.tier_kind()) {
+            Some(mut arena) => arena.pop_to_marker(marker)?,
+            None => return Err(format!("Marker '{}' names unknown tier {}", marker.name(), marker.tier())),
+        }
+        self.check_pressure(marker.tier_kind());
+        Ok(())
+    }
+
+    pub fn marker_depth(&self, tier: Tier) -> usize {
+        match self.lock_arena(tier) {
+            Some(arena) => arena.marker_depth(),
+            None => 0,
+        }
+    }
+
+    // Reset a specific tier
+    pub fn reset_tier(&mut self, tier: Tier) {
+        if let Some(mut arena) = self.lock_arena(tier) {
+            arena.reset();
+        }
+        self.check_pressure(tier);
+    }
+    
+    pub fn tier_stats(&self, tier: Tier) -> ArenaStats {
+        match self.lock_arena(tier) {
+            Some(arena) => arena.get_stats(),
+            None => ArenaStats::default(),
+        }
+    }
+
+    // Number of segments a tier has grown to
+    pub fn tier_segments(&self, tier: Tier) -> usize {
+        match self.lock_arena(tier) {
+            Some(arena) => arena.segment_count(),
+            None => 0,
+        }
+    }
+    
+    // Current size of the backing memory in pages
+    pub fn memory_pages(&self) -> usize {
+        match self.memory.lock() {
+            Ok(memory) => memory.pages(),
+            Err(_) => 0,
+        }
+    }
+
+    // Current size of the backing memory in bytes
+    pub fn memory_size(&self) -> usize {
+        self.memory_pages() * PAGE_SIZE
+    }
+    
+    // Check if a pointer is valid
+    pub fn is_ptr_valid(&self, ptr: *mut u8) -> bool {
+        self.is_ptr_in_arena(ptr)
+    }
+
+    // Fetch assets over HTTP from `url` + path
+    pub fn set_base_url(&self, url: String) {
+        self.set_asset_source(Box::new(HttpSource::new(&url)));
+    }
+
+    // Where load_asset fetches from. Every clone of this allocator shares it.
+    pub fn set_asset_source(&self, source: Box<dyn AssetSource>) {
+        if let Ok(mut asset_source) = self.asset_source.lock() {
+            *asset_source = Arc::from(source);
+        }
+    }
+
+    // How failed fetches are retried and how long each may take. Every clone
+    // of this allocator shares it.
+    pub fn set_retry_policy(&self, policy: RetryPolicy) {
+        if let Ok(mut retry) = self.retry.lock() {
+            *retry = policy;
+        }
+    }
+
+    pub fn retry_policy(&self) -> RetryPolicy {
+        match self.retry.lock() {
+            Ok(retry) => retry.clone(),
+            Err(_) => RetryPolicy::default(),
+        }
+    }
+
+    // Keep whole assets fetched from any asset source in `cache` across
+    // sessions. Every clone of this allocator shares it.
+    pub fn set_offline_cache(&self, cache: OfflineCache) {
+        if let Ok(mut offline_cache) = self.offline_cache.lock() {
+            *offline_cache = Some(Arc::new(cache));
+        }
+    }
+
+    // Fetch straight from the asset source again. The store keeps its entries.
+    pub fn remove_offline_cache(&self) {
+        if let Ok(mut offline_cache) = self.offline_cache.lock() {
+            *offline_cache = None;
+        }
+    }
+
+    pub fn offline_cache(&self) -> Option<Arc<OfflineCache>> {
+        self.offline_cache.lock().ok().and_then(|cache| cache.clone())
+    }
+
+    // Drop every entry of the offline cache, if one is installed. Resident
+    // assets stay where they are.
+    pub async fn clear_cache(&self) -> Result<(), WallocError> {
+        match self.offline_cache() {
+            Some(cache) => cache.clear().await,
+            None => Ok(()),
+        }
+    }
+
+    // The asset source, behind the offline cache if one is installed
+    fn current_asset_source(&self) -> Result<Arc<dyn AssetSource>, WallocError> {
+        let source = match self.asset_source.lock() {
+            Ok(source) => Arc::clone(&source),
+            Err(_) => return Err(WallocError::Lock("the asset source")),
+        };
+        match self.offline_cache() {
+            Some(cache) => Ok(Arc::new(CachedSource::new(source, cache))),
+            None => Ok(source),
+        }
+    }
+
+    pub async fn load_asset(&mut self, path: String, asset_type: u8) -> Result<AllocHandle, WallocError> {
+        self.load_asset_with_progress(path, asset_type, |_| {}).await
+    }
+
+    // Load every (path, asset type) of `requests`, at most `max_concurrent`
+    // at a time, and resolve with a result per request in the same order.
+    // A path listed twice is fetched once.
+    pub async fn load_assets(&self, requests: Vec<(String, u8)>, max_concurrent: usize) -> Vec<Result<AllocHandle, WallocError>> {
+        let loads: Vec<BoxedLoad<Result<AllocHandle, WallocError>>> = requests
+            .into_iter()
+            .map(|(path, asset_type)| {
+                let mut allocator = self.clone();
+                Box::pin(async move { allocator.load_asset(path, asset_type).await }) as BoxedLoad<_>
+            })
+            .collect();
+        batch::join_limited(loads, max_concurrent).await
+    }
+
+    // Load every request at once, leaving it to the load queue which fetch
+    // starts when. Resolves with a result per request in the same order.
+    pub async fn load_requests(&self, requests: Vec<AssetRequest>) -> Vec<Result<AllocHandle, WallocError>> {
+        let count = requests.len();
+        let loads: Vec<BoxedLoad<Result<AllocHandle, WallocError>>> = requests
+            .into_iter()
+            .map(|request| {
+                let mut allocator = self.clone();
+                Box::pin(async move { allocator.load(request).await }) as BoxedLoad<_>
+            })
+            .collect();
+        batch::join_limited(loads, count).await
+    }
+
+    // Loads that may fetch at once, DEFAULT_MAX_CONCURRENT_LOADS at first.
+    // The rest wait in the load queue, highest priority first.
+    pub fn set_max_concurrent_loads(&self, limit: usize) {
+        self.queue.set_limit(limit);
+    }
+
+    pub fn max_concurrent_loads(&self) -> usize {
+        self.queue.limit()
+    }
+
+    // Loads waiting for a slot in the load queue
+    pub fn queued_loads(&self) -> usize {
+        self.queue.queued()
+    }
+
+    // Load an asset as it streams in, calling `on_progress` after every chunk.
+    // Chunks are copied straight into a staging block of the asset tier,
+    // sized from the length the source reports up front or grown as they
+    // arrive. Types stored as fetched keep that block, the others are decoded
+    // from it into their own block.
+    //
+    // A load of a path that is already on its way waits for that load and
+    // resolves with its handle, whatever type it asked for. It reports no
+    // progress of its own.
+    pub async fn load_asset_with_progress(
+        &mut self,
+        path: String,
+        asset_type: u8,
+        on_progress: impl FnMut(LoadProgress),
+    ) -> Result<AllocHandle, WallocError> {
+        let asset_type = match AssetType::from_u8(asset_type) {
+            Some(asset_type) => asset_type,
+            None => return Err(WallocError::InvalidArgument(format!("Invalid asset type {}: must be 0 to 7", asset_type))),
+        };
+        self.load_with_progress(AssetRequest::new(&path, asset_type), on_progress).await
+    }
+
+    pub async fn load(&mut self, request: AssetRequest) -> Result<AllocHandle, WallocError> {
+        self.load_with_progress(request, |_| {}).await
+    }
+
+    // Load what `request` describes, like load_asset_with_progress. The fetch
+    // waits for a slot in the load queue, behind loads of higher priority.
+    pub async fn load_with_progress(
+        &mut self,
+        request: AssetRequest,
+        on_progress: impl FnMut(LoadProgress),
+    ) -> Result<AllocHandle, WallocError> {
+        if let Some(tier) = request.tier
+            && tier.index() >= self.tier_count()
+        {
+            return Err(WallocError::InvalidArgument(format!("No tier {} to load {} into", tier.id(), request.path)));
+        }
+
+        // A resident asset is served from the cache
+        let key = request.key();
+        if let Some(handle) = self.cached_asset(&key) {
+            return Ok(handle);
+        }
+
+        let flight = match batch::join(&self.loading, &key) {
+            Flight::Leader(flight) => flight,
+            Flight::Follower(flight) => return flight.wait().await,
+        };
+        let permit = self.queue.acquire(request.priority).await;
+        let result = self.fetch_asset(&request, on_progress).await;
+        drop(permit);
+        flight.complete(result.clone());
+        result
+    }
+
+    // Fetch and place an asset that is neither resident nor on its way
+    async fn fetch_asset(
+        &mut self,
+        request: &AssetRequest,
+        mut on_progress: impl FnMut(LoadProgress),
+    ) -> Result<AllocHandle, WallocError> {
+        let key = request.key();
+        let asset_type = request.asset_type;
+        let tier = request.tier.unwrap_or_else(|| self.default_tier(asset_type));
+
+        // Pack entries are read with one range request of known size
+        if let Some((bytes, pack_hash)) = self.fetch_packed(request).await? {
+            let received = bytes.len() as u64;
+            on_progress(LoadProgress { received, total: Some(received) });
+            let hash = self.verify_request(request, &bytes, pack_hash)?;
+            let encoding = self.asset_encoding(request, None)?;
+            return self.store_fetched(request, tier, &bytes, hash, encoding);
+        }
+
+        // Open the stream without holding the source lock across the await
+        let source = self.current_asset_source()?;
+        let policy = self.retry_policy();
+        let options = request.fetch_options();
+
+        // Ranges are small reads of large files and arrive in one piece
+        if let Some((start, len)) = request.range {
+            console_log(&format!("Loading asset {} from {}", key, source.describe()));
+            let bytes = retry::with_retry(&policy, &key, || source.fetch_range_with(&request.path, start, len, &options)).await?;
+            let received = bytes.len() as u64;
+            on_progress(LoadProgress { received, total: Some(received) });
+            let hash = self.verify_request(request, &bytes, None)?;
+            return self.store_asset(key, asset_type, tier, &bytes, hash, Shipped::identity(bytes.len()));
+        }
+
+        console_log(&format!("Loading asset {} from {}", key, source.describe()));
+
+        // A failed attempt starts over, its progress included
+        let mut attempts = 0;
+        let (staging, streamed) = loop {
+            attempts += 1;
+            match self.stream_asset(source.as_ref(), &request.path, &options, policy.timeout, &mut on_progress).await {
+                Ok(streamed) => break streamed,
+                Err(e) if policy.should_retry(&e, attempts) => {
+                    console_log(&format!("Retrying {} after: {}", key, e));
+                    retry::sleep(policy.backoff(attempts)).await;
+                },
+                Err(e) => return Err(e),
+            }
+        };
+
+        let result = self.store_streamed(request, tier, &staging, streamed);
+        if !matches!(result, Ok((_, true))) {
+            self.free_block(&staging.handle, staging.block_size);
+        }
+        result.map(|(handle, _)| handle)
+    }
+
+    // How the bytes of `request` are compressed: as the manifest lists it,
+    // else as the path's extension says, else as the source reported while
+    // streaming. Ranges are taken as they are stored.
+    fn asset_encoding(&self, request: &AssetRequest, streamed: Option<AssetEncoding>) -> Result<AssetEncoding, WallocError> {
+        if request.range.is_some() {
+            return Ok(AssetEncoding::Identity);
+        }
+        let listed = match self.manifest.lock() {
+            Ok(manifest) => manifest.get(&request.path).and_then(|entry| entry.encoding),
+            Err(_) => return Err(WallocError::Lock("the asset manifest")),
+        };
+        Ok(listed
+            .or_else(|| AssetEncoding::from_extension(&request.path))
+            .or(streamed)
+            .unwrap_or_default())
+    }
+
+    // Tier an asset of `asset_type` goes to unless its request picks one.
+    // Textures and meshes are GPU data and go to the render tier.
+    fn default_tier(&self, asset_type: AssetType) -> Tier {
+        if asset_type.is_render_data() {
+            self.render_tier
+        } else {
+            self.asset_tier
+        }
+    }
+
+    // Check fetched bytes against the manifest, for whole files, and the
+    // request's own hash. Returns their verified hash, if any.
+    fn verify_request(&self, request: &AssetRequest, bytes: &[u8], pack_hash: Option<[u8; 32]>) -> Result<Option<[u8; 32]>, WallocError> {
+        let verified = match request.range {
+            Some(_) => None,
+            None => self.verify_asset(&request.path, bytes, pack_hash)?,
+        };
+        Ok(request.verify(bytes, verified)?)
+    }
+
+    // Bytes of a pack entry, with the hash they were checked against, or
+    // None if no pack indexes the path. The source lock is not held across
+    // the await. A ranged request reads only its part of the entry, which
+    // the pack's hash cannot vouch for.
+    async fn fetch_packed(&self, request: &AssetRequest) -> Result<Option<(Vec<u8>, Option<[u8; 32]>)>, WallocError> {
+        let path = request.path.as_str();
+        let packed = match self.assets.lock() {
+            Ok(assets) => assets.packed_entry(path),
+            Err(_) => return Err(WallocError::Lock("the asset cache")),
+        };
+        let (pack_path, entry) = match packed {
+            Some(packed) => packed,
+            None => return Ok(None),
+        };
+
+        let source = self.current_asset_source()?;
+        console_log(&format!("Loading asset {} from pack {} on {}", path, pack_path, source.describe()));
+        let policy = self.retry_policy();
+        let options = request.fetch_options();
+        if let Some((start, len)) = request.range {
+            let start = start.min(entry.size);
+            let len = len.min(entry.size - start);
+            let bytes = retry::with_retry(&policy, path, || source.fetch_range_with(&pack_path, entry.offset + start, len, &options)).await?;
+            return Ok(Some((bytes, None)));
+        }
+        let bytes = retry::with_retry(&policy, path, || source.fetch_range_with(&pack_path, entry.offset, entry.size, &options)).await?;
+        entry.check(&bytes)?;
+        Ok(Some((bytes, Some(entry.hash))))
+    }
+
+    // === Streaming ===
+
+    // One attempt at streaming an asset into a staging block. The block is
+    // freed again if the attempt fails or outlasts `timeout`.
+    async fn stream_asset(
+        &mut self,
+        source: &dyn AssetSource,
+        path: &str,
+        options: &FetchOptions,
+        timeout: Option<Duration>,
+        on_progress: &mut impl FnMut(LoadProgress),
+    ) -> Result<(Staging, Option<AssetEncoding>), WallocError> {
+        let mut deadline = Deadline::new(timeout);
+        let mut stream = deadline.run(path, source.fetch_stream_with(path, options)).await?;
+        let total = stream.size_hint();
+
+        // A listed asset has its size in the manifest. Content-Length is only
+        // what the server claims, so it reserves no more than the limit.
+        let listed = match self.manifest.lock() {
+            Ok(manifest) => manifest.get(path).map(|entry| entry.size),
+            Err(_) => return Err(WallocError::Lock("the asset manifest")),
+        };
+        let claimed = total.and_then(|total| usize::try_from(total).ok());
+        let capacity = listed.unwrap_or_else(|| claimed.map_or(STREAM_CHUNK_SIZE, |total| total.min(STREAM_RESERVE_LIMIT)));
+        let mut staging = self.reserve_staging(capacity, self.asset_tier)?;
+        loop {
+            let chunk = match deadline.run(path, stream.next_chunk()).await {
+                Ok(Some(chunk)) => chunk,
+                Ok(None) => break,
+                Err(e) => {
+                    self.free_block(&staging.handle, staging.block_size);
+                    return Err(e);
+                },
+            };
+            if let Err(e) = self.append_staging(path, &mut staging, &chunk) {
+                self.free_block(&staging.handle, staging.block_size);
+                return Err(e);
+            }
+            on_progress(LoadProgress { received: staging.len as u64, total });
+        }
+        Ok((staging, stream.content_encoding()))
+    }
+
+    // Block of `tier` for `capacity` bytes of a streaming load or decompression
+    fn reserve_staging(&mut self, capacity: usize, tier: Tier) -> Result<Staging, WallocError> {
+        let capacity = capacity.max(1);
+        let tag = self.intern_tag(STAGING_TAG);
+        let (ptr, block_size) = self.allocate_block(capacity, tier, Some(&tag))
+            .ok_or(WallocError::OutOfMemory { size: capacity })?;
+
+        match self.handle_for(ptr, capacity, tier) {
+            Some(handle) => Ok(Staging { handle, block_size, len: 0, tier }),
+            None => Err(WallocError::OutOfMemory { size: capacity }),
+        }
+    }
+
+    // Copy a chunk in, moving to a block twice the size when it does not fit.
+    // The block is resolved through its handle every time, since the tier may
+    // have been reset while the chunk was on its way.
+    fn append_staging(&mut self, path: &str, staging: &mut Staging, chunk: &[u8]) -> Result<(), WallocError> {
+        let recycled = || WallocError::Recycled { path: path.to_string() };
+
+        let needed = staging.len.checked_add(chunk.len()).ok_or(WallocError::OutOfMemory { size: usize::MAX })?;
+        if needed > staging.handle.size() {
+            let mut grown = self.reserve_staging(needed.max(staging.handle.size().saturating_mul(2)), staging.tier)?;
+            let (from, to) = match (self.resolve_handle(&staging.handle), self.resolve_handle(&grown.handle)) {
+                (Some(from), Some(to)) => (from, to),
+                _ => {
+                    self.free_block(&grown.handle, grown.block_size);
+                    return Err(recycled());
+                },
+            };
+            unsafe { std::ptr::copy_nonoverlapping(from, to, staging.len) };
+            self.free_block(&staging.handle, staging.block_size);
+            grown.len = staging.len;
+            *staging = grown;
+        }
+
+        let ptr = self.resolve_handle(&staging.handle).ok_or_else(recycled)?;
+        unsafe { std::ptr::copy_nonoverlapping(chunk.as_ptr(), ptr.add(staging.len), chunk.len()) };
+        staging.len = needed;
+        Ok(())
+    }
+
+    // Verify a completely streamed asset and place it in `tier`, decompressing
+    // it first if it arrived compressed. The flag is true if the asset kept
+    // the staging block, which is otherwise the caller's to free.
+    fn store_streamed(
+        &mut self,
+        request: &AssetRequest,
+        tier: Tier,
+        staging: &Staging,
+        streamed: Option<AssetEncoding>,
+    ) -> Result<(AllocHandle, bool), WallocError> {
+        let ptr = self.resolve_handle(&staging.handle)
+            .ok_or_else(|| WallocError::Recycled { path: request.key() })?;
+        let raw = unsafe { std::slice::from_raw_parts(ptr, staging.len) };
+
+        // Hashes are of the bytes as shipped
+        let hash = self.verify_request(request, raw, None)?;
+        let encoding = self.asset_encoding(request, streamed)?;
+        if !encoding.is_compressed() {
+            return self.store_staged(request, tier, staging, hash, Shipped::identity(staging.len));
+        }
+
+        // Decompressing allocates, and the pressure handlers it may call can
+        // reset or evict the staging block, so the decoder reads a copy
+        let compressed = raw.to_vec();
+        let shipped = Shipped { encoding, size: staging.len };
+        let decompressed = self.decompress(&request.key(), encoding, &compressed, tier)?;
+        let result = self.store_staged(request, tier, &decompressed, hash, shipped);
+        if !matches!(result, Ok((_, true))) {
+            self.free_block(&decompressed.handle, decompressed.block_size);
+        }
+        result.map(|(handle, _)| (handle, false))
+    }
+
+    // Place verified bytes of a pack entry or a range in `tier`,
+    // decompressing them first if they are compressed
+    fn store_fetched(
+        &mut self,
+        request: &AssetRequest,
+        tier: Tier,
+        bytes: &[u8],
+        hash: Option<[u8; 32]>,
+        encoding: AssetEncoding,
+    ) -> Result<AllocHandle, WallocError> {
+        if !encoding.is_compressed() {
+            return self.store_asset(request.key(), request.asset_type, tier, bytes, hash, Shipped::identity(bytes.len()));
+        }
+
+        let shipped = Shipped { encoding, size: bytes.len() };
+        let decompressed = self.decompress(&request.key(), encoding, bytes, tier)?;
+        let result = self.store_staged(request, tier, &decompressed, hash, shipped);
+        if !matches!(result, Ok((_, true))) {
+            self.free_block(&decompressed.handle, decompressed.block_size);
+        }
+        result.map(|(handle, _)| handle)
+    }
+
+    // Decompress `bytes` straight into a staging block of `tier`, sized from
+    // what the encoding says about the decompressed size and grown by
+    // doubling if that falls short
+    fn decompress(&mut self, path: &str, encoding: AssetEncoding, bytes: &[u8], tier: Tier) -> Result<Staging, WallocError> {
+        let mut staging = self.reserve_staging(encoding.size_hint(bytes), tier)?;
+        match self.decompress_into(path, encoding, bytes, &mut staging) {
+            Ok(()) => Ok(staging),
+            Err(e) => {
+                self.free_block(&staging.handle, staging.block_size);
+                Err(e)
+            },
+        }
+    }
+
+    fn decompress_into(&mut self, path: &str, encoding: AssetEncoding, bytes: &[u8], staging: &mut Staging) -> Result<(), WallocError> {
+        let corrupt = |message: String| WallocError::Decode {
+            path: path.to_string(),
+            message: format!("Invalid {} data: {}", encoding.name(), message),
+        };
+        let mut decoder = encoding.decoder(bytes).map_err(corrupt)?;
+        let mut probe = [0u8; DECOMPRESS_PROBE_SIZE];
+        loop {
+            // A full block only grows once the decoder has more to give
+            let free = staging.handle.size() - staging.len;
+            if free == 0 {
+                match decoder.read(&mut probe) {
+                    Ok(0) => return Ok(()),
+                    Ok(read) => self.append_staging(path, staging, &probe[..read])?,
+                    Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {},
+                    Err(e) => return Err(corrupt(e.to_string())),
+                }
+                continue;
+            }
+
+            let ptr = self.resolve_handle(&staging.handle)
+                .ok_or_else(|| WallocError::Recycled { path: path.to_string() })?;
+            let out = unsafe { std::slice::from_raw_parts_mut(ptr.add(staging.len), free) };
+            match decoder.read(out) {
+                Ok(0) => return Ok(()),
+                Ok(read) => staging.len += read,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {},
+                Err(e) => return Err(corrupt(e.to_string())),
+            }
+        }
+    }
+
+    // Place a verified, decompressed asset held in a staging block in `tier`.
+    // The flag is true if the asset kept the staging block, which is otherwise
+    // the caller's to free.
+    fn store_staged(
+        &mut self,
+        request: &AssetRequest,
+        tier: Tier,
+        staging: &Staging,
+        hash: Option<[u8; 32]>,
+        shipped: Shipped,
+    ) -> Result<(AllocHandle, bool), WallocError> {
+        let path = request.key();
+        let asset_type = request.asset_type;
+        let ptr = self.resolve_handle(&staging.handle)
+            .ok_or_else(|| WallocError::Recycled { path: path.clone() })?;
+        let raw = unsafe { std::slice::from_raw_parts(ptr, staging.len) };
+
+        let (format, data_size) = match asset::inspect(asset_type, raw) {
+            Ok(decoded) => decoded,
+            Err(message) => return Err(WallocError::Decode { path, message }),
+        };
+
+        // Keep the staging block if the asset is stored as fetched and the block
+        // is not much larger than it, otherwise decode it into a block of its own
+        let slack = staging.handle.size() - staging.len;
+        let keep = asset::is_stored_as_fetched(&format, raw.len(), data_size)
+            && tier == staging.tier
+            && slack <= staging.len / STAGING_SLACK_DIVISOR;
+        if !keep {
+            return self.store_asset(path, asset_type, tier, raw, hash, shipped).map(|handle| (handle, false));
+        }
+
+        let victims = match self.assets.lock() {
+            Ok(mut assets) => {
+                assets.retain(|handle| self.is_handle_valid(handle));
+                assets.make_room(data_size)?
+            },
+            Err(_) => return Err(WallocError::Lock("the asset cache")),
+        };
+        for victim in &victims {
+            self.free_asset(victim);
+        }
+
+        let handle = staging.handle.sub_handle(0, data_size);
+        let tag = self.intern_tag(&asset_tag(asset_type));
+        if let Some(mut arena) = self.lock_arena(staging.tier) {
+            arena.retag(&handle, &tag);
+        }
+        let metadata = AssetMetadata {
+            asset_type,
+            format,
+            encoding: shipped.encoding,
+            compressed_size: shipped.size,
+            raw_size: raw.len(),
+            size: data_size,
+            block_size: staging.block_size,
+            handle,
+            loaded_at: 0,
+            last_access: 0,
+            access_count: 0,
+            pinned: false,
+            pack: None,
+            hash,
+        };
+        match self.assets.lock() {
+            Ok(mut assets) => assets.insert(path, metadata),
+            Err(_) => return Err(WallocError::Lock("the asset cache")),
+        }
+        Ok((handle, true))
+    }
+
+    // Check fetched bytes against the manifest and return their verified hash,
+    // if the manifest or their pack vouched for them
+    fn verify_asset(&self, path: &str, bytes: &[u8], pack_hash: Option<[u8; 32]>) -> Result<Option<[u8; 32]>, WallocError> {
+        let manifest = match self.manifest.lock() {
+            Ok(manifest) => manifest,
+            Err(_) => return Err(WallocError::Lock("the asset manifest")),
+        };
+        let verified = match pack_hash {
+            Some(hash) => manifest.verify_hash(path, bytes.len(), &hash)?.or(pack_hash),
+            None => manifest.verify(path, bytes)?,
+        };
+        Ok(verified)
+    }
+
+    // Decode fetched, decompressed bytes into a new block of `tier` and cache them
+    fn store_asset(
+        &mut self,
+        path: String,
+        asset_type: AssetType,
+        tier: Tier,
+        bytes: &[u8],
+        hash: Option<[u8; 32]>,
+        shipped: Shipped,
+    ) -> Result<AllocHandle, WallocError> {
+        // Work out the decoded size so the destination can be allocated first
+        let (format, data_size) = match asset::inspect(asset_type, bytes) {
+            Ok(decoded) => decoded,
+            Err(message) => return Err(WallocError::Decode { path, message }),
+        };
+
+        let (ptr, block_size, handle) = self.allocate_asset_block(data_size, tier, &asset_tag(asset_type))?;
+
+        // Decode straight into tier memory
+        let out = unsafe { std::slice::from_raw_parts_mut(ptr, data_size) };
+        if let Err(e) = asset::decode_into(asset_type, bytes, out) {
+            if let Some(mut arena) = self.lock_arena(tier) {
+                arena.free(ptr, block_size);
+            }
+            return Err(WallocError::Decode { path, message: e });
+        }
+
+        // Save metadata
+        let metadata = AssetMetadata {
+            asset_type,
+            format,
+            encoding: shipped.encoding,
+            compressed_size: shipped.size,
+            raw_size: bytes.len(),
+            size: data_size,
+            block_size,
+            handle,
+            loaded_at: 0,
+            last_access: 0,
+            access_count: 0,
+            pinned: false,
+            pack: None,
+            hash,
+        };
+        match self.assets.lock() {
+            Ok(mut assets) => assets.insert(path, metadata),
+            Err(_) => return Err(WallocError::Lock("the asset cache")),
+        }
+
+        Ok(handle)
+    }
+
+    // Evict what the policy picks until `size` more bytes fit in the budget,
+    // then allocate them in `tier`. Unlike allocate(), this never resets the
+    // tier, resident assets stay put.
+    fn allocate_asset_block(&mut self, size: usize, tier: Tier, tag: &str) -> Result<(*mut u8, usize, AllocHandle), WallocError> {
+        let victims = match self.assets.lock() {
+            Ok(mut assets) => {
+                assets.retain(|handle| self.is_handle_valid(handle));
+                assets.make_room(size)?
+            },
+            Err(_) => return Err(WallocError::Lock("the asset cache")),
+        };
+        for victim in &victims {
+            self.free_asset(victim);
+        }
+
+        let tag = self.intern_tag(tag);
+        let (ptr, block_size) = self.allocate_block(size, tier, Some(&tag))
+            .ok_or(WallocError::OutOfMemory { size })?;
+
+        // Handle to where the block lives inside its tier
+        match self.handle_for(ptr, size, tier) {
+            Some(handle) => Ok((ptr, block_size, handle)),
+            None => Err(WallocError::OutOfMemory { size }),
+        }
+    }
+
+    // === Packs ===
+
+    // Fetch a whole pack in one request and copy its payloads into one block
+    // of the asset tier. Every uncompressed entry becomes a resident asset
+    // inside that block, stored as packed, until evict_pack frees it.
+    // Compressed entries are only decompressed into blocks of their own.
+    pub async fn load_pack(&mut self, path: String) -> Result<AllocHandle, WallocError> {
+        let resident = match self.assets.lock() {
+            Ok(assets) => assets.pack(&path).map(|pack| pack.handle),
+            Err(_) => return Err(WallocError::Lock("the asset cache")),
+        };
+        if let Some(handle) = resident
+            && self.is_handle_valid(&handle)
+        {
+            return Ok(handle);
+        }
+
+        let source = self.current_asset_source()?;
+        console_log(&format!("Loading pack {} from {}", path, source.describe()));
+        let policy = self.retry_policy();
+        let bytes = retry::with_retry(&policy, &path, || source.fetch(&path)).await?;
+        self.verify_asset(&path, &bytes, None)?;
+
+        let pack_error = |message: String| WallocError::Decode { path: path.clone(), message };
+        let index = PackIndex::parse(&bytes).map_err(pack_error)?;
+        if index.payload_size == 0 {
+            return Err(pack_error("Pack holds no data".to_string()));
+        }
+        let payloads = index
+            .payloads(&bytes)
+            .ok_or_else(|| pack_error("Pack is truncated".to_string()))?;
+        for entry in &index.entries {
+            let payload = index
+                .payload(entry, payloads)
+                .ok_or_else(|| pack_error("Pack is truncated".to_string()))?;
+            entry.check(payload)?;
+            self.verify_asset(&entry.path, payload, Some(entry.hash))?;
+        }
+
+        // Compressed entries are decompressed into blocks of their own, the
+        // others are laid out in the pack's block as packed
+        let mut compressed = Vec::new();
+        let mut packed = Vec::new();
+        let mut packed_size: usize = 0;
+        for entry in &index.entries {
+            let request = AssetRequest::new(&entry.path, entry.asset_type);
+            let encoding = self.asset_encoding(&request, None)?;
+            if encoding.is_compressed() {
+                compressed.push((request, entry, encoding));
+            } else {
+                let offset = packed_size.next_multiple_of(PACK_ALIGNMENT);
+                packed_size = offset + entry.size as usize;
+                packed.push((entry, offset));
+            }
+        }
+
+        let (ptr, block_size, handle) = self.allocate_asset_block(packed_size, self.asset_tier, PACK_TAG)?;
+        for (entry, offset) in &packed {
+            if let Some(payload) = index.payload(entry, payloads) {
+                unsafe { std::ptr::copy_nonoverlapping(payload.as_ptr(), ptr.add(*offset), payload.len()) };
+            }
+        }
+
+        let entries: Vec<_> = packed
+            .iter()
+            .map(|&(entry, offset)| {
+                let metadata = AssetMetadata {
+                    asset_type: entry.asset_type,
+                    format: AssetFormat::Encoded,
+                    encoding: AssetEncoding::Identity,
+                    compressed_size: entry.size as usize,
+                    raw_size: entry.size as usize,
+                    size: entry.size as usize,
+                    block_size: 0,
+                    handle: handle.sub_handle(offset, entry.size as usize),
+                    loaded_at: 0,
+                    last_access: 0,
+                    access_count: 0,
+                    pinned: false,
+                    pack: None,
+                    hash: Some(entry.hash),
+                };
+                (entry.path.clone(), metadata)
+            })
+            .collect();
+        let pack = PackMetadata {
+            handle,
+            block_size,
+            entries: entries.len(),
+        };
+
+        let replaced = match self.assets.lock() {
+            Ok(mut assets) => {
+                assets.index_pack(&path, &index);
+                assets.insert_pack(&path, pack, entries)
+            },
+            Err(_) => return Err(WallocError::Lock("the asset cache")),
+        };
+        for old in &replaced {
+            self.free_asset(old);
+        }
+
+        for (request, entry, encoding) in compressed {
+            // Already decompressed by an earlier load
+            if self.asset_handle(&entry.path).is_some_and(|handle| self.is_handle_valid(&handle)) {
+                continue;
+            }
+            let payload = index
+                .payload(entry, payloads)
+                .ok_or_else(|| pack_error("Pack is truncated".to_string()))?;
+            let tier = self.default_tier(entry.asset_type);
+            self.store_fetched(&request, tier, payload, Some(entry.hash), encoding)?;
+        }
+
+        console_log(&format!("Loaded pack {}: {} entries, {} bytes", path, index.len(), packed_size));
+        Ok(handle)
+    }
+
+    // Read only a pack's index, with a single range request unless the index
+    // is unusually large. Its entries are then fetched one at a time, with a
+    // range request each, when they are loaded. Returns the number of entries.
+    pub async fn open_pack(&mut self, path: String) -> Result<usize, WallocError> {
+        let source = self.current_asset_source()?;
+        console_log(&format!("Opening pack {} from {}", path, source.describe()));
+
+        let policy = self.retry_policy();
+        let pack_error = |message: String| WallocError::Decode { path: path.clone(), message };
+        let mut bytes = retry::with_retry(&policy, &path, || source.fetch_range(&path, 0, pack::PACK_INDEX_PREFETCH as u64)).await?;
+        let index_size = PackIndex::index_size(&bytes).map_err(pack_error)?;
+        if bytes.len() < index_size {
+            let (start, len) = (bytes.len() as u64, (index_size - bytes.len()) as u64);
+            let rest = retry::with_retry(&policy, &path, || source.fetch_range(&path, start, len)).await?;
+            bytes.extend_from_slice(&rest);
+        }
+        let index = PackIndex::parse(&bytes).map_err(pack_error)?;
+
+        match self.assets.lock() {
+            Ok(mut assets) => assets.index_pack(&path, &index),
+            Err(_) => return Err(WallocError::Lock("the asset cache")),
+        }
+        Ok(index.len())
+    }
+
+    // Load an entry of a pack loaded or opened before, decoded as the type
+    // the pack records for it
+    pub async fn load_pack_entry(&mut self, path: String) -> Result<AllocHandle, WallocError> {
+        let asset_type = match self.assets.lock() {
+            Ok(assets) => match assets.packed_entry(&path) {
+                Some((_, entry)) => entry.asset_type,
+                None => return Err(WallocError::NotFound { path }),
+            },
+            Err(_) => return Err(WallocError::Lock("the asset cache")),
+        };
+        self.load_asset(path, asset_type as u8).await
+    }
+
+    // Free a loaded pack's block along with every entry inside it. The
+    // entries stay indexed and load_asset reads them from the pack one by one.
+    pub fn evict_pack(&mut self, path: &str) -> Result<(), WallocError> {
+        let pack = match self.assets.lock() {
+            Ok(mut assets) => match assets.remove_pack(path) {
+                Some(pack) => pack,
+                None => return Err(WallocError::NotFound { path: path.to_string() }),
+            },
+            Err(_) => return Err(WallocError::Lock("the asset cache")),
+        };
+
+        self.free_block(&pack.handle, pack.block_size);
+        self.check_pressure(pack.handle.tier_kind());
+        console_log(&format!(
+            "Evicted pack: {} with {} entries and freed {} bytes",
+            path, pack.entries, pack.handle.size()
+        ));
+        Ok(())
+    }
+
+    // Handle for memory just allocated in a tier
+    fn handle_for(&self, ptr: *mut u8, size: usize, tier: Tier) -> Option<AllocHandle> {
+        self.lock_arena(tier)?.handle_for(ptr, size)
+    }
+
+    // Handle of a resident asset, recording the access. Assets whose memory
+    // a reset of the asset tier recycled are dropped from the cache.
+    fn cached_asset(&self, path: &str) -> Option<AllocHandle> {
+        let mut assets = self.assets.lock().ok()?;
+        if let Some(metadata) = assets.peek(path)
+            && !self.is_handle_valid(&metadata.handle)
+        {
+            assets.remove(path);
+        }
+        assets.get(path).map(|metadata| metadata.handle)
+    }
+
+    // Give an evicted asset's block back to its tier. Entries of a loaded
+    // pack have no block of their own.
+    fn free_asset(&self, metadata: &AssetMetadata) {
+        #[cfg(feature = "trace")]
+        if let Some(arena) = self.lock_arena(metadata.handle.tier_kind()) {
+            let tag = arena.tag_of(&metadata.handle);
+            self.trace.record(TraceOp::Evict, arena.tier, None, metadata.size, arena.usage(), tag.as_ref());
+        }
+        if metadata.pack.is_none() {
+            self.free_block(&metadata.handle, metadata.block_size);
+        }
+    }
+
+    fn free_block(&self, handle: &AllocHandle, block_size: usize) {
+        if let Some(ptr) = self.resolve_handle(handle)
+            && let Some(mut arena) = self.lock_arena(handle.tier_kind())
+        {
+            arena.free(ptr, block_size);
+        }
+    }
+
+    // Handle of a loaded asset
+    pub fn asset_handle(&self, path: &str) -> Option<AllocHandle> {
+        let assets = self.assets.lock().ok()?;
+        assets.peek(path).map(|metadata| metadata.handle)
+    }
+
+    // Type, decoded format, sizes and tier of a loaded asset
+    pub fn asset_info(&self, path: &str) -> Option<AssetInfo> {
+        let assets = self.assets.lock().ok()?;
+        assets.peek(path).map(|metadata| AssetInfo {
+            asset_type: metadata.asset_type,
+            format: metadata.format,
+            encoding: metadata.encoding,
+            compressed_size: metadata.compressed_size,
+            raw_size: metadata.raw_size,
+            size: metadata.size,
+            tier: metadata.handle.tier_kind(),
+            hash: metadata.hash,
+        })
+    }
+
+    // Path of a resident asset whose verified hash is `hash`
+    pub fn find_asset_by_hash(&self, hash: &[u8; 32]) -> Option<String> {
+        let assets = self.assets.lock().ok()?;
+        assets.find_by_hash(hash).map(str::to_string)
+    }
+
+    // === Asset manifest ===
+
+    // Check every asset fetched from now on against `manifest`. Every clone
+    // of this allocator shares it.
+    pub fn set_asset_manifest(&self, manifest: AssetManifest) {
+        if let Ok(mut current) = self.manifest.lock() {
+            *current = manifest;
+        }
+    }
+
+    pub fn asset_manifest(&self) -> AssetManifest {
+        match self.manifest.lock() {
+            Ok(manifest) => manifest.clone(),
+            Err(_) => AssetManifest::new(),
+        }
+    }
+
+    // Fetch a JSON manifest from the asset source and install it. Returns the
+    // number of assets it lists.
+    pub async fn load_manifest(&self, path: &str) -> Result<usize, WallocError> {
+        let source = self.current_asset_source()?;
+        console_log(&format!("Loading asset manifest {} from {}", path, source.describe()));
+        let bytes = retry::with_retry(&self.retry_policy(), path, || source.fetch(path)).await?;
+        let manifest_error = |message: String| WallocError::Decode { path: path.to_string(), message };
+        let json = std::str::from_utf8(&bytes).map_err(|e| manifest_error(format!("Asset manifest is not UTF-8: {}", e)))?;
+        let manifest = AssetManifest::from_json(json).map_err(manifest_error)?;
+        let len = manifest.len();
+        self.set_asset_manifest(manifest);
+        Ok(len)
+    }
+
+    // === Asset cache ===
+
+    // Bytes resident assets may take up. Lowering the budget evicts right away.
+    pub fn set_asset_budget(&mut self, budget: usize) -> Result<(), WallocError> {
+        let victims = {
+            let mut assets = self.assets.lock().map_err(|_| WallocError::Lock("the asset cache"))?;
+            assets.set_budget(budget);
+            assets.make_room(0)?
+        };
+        for victim in &victims {
+            self.free_asset(victim);
+        }
+        Ok(())
+    }
+
+    pub fn set_eviction_policy(&mut self, policy: Box<dyn EvictionPolicy>) {
+        if let Ok(mut assets) = self.assets.lock() {
+            assets.set_policy(policy);
+        }
+    }
+
+    pub fn eviction_policy(&self) -> &'static str {
+        match self.assets.lock() {
+            Ok(assets) => assets.policy_name(),
+            Err(_) => "",
+        }
+    }
+
+    // Pinned assets are never evicted to make room, only by evict_asset
+    pub fn pin_asset(&mut self, path: &str, pinned: bool) -> bool {
+        match self.assets.lock() {
+            Ok(mut assets) => assets.set_pinned(path, pinned),
+            Err(_) => false,
+        }
+    }
+
+    pub fn asset_cache_stats(&self) -> AssetCacheStats {
+        match self.assets.lock() {
+            Ok(assets) => assets.stats(),
+            Err(_) => AssetCacheStats::default(),
+        }
+    }
+
+    // Offset of a pointer from the memory source's base
+    pub fn memory_offset(&self, ptr: *mut u8) -> usize {
+        let memory_base = match self.memory.lock() {
+            Ok(memory) => memory.base(),
+            Err(_) => std::ptr::null_mut(),
+        };
+        (ptr as usize) - (memory_base as usize)
+    }
+
+    // Fetch `path` from the asset source as text, without caching it
+    pub async fn test_fetch_json(&self, path: &str) -> Result<JsValue, WallocError> {
+        console_log(&format!("Testing JSON fetch of {}", path));
+        
+        let source = self.current_asset_source()?;
+        let bytes = source.fetch(path).await?;
+        
+        let text = match String::from_utf8(bytes) {
+            Ok(t) => t,
+            Err(e) => return Err(WallocError::Decode { path: path.to_string(), message: e.to_string() }),
+        };
+        
+        console_log(&format!("Received JSON: {}", text));
+        
+        Ok(JsValue::from_str(&text))
+    }
+
+    // Free one asset's block. Every other asset stays where it is. Entries
+    // of a loaded pack only go with the whole pack, through evict_pack.
+    pub fn evict_asset(&mut self, path: &str) -> Result<(), WallocError> {
+        let metadata = match self.assets.lock() {
+            Ok(mut assets) => {
+                if let Some(pack) = assets.peek(path).and_then(|metadata| metadata.pack.as_ref()) {
+                    return Err(WallocError::InvalidArgument(format!(
+                        "Asset {} lives in pack {}, evict the pack instead",
+                        path, pack
+                    )));
+                }
+                match assets.remove(path) {
+                    Some(metadata) => metadata,
+                    None => return Err(WallocError::NotFound { path: path.to_string() }),
+                }
+            },
+            Err(_) => return Err(WallocError::Lock("the asset cache")),
+        };
+
+        self.free_asset(&metadata);
+        self.check_pressure(metadata.handle.tier_kind());
+        
+        console_log(&format!(
+            "Evicted asset: {} and freed {} bytes",
+            path, metadata.size
+        ));
+        
+        Ok(())
+    }
+    
+    // Copy of a resident asset's bytes
+    pub fn get_asset(&self, path: &str) -> Result<js_sys::Uint8Array, WallocError> {
+        let (ptr, size) = self.resident_asset(path)?;
+        unsafe {
+            let mem_slice = std::slice::from_raw_parts(ptr, size);
+            Ok(js_sys::Uint8Array::from(mem_slice))
+        }
+    }
+
+    // View of a resident asset's bytes where they live, see view.rs for how
+    // long it stays usable
+    pub fn asset_view(&self, path: &str, kind: ViewKind) -> Result<JsValue, WallocError> {
+        let (ptr, size) = self.resident_asset(path)?;
+        unsafe { view::typed_view(ptr, size, kind) }.map_err(WallocError::InvalidArgument)
+    }
+
+    // Where a resident asset's bytes are. Looking it up counts as an access
+    // for the eviction policy.
+    fn resident_asset(&self, path: &str) -> Result<(*mut u8, usize), WallocError> {
+        let handle = match self.cached_asset(path) {
+            Some(handle) => handle,
+            None => return Err(WallocError::NotFound { path: path.to_string() }),
+        };
+        
+        match self.resolve_handle(&handle) {
+            Some(ptr) => Ok((ptr, handle.size())),
+            None => Err(WallocError::Recycled { path: path.to_string() }),
+        }
+    }
+}
+
+impl Clone for TieredAllocator {
+    fn clone(&self) -> Self {
+        TieredAllocator {
+            arenas: self.arenas.iter().map(Arc::clone).collect(),
+            asset_tier: self.asset_tier,
+            render_tier: self.render_tier,
+            memory: Arc::clone(&self.memory),
+            assets: Arc::clone(&self.assets),
+            asset_source: Arc::clone(&self.asset_source),
+            manifest: Arc::clone(&self.manifest),
+            loading: Arc::clone(&self.loading),
+            queue: self.queue.clone(),
+            offline_cache: Arc::clone(&self.offline_cache),
+            retry: Arc::clone(&self.retry),
+            pools: Arc::clone(&self.pools),
+            tag: Arc::clone(&self.tag),
+            tag_names: Arc::clone(&self.tag_names),
+            #[cfg(feature = "trace")]
+            trace: Arc::clone(&self.trace),
+            pressure: Arc::clone(&self.pressure),
+        }
+    }
+}
+
+impl Default for Walloc {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[wasm_bindgen]
+impl Walloc {
+    pub fn new() -> Self {
+        let memory = default_source();
+        let memory_base = memory.base();
+
+        let strategy = TieredAllocator::new(memory, INITIAL_HEAP_PAGES);
+
+        Walloc {
+            strategy,
+            memory_base,
+            frames: None,
+            #[cfg(target_arch = "wasm32")]
+            pressure_handler: None,
+        }
+    }
+
+    // Walloc with a custom tier layout, described by a JS object:
+    // { tiers: [{ name, size, alignment, strategy, growth }, ...], assetTier }
+    #[wasm_bindgen]
+    pub fn with_config(config: &JsValue) -> Result<Walloc, JsValue> {
+        let config = TieredAllocatorConfig::from_js(config)?;
+        let memory = default_source();
+        let memory_base = memory.base();
+
+        let strategy = TieredAllocator::with_config(memory, config).map_err(|e| JsValue::from_str(&e))?;
+
+        Ok(Walloc {
+            strategy,
+            memory_base,
+            frames: None,
+            #[cfg(target_arch = "wasm32")]
+            pressure_handler: None,
+        })
+    }
+
+    // Tier number for a tier name from the layout
+    #[wasm_bindgen]
+    pub fn tier_index(&self, name: &str) -> Result<u8, JsValue> {
+        match self.strategy.tier_by_name(name) {
+            Some(tier) => Ok(tier.id()),
+            None => Err(JsValue::from_str(&format!("Unknown tier '{}'", name))),
         }
     }
     
@@ -997,76 +2880,683 @@ impl Walloc {
         self.strategy.set_base_url(url);
         Ok(())
     }
+
+    // How failed fetches are retried and how long each may take, see
+    // RetryPolicy::from_js for the fields. Rejected loads reject with an
+    // Error whose `code` says what went wrong.
+    #[wasm_bindgen]
+    pub fn set_retry_policy(&self, policy: &JsValue) -> Result<(), JsValue> {
+        self.strategy.set_retry_policy(RetryPolicy::from_js(policy)?);
+        Ok(())
+    }
+    
+    // Async methods return Promise. `on_progress(received, total)` is called
+    // as the asset streams in, with total undefined if the length is unknown.
+    #[wasm_bindgen]
+    pub fn load_asset(&mut self, path: String, asset_type: u8, on_progress: Option<js_sys::Function>) -> Promise {
+        let mut allocator_clone = self.strategy.clone();
+        
+        future_to_promise(async move {
+            let report = |progress: LoadProgress| {
+                if let Some(callback) = &on_progress {
+                    let total = progress.total.map_or(JsValue::UNDEFINED, |total| JsValue::from_f64(total as f64));
+                    let _ = callback.call2(&JsValue::NULL, &JsValue::from_f64(progress.received as f64), &total);
+                }
+            };
+            match allocator_clone.load_asset_with_progress(path, asset_type, report).await {
+                Ok(handle) => Ok(JsValue::from(handle)),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
     
-    // Async methods return Promise
+    // Load a list of assets, given as [path, type] pairs, with at most
+    // `max_concurrent` requests in flight (6 if omitted). Resolves to one
+    // { path, handle } or { path, error } per request, in the same order.
+    #[wasm_bindgen]
+    pub fn load_assets(&mut self, requests: js_sys::Array, max_concurrent: Option<usize>) -> Promise {
+        let allocator_clone = self.strategy.clone();
+
+        future_to_promise(async move {
+            let mut pairs = Vec::with_capacity(requests.length() as usize);
+            for request in requests.iter() {
+                let pair = js_sys::Array::from(&request);
+                let path = pair.get(0).as_string();
+                let asset_type = pair.get(1).as_f64();
+                match (path, asset_type) {
+                    (Some(path), Some(asset_type)) if (0.0..=255.0).contains(&asset_type) => {
+                        pairs.push((path, asset_type as u8));
+                    },
+                    _ => return Err(JsValue::from_str("load_assets expects [path, type] pairs")),
+                }
+            }
+
+            let paths: Vec<String> = pairs.iter().map(|(path, _)| path.clone()).collect();
+            let results = allocator_clone
+                .load_assets(pairs, max_concurrent.unwrap_or(DEFAULT_MAX_CONCURRENT_LOADS))
+                .await;
+
+            let output = js_sys::Array::new();
+            for (path, result) in paths.into_iter().zip(results) {
+                let entry = js_sys::Object::new();
+                let set = |key: &str, value: JsValue| js_sys::Reflect::set(&entry, &JsValue::from_str(key), &value);
+                set("path", JsValue::from_str(&path))?;
+                match result {
+                    Ok(handle) => set("handle", JsValue::from(handle))?,
+                    Err(e) => set("error", e.into())?,
+                };
+                output.push(&entry);
+            }
+            Ok(output.into())
+        })
+    }
+
+    // Load what a request object describes, see AssetRequest::from_js for the
+    // fields. `on_progress` works as for load_asset.
+    #[wasm_bindgen]
+    pub fn load(&mut self, request: &JsValue, on_progress: Option<js_sys::Function>) -> Promise {
+        let mut allocator_clone = self.strategy.clone();
+        let request = AssetRequest::from_js(request, |name| self.strategy.tier_by_name(name));
+
+        future_to_promise(async move {
+            let report = |progress: LoadProgress| {
+                if let Some(callback) = &on_progress {
+                    let total = progress.total.map_or(JsValue::UNDEFINED, |total| JsValue::from_f64(total as f64));
+                    let _ = callback.call2(&JsValue::NULL, &JsValue::from_f64(progress.received as f64), &total);
+                }
+            };
+            match allocator_clone.load_with_progress(request?, report).await {
+                Ok(handle) => Ok(JsValue::from(handle)),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    // Load a list of request objects, scheduled by priority in the load
+    // queue. Resolves like load_assets, with the key each is cached under.
+    #[wasm_bindgen]
+    pub fn load_requests(&mut self, requests: js_sys::Array) -> Promise {
+        let allocator_clone = self.strategy.clone();
+        let requests: Result<Vec<AssetRequest>, WallocError> = requests
+            .iter()
+            .map(|request| AssetRequest::from_js(&request, |name| self.strategy.tier_by_name(name)))
+            .collect();
+
+        future_to_promise(async move {
+            let requests = requests?;
+            let keys: Vec<String> = requests.iter().map(|request| request.key()).collect();
+            let results = allocator_clone.load_requests(requests).await;
+
+            let output = js_sys::Array::new();
+            for (key, result) in keys.into_iter().zip(results) {
+                let entry = js_sys::Object::new();
+                let set = |key: &str, value: JsValue| js_sys::Reflect::set(&entry, &JsValue::from_str(key), &value);
+                set("path", JsValue::from_str(&key))?;
+                match result {
+                    Ok(handle) => set("handle", JsValue::from(handle))?,
+                    Err(e) => set("error", e.into())?,
+                };
+                output.push(&entry);
+            }
+            Ok(output.into())
+        })
+    }
+
+    // Keep fetched assets across sessions in a JS store, such as one backed by
+    // IndexedDB (see JsCacheStore for its methods), with room for `limit`
+    // bytes (256MB if omitted)
+    #[cfg(target_arch = "wasm32")]
+    #[wasm_bindgen]
+    pub fn set_offline_cache(&self, store: JsValue, limit: Option<usize>) {
+        let cache = OfflineCache::new(Box::new(JsCacheStore::new(store)))
+            .with_limit(limit.unwrap_or(DEFAULT_OFFLINE_CACHE_LIMIT));
+        self.strategy.set_offline_cache(cache);
+    }
+
+    // Empty the offline cache. Resolves once the store is empty.
+    #[wasm_bindgen]
+    pub fn clear_cache(&self) -> Promise {
+        let allocator_clone = self.strategy.clone();
+
+        future_to_promise(async move {
+            allocator_clone.clear_cache().await?;
+            Ok(JsValue::UNDEFINED)
+        })
+    }
+
+    // Resolves to { limit, used, entries, hits, misses, updates, offlineHits,
+    // evictions }, or undefined without an offline cache
+    #[wasm_bindgen]
+    pub fn offline_cache_stats(&self) -> Promise {
+        let cache = self.strategy.offline_cache();
+
+        future_to_promise(async move {
+            let cache = match cache {
+                Some(cache) => cache,
+                None => return Ok(JsValue::UNDEFINED),
+            };
+            let (used, entries) = cache.usage().await?;
+            let stats = cache.stats();
+
+            let obj = js_sys::Object::new();
+            let set = |key: &str, value: f64| js_sys::Reflect::set(&obj, &JsValue::from_str(key), &JsValue::from_f64(value));
+            set("limit", stats.limit as f64)?;
+            set("used", used as f64)?;
+            set("entries", entries as f64)?;
+            set("hits", stats.hits as f64)?;
+            set("misses", stats.misses as f64)?;
+            set("updates", stats.updates as f64)?;
+            set("offlineHits", stats.offline_hits as f64)?;
+            set("evictions", stats.evictions as f64)?;
+            Ok(obj.into())
+        })
+    }
+
+    // Fetches that may run at once, the rest wait by priority
+    #[wasm_bindgen]
+    pub fn set_max_concurrent_loads(&self, limit: usize) {
+        self.strategy.set_max_concurrent_loads(limit);
+    }
+
+    // Fetch a pack in one request and make every entry a resident asset.
+    // Resolves to the handle of the block holding the pack.
+    #[wasm_bindgen]
+    pub fn load_pack(&mut self, path: String) -> Promise {
+        let mut allocator_clone = self.strategy.clone();
+
+        future_to_promise(async move {
+            match allocator_clone.load_pack(path).await {
+                Ok(handle) => Ok(JsValue::from(handle)),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    // Read only a pack's index, so its entries can be loaded one at a time.
+    // Resolves to the number of entries.
+    #[wasm_bindgen]
+    pub fn open_pack(&mut self, path: String) -> Promise {
+        let mut allocator_clone = self.strategy.clone();
+
+        future_to_promise(async move {
+            match allocator_clone.open_pack(path).await {
+                Ok(entries) => Ok(JsValue::from_f64(entries as f64)),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    // Load one entry of an opened pack with a range request
     #[wasm_bindgen]
-    pub fn load_asset(&mut self, path: String, asset_type: u8) -> Promise {
+    pub fn load_pack_entry(&mut self, path: String) -> Promise {
         let mut allocator_clone = self.strategy.clone();
+
+        future_to_promise(async move {
+            match allocator_clone.load_pack_entry(path).await {
+                Ok(handle) => Ok(JsValue::from(handle)),
+                Err(e) => Err(e.into()),
+            }
+        })
+    }
+
+    #[wasm_bindgen]
+    pub fn evict_pack(&mut self, path: String) -> Result<(), JsValue> {
+        Ok(self.strategy.evict_pack(&path)?)
+    }
+    
+    #[wasm_bindgen]
+    pub fn test_fetch_json(&self, path: String) -> Promise {
+        let allocator_clone = self.strategy.clone();
         
         future_to_promise(async move {
-            match allocator_clone.load_asset(path, asset_type).await {
-                Ok(offset) => Ok(JsValue::from_f64(offset as f64)),
-                Err(e) => Err(e),
+            Ok(allocator_clone.test_fetch_json(&path).await?)
+        })
+    }
+
+    #[wasm_bindgen]
+    pub fn evict_asset(&mut self, path: String) -> Result<(), JsValue> {
+        Ok(self.strategy.evict_asset(&path)?)
+    }
+    
+    // Copy of a loaded asset's bytes, safe to keep
+    #[wasm_bindgen]
+    pub fn get_asset(&self, path: String) -> Result<js_sys::Uint8Array, JsValue> {
+        Ok(self.strategy.get_asset(&path)?)
+    }
+
+    // View of a loaded asset's bytes without copying them, as "u8" (the
+    // default), "u32" or "f32". It reads as empty once memory grows.
+    #[wasm_bindgen]
+    pub fn asset_view(&self, path: String, kind: Option<String>) -> Result<JsValue, JsValue> {
+        let kind = view_kind(kind.as_deref())?;
+        Ok(self.strategy.asset_view(&path, kind)?)
+    }
+
+    // Handle of a loaded asset, valid for as long as the asset stays resident
+    #[wasm_bindgen]
+    pub fn asset_handle(&self, path: String) -> Result<AllocHandle, JsValue> {
+        self.strategy
+            .asset_handle(&path)
+            .ok_or_else(|| WallocError::NotFound { path: path.clone() }.into())
+    }
+
+    // What load_asset recorded about an asset: { type, format, encoding,
+    // compressedSize, rawSize, size, tier },
+    // the SHA-256 as hex once the manifest or a pack verified it,
+    // plus width/height for textures, the vertex and index layout for meshes and
+    // the sample format for audio
+    #[wasm_bindgen]
+    pub fn asset_info(&self, path: String) -> Result<js_sys::Object, JsValue> {
+        let info = self.strategy
+            .asset_info(&path)
+            .ok_or_else(|| WallocError::NotFound { path: path.clone() })?;
+
+        let obj = js_sys::Object::new();
+        let set = |key: &str, value: JsValue| js_sys::Reflect::set(&obj, &JsValue::from_str(key), &value);
+        set("type", JsValue::from_str(info.asset_type.name()))?;
+        set("format", JsValue::from_str(info.format.name()))?;
+        set("encoding", JsValue::from_str(info.encoding.name()))?;
+        set("compressedSize", JsValue::from_f64(info.compressed_size as f64))?;
+        set("rawSize", JsValue::from_f64(info.raw_size as f64))?;
+        set("size", JsValue::from_f64(info.size as f64))?;
+        set("tier", JsValue::from_f64(info.tier.index() as f64))?;
+        if let Some(hash) = info.hash {
+            set("hash", JsValue::from_str(&hash_hex(&hash)))?;
+        }
+
+        match info.format {
+            AssetFormat::Rgba8 { width, height } => {
+                set("width", JsValue::from_f64(width as f64))?;
+                set("height", JsValue::from_f64(height as f64))?;
+            },
+            AssetFormat::Mesh { vertex_count, vertex_stride, index_count, index_size } => {
+                set("vertexCount", JsValue::from_f64(vertex_count as f64))?;
+                set("vertexStride", JsValue::from_f64(vertex_stride as f64))?;
+                set("indexCount", JsValue::from_f64(index_count as f64))?;
+                set("indexSize", JsValue::from_f64(index_size as f64))?;
+            },
+            AssetFormat::Pcm { sample_rate, channels, bits_per_sample, float } => {
+                set("sampleRate", JsValue::from_f64(sample_rate as f64))?;
+                set("channels", JsValue::from_f64(channels as f64))?;
+                set("bitsPerSample", JsValue::from_f64(bits_per_sample as f64))?;
+                set("float", JsValue::from_bool(float))?;
+            },
+            _ => {},
+        }
+
+        Ok(obj)
+    }
+
+    // Bytes loaded assets may take up before the eviction policy frees some
+    #[wasm_bindgen]
+    pub fn set_asset_budget(&mut self, bytes: usize) -> Result<(), JsValue> {
+        Ok(self.strategy.set_asset_budget(bytes)?)
+    }
+
+    // "lru", "lfu" or "pinned"
+    #[wasm_bindgen]
+    pub fn set_eviction_policy(&mut self, name: String) -> Result<(), JsValue> {
+        let policy = eviction_policy(&name)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown eviction policy '{}'", name)))?;
+        self.strategy.set_eviction_policy(policy);
+        Ok(())
+    }
+
+    // Keep an asset resident no matter what the eviction policy picks
+    #[wasm_bindgen]
+    pub fn pin_asset(&mut self, path: String, pinned: bool) -> Result<(), JsValue> {
+        if self.strategy.pin_asset(&path, pinned) {
+            Ok(())
+        } else {
+            Err(WallocError::NotFound { path }.into())
+        }
+    }
+    
+    // Fetch a JSON asset manifest and check every later load against it.
+    // Resolves to the number of assets it lists.
+    #[wasm_bindgen]
+    pub fn load_manifest(&self, path: String) -> Promise {
+        let allocator_clone = self.strategy.clone();
+
+        future_to_promise(async move {
+            match allocator_clone.load_manifest(&path).await {
+                Ok(len) => Ok(JsValue::from_f64(len as f64)),
+                Err(e) => Err(e.into()),
             }
         })
     }
-    
+
+    // Install a manifest from its JSON text, returning the number of assets it lists
+    #[wasm_bindgen]
+    pub fn set_asset_manifest(&self, json: String) -> Result<usize, JsValue> {
+        let manifest = AssetManifest::from_json(&json).map_err(|e| JsValue::from_str(&e))?;
+        let len = manifest.len();
+        self.strategy.set_asset_manifest(manifest);
+        Ok(len)
+    }
+
+    // Path of a resident asset by its SHA-256 in hex, undefined if none matches
+    #[wasm_bindgen]
+    pub fn find_asset_by_hash(&self, hash: String) -> Result<Option<String>, JsValue> {
+        let hash = hash_from_hex(&hash)
+            .ok_or_else(|| JsValue::from_str(&format!("Not a SHA-256 hash: {}", hash)))?;
+        Ok(self.strategy.find_asset_by_hash(&hash))
+    }
+    
+    // View of `length` bytes of WASM memory, no copy. It reads as empty once
+    // memory grows, take a new one after allocating.
+    #[wasm_bindgen]
+    pub fn get_memory_view(&self, offset: usize, length: usize) -> Result<js_sys::Uint8Array, JsValue> {
+        let ptr = self.memory_ptr(offset, length)?;
+        Ok(unsafe { js_sys::Uint8Array::view_mut_raw(ptr, length) })
+    }
+
+    // View of `count` floats at `offset`, which has to be 4-byte aligned
+    #[wasm_bindgen]
+    pub fn get_float32_view(&self, offset: usize, count: usize) -> Result<js_sys::Float32Array, JsValue> {
+        let ptr = self.memory_ptr(offset, count.saturating_mul(4))?;
+        let view = unsafe { view::typed_view(ptr, count * 4, ViewKind::Float32) }.map_err(|e| JsValue::from_str(&e))?;
+        Ok(view.unchecked_into())
+    }
+
+    // View of `count` u32s at `offset`, which has to be 4-byte aligned
+    #[wasm_bindgen]
+    pub fn get_uint32_view(&self, offset: usize, count: usize) -> Result<js_sys::Uint32Array, JsValue> {
+        let ptr = self.memory_ptr(offset, count.saturating_mul(4))?;
+        let view = unsafe { view::typed_view(ptr, count * 4, ViewKind::Uint32) }.map_err(|e| JsValue::from_str(&e))?;
+        Ok(view.unchecked_into())
+    }
+
+    // View of the memory behind a handle as "u8" (the default), "u32" or
+    // "f32". Fails for a stale handle, but does not notice if the handle goes
+    // stale later.
+    #[wasm_bindgen]
+    pub fn view_handle(&self, handle: &AllocHandle, kind: Option<String>) -> Result<JsValue, JsValue> {
+        let kind = view_kind(kind.as_deref())?;
+        let ptr = self.strategy
+            .resolve_handle(handle)
+            .ok_or_else(|| JsValue::from_str(&format!("Stale handle: {}", handle.to_js_string())))?;
+        unsafe { view::typed_view(ptr, handle.size(), kind) }.map_err(|e| JsValue::from_str(&e))
+    }
+
+    // The ArrayBuffer views are taken over. A view whose buffer is no longer
+    // this one was detached by memory growth.
+    #[wasm_bindgen]
+    pub fn memory_buffer(&self) -> JsValue {
+        view::memory_buffer()
+    }
+
+    // Allocate a block for a struct of arrays, in the render tier unless a
+    // tier is given
+    #[wasm_bindgen]
+    pub fn allocate_soa(&mut self, layout: &SoaLayout, tier_number: Option<u8>) -> Result<AllocHandle, JsValue> {
+        let tier = match tier_number {
+            Some(number) => self.strategy
+                .tier(number)
+                .ok_or_else(|| JsValue::from_str(&format!("Unknown tier {}", number)))?,
+            None => self.strategy.render_tier(),
+        };
+        let handle = self.strategy.allocate_handle(layout.size().max(1), tier);
+        handle.ok_or_else(|| JsValue::from_str(&format!(
+            "Failed to allocate {} bytes in tier {:?}", layout.size(), tier
+        )))
+    }
+
+    // One typed view per field of a struct of arrays allocated with
+    // allocate_soa, keyed by field name
+    #[wasm_bindgen]
+    pub fn soa_views(&self, layout: &SoaLayout, handle: &AllocHandle) -> Result<js_sys::Object, JsValue> {
+        if handle.size() < layout.size() {
+            return Err(JsValue::from_str("Handle is smaller than the layout"));
+        }
+        let ptr = self.strategy
+            .resolve_handle(handle)
+            .ok_or_else(|| JsValue::from_str(&format!("Stale handle: {}", handle.to_js_string())))?;
+
+        let views = js_sys::Object::new();
+        for field in layout.fields() {
+            let bytes = field.size(layout.count()).ok_or_else(|| JsValue::from_str("Field is larger than memory"))?;
+            let view = unsafe { view::typed_view(ptr.add(field.offset), bytes, field.kind) }
+                .map_err(|e| JsValue::from_str(&e))?;
+            js_sys::Reflect::set(&views, &JsValue::from_str(&field.name), &view)?;
+        }
+        Ok(views)
+    }
+
+    // Address of `length` bytes at `offset`, if they lie in memory
+    fn memory_ptr(&self, offset: usize, length: usize) -> Result<*mut u8, JsValue> {
+        match offset.checked_add(length) {
+            Some(end) if end <= self.strategy.memory_size() => Ok(self.memory_base.wrapping_add(offset)),
+            _ => Err(JsValue::from_str("Memory access out of bounds")),
+        }
+    }
+    
+    // Allocate like allocate_tiered, tagging the block with `tag`, e.g. a
+    // subsystem name or a call site
+    #[wasm_bindgen]
+    pub fn allocate_tagged(&mut self, size: usize, tier_number: u8, tag: String) -> Result<AllocHandle, JsValue> {
+        let tier = self.strategy
+            .tier(tier_number)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown tier {}", tier_number)))?;
+        let handle = self.strategy.allocate_handle_tagged(size, tier, &tag);
+        handle.ok_or_else(|| JsValue::from_str(&format!(
+            "Failed to allocate {} bytes in tier {:?} for '{}'", size, tier, tag
+        )))
+    }
+
+    // Tag every allocation that names none with `tag`, or stop with undefined
+    #[wasm_bindgen]
+    pub fn set_allocation_tag(&self, tag: Option<String>) {
+        self.strategy.set_allocation_tag(tag.as_deref());
+    }
+
+    #[wasm_bindgen]
+    pub fn tag_of(&self, handle: &AllocHandle) -> Option<String> {
+        self.strategy.tag_of(handle)
+    }
+
+    // [{ tag, tier, liveBytes, liveAllocations, totalBytes, totalAllocations }]
+    // for one tier, or every tier
+    #[wasm_bindgen]
+    pub fn tag_stats(&self, tier_number: Option<u8>) -> js_sys::Array {
+        let tiers: Vec<Tier> = match tier_number {
+            Some(number) => self.strategy.tier(number).into_iter().collect(),
+            None => self.strategy.tiers().collect(),
+        };
+        tiers
+            .into_iter()
+            .flat_map(|tier| self.strategy.tag_stats(tier))
+            .map(|stats| tag_stats_object(&stats))
+            .collect()
+    }
+
+    // The allocation trace as JSON, { capacity, dropped, events: [...] }
+    #[cfg(feature = "trace")]
+    #[wasm_bindgen]
+    pub fn trace_json(&self) -> String {
+        self.strategy.trace_json()
+    }
+
+    // The allocation trace in Chrome Trace Event format, for
+    // chrome://tracing or ui.perfetto.dev
+    #[cfg(feature = "trace")]
+    #[wasm_bindgen]
+    pub fn chrome_trace(&self) -> String {
+        self.strategy.chrome_trace()
+    }
+
+    #[cfg(feature = "trace")]
+    #[wasm_bindgen]
+    pub fn clear_trace(&self) {
+        self.strategy.trace().clear();
+    }
+
+    #[cfg(feature = "trace")]
+    #[wasm_bindgen]
+    pub fn set_tracing(&self, enabled: bool) {
+        self.strategy.trace().set_enabled(enabled);
+    }
+
+    // Events kept before the oldest are dropped, DEFAULT_TRACE_CAPACITY at first
+    #[cfg(feature = "trace")]
+    #[wasm_bindgen]
+    pub fn set_trace_capacity(&self, capacity: usize) {
+        self.strategy.trace().set_capacity(capacity);
+    }
+
+    // Walk every tier and list the blocks whose canaries were overwritten:
+    // [{ tier, tierName, offset, size, tag, side, corruptedBytes, freed }], with
+    // side "front" or "rear" and offset the block's offset from memory base.
+    // Blocks found damaged when they were freed since the last call come first,
+    // with freed set and no offset.
+    #[cfg(feature = "guard")]
+    #[wasm_bindgen]
+    pub fn validate(&self) -> js_sys::Array {
+        self.strategy
+            .validate()
+            .iter()
+            .map(|corruption| {
+                let tier = corruption.handle.tier_kind();
+                let offset = self.strategy
+                    .resolve_handle(&corruption.handle)
+                    .map(|ptr| self.strategy.memory_offset(ptr));
+
+                let obj = js_sys::Object::new();
+                let set = |key: &str, value: JsValue| {
+                    let _ = js_sys::Reflect::set(&obj, &JsValue::from_str(key), &value);
+                };
+                set("tier", JsValue::from_f64(tier.index() as f64));
+                set("tierName", JsValue::from_str(&self.strategy.tier_name(tier).unwrap_or_default()));
+                set("offset", offset.map_or(JsValue::UNDEFINED, |offset| JsValue::from_f64(offset as f64)));
+                set("size", JsValue::from_f64(corruption.handle.size() as f64));
+                set("tag", corruption.tag.as_deref().map_or(JsValue::UNDEFINED, JsValue::from_str));
+                set("side", JsValue::from_str(corruption.side.name()));
+                set("corruptedBytes", JsValue::from_f64(corruption.corrupted as f64));
+                set("freed", JsValue::from_bool(corruption.freed));
+                JsValue::from(obj)
+            })
+            .collect()
+    }
+
+    // Call `callback(event)` whenever a tier's pressure level changes or it
+    // runs out of space, with event { tier, tierName, level, previous, used,
+    // capacity, requested, exhausted }. It runs in the middle of an allocation
+    // and must not call into this Walloc, but it can return an array of asset
+    // paths to evict. Replaces the previous callback, undefined removes it.
+    #[cfg(target_arch = "wasm32")]
     #[wasm_bindgen]
-    pub fn test_fetch_json(&self) -> Promise {
-        let allocator_clone = self.strategy.clone();
-        
-        future_to_promise(async move {
-            allocator_clone.test_fetch_json().await
-        })
+    pub fn on_memory_pressure(&mut self, callback: Option<js_sys::Function>) {
+        if let Some(id) = self.pressure_handler.take() {
+            self.strategy.remove_pressure_handler(id);
+        }
+        let handler = match callback {
+            Some(callback) => JsPressureHandler(callback),
+            None => return,
+        };
+
+        let id = self.strategy.on_pressure(move |allocator, event| {
+            let tier_name = allocator.tier_name(event.tier).unwrap_or_default();
+            let paths = match handler.0.call1(&JsValue::NULL, &pressure_event_object(event, &tier_name)) {
+                Ok(paths) => paths,
+                Err(e) => {
+                    console_log(&format!("Memory pressure callback failed: {:?}", e));
+                    return;
+                },
+            };
+            if !js_sys::Array::is_array(&paths) {
+                return;
+            }
+            for path in js_sys::Array::from(&paths).iter().filter_map(|path| path.as_string()) {
+                if let Err(e) = allocator.evict_asset(&path) {
+                    console_log(&format!("Memory pressure callback could not evict {}: {}", path, e));
+                }
+            }
+        });
+        self.pressure_handler = Some(id);
     }
 
+    // Bytes in use at which a tier turns warning and critical, undefined for never
     #[wasm_bindgen]
-    pub fn evict_asset(&mut self, path: String) -> Result<(), JsValue> {
-        self.strategy.evict_asset(&path)
+    pub fn set_pressure_thresholds(&mut self, tier_number: u8, warning: Option<usize>, critical: Option<usize>) -> Result<(), JsValue> {
+        let tier = self.strategy
+            .tier(tier_number)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown tier {}", tier_number)))?;
+        self.strategy
+            .set_pressure_thresholds(tier, PressureThresholds { warning, critical })
+            .map_err(|e| JsValue::from_str(&e))
     }
-    
+
+    // "normal", "warning" or "critical"
     #[wasm_bindgen]
-    pub fn get_asset(&self, path: String) -> Result<js_sys::Uint8Array, JsValue> {
-        self.strategy.get_asset(&path)
+    pub fn pressure_level(&self, tier_number: u8) -> Result<String, JsValue> {
+        let tier = self.strategy
+            .tier(tier_number)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown tier {}", tier_number)))?;
+        Ok(self.strategy.pressure_level(tier).name().to_string())
     }
-    
-    // Get a direct view into WASM memory as a typed array
+
+    // What an allocation does once its tier is out of space: "reset" the tier
+    // and retry, or "fail"
     #[wasm_bindgen]
-    pub fn get_memory_view(&self, offset: usize, length: usize) -> Result<js_sys::Uint8Array, JsValue> {
-        if offset + length > self.memory_size {
-            return Err(JsValue::from_str("Memory access out of bounds"));
-        }
-        
-        unsafe {
-            let ptr = self.memory_base.add(offset);
-            let mem_slice = std::slice::from_raw_parts(ptr, length);
-            Ok(js_sys::Uint8Array::from(mem_slice))
-        }
+    pub fn set_exhaustion_policy(&self, tier_number: u8, policy: String) -> Result<(), JsValue> {
+        let tier = self.strategy
+            .tier(tier_number)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown tier {}", tier_number)))?;
+        let policy = ExhaustionPolicy::from_name(&policy)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown exhaustion policy '{}'", policy)))?;
+        self.strategy.set_exhaustion_policy(tier, policy);
+        Ok(())
     }
-    
+
     // Allocate memory from a specific tier
     #[wasm_bindgen]
-    pub fn allocate_tiered(&mut self, size: usize, tier_number: u8) -> usize {
-        let tier = match Tier::from_u8(tier_number) {
+    pub fn allocate_tiered(&mut self, size: usize, tier_number: u8) -> Result<AllocHandle, JsValue> {
+        let tier = match self.strategy.tier(tier_number) {
             Some(t) => t,
-            None => Tier::Entity, // Default to Entity tier if invalid
+            None => Tier::new((self.strategy.tier_count() - 1) as u8), // Default to the bottom tier if invalid
         };
 
-        let ptr = self.strategy.allocate(size, tier);
+        let handle = self.strategy.allocate_handle(size, tier);
 
-        self.memory_size = core::arch::wasm32::memory_size(0) * 65536;
-        
-        // Return offset from memory base
-        if ptr.is_null() {
-            0 // Error case, return 0 (null) pointer
-        } else {
-            (ptr as usize) - (self.memory_base as usize)
+        handle.ok_or_else(|| JsValue::from_str(&format!(
+            "Failed to allocate {} bytes in tier {:?}", size, tier
+        )))
+    }
+
+    // Offset from memory base for a handle, fails once its memory was recycled
+    #[wasm_bindgen]
+    pub fn resolve_handle(&self, handle: &AllocHandle) -> Result<usize, JsValue> {
+        match self.strategy.resolve_handle(handle) {
+            Some(ptr) => Ok((ptr as usize) - (self.memory_base as usize)),
+            None => Err(JsValue::from_str(&format!("Stale handle: {}", handle.to_js_string()))),
+        }
+    }
+
+    #[wasm_bindgen]
+    pub fn is_handle_valid(&self, handle: &AllocHandle) -> bool {
+        self.strategy.is_handle_valid(handle)
+    }
+
+    // Copy the bytes behind a handle into JS
+    #[wasm_bindgen]
+    pub fn read_handle(&self, handle: &AllocHandle) -> Result<js_sys::Uint8Array, JsValue> {
+        let offset = self.resolve_handle(handle)?;
+        self.copy_to_js(offset, handle.size())
+    }
+
+    // Copy JS data into the memory behind a handle
+    #[wasm_bindgen]
+    pub fn write_handle(&mut self, handle: &AllocHandle, data: &js_sys::Uint8Array) -> Result<(), JsValue> {
+        if data.length() as usize > handle.size() {
+            return Err(JsValue::from_str("Data is larger than the allocation"));
         }
+        let offset = self.resolve_handle(handle)?;
+        self.copy_from_js(offset, data)
     }
 
     #[wasm_bindgen]
     pub fn fast_compact_tier(&mut self, tier_number: u8, preserve_bytes: usize) -> bool {
-        let tier = match Tier::from_u8(tier_number) {
+        let tier = match self.strategy.tier(tier_number) {
             Some(t) => t,
             None => return false,
         };
@@ -1074,10 +3564,29 @@ impl Walloc {
         self.strategy.fast_compact_tier(tier, preserve_bytes)
     }
     
+    // Push a named rollback point onto a tier
+    #[wasm_bindgen]
+    pub fn push_marker(&mut self, tier_number: u8, name: String) -> Result<Marker, JsValue> {
+        let tier = self.strategy
+            .tier(tier_number)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown tier {}", tier_number)))?;
+
+        self.strategy
+            .push_marker(tier, &name)
+            .ok_or_else(|| JsValue::from_str("Tier is unavailable"))
+    }
+
+    // Free everything allocated in the marker's tier since it was pushed.
+    // Fails unless the marker is the innermost one on its tier.
+    #[wasm_bindgen]
+    pub fn pop_to_marker(&mut self, marker: &Marker) -> Result<(), JsValue> {
+        self.strategy.pop_to_marker(marker).map_err(|e| JsValue::from_str(&e))
+    }
+
     // Reset a specific tier
     #[wasm_bindgen]
     pub fn reset_tier(&mut self, tier_number: u8) -> bool {
-        let tier = match Tier::from_u8(tier_number) {
+        let tier = match self.strategy.tier(tier_number) {
             Some(t) => t,
             None => return false,
         };
@@ -1086,26 +3595,95 @@ impl Walloc {
         true
     }
 
+    // === Frame arena ===
+
+    // Give the renderer a double or triple buffered frame arena with
+    // `frame_size` bytes per frame, replacing the one it had
+    #[wasm_bindgen]
+    pub fn create_frame_arena(&mut self, frame_size: usize, buffer_count: usize) -> Result<(), JsValue> {
+        self.frames = None;
+        let frames = self.strategy.create_frame_arena(frame_size, buffer_count).ok_or_else(|| JsValue::from_str(&format!(
+            "Failed to create a frame arena of {} buffers of {} bytes", buffer_count, frame_size
+        )))?;
+        self.frames = Some(frames);
+        Ok(())
+    }
+
+    fn frame_arena(&mut self) -> Result<&mut FrameArena, JsValue> {
+        self.frames.as_mut().ok_or_else(|| JsValue::from_str("No frame arena, call create_frame_arena first"))
+    }
+
+    // Start a frame, ventilating the buffer of the frame `buffer_count` frames
+    // ago. Returns the new frame's number.
+    #[wasm_bindgen]
+    pub fn begin_frame(&mut self) -> Result<f64, JsValue> {
+        Ok(self.frame_arena()?.begin_frame() as f64)
+    }
+
+    #[wasm_bindgen]
+    pub fn end_frame(&mut self) -> Result<(), JsValue> {
+        self.frame_arena()?.end_frame();
+        Ok(())
+    }
+
+    // Scratch memory for the current frame
+    #[wasm_bindgen]
+    pub fn frame_allocate(&mut self, size: usize) -> Result<FrameAllocation, JsValue> {
+        self.frame_arena()?.allocate(size).ok_or_else(|| JsValue::from_str(&format!(
+            "Failed to allocate {} bytes of frame memory, outside a frame or the frame buffer is full", size
+        )))
+    }
+
+    // View of a frame allocation, no copy. Fails once its buffer was ventilated.
+    #[wasm_bindgen]
+    pub fn frame_view(&mut self, allocation: &FrameAllocation) -> Result<js_sys::Uint8Array, JsValue> {
+        let bytes = self.frame_arena()?
+            .get_mut(allocation)
+            .ok_or_else(|| JsValue::from_str(&format!("Stale frame allocation from frame {}", allocation.frame())))?;
+        Ok(unsafe { js_sys::Uint8Array::view_mut_raw(bytes.as_mut_ptr(), bytes.len()) })
+    }
+
+    // { frame, bufferCount, frameUsage, frameCapacity, peakFrameUsage, staleReads },
+    // undefined without a frame arena
+    #[wasm_bindgen]
+    pub fn frame_stats(&self) -> JsValue {
+        let frames = match &self.frames {
+            Some(frames) => frames,
+            None => return JsValue::UNDEFINED,
+        };
+        let obj = js_sys::Object::new();
+        let set = |key: &str, value: f64| {
+            let _ = js_sys::Reflect::set(&obj, &JsValue::from_str(key), &JsValue::from_f64(value));
+        };
+        set("frame", frames.current_frame() as f64);
+        set("bufferCount", frames.buffer_count() as f64);
+        set("frameUsage", frames.frame_usage() as f64);
+        set("frameCapacity", frames.frame_capacity() as f64);
+        set("peakFrameUsage", frames.peak_frame_usage() as f64);
+        set("staleReads", frames.stale_reads() as f64);
+        obj.into()
+    }
+
     // Copy data from JS to WASM memory
     #[wasm_bindgen]
     pub fn copy_from_js(&mut self, offset: usize, data: &js_sys::Uint8Array) -> Result<(), JsValue> {
         let data_len = data.length() as usize;
-        if offset + data_len > self.memory_size {
-            return Err(JsValue::from_str("Memory access out of bounds"));
-        }
-        
+        let dest_ptr = self.memory_ptr(offset, data_len)?;
         unsafe {
-            let dest_ptr = self.memory_base.add(offset);
             let dest_slice = std::slice::from_raw_parts_mut(dest_ptr, data_len);
             data.copy_to(dest_slice);
             Ok(())
         }
     }
     
-    // Copy data from WASM memory to JS
+    // Copy data from WASM memory to JS, unaffected by later memory growth
     #[wasm_bindgen]
     pub fn copy_to_js(&self, offset: usize, length: usize) -> Result<js_sys::Uint8Array, JsValue> {
-        self.get_memory_view(offset, length)
+        let ptr = self.memory_ptr(offset, length)?;
+        unsafe {
+            let mem_slice = std::slice::from_raw_parts(ptr, length);
+            Ok(js_sys::Uint8Array::from(mem_slice))
+        }
     }
     
     // Memory statistics
@@ -1113,9 +3691,9 @@ impl Walloc {
     pub fn memory_stats(&self) -> js_sys::Object {
         let obj = js_sys::Object::new();
         
-        // Get current memory size from WebAssembly directly
-        let current_pages = core::arch::wasm32::memory_size(0);
-        let current_size = current_pages * 65536;
+        // Get current memory size from the memory source
+        let current_pages = self.strategy.memory_pages();
+        let current_size = current_pages * PAGE_SIZE;
         
         // Track total in-use memory
         let mut total_in_use = 0;
@@ -1123,63 +3701,111 @@ impl Walloc {
         // Add tier information
         let tiers = js_sys::Array::new();
         
-        for tier_num in 0..3 {
-            if let Some(tier) = Tier::from_u8(tier_num) {
-                let (used, capacity, high_water, total_allocated) = self.strategy.tier_stats(tier);
-                let tier_obj = js_sys::Object::new();
-                
-                // Add current usage to total
-                total_in_use += used;
-                
-                js_sys::Reflect::set(
-                    &tier_obj,
-                    &JsValue::from_str("name"),
-                    &JsValue::from_str(match tier {
-                        Tier::Render => "render",
-                        Tier::Scene => "scene",
-                        Tier::Entity => "entity",
-                    })
-                ).unwrap();
-                
-                js_sys::Reflect::set(
-                    &tier_obj,
-                    &JsValue::from_str("used"),
-                    &JsValue::from_f64(used as f64)
-                ).unwrap();
-                
-                js_sys::Reflect::set(
-                    &tier_obj,
-                    &JsValue::from_str("capacity"),
-                    &JsValue::from_f64(capacity as f64)
-                ).unwrap();
-                
-                js_sys::Reflect::set(
-                    &tier_obj,
-                    &JsValue::from_str("highWaterMark"),
-                    &JsValue::from_f64(high_water as f64)
-                ).unwrap();
-                
-                js_sys::Reflect::set(
-                    &tier_obj,
-                    &JsValue::from_str("totalAllocated"),
-                    &JsValue::from_f64(total_allocated as f64)
-                ).unwrap();
-                
-                // Calculate memory savings
-                let saved = if total_allocated > used {
-                    total_allocated - used
-                } else {
-                    0
-                };
-                
-                js_sys::Reflect::set(
-                    &tier_obj,
-                    &JsValue::from_str("memorySaved"),
-                    &JsValue::from_f64(saved as f64)
-                ).unwrap();
-                
-                tiers.push(&tier_obj);
-            }
+        for tier in self.strategy.tiers() {
+            let stats = self.strategy.tier_stats(tier);
+            let (used, capacity, high_water, total_allocated) =
+                (stats.used, stats.capacity, stats.high_water_mark, stats.total_allocated);
+            let tier_obj = js_sys::Object::new();
+            
+            // Add current usage to total
+            total_in_use += used;
+            
+            js_sys::Reflect::set(
+                &tier_obj,
+                &JsValue::from_str("name"),
+                &JsValue::from_str(&self.strategy.tier_name(tier).unwrap_or_default())
+            ).unwrap();
+            
+            js_sys::Reflect::set(
+                &tier_obj,
+                &JsValue::from_str("used"),
+                &JsValue::from_f64(used as f64)
+            ).unwrap();
+            
+            js_sys::Reflect::set(
+                &tier_obj,
+                &JsValue::from_str("capacity"),
+                &JsValue::from_f64(capacity as f64)
+            ).unwrap();
+            
+            js_sys::Reflect::set(
+                &tier_obj,
+                &JsValue::from_str("highWaterMark"),
+                &JsValue::from_f64(high_water as f64)
+            ).unwrap();
+            
+            js_sys::Reflect::set(
+                &tier_obj,
+                &JsValue::from_str("totalAllocated"),
+                &JsValue::from_f64(total_allocated as f64)
+            ).unwrap();
+            
+            js_sys::Reflect::set(
+                &tier_obj,
+                &JsValue::from_str("segments"),
+                &JsValue::from_f64(self.strategy.tier_segments(tier) as f64)
+            ).unwrap();
+            
+            js_sys::Reflect::set(
+                &tier_obj,
+                &JsValue::from_str("strategy"),
+                &JsValue::from_str(stats.strategy.name())
+            ).unwrap();
+            
+            js_sys::Reflect::set(
+                &tier_obj,
+                &JsValue::from_str("freeListBytes"),
+                &JsValue::from_f64(stats.free_bytes as f64)
+            ).unwrap();
+            
+            js_sys::Reflect::set(
+                &tier_obj,
+                &JsValue::from_str("largestFreeBlock"),
+                &JsValue::from_f64(stats.largest_free_block as f64)
+            ).unwrap();
+            
+            js_sys::Reflect::set(
+                &tier_obj,
+                &JsValue::from_str("fragmentation"),
+                &JsValue::from_f64(stats.fragmentation as f64)
+            ).unwrap();
+            
+            js_sys::Reflect::set(
+                &tier_obj,
+                &JsValue::from_str("markers"),
+                &JsValue::from_f64(self.strategy.marker_depth(tier) as f64)
+            ).unwrap();
+            
+            js_sys::Reflect::set(
+                &tier_obj,
+                &JsValue::from_str("pressure"),
+                &JsValue::from_str(self.strategy.pressure_level(tier).name())
+            ).unwrap();
+            
+            js_sys::Reflect::set(
+                &tier_obj,
+                &JsValue::from_str("exhaustion"),
+                &JsValue::from_str(self.strategy.exhaustion_policy(tier).name())
+            ).unwrap();
+            
+            // Calculate memory savings
+            let saved = total_allocated.saturating_sub(used);
+            
+            js_sys::Reflect::set(
+                &tier_obj,
+                &JsValue::from_str("memorySaved"),
+                &JsValue::from_f64(saved as f64)
+            ).unwrap();
+
+            // Who holds the tier's memory, by allocation tag
+            let tags: js_sys::Array = self.strategy.tag_stats(tier).iter().map(tag_stats_object).collect();
+            js_sys::Reflect::set(
+                &tier_obj,
+                &JsValue::from_str("tags"),
+                &tags
+            ).unwrap();
+            
+            tiers.push(&tier_obj);
         }
         
         js_sys::Reflect::set(
@@ -1188,6 +3814,91 @@ impl Walloc {
             &tiers
         ).unwrap();
         
+        // Object pools, with their occupancy
+        let pools = js_sys::Array::new();
+        for stats in self.strategy.pool_stats() {
+            let pool_obj = js_sys::Object::new();
+            
+            js_sys::Reflect::set(
+                &pool_obj,
+                &JsValue::from_str("type"),
+                &JsValue::from_str(stats.name)
+            ).unwrap();
+            
+            js_sys::Reflect::set(
+                &pool_obj,
+                &JsValue::from_str("tier"),
+                &JsValue::from_f64(stats.tier.index() as f64)
+            ).unwrap();
+            
+            js_sys::Reflect::set(
+                &pool_obj,
+                &JsValue::from_str("capacity"),
+                &JsValue::from_f64(stats.capacity as f64)
+            ).unwrap();
+            
+            js_sys::Reflect::set(
+                &pool_obj,
+                &JsValue::from_str("live"),
+                &JsValue::from_f64(stats.live as f64)
+            ).unwrap();
+            
+            js_sys::Reflect::set(
+                &pool_obj,
+                &JsValue::from_str("occupancy"),
+                &JsValue::from_f64(stats.occupancy() as f64)
+            ).unwrap();
+            
+            js_sys::Reflect::set(
+                &pool_obj,
+                &JsValue::from_str("slotSize"),
+                &JsValue::from_f64(stats.slot_size as f64)
+            ).unwrap();
+            
+            pools.push(&pool_obj);
+        }
+        
+        js_sys::Reflect::set(
+            &obj,
+            &JsValue::from_str("pools"),
+            &pools
+        ).unwrap();
+        
+        // Asset cache
+        let cache = self.strategy.asset_cache_stats();
+        let cache_obj = js_sys::Object::new();
+        for (key, value) in [
+            ("budget", cache.budget as f64),
+            ("used", cache.used as f64),
+            ("assets", cache.assets as f64),
+            ("pinned", cache.pinned as f64),
+            ("packs", cache.packs as f64),
+            ("compressedAssets", cache.compressed as f64),
+            ("compressedBytes", cache.compressed_bytes as f64),
+            ("uncompressedBytes", cache.uncompressed_bytes as f64),
+            ("hits", cache.hits as f64),
+            ("misses", cache.misses as f64),
+            ("evictions", cache.evictions as f64),
+        ] {
+            js_sys::Reflect::set(
+                &cache_obj,
+                &JsValue::from_str(key),
+                &JsValue::from_f64(value)
+            ).unwrap();
+        }
+        
+        js_sys::Reflect::set(
+            &cache_obj,
+            &JsValue::from_str("policy"),
+            &JsValue::from_str(self.strategy.eviction_policy())
+        ).unwrap();
+        
+        js_sys::Reflect::set(
+            &obj,
+            &JsValue::from_str("assetCache"),
+            &cache_obj
+        ).unwrap();
+        
         // Set the total size to the in-use memory (not just raw WASM memory size)
         js_sys::Reflect::set(
             &obj, 
@@ -1224,4 +3935,463 @@ impl Walloc {
         
         obj
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use futures_executor::block_on;
+
+    use super::*;
+
+    fn allocator() -> TieredAllocator {
+        TieredAllocator::new(Box::new(NativeMemory::new(256)), INITIAL_HEAP_PAGES)
+    }
+
+    #[test]
+    fn freed_block_invalidates_its_handle() {
+        let mut allocator = allocator();
+        let (mut owner, _) = allocator.allocate_with_owner(256, Tier::ENTITY).unwrap();
+        let freed = owner.handle(0).unwrap();
+        assert!(owner.free(0));
+
+        // The same spot is handed out again, in the same generation
+        let reused = allocator.allocate_handle(256, Tier::ENTITY).unwrap();
+        assert_eq!((reused.segment(), reused.offset()), (freed.segment(), freed.offset()));
+        assert_eq!(reused.generation(), freed.generation());
+
+        assert!(!allocator.is_handle_valid(&freed));
+        assert!(allocator.resolve_handle(&freed).is_none());
+        assert!(allocator.resolve_handle(&reused).is_some());
+    }
+
+    #[test]
+    fn owner_keeps_its_memory_mapped_after_the_allocator_is_dropped() {
+        let mut allocator = allocator();
+        let (mut owner, _) = allocator.allocate_with_owner(64, Tier::ENTITY).unwrap();
+        owner.with_slice_mut(0, |bytes| bytes.fill(7)).unwrap();
+        drop(allocator);
+
+        assert_eq!(owner.with_slice(0, |bytes| bytes.iter().all(|&byte| byte == 7)), Some(true));
+        assert!(owner.free(0));
+    }
+
+    #[test]
+    fn arena_rejects_double_free() {
+        let allocator = allocator();
+        let mut arena = allocator.lock_arena(Tier::SCENE).unwrap();
+        let (ptr, block_size) = arena.allocate(64).unwrap();
+        assert!(arena.free(ptr, block_size));
+        assert!(!arena.free(ptr, block_size));
+        assert_eq!(arena.usage(), 0);
+    }
+
+    #[test]
+    fn evicted_asset_handle_stays_invalid_after_reuse() {
+        let mut allocator = allocator();
+        allocator.set_asset_source(Box::new(InMemorySource::new().with_asset("notes.txt", vec![b'a'; 4096])));
+        let handle = block_on(allocator.load_asset("notes.txt".to_string(), AssetType::Text as u8)).unwrap();
+        allocator.evict_asset("notes.txt").unwrap();
+
+        let reused = allocator.allocate_handle(4096, handle.tier_kind()).unwrap();
+        assert_eq!((reused.segment(), reused.offset()), (handle.segment(), handle.offset()));
+        assert!(allocator.resolve_handle(&handle).is_none());
+        assert!(allocator.asset_handle("notes.txt").is_none());
+    }
+
+    #[test]
+    fn evicted_pack_invalidates_its_entries() {
+        let pack = PackBuilder::new()
+            .with_entry("a.txt", AssetType::Text, vec![b'a'; 1000])
+            .with_entry("b.txt", AssetType::Text, vec![b'b'; 1000])
+            .build()
+            .unwrap();
+        let mut allocator = allocator();
+        allocator.set_asset_source(Box::new(InMemorySource::new().with_asset("level.pack", pack)));
+        let handle = block_on(allocator.load_pack("level.pack".to_string())).unwrap();
+        let entry = allocator.asset_handle("b.txt").unwrap();
+        assert!(allocator.is_handle_valid(&entry));
+
+        allocator.evict_pack("level.pack").unwrap();
+        let reused = allocator.allocate_handle(handle.size(), handle.tier_kind()).unwrap();
+        assert_eq!((reused.segment(), reused.offset()), (handle.segment(), handle.offset()));
+        assert!(allocator.resolve_handle(&handle).is_none());
+        assert!(allocator.resolve_handle(&entry).is_none());
+    }
+
+    // Counts the fetches that reach an in-memory source
+    struct CountingSource {
+        inner: InMemorySource,
+        fetches: Arc<AtomicUsize>,
+    }
+
+    impl AssetSource for CountingSource {
+        fn describe(&self) -> String {
+            "counting".to_string()
+        }
+
+        fn fetch<'a>(&'a self, path: &'a str) -> FetchFuture<'a> {
+            self.fetches.fetch_add(1, Ordering::Relaxed);
+            self.inner.fetch(path)
+        }
+    }
+
+    fn asset_bytes(allocator: &TieredAllocator, handle: &AllocHandle) -> Vec<u8> {
+        let ptr = allocator.resolve_handle(handle).unwrap();
+        unsafe { std::slice::from_raw_parts(ptr, handle.size()) }.to_vec()
+    }
+
+    #[test]
+    fn load_asset_falls_back_to_the_next_source() {
+        let mut allocator = allocator();
+        allocator.set_asset_source(Box::new(
+            FallbackSource::new()
+                .with_source(InMemorySource::new().with_asset("a.txt", b"local".to_vec()))
+                .with_source(InMemorySource::new().with_asset("a.txt", b"remote".to_vec()).with_asset("b.txt", b"remote only".to_vec())),
+        ));
+
+        let a = block_on(allocator.load_asset("a.txt".to_string(), AssetType::Text as u8)).unwrap();
+        let b = block_on(allocator.load_asset("b.txt".to_string(), AssetType::Text as u8)).unwrap();
+        assert_eq!(asset_bytes(&allocator, &a), b"local");
+        assert_eq!(asset_bytes(&allocator, &b), b"remote only");
+
+        let missing = block_on(allocator.load_asset("c.txt".to_string(), AssetType::Text as u8));
+        assert!(matches!(missing, Err(WallocError::NotFound { .. })));
+        assert!(block_on(allocator.load_asset("a.txt".to_string(), 42)).is_err());
+    }
+
+    #[test]
+    fn batch_loads_fetch_a_path_once() {
+        let allocator = allocator();
+        let fetches = Arc::new(AtomicUsize::new(0));
+        allocator.set_asset_source(Box::new(CountingSource {
+            inner: InMemorySource::new().with_asset("a.txt", b"aaaa".to_vec()).with_asset("b.txt", b"bbbb".to_vec()),
+            fetches: Arc::clone(&fetches),
+        }));
+
+        let requests = vec![
+            ("a.txt".to_string(), AssetType::Text as u8),
+            ("b.txt".to_string(), AssetType::Text as u8),
+            ("a.txt".to_string(), AssetType::Text as u8),
+            ("missing.txt".to_string(), AssetType::Text as u8),
+        ];
+        let results = block_on(allocator.load_assets(requests, 2));
+        assert_eq!(results.len(), 4);
+        assert_eq!(results[0].as_ref().unwrap(), results[2].as_ref().unwrap());
+        assert_eq!(asset_bytes(&allocator, results[1].as_ref().unwrap()), b"bbbb");
+        assert!(matches!(results[3], Err(WallocError::NotFound { .. })));
+        assert_eq!(fetches.load(Ordering::Relaxed), 3);
+
+        // Resident now, so loading it again is a cache hit
+        let hits = allocator.asset_cache_stats().hits;
+        let mut loader = allocator.clone();
+        block_on(loader.load_asset("a.txt".to_string(), AssetType::Text as u8)).unwrap();
+        assert_eq!(allocator.asset_cache_stats().hits, hits + 1);
+        assert_eq!(fetches.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    fn load_reports_progress_up_to_the_total() {
+        let mut allocator = allocator();
+        allocator.set_asset_source(Box::new(InMemorySource::new().with_asset("a.bin", vec![7u8; 5000])));
+
+        let mut events = Vec::new();
+        let handle = block_on(allocator.load_asset_with_progress("a.bin".to_string(), AssetType::Image as u8, |progress| events.push(progress))).unwrap();
+        assert!(!events.is_empty());
+        assert!(events.windows(2).all(|pair| pair[0].received <= pair[1].received));
+        assert_eq!(events.last(), Some(&LoadProgress { received: 5000, total: Some(5000) }));
+        assert_eq!(asset_bytes(&allocator, &handle), vec![7u8; 5000]);
+    }
+
+    // Streams an in-memory asset in small chunks, claiming it is `claimed` bytes long
+    struct ClaimingSource {
+        bytes: Vec<u8>,
+        claimed: u64,
+    }
+
+    struct ClaimingStream {
+        chunks: Vec<Vec<u8>>,
+        claimed: u64,
+    }
+
+    impl AssetStream for ClaimingStream {
+        fn size_hint(&self) -> Option<u64> {
+            Some(self.claimed)
+        }
+
+        fn next_chunk(&mut self) -> ChunkFuture<'_> {
+            let chunk = (!self.chunks.is_empty()).then(|| self.chunks.remove(0));
+            Box::pin(std::future::ready(Ok(chunk)))
+        }
+    }
+
+    impl AssetSource for ClaimingSource {
+        fn describe(&self) -> String {
+            "claiming".to_string()
+        }
+
+        fn fetch<'a>(&'a self, _path: &'a str) -> FetchFuture<'a> {
+            Box::pin(std::future::ready(Ok(self.bytes.clone())))
+        }
+
+        fn fetch_stream<'a>(&'a self, _path: &'a str) -> StreamFuture<'a> {
+            let stream = ClaimingStream {
+                chunks: self.bytes.chunks(1000).map(<[u8]>::to_vec).collect(),
+                claimed: self.claimed,
+            };
+            Box::pin(std::future::ready(Ok(Box::new(stream) as Box<dyn AssetStream>)))
+        }
+    }
+
+    #[test]
+    fn content_length_does_not_size_staging_on_its_own() {
+        let bytes: Vec<u8> = (0..5000).map(|byte| byte as u8).collect();
+        let mut allocator = allocator();
+        allocator.set_asset_source(Box::new(ClaimingSource { bytes: bytes.clone(), claimed: 1 << 40 }));
+
+        let handle = block_on(allocator.load_asset("a.bin".to_string(), AssetType::Image as u8)).unwrap();
+        assert_eq!(asset_bytes(&allocator, &handle), bytes);
+        assert!(allocator.tier_stats(allocator.asset_tier()).total_allocated <= 2 * STREAM_RESERVE_LIMIT);
+    }
+
+    #[test]
+    fn listed_assets_stage_their_manifest_size() {
+        let bytes: Vec<u8> = (0..5000).map(|byte| byte as u8).collect();
+        let mut allocator = allocator();
+        allocator.set_asset_source(Box::new(ClaimingSource { bytes: bytes.clone(), claimed: 1 << 40 }));
+        allocator.set_asset_manifest(AssetManifest::new().with_asset("a.bin", &bytes));
+
+        let handle = block_on(allocator.load_asset("a.bin".to_string(), AssetType::Image as u8)).unwrap();
+        assert_eq!(asset_bytes(&allocator, &handle), bytes);
+        assert!(allocator.tier_stats(allocator.asset_tier()).total_allocated < STREAM_CHUNK_SIZE);
+    }
+
+    fn text(lines: usize) -> Vec<u8> {
+        (0..lines).map(|line| format!("line {}\n", line)).collect::<String>().into_bytes()
+    }
+
+    fn gzip(bytes: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn brotli(bytes: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+        let mut compressed = Vec::new();
+        let mut encoder = ::brotli::CompressorWriter::new(&mut compressed, 4096, 5, 22);
+        encoder.write_all(bytes).unwrap();
+        drop(encoder);
+        compressed
+    }
+
+    fn zstd(bytes: &[u8]) -> Vec<u8> {
+        ruzstd::encoding::compress_to_vec(bytes, ruzstd::encoding::CompressionLevel::Fastest)
+    }
+
+    // Load `compressed` as `path` and check it decompresses to `expected`
+    fn assert_round_trip(path: &str, compressed: Vec<u8>, expected: &[u8]) {
+        let mut allocator = allocator();
+        allocator.set_asset_source(Box::new(InMemorySource::new().with_asset(path, compressed)));
+        let handle = block_on(allocator.load_asset(path.to_string(), AssetType::Text as u8)).unwrap();
+        assert_eq!(asset_bytes(&allocator, &handle), expected, "{}", path);
+    }
+
+    #[test]
+    fn compressed_assets_round_trip() {
+        let expected = text(2000);
+        assert_round_trip("a.txt.gz", gzip(&expected), &expected);
+        assert_round_trip("a.txt.zst", zstd(&expected), &expected);
+        assert_round_trip("a.txt.br", brotli(&expected), &expected);
+    }
+
+    #[test]
+    fn packs_keep_only_uncompressed_entries_in_their_block() {
+        let expected = text(2000);
+        let pack = PackBuilder::new()
+            .with_entry("a.txt", AssetType::Text, b"packed".to_vec())
+            .with_entry("b.txt.gz", AssetType::Text, gzip(&expected))
+            .with_entry("c.txt", AssetType::Text, b"also packed".to_vec())
+            .build()
+            .unwrap();
+        let mut allocator = allocator();
+        allocator.set_asset_source(Box::new(InMemorySource::new().with_asset("level.pack", pack)));
+        let handle = block_on(allocator.load_pack("level.pack".to_string())).unwrap();
+
+        assert_eq!(handle.size(), PACK_ALIGNMENT + b"also packed".len());
+        assert_eq!(asset_bytes(&allocator, &allocator.asset_handle("a.txt").unwrap()), b"packed");
+        assert_eq!(asset_bytes(&allocator, &allocator.asset_handle("c.txt").unwrap()), b"also packed");
+        assert_eq!(asset_bytes(&allocator, &allocator.asset_handle("b.txt.gz").unwrap()), expected);
+    }
+
+    #[test]
+    fn decompression_grows_past_a_short_size_hint() {
+        // The gzip trailer only knows the size of the last member
+        let expected = text(3000);
+        let (first, second) = expected.split_at(expected.len() - 10);
+        let mut members = gzip(first);
+        members.extend(gzip(second));
+        assert_eq!(AssetEncoding::Gzip.size_hint(&members), 10);
+        assert_round_trip("members.txt.gz", members, &expected);
+
+        // Brotli guesses a few times the compressed size, far short of a run of one byte
+        let expected = vec![b'a'; 100_000];
+        let compressed = brotli(&expected);
+        assert!(AssetEncoding::Brotli.size_hint(&compressed) < expected.len());
+        assert_round_trip("run.txt.br", compressed, &expected);
+    }
+
+    #[test]
+    fn corrupt_compressed_asset_fails_to_decode() {
+        let mut allocator = allocator();
+        let mut compressed = gzip(&text(100));
+        // Past the header, the trailer still holds the right size
+        compressed[10..20].fill(0xFF);
+        allocator.set_asset_source(Box::new(InMemorySource::new().with_asset("broken.txt.gz", compressed)));
+        let result = block_on(allocator.load_asset("broken.txt.gz".to_string(), AssetType::Text as u8));
+        assert!(matches!(result, Err(WallocError::Decode { .. })));
+        assert!(allocator.asset_handle("broken.txt.gz").is_none());
+    }
+
+    #[test]
+    fn default_layout_splits_memory_50_30_20() {
+        let allocator = allocator();
+        let total = INITIAL_HEAP_PAGES * PAGE_SIZE;
+        let tiers = [(Tier::RENDER, "render", 50, 128), (Tier::SCENE, "scene", 30, 64), (Tier::ENTITY, "entity", 20, 8)];
+        assert_eq!(allocator.tier_count(), tiers.len());
+
+        let mut end = 0;
+        for (tier, name, percent, alignment) in tiers {
+            let arena = allocator.lock_arena(tier).unwrap();
+            let share = total * percent / 100;
+            assert_eq!(arena.name(), name);
+            assert_eq!(arena.alignment(), alignment);
+            assert!(arena.capacity() <= share && share - arena.capacity() < alignment, "{} has {} bytes", name, arena.capacity());
+            assert_eq!(arena.capacity() % alignment, 0);
+            end += arena.capacity();
+        }
+        assert!(end <= allocator.memory_size());
+    }
+
+    #[test]
+    fn allocations_stay_in_their_tier() {
+        let mut allocator = allocator();
+        for tier in [Tier::RENDER, Tier::SCENE, Tier::ENTITY] {
+            let ptr = allocator.allocate(100, tier);
+            assert!(!ptr.is_null());
+            for other in [Tier::RENDER, Tier::SCENE, Tier::ENTITY] {
+                assert_eq!(allocator.lock_arena(other).unwrap().contains(ptr), other == tier);
+            }
+            assert_eq!(ptr as usize % allocator.lock_arena(tier).unwrap().alignment(), 0);
+        }
+    }
+
+    #[test]
+    fn handles_resolve_to_their_allocation() {
+        let mut allocator = allocator();
+        let a = allocator.allocate_handle(64, Tier::SCENE).unwrap();
+        let b = allocator.allocate_handle(64, Tier::SCENE).unwrap();
+        let (pa, pb) = (allocator.resolve_handle(&a).unwrap(), allocator.resolve_handle(&b).unwrap());
+        assert_ne!(pa, pb);
+        assert_eq!(a.size(), 64);
+        assert_eq!(a.tier_kind(), Tier::SCENE);
+
+        unsafe { std::ptr::write_bytes(pa, 7, 64) };
+        assert_eq!(unsafe { *allocator.resolve_handle(&a).unwrap().add(63) }, 7);
+
+        // A handle names its tier, another tier never resolves it
+        let forged = AllocHandle::new(Tier::ENTITY, a.segment(), a.offset(), a.size(), a.generation(), a.epoch());
+        assert!(allocator.resolve_handle(&forged).is_none());
+    }
+
+    #[test]
+    fn reset_tier_recycles_everything_in_it() {
+        let mut allocator = allocator();
+        let entity = allocator.allocate_handle(64, Tier::ENTITY).unwrap();
+        let scene = allocator.allocate_handle(64, Tier::SCENE).unwrap();
+        allocator.reset_tier(Tier::ENTITY);
+
+        assert!(!allocator.is_handle_valid(&entity));
+        assert!(allocator.is_handle_valid(&scene));
+        assert_eq!(allocator.tier_stats(Tier::ENTITY).used, 0);
+
+        let reused = allocator.allocate_handle(64, Tier::ENTITY).unwrap();
+        assert_eq!(reused.offset(), entity.offset());
+        assert_ne!(reused.generation(), entity.generation());
+    }
+
+    #[test]
+    fn fast_compact_keeps_the_preserved_bytes() {
+        let mut allocator = allocator();
+        let kept = allocator.allocate_handle(64, Tier::ENTITY).unwrap();
+        let preserved = allocator.tier_stats(Tier::ENTITY).used;
+        let recycled = allocator.allocate_handle(64, Tier::ENTITY).unwrap();
+        assert!(allocator.fast_compact_tier(Tier::ENTITY, preserved));
+
+        assert!(allocator.is_handle_valid(&kept));
+        assert!(!allocator.is_handle_valid(&recycled));
+        assert_eq!(allocator.tier_stats(Tier::ENTITY).used, preserved);
+
+        // A later compaction that preserves less takes the older handle too
+        assert!(allocator.fast_compact_tier(Tier::ENTITY, 0));
+        assert!(!allocator.is_handle_valid(&kept));
+    }
+
+    #[test]
+    fn repeated_pops_keep_older_blocks_alive() {
+        let mut allocator = allocator();
+        let (owner, _) = allocator.allocate_with_owner(64, Tier::ENTITY).unwrap();
+        let handle = owner.handle(0).unwrap();
+
+        for name in ["first", "second"] {
+            let marker = allocator.push_marker(Tier::ENTITY, name).unwrap();
+            allocator.allocate_handle(64, Tier::ENTITY).unwrap();
+            allocator.pop_to_marker(&marker).unwrap();
+        }
+        assert!(allocator.is_handle_valid(&handle));
+
+        // Dropping the owner still frees its block
+        drop(owner);
+        assert_eq!(allocator.tier_stats(Tier::ENTITY).used, 0);
+    }
+
+    #[test]
+    fn growing_compactions_keep_older_blocks_alive() {
+        let mut allocator = allocator();
+        let kept = allocator.allocate_handle(64, Tier::ENTITY).unwrap();
+        let preserved = allocator.tier_stats(Tier::ENTITY).used;
+        allocator.allocate_handle(64, Tier::ENTITY).unwrap();
+        assert!(allocator.fast_compact_tier(Tier::ENTITY, preserved));
+
+        let later = allocator.allocate_handle(64, Tier::ENTITY).unwrap();
+        let preserved = allocator.tier_stats(Tier::ENTITY).used;
+        assert!(allocator.fast_compact_tier(Tier::ENTITY, preserved));
+
+        assert!(allocator.is_handle_valid(&kept));
+        assert!(allocator.is_handle_valid(&later));
+    }
+
+    #[test]
+    fn oversized_allocations_fail_cleanly() {
+        let mut allocator = allocator();
+        let kept = allocator.allocate_handle(64, Tier::ENTITY).unwrap();
+        for size in [usize::MAX, usize::MAX - 3, usize::MAX / 2 + 1] {
+            assert!(allocator.allocate(size, Tier::ENTITY).is_null());
+            assert!(allocator.allocate_handle(size, Tier::ENTITY).is_none());
+        }
+        assert!(allocator.is_handle_valid(&kept));
+    }
+
+    #[test]
+    fn fast_compact_past_the_end_reserves_and_grows() {
+        let mut allocator = allocator();
+        assert!(allocator.fast_compact_tier(Tier::ENTITY, 4096));
+        assert_eq!(allocator.tier_stats(Tier::ENTITY).used, 4096);
+        assert_eq!(allocator.lock_arena(Tier::ENTITY).unwrap().segment_count(), 1);
+
+        // More than the tier holds adds a segment behind the existing ones
+        let capacity = allocator.tier_stats(Tier::ENTITY).capacity;
+        assert!(allocator.fast_compact_tier(Tier::ENTITY, capacity + 100));
+        assert_eq!(allocator.lock_arena(Tier::ENTITY).unwrap().segment_count(), 2);
+        assert!(allocator.tier_stats(Tier::ENTITY).capacity >= capacity + 100);
+    }
+}
@@ -0,0 +1,299 @@
+// Asset manifests: the size and SHA-256 of every asset as it was shipped.
+//
+// With a manifest installed, every asset load_asset or load_pack fetches is
+// checked against it before it is placed, and a mismatch fails the load with
+// an IntegrityError. Assets the manifest does not list load unverified,
+// unless the manifest is strict. The JSON form is
+//
+//   { "strict": false, "assets": { "<path>": { "size": 1234, "sha256": "<hex>" } } }
+//
+// An asset can add "encoding": "gzip", "zstd" or "br" if it is shipped
+// compressed. Size and hash are always those of the bytes as shipped.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use wasm_bindgen::prelude::*;
+
+use super::{AssetEncoding, content_hash, hash_from_hex, hash_hex};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ManifestEntry {
+    pub size: usize,
+    pub hash: [u8; 32],
+    pub encoding: Option<AssetEncoding>,  // None leaves it to the extension or the server
+}
+
+impl ManifestEntry {
+    pub fn new(size: usize, hash: [u8; 32]) -> Self {
+        ManifestEntry { size, hash, encoding: None }
+    }
+
+    pub fn with_encoding(mut self, encoding: AssetEncoding) -> Self {
+        self.encoding = Some(encoding);
+        self
+    }
+}
+
+// Why fetched bytes were rejected
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum IntegrityError {
+    // A strict manifest does not list the asset
+    Unlisted { path: String },
+    SizeMismatch { path: String, expected: usize, actual: usize },
+    HashMismatch { path: String, expected: [u8; 32], actual: [u8; 32] },
+}
+
+impl IntegrityError {
+    pub fn path(&self) -> &str {
+        match self {
+            IntegrityError::Unlisted { path }
+            | IntegrityError::SizeMismatch { path, .. }
+            | IntegrityError::HashMismatch { path, .. } => path,
+        }
+    }
+
+    pub fn kind(&self) -> &'static str {
+        match self {
+            IntegrityError::Unlisted { .. } => "unlisted",
+            IntegrityError::SizeMismatch { .. } => "size",
+            IntegrityError::HashMismatch { .. } => "hash",
+        }
+    }
+}
+
+impl std::fmt::Display for IntegrityError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            IntegrityError::Unlisted { path } => write!(f, "{} is not in the asset manifest", path),
+            IntegrityError::SizeMismatch { path, expected, actual } => {
+                write!(f, "{} should be {} bytes, got {}", path, expected, actual)
+            },
+            IntegrityError::HashMismatch { path, expected, actual } => write!(
+                f,
+                "{} should hash to {}, got {}",
+                path, hash_hex(expected), hash_hex(actual)
+            ),
+        }
+    }
+}
+
+impl std::error::Error for IntegrityError {}
+
+// A JS Error named "IntegrityError", with `kind` and `path` set
+impl From<IntegrityError> for JsValue {
+    fn from(error: IntegrityError) -> JsValue {
+        let js_error = js_sys::Error::new(&error.to_string());
+        js_error.set_name("IntegrityError");
+        let _ = js_sys::Reflect::set(&js_error, &JsValue::from_str("kind"), &JsValue::from_str(error.kind()));
+        let _ = js_sys::Reflect::set(&js_error, &JsValue::from_str("path"), &JsValue::from_str(error.path()));
+        js_error.into()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ManifestFile {
+    #[serde(default)]
+    strict: bool,
+    assets: BTreeMap<String, ManifestRecord>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ManifestRecord {
+    size: usize,
+    sha256: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encoding: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct AssetManifest {
+    entries: BTreeMap<String, ManifestEntry>,
+    strict: bool,  // Reject assets that are not listed
+}
+
+impl AssetManifest {
+    pub fn new() -> Self {
+        AssetManifest::default()
+    }
+
+    pub fn from_json(json: &str) -> Result<Self, String> {
+        let file: ManifestFile = serde_json::from_str(json).map_err(|e| format!("Invalid asset manifest: {}", e))?;
+        let mut manifest = AssetManifest::new().with_strict(file.strict);
+        for (path, record) in file.assets {
+            let hash = hash_from_hex(&record.sha256)
+                .ok_or_else(|| format!("Invalid SHA-256 for {} in the asset manifest", path))?;
+            let mut entry = ManifestEntry::new(record.size, hash);
+            if let Some(encoding) = record.encoding {
+                let encoding = AssetEncoding::from_name(&encoding)
+                    .ok_or_else(|| format!("Unknown encoding '{}' for {} in the asset manifest", encoding, path))?;
+                entry = entry.with_encoding(encoding);
+            }
+            manifest.insert(&path, entry);
+        }
+        Ok(manifest)
+    }
+
+    pub fn to_json(&self) -> String {
+        let file = ManifestFile {
+            strict: self.strict,
+            assets: self.entries
+                .iter()
+                .map(|(path, entry)| {
+                    let record = ManifestRecord {
+                        size: entry.size,
+                        sha256: hash_hex(&entry.hash),
+                        encoding: entry.encoding.map(|encoding| encoding.name().to_string()),
+                    };
+                    (path.clone(), record)
+                })
+                .collect(),
+        };
+        serde_json::to_string_pretty(&file).unwrap_or_default()
+    }
+
+    pub fn with_strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    // Record what `bytes` hash to under `path`
+    pub fn with_asset(mut self, path: &str, bytes: &[u8]) -> Self {
+        self.insert(path, ManifestEntry::new(bytes.len(), content_hash(bytes)));
+        self
+    }
+
+    pub fn insert(&mut self, path: &str, entry: ManifestEntry) {
+        self.entries.insert(path.to_string(), entry);
+    }
+
+    pub fn get(&self, path: &str) -> Option<&ManifestEntry> {
+        self.entries.get(path)
+    }
+
+    pub fn is_strict(&self) -> bool {
+        self.strict
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Check fetched bytes, hashing them only if the manifest lists the path.
+    // Returns the verified hash, None for an unlisted asset.
+    pub fn verify(&self, path: &str, bytes: &[u8]) -> Result<Option<[u8; 32]>, IntegrityError> {
+        match self.get(path) {
+            Some(_) => self.verify_hash(path, bytes.len(), &content_hash(bytes)),
+            None if self.strict => Err(IntegrityError::Unlisted { path: path.to_string() }),
+            None => Ok(None),
+        }
+    }
+
+    // Check an asset whose hash is already known, e.g. from its pack
+    pub fn verify_hash(&self, path: &str, size: usize, hash: &[u8; 32]) -> Result<Option<[u8; 32]>, IntegrityError> {
+        let entry = match self.get(path) {
+            Some(entry) => entry,
+            None if self.strict => return Err(IntegrityError::Unlisted { path: path.to_string() }),
+            None => return Ok(None),
+        };
+
+        if entry.size != size {
+            return Err(IntegrityError::SizeMismatch {
+                path: path.to_string(),
+                expected: entry.size,
+                actual: size,
+            });
+        }
+        if entry.hash != *hash {
+            return Err(IntegrityError::HashMismatch {
+                path: path.to_string(),
+                expected: entry.hash,
+                actual: *hash,
+            });
+        }
+        Ok(Some(*hash))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_executor::block_on;
+
+    use super::*;
+    use crate::{AssetType, InMemorySource, NativeMemory, TieredAllocator, WallocError};
+
+    const BYTES: &[u8] = b"shipped bytes";
+
+    #[test]
+    fn listed_assets_must_match_size_and_hash() {
+        let manifest = AssetManifest::new().with_asset("a.txt", BYTES);
+        assert_eq!(manifest.verify("a.txt", BYTES), Ok(Some(content_hash(BYTES))));
+
+        let error = manifest.verify("a.txt", b"shipped").unwrap_err();
+        assert_eq!(error, IntegrityError::SizeMismatch { path: "a.txt".to_string(), expected: 13, actual: 7 });
+        assert_eq!(error.to_string(), "a.txt should be 13 bytes, got 7");
+
+        let error = manifest.verify("a.txt", b"shipped BYTES").unwrap_err();
+        assert_eq!(error.kind(), "hash");
+        assert!(matches!(error, IntegrityError::HashMismatch { expected, .. } if expected == content_hash(BYTES)));
+    }
+
+    #[test]
+    fn strict_manifests_reject_unlisted_assets() {
+        let manifest = AssetManifest::new().with_asset("a.txt", BYTES);
+        assert_eq!(manifest.verify("b.txt", BYTES), Ok(None));
+
+        let manifest = manifest.with_strict(true);
+        let error = manifest.verify("b.txt", BYTES).unwrap_err();
+        assert_eq!(error, IntegrityError::Unlisted { path: "b.txt".to_string() });
+        assert_eq!(manifest.verify_hash("b.txt", BYTES.len(), &content_hash(BYTES)), Err(error));
+    }
+
+    #[test]
+    fn manifest_round_trips_through_json() {
+        let mut manifest = AssetManifest::new().with_strict(true).with_asset("a.txt", BYTES);
+        manifest.insert("b.txt.gz", ManifestEntry::new(3, content_hash(b"abc")).with_encoding(AssetEncoding::Gzip));
+
+        let parsed = AssetManifest::from_json(&manifest.to_json()).unwrap();
+        assert_eq!(parsed, manifest);
+        assert_eq!(parsed.get("b.txt.gz").unwrap().encoding, Some(AssetEncoding::Gzip));
+    }
+
+    #[test]
+    fn invalid_manifests_are_rejected() {
+        assert!(AssetManifest::from_json("{").unwrap_err().starts_with("Invalid asset manifest"));
+
+        let json = r#"{ "assets": { "a.txt": { "size": 1, "sha256": "00" } } }"#;
+        assert_eq!(AssetManifest::from_json(json).unwrap_err(), "Invalid SHA-256 for a.txt in the asset manifest");
+
+        let json = format!(r#"{{ "assets": {{ "a.txt": {{ "size": 1, "sha256": "{}", "encoding": "lzma" }} }} }}"#, "0".repeat(64));
+        assert_eq!(AssetManifest::from_json(&json).unwrap_err(), "Unknown encoding 'lzma' for a.txt in the asset manifest");
+    }
+
+    #[test]
+    fn loads_that_do_not_match_the_manifest_fail() {
+        let mut allocator = TieredAllocator::new(Box::new(NativeMemory::new(64)), 16);
+        allocator.set_asset_source(Box::new(
+            InMemorySource::new()
+                .with_asset("good.txt", BYTES.to_vec())
+                .with_asset("short.txt", b"shipped".to_vec())
+                .with_asset("tampered.txt", b"shipped BYTES".to_vec()),
+        ));
+        allocator.set_asset_manifest(
+            AssetManifest::new()
+                .with_asset("good.txt", BYTES)
+                .with_asset("short.txt", BYTES)
+                .with_asset("tampered.txt", BYTES),
+        );
+
+        let mut load = |path: &str| block_on(allocator.load_asset(path.to_string(), AssetType::Text as u8));
+        assert!(load("good.txt").is_ok());
+        assert!(matches!(load("short.txt"), Err(WallocError::Integrity(IntegrityError::SizeMismatch { .. }))));
+        assert!(matches!(load("tampered.txt"), Err(WallocError::Integrity(IntegrityError::HashMismatch { .. }))));
+        assert!(allocator.asset_handle("tampered.txt").is_none());
+    }
+}
@@ -0,0 +1,154 @@
+// Stack markers for scoped rollback of a tier.
+//
+// push_marker records where a tier's allocated region currently ends. While
+// a marker is on the tier's stack, every allocation is placed above it, so
+// pop_to_marker can recycle everything allocated since in one go, the way a
+// loading screen or level section throws away its scratch data. Markers nest
+// and must be popped in the reverse order they were pushed.
+
+use wasm_bindgen::prelude::*;
+
+use super::Tier;
+
+#[wasm_bindgen]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Marker {
+    tier: Tier,
+    id: u64,          // Unique within the tier, a popped marker is never reused
+    depth: usize,     // 0 for the outermost marker
+    position: usize,  // Logical end of the tier when the marker was pushed
+    name: String,
+}
+
+impl Marker {
+    pub(crate) fn new(tier: Tier, id: u64, depth: usize, position: usize, name: &str) -> Self {
+        Marker {
+            tier,
+            id,
+            depth,
+            position,
+            name: name.to_string(),
+        }
+    }
+
+    pub(crate) fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn tier_kind(&self) -> Tier {
+        self.tier
+    }
+}
+
+#[wasm_bindgen]
+impl Marker {
+    #[wasm_bindgen(getter)]
+    pub fn tier(&self) -> u8 {
+        self.tier.id()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+
+    // Bytes of the tier (segments laid end to end) kept when the marker is popped
+    #[wasm_bindgen(getter)]
+    pub fn position(&self) -> usize {
+        self.position
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+
+    #[wasm_bindgen(js_name = toString)]
+    pub fn to_js_string(&self) -> String {
+        format!(
+            "marker '{}' [tier {}, depth {}, {} bytes]",
+            self.name, self.tier.index(), self.depth, self.position
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{NativeMemory, TieredAllocator};
+
+    fn allocator() -> TieredAllocator {
+        TieredAllocator::new(Box::new(NativeMemory::new(64)), 16)
+    }
+
+    #[test]
+    fn nested_markers_pop_innermost_first() {
+        let mut allocator = allocator();
+        let kept = allocator.allocate(100, Tier::SCENE);
+        let outer = allocator.push_marker(Tier::SCENE, "level").unwrap();
+        allocator.allocate(100, Tier::SCENE);
+        let inner = allocator.push_marker(Tier::SCENE, "section").unwrap();
+        let scratch = allocator.allocate(100, Tier::SCENE);
+        assert_eq!((outer.depth(), inner.depth()), (0, 1));
+        assert_eq!(allocator.marker_depth(Tier::SCENE), 2);
+
+        let error = allocator.pop_to_marker(&outer).unwrap_err();
+        assert_eq!(error, "Marker 'section' must be popped before 'level'");
+        assert_eq!(allocator.marker_depth(Tier::SCENE), 2);
+
+        allocator.pop_to_marker(&inner).unwrap();
+        assert_eq!(allocator.tier_stats(Tier::SCENE).used, inner.position());
+        assert_eq!(allocator.allocate(100, Tier::SCENE), scratch);
+
+        allocator.pop_to_marker(&outer).unwrap();
+        assert_eq!(allocator.marker_depth(Tier::SCENE), 0);
+        assert_eq!(allocator.tier_stats(Tier::SCENE).used, outer.position());
+        assert!(allocator.is_ptr_in_arena(kept));
+    }
+
+    #[test]
+    fn popped_marker_cannot_be_popped_again() {
+        let mut allocator = allocator();
+        let marker = allocator.push_marker(Tier::SCENE, "once").unwrap();
+        allocator.pop_to_marker(&marker).unwrap();
+
+        let error = allocator.pop_to_marker(&marker).unwrap_err();
+        assert_eq!(error, "Marker 'once' is no longer on tier 'scene'");
+
+        // A marker pushed since is a different one
+        allocator.push_marker(Tier::SCENE, "once").unwrap();
+        assert!(allocator.pop_to_marker(&marker).is_err());
+        assert_eq!(allocator.marker_depth(Tier::SCENE), 1);
+    }
+
+    #[test]
+    fn reset_drops_the_markers() {
+        let mut allocator = allocator();
+        let marker = allocator.push_marker(Tier::ENTITY, "wave").unwrap();
+        allocator.reset_tier(Tier::ENTITY);
+        assert_eq!(allocator.marker_depth(Tier::ENTITY), 0);
+        assert!(allocator.pop_to_marker(&marker).is_err());
+    }
+
+    #[test]
+    fn memory_below_a_marker_is_not_reused() {
+        let mut allocator = allocator();
+        let (mut owner, below) = allocator.allocate_with_owner(100, Tier::SCENE).unwrap();
+        let marker = allocator.push_marker(Tier::SCENE, "scratch").unwrap();
+        assert!(owner.free(0));
+
+        let above = allocator.allocate(100, Tier::SCENE);
+        assert_ne!(above, below);
+        assert!(above as usize > below as usize);
+        allocator.pop_to_marker(&marker).unwrap();
+        assert_eq!(allocator.allocate(100, Tier::SCENE), below);
+    }
+
+    #[test]
+    fn markers_of_unknown_tiers_are_rejected() {
+        let mut allocator = allocator();
+        assert!(allocator.push_marker(Tier::new(9), "missing").is_none());
+        let forged = Marker::new(Tier::new(9), 0, 0, 0, "forged");
+        assert_eq!(allocator.pop_to_marker(&forged).unwrap_err(), "Marker 'forged' names unknown tier 9");
+    }
+}
@@ -0,0 +1,257 @@
+// Memory sources for the tiered allocator.
+//
+// A MemorySource models a contiguous linear address space that only ever grows
+// in whole pages, which is exactly how WebAssembly memory behaves. The wasm32
+// source wraps memory.size / memory.grow, while the native source reserves an
+// address range from the OS up front and commits pages into it on demand, so
+// the arenas see the same growth behaviour on both targets.
+
+pub const PAGE_SIZE: usize = 65536; // 64KiB, the WebAssembly page size
+pub const MAX_PAGES: usize = 65536; // 4GB (65536 pages * 64KB per page)
+
+pub trait MemorySource: Send {
+    // Origin of the address space - JS facing offsets are measured from here
+    fn base(&self) -> *mut u8;
+
+    // Current size in pages
+    fn pages(&self) -> usize;
+
+    // Largest size this source can ever grow to, in pages
+    fn max_pages(&self) -> usize;
+
+    // Grow by `pages` and return a pointer to the start of the new pages
+    fn grow(&mut self, pages: usize) -> Option<*mut u8>;
+
+    // Current size in bytes
+    fn size(&self) -> usize {
+        self.pages() * PAGE_SIZE
+    }
+}
+
+// Default source for the current target
+pub fn default_source() -> Box<dyn MemorySource> {
+    #[cfg(target_arch = "wasm32")]
+    {
+        Box::new(WasmMemory)
+    }
+
+    #[cfg(all(not(target_arch = "wasm32"), unix))]
+    {
+        Box::new(NativeMemory::new(MAX_PAGES))
+    }
+
+    // Platforms without a reserve/commit split pay for the whole range up front
+    #[cfg(all(not(target_arch = "wasm32"), not(unix)))]
+    {
+        Box::new(NativeMemory::new(MAX_PAGES / 16))
+    }
+}
+
+// === WebAssembly linear memory ===
+#[cfg(target_arch = "wasm32")]
+pub struct WasmMemory;
+
+#[cfg(target_arch = "wasm32")]
+impl MemorySource for WasmMemory {
+    fn base(&self) -> *mut u8 {
+        // Linear memory starts at address 0, so offsets are plain addresses
+        std::ptr::null_mut()
+    }
+
+    fn pages(&self) -> usize {
+        core::arch::wasm32::memory_size(0)
+    }
+
+    fn max_pages(&self) -> usize {
+        MAX_PAGES
+    }
+
+    fn grow(&mut self, pages: usize) -> Option<*mut u8> {
+        let old_pages = core::arch::wasm32::memory_grow(0, pages);
+        if old_pages == usize::MAX {
+            return None;
+        }
+
+        Some((old_pages * PAGE_SIZE) as *mut u8)
+    }
+}
+
+// === Native memory reserved from the OS ===
+#[cfg(not(target_arch = "wasm32"))]
+pub struct NativeMemory {
+    base: *mut u8,
+    committed_pages: usize,
+    max_pages: usize,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl NativeMemory {
+    // Reserve address space for up to `max_pages` pages. Nothing is committed
+    // until the allocator grows into it. If the reservation fails the source
+    // is empty and every grow fails, just like a wasm module at its limit.
+    pub fn new(max_pages: usize) -> Self {
+        let base = Self::reserve(max_pages * PAGE_SIZE);
+        NativeMemory {
+            base,
+            committed_pages: 0,
+            max_pages: if base.is_null() { 0 } else { max_pages },
+        }
+    }
+
+    #[cfg(unix)]
+    fn reserve(bytes: usize) -> *mut u8 {
+        if bytes == 0 {
+            return std::ptr::null_mut();
+        }
+
+        // PROT_NONE + MAP_NORESERVE only claims the address range
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                bytes,
+                libc::PROT_NONE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS | libc::MAP_NORESERVE,
+                -1,
+                0,
+            )
+        };
+
+        if ptr == libc::MAP_FAILED {
+            std::ptr::null_mut()
+        } else {
+            ptr as *mut u8
+        }
+    }
+
+    #[cfg(unix)]
+    fn commit(&mut self, first_page: usize, pages: usize) -> bool {
+        let result = unsafe {
+            libc::mprotect(
+                self.base.add(first_page * PAGE_SIZE) as *mut libc::c_void,
+                pages * PAGE_SIZE,
+                libc::PROT_READ | libc::PROT_WRITE,
+            )
+        };
+        result == 0
+    }
+
+    #[cfg(unix)]
+    fn release(&mut self) {
+        unsafe {
+            libc::munmap(self.base as *mut libc::c_void, self.max_pages * PAGE_SIZE);
+        }
+    }
+
+    // Without a reserve/commit split the whole range is taken from the system
+    // allocator up front, so keep max_pages modest on these platforms.
+    #[cfg(not(unix))]
+    fn reserve(bytes: usize) -> *mut u8 {
+        match std::alloc::Layout::from_size_align(bytes, PAGE_SIZE) {
+            Ok(layout) if bytes > 0 => unsafe { std::alloc::alloc_zeroed(layout) },
+            _ => std::ptr::null_mut(),
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn commit(&mut self, _first_page: usize, _pages: usize) -> bool {
+        true
+    }
+
+    #[cfg(not(unix))]
+    fn release(&mut self) {
+        let bytes = self.max_pages * PAGE_SIZE;
+        if let Ok(layout) = std::alloc::Layout::from_size_align(bytes, PAGE_SIZE) {
+            unsafe { std::alloc::dealloc(self.base, layout) };
+        }
+    }
+}
+
+// The reservation is owned exclusively by this source
+#[cfg(not(target_arch = "wasm32"))]
+unsafe impl Send for NativeMemory {}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl MemorySource for NativeMemory {
+    fn base(&self) -> *mut u8 {
+        self.base
+    }
+
+    fn pages(&self) -> usize {
+        self.committed_pages
+    }
+
+    fn max_pages(&self) -> usize {
+        self.max_pages
+    }
+
+    fn grow(&mut self, pages: usize) -> Option<*mut u8> {
+        if self.committed_pages + pages > self.max_pages {
+            return None;
+        }
+
+        let first_page = self.committed_pages;
+        if pages > 0 && !self.commit(first_page, pages) {
+            return None;
+        }
+
+        self.committed_pages += pages;
+        Some(unsafe { self.base.add(first_page * PAGE_SIZE) })
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for NativeMemory {
+    fn drop(&mut self) {
+        if !self.base.is_null() {
+            self.release();
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reserve_commits_nothing_up_front() {
+        let memory = NativeMemory::new(64);
+        assert!(!memory.base().is_null());
+        assert_eq!(memory.pages(), 0);
+        assert_eq!(memory.size(), 0);
+        assert_eq!(memory.max_pages(), 64);
+    }
+
+    #[test]
+    fn grow_commits_contiguous_writable_pages() {
+        let mut memory = NativeMemory::new(8);
+        let first = memory.grow(2).unwrap();
+        assert_eq!(first, memory.base());
+        let second = memory.grow(3).unwrap();
+        assert_eq!(second as usize - first as usize, 2 * PAGE_SIZE);
+        assert_eq!(memory.pages(), 5);
+
+        // Every committed byte can be written, across the grow boundary too
+        unsafe {
+            std::ptr::write_bytes(first, 0xAB, 5 * PAGE_SIZE);
+            assert_eq!(*first.add(5 * PAGE_SIZE - 1), 0xAB);
+        }
+    }
+
+    #[test]
+    fn grow_stops_at_max_pages() {
+        let mut memory = NativeMemory::new(4);
+        assert!(memory.grow(3).is_some());
+        assert!(memory.grow(2).is_none());
+        assert_eq!(memory.pages(), 3);
+        assert!(memory.grow(1).is_some());
+        assert!(memory.grow(1).is_none());
+    }
+
+    #[test]
+    fn empty_reservation_never_grows() {
+        let mut memory = NativeMemory::new(0);
+        assert_eq!(memory.max_pages(), 0);
+        assert!(memory.grow(1).is_none());
+        assert_eq!(memory.grow(0), Some(memory.base()));
+    }
+}
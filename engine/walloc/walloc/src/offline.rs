@@ -0,0 +1,848 @@
+// Assets kept across sessions.
+//
+// An OfflineCache keeps fetched assets in a CacheStore, keyed by their URL,
+// with the ETag and Last-Modified the server sent. Once one is installed,
+// every fetch of a whole asset goes through a CachedSource: a stored asset is
+// revalidated with a conditional request and served from the store on a 304,
+// and a fetch that fails without an answer falls back to the stored copy.
+// Ranges go straight to the network.
+//
+// DirectoryStore keeps entries as files on native targets and WASI,
+// InMemoryStore for one session, and JsCacheStore hands them to a JS object,
+// which the browser build backs with IndexedDB. The cache stays under its
+// size limit by dropping the least recently used entries when it stores one.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use super::{
+    AssetSource, AssetStream, ConditionalFuture, FetchFuture, FetchOptions, Revalidation, StreamFuture, Validators,
+    WallocError,
+};
+use super::source::{BufferedStream, NO_OPTIONS};
+
+// Offline caches start out with room for 256MB
+pub const DEFAULT_OFFLINE_CACHE_LIMIT: usize = 256 * 1024 * 1024;
+
+pub type StoreFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, WallocError>> + 'a>>;
+
+// A stored asset without its bytes
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct CacheRecord {
+    pub key: String,
+    pub size: usize,
+    pub validators: Validators,
+    pub stored_at: u64,  // Milliseconds since the Unix epoch
+    pub last_used: u64,
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct CachedAsset {
+    pub record: CacheRecord,
+    pub bytes: Vec<u8>,
+}
+
+// Where an OfflineCache keeps its entries. Every call may wait on storage,
+// so they all return futures.
+pub trait CacheStore: Send + Sync {
+    // Short description for logs
+    fn describe(&self) -> String;
+
+    fn get<'a>(&'a self, key: &'a str) -> StoreFuture<'a, Option<CachedAsset>>;
+
+    // Store an asset, replacing what was stored under its key
+    fn put(&self, asset: CachedAsset) -> StoreFuture<'_, ()>;
+
+    fn remove<'a>(&'a self, key: &'a str) -> StoreFuture<'a, ()>;
+
+    // Record that an entry was used at `time`
+    fn touch<'a>(&'a self, key: &'a str, time: u64) -> StoreFuture<'a, ()>;
+
+    fn records(&self) -> StoreFuture<'_, Vec<CacheRecord>>;
+
+    fn clear(&self) -> StoreFuture<'_, ()>;
+}
+
+// Milliseconds since the Unix epoch
+pub(crate) fn now_millis() -> u64 {
+    #[cfg(target_arch = "wasm32")]
+    {
+        js_sys::Date::now() as u64
+    }
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |since| since.as_millis() as u64)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct OfflineCacheStats {
+    pub limit: usize,
+    pub hits: u64,         // Served from the store after a 304
+    pub misses: u64,       // Nothing stored, fetched
+    pub updates: u64,      // Stored but out of date, fetched again
+    pub offline_hits: u64, // Served from the store because the fetch failed
+    pub evictions: u64,
+}
+
+pub struct OfflineCache {
+    store: Box<dyn CacheStore>,
+    limit: AtomicUsize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    updates: AtomicU64,
+    offline_hits: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl OfflineCache {
+    pub fn new(store: Box<dyn CacheStore>) -> Self {
+        OfflineCache {
+            store,
+            limit: AtomicUsize::new(DEFAULT_OFFLINE_CACHE_LIMIT),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            updates: AtomicU64::new(0),
+            offline_hits: AtomicU64::new(0),
+            evictions: AtomicU64::new(0),
+        }
+    }
+
+    pub fn with_limit(self, limit: usize) -> Self {
+        self.set_limit(limit);
+        self
+    }
+
+    // Bytes the store may hold. Lowering it takes effect on the next store.
+    pub fn set_limit(&self, limit: usize) {
+        self.limit.store(limit, Ordering::Relaxed);
+    }
+
+    pub fn limit(&self) -> usize {
+        self.limit.load(Ordering::Relaxed)
+    }
+
+    pub fn describe(&self) -> String {
+        self.store.describe()
+    }
+
+    pub fn stats(&self) -> OfflineCacheStats {
+        OfflineCacheStats {
+            limit: self.limit(),
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            updates: self.updates.load(Ordering::Relaxed),
+            offline_hits: self.offline_hits.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
+
+    // Bytes and entries held by the store
+    pub async fn usage(&self) -> Result<(usize, usize), WallocError> {
+        let records = self.store.records().await?;
+        Ok((records.iter().map(|record| record.size).sum(), records.len()))
+    }
+
+    pub async fn clear(&self) -> Result<(), WallocError> {
+        self.store.clear().await
+    }
+
+    pub async fn remove(&self, key: &str) -> Result<(), WallocError> {
+        self.store.remove(key).await
+    }
+
+    // Fetch `path` from `source` through the cache
+    pub async fn fetch(&self, source: &dyn AssetSource, path: &str, options: &FetchOptions) -> Result<Vec<u8>, WallocError> {
+        let key = source.url(path);
+        // A store that cannot be read is as good as empty
+        let cached = self.store.get(&key).await.ok().flatten();
+        let validators = cached.as_ref().map(|cached| cached.record.validators.clone()).unwrap_or_default();
+
+        match source.fetch_conditional(path, options, &validators).await {
+            Ok(Revalidation::NotModified) => match cached {
+                Some(cached) => {
+                    self.hits.fetch_add(1, Ordering::Relaxed);
+                    let _ = self.store.touch(&key, now_millis()).await;
+                    Ok(cached.bytes)
+                },
+                None => Err(WallocError::Http { url: key, status: 304 }),
+            },
+            Ok(Revalidation::Modified { bytes, validators }) => {
+                let counter = if cached.is_some() { &self.updates } else { &self.misses };
+                counter.fetch_add(1, Ordering::Relaxed);
+                // The asset is still good if it cannot be stored
+                let _ = self.store(&key, &bytes, validators).await;
+                Ok(bytes)
+            },
+            Err(e @ (WallocError::Fetch { .. } | WallocError::Timeout { .. })) => match cached {
+                Some(cached) => {
+                    self.offline_hits.fetch_add(1, Ordering::Relaxed);
+                    let _ = self.store.touch(&key, now_millis()).await;
+                    Ok(cached.bytes)
+                },
+                None => Err(e),
+            },
+            Err(e) => Err(e),
+        }
+    }
+
+    // Store an asset, first dropping the least recently used entries until it
+    // fits. Assets larger than the whole limit are not stored.
+    async fn store(&self, key: &str, bytes: &[u8], validators: Validators) -> Result<(), WallocError> {
+        let limit = self.limit();
+        if bytes.len() > limit {
+            return self.store.remove(key).await;
+        }
+
+        let mut records: Vec<CacheRecord> = self.store
+            .records()
+            .await?
+            .into_iter()
+            .filter(|record| record.key != key)
+            .collect();
+        records.sort_by_key(|record| std::cmp::Reverse(record.last_used));
+        let mut used: usize = records.iter().map(|record| record.size).sum();
+        while used + bytes.len() > limit {
+            let victim = match records.pop() {
+                Some(victim) => victim,
+                None => break,
+            };
+            self.store.remove(&victim.key).await?;
+            self.evictions.fetch_add(1, Ordering::Relaxed);
+            used -= victim.size;
+        }
+
+        let now = now_millis();
+        let record = CacheRecord {
+            key: key.to_string(),
+            size: bytes.len(),
+            validators,
+            stored_at: now,
+            last_used: now,
+        };
+        self.store.put(CachedAsset { record, bytes: bytes.to_vec() }).await
+    }
+}
+
+// === Cached source ===
+
+// An asset source with an offline cache in front of it
+pub struct CachedSource {
+    inner: Arc<dyn AssetSource>,
+    cache: Arc<OfflineCache>,
+}
+
+impl CachedSource {
+    pub fn new(inner: Arc<dyn AssetSource>, cache: Arc<OfflineCache>) -> Self {
+        CachedSource { inner, cache }
+    }
+}
+
+impl AssetSource for CachedSource {
+    fn describe(&self) -> String {
+        format!("{} cached in {}", self.inner.describe(), self.cache.describe())
+    }
+
+    fn fetch<'a>(&'a self, path: &'a str) -> FetchFuture<'a> {
+        Box::pin(self.cache.fetch(self.inner.as_ref(), path, &NO_OPTIONS))
+    }
+
+    fn fetch_range<'a>(&'a self, path: &'a str, start: u64, len: u64) -> FetchFuture<'a> {
+        self.inner.fetch_range(path, start, len)
+    }
+
+    // The whole asset is needed to store it, so it arrives as one chunk
+    fn fetch_stream<'a>(&'a self, path: &'a str) -> StreamFuture<'a> {
+        self.fetch_stream_with(path, &NO_OPTIONS)
+    }
+
+    fn url(&self, path: &str) -> String {
+        self.inner.url(path)
+    }
+
+    fn fetch_conditional<'a>(&'a self, path: &'a str, options: &'a FetchOptions, validators: &'a Validators) -> ConditionalFuture<'a> {
+        self.inner.fetch_conditional(path, options, validators)
+    }
+
+    fn fetch_range_with<'a>(&'a self, path: &'a str, start: u64, len: u64, options: &'a FetchOptions) -> FetchFuture<'a> {
+        self.inner.fetch_range_with(path, start, len, options)
+    }
+
+    fn fetch_stream_with<'a>(&'a self, path: &'a str, options: &'a FetchOptions) -> StreamFuture<'a> {
+        Box::pin(async move {
+            let bytes = self.cache.fetch(self.inner.as_ref(), path, options).await?;
+            let stream: Box<dyn AssetStream + 'a> = Box::new(BufferedStream::new(bytes));
+            Ok(stream)
+        })
+    }
+}
+
+// === In memory ===
+
+// Entries for as long as the store lives, for tests and sessions without
+// persistent storage
+#[derive(Default)]
+pub struct InMemoryStore {
+    assets: Mutex<HashMap<String, CachedAsset>>,
+}
+
+impl InMemoryStore {
+    pub fn new() -> Self {
+        InMemoryStore::default()
+    }
+
+    fn with_assets<T>(&self, f: impl FnOnce(&mut HashMap<String, CachedAsset>) -> T) -> StoreFuture<'_, T>
+    where
+        T: 'static,
+    {
+        let result = match self.assets.lock() {
+            Ok(mut assets) => Ok(f(&mut assets)),
+            Err(_) => Err(WallocError::Lock("the in-memory cache store")),
+        };
+        Box::pin(std::future::ready(result))
+    }
+}
+
+impl CacheStore for InMemoryStore {
+    fn describe(&self) -> String {
+        "memory".to_string()
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> StoreFuture<'a, Option<CachedAsset>> {
+        self.with_assets(|assets| assets.get(key).cloned())
+    }
+
+    fn put(&self, asset: CachedAsset) -> StoreFuture<'_, ()> {
+        self.with_assets(|assets| {
+            assets.insert(asset.record.key.clone(), asset);
+        })
+    }
+
+    fn remove<'a>(&'a self, key: &'a str) -> StoreFuture<'a, ()> {
+        self.with_assets(|assets| {
+            assets.remove(key);
+        })
+    }
+
+    fn touch<'a>(&'a self, key: &'a str, time: u64) -> StoreFuture<'a, ()> {
+        self.with_assets(|assets| {
+            if let Some(asset) = assets.get_mut(key) {
+                asset.record.last_used = time;
+            }
+        })
+    }
+
+    fn records(&self) -> StoreFuture<'_, Vec<CacheRecord>> {
+        self.with_assets(|assets| assets.values().map(|asset| asset.record.clone()).collect())
+    }
+
+    fn clear(&self) -> StoreFuture<'_, ()> {
+        self.with_assets(|assets| assets.clear())
+    }
+}
+
+// === Directory ===
+
+// Entries as files below a directory: the bytes in `<hash>.bin` and the
+// record in `<hash>.json`, named by the SHA-256 of the key. An entry counts
+// once its record is written, which happens after its bytes.
+#[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+pub struct DirectoryStore {
+    root: std::path::PathBuf,
+}
+
+#[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+#[derive(serde::Serialize, serde::Deserialize)]
+struct RecordFile {
+    key: String,
+    size: usize,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    stored_at: u64,
+    last_used: u64,
+}
+
+#[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+impl DirectoryStore {
+    // The directory is created when the first entry is stored
+    pub fn new(root: impl Into<std::path::PathBuf>) -> Self {
+        DirectoryStore { root: root.into() }
+    }
+
+    pub fn root(&self) -> &std::path::Path {
+        &self.root
+    }
+
+    fn file(&self, key: &str, extension: &str) -> std::path::PathBuf {
+        let name = super::hash_hex(&super::content_hash(key.as_bytes()));
+        self.root.join(format!("{}.{}", name, extension))
+    }
+
+    fn io_error(&self, error: std::io::Error) -> WallocError {
+        WallocError::Fetch { url: self.root.display().to_string(), message: error.to_string() }
+    }
+
+    fn read_record(path: &std::path::Path) -> Option<CacheRecord> {
+        let json = std::fs::read_to_string(path).ok()?;
+        let file: RecordFile = serde_json::from_str(&json).ok()?;
+        Some(CacheRecord {
+            key: file.key,
+            size: file.size,
+            validators: Validators { etag: file.etag, last_modified: file.last_modified },
+            stored_at: file.stored_at,
+            last_used: file.last_used,
+        })
+    }
+
+    fn write_record(&self, record: &CacheRecord) -> Result<(), WallocError> {
+        let file = RecordFile {
+            key: record.key.clone(),
+            size: record.size,
+            etag: record.validators.etag.clone(),
+            last_modified: record.validators.last_modified.clone(),
+            stored_at: record.stored_at,
+            last_used: record.last_used,
+        };
+        let json = serde_json::to_string(&file).map_err(|e| WallocError::InvalidArgument(e.to_string()))?;
+        std::fs::write(self.file(&record.key, "json"), json).map_err(|e| self.io_error(e))
+    }
+
+    fn get_now(&self, key: &str) -> Option<CachedAsset> {
+        let record = DirectoryStore::read_record(&self.file(key, "json"))?;
+        let bytes = std::fs::read(self.file(key, "bin")).ok()?;
+        // A record for other bytes, e.g. from an interrupted write, is a miss
+        if record.key != key || bytes.len() != record.size {
+            return None;
+        }
+        Some(CachedAsset { record, bytes })
+    }
+
+    fn put_now(&self, asset: &CachedAsset) -> Result<(), WallocError> {
+        std::fs::create_dir_all(&self.root).map_err(|e| self.io_error(e))?;
+        std::fs::write(self.file(&asset.record.key, "bin"), &asset.bytes).map_err(|e| self.io_error(e))?;
+        self.write_record(&asset.record)
+    }
+
+    fn remove_now(&self, key: &str) -> Result<(), WallocError> {
+        for extension in ["json", "bin"] {
+            match std::fs::remove_file(self.file(key, extension)) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(self.io_error(e)),
+                _ => {},
+            }
+        }
+        Ok(())
+    }
+
+    fn touch_now(&self, key: &str, time: u64) -> Result<(), WallocError> {
+        match DirectoryStore::read_record(&self.file(key, "json")) {
+            Some(mut record) => {
+                record.last_used = time;
+                self.write_record(&record)
+            },
+            None => Ok(()),
+        }
+    }
+
+    // Entry files in the directory, none if it does not exist yet
+    fn entry_files(&self, extensions: &[&str]) -> Result<Vec<std::path::PathBuf>, WallocError> {
+        let entries = match std::fs::read_dir(&self.root) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(self.io_error(e)),
+        };
+        Ok(entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                path.extension()
+                    .and_then(|extension| extension.to_str())
+                    .is_some_and(|extension| extensions.contains(&extension))
+            })
+            .collect())
+    }
+
+    fn records_now(&self) -> Result<Vec<CacheRecord>, WallocError> {
+        Ok(self.entry_files(&["json"])?
+            .iter()
+            .filter_map(|path| DirectoryStore::read_record(path))
+            .collect())
+    }
+
+    fn clear_now(&self) -> Result<(), WallocError> {
+        for path in self.entry_files(&["json", "bin"])? {
+            std::fs::remove_file(&path).map_err(|e| self.io_error(e))?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+impl CacheStore for DirectoryStore {
+    fn describe(&self) -> String {
+        format!("directory {}", self.root.display())
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> StoreFuture<'a, Option<CachedAsset>> {
+        Box::pin(std::future::ready(Ok(self.get_now(key))))
+    }
+
+    fn put(&self, asset: CachedAsset) -> StoreFuture<'_, ()> {
+        Box::pin(std::future::ready(self.put_now(&asset)))
+    }
+
+    fn remove<'a>(&'a self, key: &'a str) -> StoreFuture<'a, ()> {
+        Box::pin(std::future::ready(self.remove_now(key)))
+    }
+
+    fn touch<'a>(&'a self, key: &'a str, time: u64) -> StoreFuture<'a, ()> {
+        Box::pin(std::future::ready(self.touch_now(key, time)))
+    }
+
+    fn records(&self) -> StoreFuture<'_, Vec<CacheRecord>> {
+        Box::pin(std::future::ready(self.records_now()))
+    }
+
+    fn clear(&self) -> StoreFuture<'_, ()> {
+        Box::pin(std::future::ready(self.clear_now()))
+    }
+}
+
+// === JS ===
+
+// Entries kept by a JS object, such as one backed by IndexedDB. Every method
+// returns a Promise:
+//
+//   get(key)         -> { bytes: Uint8Array, record } or undefined
+//   put(key, { bytes, record })
+//   delete(key)
+//   touch(key, time)
+//   records()        -> [record, ...]
+//   clear()
+//
+// where a record is { key, size, etag, lastModified, storedAt, lastUsed }.
+#[cfg(target_arch = "wasm32")]
+pub struct JsCacheStore {
+    store: wasm_bindgen::JsValue,
+}
+
+// The browser build has one thread, so the JS object never leaves it
+#[cfg(target_arch = "wasm32")]
+unsafe impl Send for JsCacheStore {}
+#[cfg(target_arch = "wasm32")]
+unsafe impl Sync for JsCacheStore {}
+
+#[cfg(target_arch = "wasm32")]
+impl JsCacheStore {
+    pub fn new(store: wasm_bindgen::JsValue) -> Self {
+        JsCacheStore { store }
+    }
+
+    // Call `method` with `args` and wait for the Promise it returns
+    fn call<'a>(&'a self, method: &'a str, args: Vec<wasm_bindgen::JsValue>) -> StoreFuture<'a, wasm_bindgen::JsValue> {
+        use wasm_bindgen::{JsCast, JsValue};
+
+        Box::pin(async move {
+            let error = |e: JsValue| WallocError::Fetch {
+                url: format!("cache store {}", method),
+                message: format!("{:?}", e),
+            };
+            let function = js_sys::Reflect::get(&self.store, &JsValue::from_str(method))
+                .map_err(error)?
+                .dyn_into::<js_sys::Function>()
+                .map_err(|_| WallocError::InvalidArgument(format!("The cache store has no {} method", method)))?;
+            let args: js_sys::Array = args.into_iter().collect();
+            let result = function.apply(&self.store, &args).map_err(error)?;
+            wasm_bindgen_futures::JsFuture::from(js_sys::Promise::resolve(&result))
+                .await
+                .map_err(error)
+        })
+    }
+
+    fn record_from_js(value: &wasm_bindgen::JsValue) -> Option<CacheRecord> {
+        use wasm_bindgen::JsValue;
+
+        let field = |name: &str| js_sys::Reflect::get(value, &JsValue::from_str(name)).ok();
+        Some(CacheRecord {
+            key: field("key")?.as_string()?,
+            size: field("size")?.as_f64()? as usize,
+            validators: Validators {
+                etag: field("etag").and_then(|etag| etag.as_string()),
+                last_modified: field("lastModified").and_then(|last_modified| last_modified.as_string()),
+            },
+            stored_at: field("storedAt").and_then(|time| time.as_f64()).unwrap_or(0.0) as u64,
+            last_used: field("lastUsed").and_then(|time| time.as_f64()).unwrap_or(0.0) as u64,
+        })
+    }
+
+    fn record_to_js(record: &CacheRecord) -> wasm_bindgen::JsValue {
+        use wasm_bindgen::JsValue;
+
+        let object = js_sys::Object::new();
+        let set = |key: &str, value: JsValue| {
+            let _ = js_sys::Reflect::set(&object, &JsValue::from_str(key), &value);
+        };
+        set("key", JsValue::from_str(&record.key));
+        set("size", JsValue::from_f64(record.size as f64));
+        set("etag", record.validators.etag.as_deref().map_or(JsValue::NULL, JsValue::from_str));
+        set("lastModified", record.validators.last_modified.as_deref().map_or(JsValue::NULL, JsValue::from_str));
+        set("storedAt", JsValue::from_f64(record.stored_at as f64));
+        set("lastUsed", JsValue::from_f64(record.last_used as f64));
+        object.into()
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl CacheStore for JsCacheStore {
+    fn describe(&self) -> String {
+        "js store".to_string()
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> StoreFuture<'a, Option<CachedAsset>> {
+        use wasm_bindgen::JsValue;
+
+        Box::pin(async move {
+            let entry = self.call("get", vec![JsValue::from_str(key)]).await?;
+            if entry.is_undefined() || entry.is_null() {
+                return Ok(None);
+            }
+            let record = js_sys::Reflect::get(&entry, &JsValue::from_str("record")).ok();
+            let bytes = js_sys::Reflect::get(&entry, &JsValue::from_str("bytes")).ok();
+            match (record.as_ref().and_then(JsCacheStore::record_from_js), bytes) {
+                (Some(record), Some(bytes)) if bytes.is_object() => {
+                    let bytes = js_sys::Uint8Array::new(&bytes).to_vec();
+                    Ok(Some(CachedAsset { record, bytes }).filter(|asset| asset.bytes.len() == asset.record.size))
+                },
+                _ => Ok(None),
+            }
+        })
+    }
+
+    fn put(&self, asset: CachedAsset) -> StoreFuture<'_, ()> {
+        use wasm_bindgen::JsValue;
+
+        Box::pin(async move {
+            let entry = js_sys::Object::new();
+            let _ = js_sys::Reflect::set(&entry, &JsValue::from_str("bytes"), &js_sys::Uint8Array::from(asset.bytes.as_slice()));
+            let _ = js_sys::Reflect::set(&entry, &JsValue::from_str("record"), &JsCacheStore::record_to_js(&asset.record));
+            self.call("put", vec![JsValue::from_str(&asset.record.key), entry.into()]).await?;
+            Ok(())
+        })
+    }
+
+    fn remove<'a>(&'a self, key: &'a str) -> StoreFuture<'a, ()> {
+        use wasm_bindgen::JsValue;
+
+        Box::pin(async move {
+            self.call("delete", vec![JsValue::from_str(key)]).await?;
+            Ok(())
+        })
+    }
+
+    fn touch<'a>(&'a self, key: &'a str, time: u64) -> StoreFuture<'a, ()> {
+        use wasm_bindgen::JsValue;
+
+        Box::pin(async move {
+            self.call("touch", vec![JsValue::from_str(key), JsValue::from_f64(time as f64)]).await?;
+            Ok(())
+        })
+    }
+
+    fn records(&self) -> StoreFuture<'_, Vec<CacheRecord>> {
+        Box::pin(async move {
+            let records = self.call("records", Vec::new()).await?;
+            Ok(js_sys::Array::from(&records)
+                .iter()
+                .filter_map(|record| JsCacheStore::record_from_js(&record))
+                .collect())
+        })
+    }
+
+    fn clear(&self) -> StoreFuture<'_, ()> {
+        Box::pin(async move {
+            self.call("clear", Vec::new()).await?;
+            Ok(())
+        })
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use std::sync::atomic::AtomicBool;
+
+    use futures_executor::block_on;
+
+    use super::*;
+
+    // One version of every asset at a time, tagged with an ETag, answering
+    // conditional fetches like a server
+    #[derive(Default)]
+    struct VersionedSource {
+        assets: Mutex<HashMap<String, (Vec<u8>, String)>>,
+        offline: AtomicBool,
+        fetches: AtomicUsize,
+    }
+
+    impl VersionedSource {
+        fn publish(&self, path: &str, bytes: &[u8], etag: &str) {
+            self.assets.lock().unwrap().insert(path.to_string(), (bytes.to_vec(), etag.to_string()));
+        }
+    }
+
+    impl AssetSource for VersionedSource {
+        fn describe(&self) -> String {
+            "versioned".to_string()
+        }
+
+        fn fetch<'a>(&'a self, path: &'a str) -> FetchFuture<'a> {
+            Box::pin(async move {
+                match self.fetch_conditional(path, &NO_OPTIONS, &Validators::default()).await? {
+                    Revalidation::Modified { bytes, .. } => Ok(bytes),
+                    Revalidation::NotModified => unreachable!(),
+                }
+            })
+        }
+
+        fn fetch_conditional<'a>(&'a self, path: &'a str, _: &'a FetchOptions, validators: &'a Validators) -> ConditionalFuture<'a> {
+            self.fetches.fetch_add(1, Ordering::Relaxed);
+            let result = if self.offline.load(Ordering::Relaxed) {
+                Err(WallocError::Fetch { url: path.to_string(), message: "offline".to_string() })
+            } else {
+                match self.assets.lock().unwrap().get(path) {
+                    Some((_, etag)) if validators.etag.as_ref() == Some(etag) => Ok(Revalidation::NotModified),
+                    Some((bytes, etag)) => Ok(Revalidation::Modified {
+                        bytes: bytes.clone(),
+                        validators: Validators { etag: Some(etag.clone()), last_modified: None },
+                    }),
+                    None => Err(WallocError::NotFound { path: path.to_string() }),
+                }
+            };
+            Box::pin(std::future::ready(result))
+        }
+    }
+
+    // An empty directory of its own under the system's temporary directory
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        let root = std::env::temp_dir().join(format!("walloc-offline-{}-{}", std::process::id(), name));
+        let _ = std::fs::remove_dir_all(&root);
+        root
+    }
+
+    fn fetch(cache: &OfflineCache, source: &VersionedSource, path: &str) -> Result<Vec<u8>, WallocError> {
+        block_on(cache.fetch(source, path, &NO_OPTIONS))
+    }
+
+    // Stores and revalidates by ETag, whatever the store
+    fn check_revalidation(store: Box<dyn CacheStore>) {
+        let cache = OfflineCache::new(store);
+        let source = VersionedSource::default();
+        source.publish("a.bin", b"first", "\"v1\"");
+
+        assert_eq!(fetch(&cache, &source, "a.bin").unwrap(), b"first");
+        let stored = block_on(cache.store.get("a.bin")).unwrap().unwrap();
+        assert_eq!(stored.record.validators.etag.as_deref(), Some("\"v1\""));
+        assert_eq!(stored.record.size, 5);
+
+        assert_eq!(fetch(&cache, &source, "a.bin").unwrap(), b"first");
+        source.publish("a.bin", b"second", "\"v2\"");
+        assert_eq!(fetch(&cache, &source, "a.bin").unwrap(), b"second");
+        assert_eq!(fetch(&cache, &source, "a.bin").unwrap(), b"second");
+
+        let stats = cache.stats();
+        assert_eq!((stats.misses, stats.hits, stats.updates), (1, 2, 1));
+        assert_eq!(block_on(cache.usage()).unwrap(), (6, 1));
+    }
+
+    #[test]
+    fn in_memory_store_revalidates_by_etag() {
+        check_revalidation(Box::new(InMemoryStore::new()));
+    }
+
+    #[test]
+    fn directory_store_revalidates_by_etag() {
+        let root = temp_dir("revalidate");
+        check_revalidation(Box::new(DirectoryStore::new(&root)));
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn failed_fetches_fall_back_to_the_stored_copy() {
+        let cache = OfflineCache::new(Box::new(InMemoryStore::new()));
+        let source = VersionedSource::default();
+        source.publish("a.bin", b"stored", "\"v1\"");
+        fetch(&cache, &source, "a.bin").unwrap();
+
+        source.offline.store(true, Ordering::Relaxed);
+        assert_eq!(fetch(&cache, &source, "a.bin").unwrap(), b"stored");
+        assert_eq!(cache.stats().offline_hits, 1);
+        assert!(matches!(fetch(&cache, &source, "b.bin"), Err(WallocError::Fetch { .. })));
+
+        // Answers other than a failed request are passed on
+        source.offline.store(false, Ordering::Relaxed);
+        assert!(matches!(fetch(&cache, &source, "b.bin"), Err(WallocError::NotFound { .. })));
+    }
+
+    #[test]
+    fn limit_drops_the_least_recently_used() {
+        let cache = OfflineCache::new(Box::new(InMemoryStore::new())).with_limit(250);
+        let source = VersionedSource::default();
+        for path in ["a.bin", "b.bin", "c.bin"] {
+            source.publish(path, &[0; 100], "\"v1\"");
+        }
+        fetch(&cache, &source, "a.bin").unwrap();
+        fetch(&cache, &source, "b.bin").unwrap();
+        block_on(cache.store.touch("a.bin", u64::MAX)).unwrap();
+        block_on(cache.store.touch("b.bin", 1)).unwrap();
+
+        fetch(&cache, &source, "c.bin").unwrap();
+        assert_eq!(cache.stats().evictions, 1);
+        assert!(block_on(cache.store.get("a.bin")).unwrap().is_some());
+        assert!(block_on(cache.store.get("b.bin")).unwrap().is_none());
+        assert_eq!(block_on(cache.usage()).unwrap(), (200, 2));
+
+        // Larger than the whole limit: served but not stored, and the old copy is gone
+        source.publish("a.bin", &[1; 300], "\"v2\"");
+        assert_eq!(fetch(&cache, &source, "a.bin").unwrap().len(), 300);
+        assert!(block_on(cache.store.get("a.bin")).unwrap().is_none());
+        assert_eq!(block_on(cache.usage()).unwrap(), (100, 1));
+    }
+
+    #[test]
+    fn directory_store_keeps_entries_across_instances() {
+        let root = temp_dir("persist");
+        let source = VersionedSource::default();
+        source.publish("a.bin", b"persisted", "\"v1\"");
+        fetch(&OfflineCache::new(Box::new(DirectoryStore::new(&root))), &source, "a.bin").unwrap();
+
+        let cache = OfflineCache::new(Box::new(DirectoryStore::new(&root)));
+        source.offline.store(true, Ordering::Relaxed);
+        assert_eq!(fetch(&cache, &source, "a.bin").unwrap(), b"persisted");
+
+        // Bytes that do not match their record are a miss
+        let store = DirectoryStore::new(&root);
+        std::fs::write(store.file("a.bin", "bin"), b"torn").unwrap();
+        assert!(block_on(store.get("a.bin")).unwrap().is_none());
+
+        block_on(cache.clear()).unwrap();
+        assert_eq!(block_on(cache.usage()).unwrap(), (0, 0));
+        assert_eq!(std::fs::read_dir(&root).unwrap().count(), 0);
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+
+    #[test]
+    fn cached_source_sends_ranges_to_the_network() {
+        let inner = Arc::new(VersionedSource::default());
+        inner.publish("a.bin", b"abcdef", "\"v1\"");
+        let cache = Arc::new(OfflineCache::new(Box::new(InMemoryStore::new())));
+        let source = CachedSource::new(inner.clone(), Arc::clone(&cache));
+
+        assert_eq!(block_on(source.fetch("a.bin")).unwrap(), b"abcdef");
+        assert_eq!(block_on(source.fetch_range("a.bin", 1, 2)).unwrap(), b"bc");
+        assert_eq!(block_on(cache.usage()).unwrap(), (6, 1));
+        assert_eq!(inner.fetches.load(Ordering::Relaxed), 2);
+        assert_eq!(source.describe(), "versioned cached in memory");
+    }
+}
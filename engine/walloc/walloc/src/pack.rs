@@ -0,0 +1,442 @@
+// Layer-W packs: many assets in one file.
+//
+// A pack is fetched with a single request instead of one request per asset.
+// It starts with a fixed header and a table of contents, followed by every
+// entry's payload. All integers are little endian.
+//
+//   header   magic "LWPK" | u32 version (1) | u32 entry_count | u32 toc_size
+//            | u64 payload_offset | u64 payload_size
+//   toc      per entry: u16 path_len | path (UTF-8) | u8 asset_type | u8 0
+//            | u64 offset | u64 size | SHA-256 of the payload (32 bytes)
+//   payloads from payload_offset on, each starting at a multiple of
+//            PACK_ALIGNMENT. Entry offsets are relative to payload_offset.
+//
+// Payloads are stored as the files they were built from, undecoded.
+
+use std::collections::HashSet;
+
+use sha2::{Digest, Sha256};
+
+use super::{AssetType, IntegrityError};
+
+pub const PACK_MAGIC: &[u8; 4] = b"LWPK";
+pub const PACK_VERSION: u32 = 1;
+pub const PACK_HEADER_SIZE: usize = 32;
+pub const PACK_ALIGNMENT: usize = 64;
+
+// Bytes open_pack asks for in its first range request. The index of most
+// packs fits, larger ones take a second request.
+pub const PACK_INDEX_PREFETCH: usize = 64 * 1024;
+
+// Table of contents entry without its path
+const TOC_ENTRY_SIZE: usize = 2 + 1 + 1 + 8 + 8 + 32;
+
+// SHA-256 of an entry's payload
+pub fn content_hash(bytes: &[u8]) -> [u8; 32] {
+    Sha256::digest(bytes).into()
+}
+
+pub fn hash_hex(hash: &[u8]) -> String {
+    hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+// SHA-256 written as 64 hex digits
+pub fn hash_from_hex(hex: &str) -> Option<[u8; 32]> {
+    if hex.len() != 64 || !hex.is_ascii() {
+        return None;
+    }
+    let mut hash = [0; 32];
+    for (byte, digits) in hash.iter_mut().zip(hex.as_bytes().chunks_exact(2)) {
+        *byte = u8::from_str_radix(std::str::from_utf8(digits).ok()?, 16).ok()?;
+    }
+    Some(hash)
+}
+
+fn read_u16(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+fn read_u64(bytes: &[u8], at: usize) -> Option<u64> {
+    Some(u64::from_le_bytes(bytes.get(at..at + 8)?.try_into().ok()?))
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct PackEntry {
+    pub path: String,
+    pub asset_type: AssetType,
+    pub offset: u64,  // From the start of the payloads
+    pub size: u64,
+    pub hash: [u8; 32],
+}
+
+impl PackEntry {
+    // Whether `payload` is exactly what the pack was built with
+    pub fn verify(&self, payload: &[u8]) -> bool {
+        self.check(payload).is_ok()
+    }
+
+    pub fn check(&self, payload: &[u8]) -> Result<(), IntegrityError> {
+        if payload.len() as u64 != self.size {
+            return Err(IntegrityError::SizeMismatch {
+                path: self.path.clone(),
+                expected: self.size as usize,
+                actual: payload.len(),
+            });
+        }
+        let actual = content_hash(payload);
+        if actual != self.hash {
+            return Err(IntegrityError::HashMismatch {
+                path: self.path.clone(),
+                expected: self.hash,
+                actual,
+            });
+        }
+        Ok(())
+    }
+}
+
+// Header and table of contents of a pack
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct PackIndex {
+    pub entries: Vec<PackEntry>,
+    pub payload_offset: u64,
+    pub payload_size: u64,
+}
+
+impl PackIndex {
+    // Bytes from the start of the pack that parse() needs, read off the header
+    pub fn index_size(header: &[u8]) -> Result<usize, String> {
+        if header.len() < PACK_HEADER_SIZE || &header[..4] != PACK_MAGIC {
+            return Err("Not a Layer-W pack".to_string());
+        }
+        let version = read_u32(header, 4).unwrap_or(0);
+        if version != PACK_VERSION {
+            return Err(format!("Unsupported pack version {}", version));
+        }
+        let toc_size = read_u32(header, 12).unwrap_or(0) as usize;
+        Ok(PACK_HEADER_SIZE + toc_size)
+    }
+
+    // Parse the index from the first index_size() bytes of a pack, or more
+    pub fn parse(bytes: &[u8]) -> Result<PackIndex, String> {
+        let index_size = Self::index_size(bytes)?;
+        let toc = bytes
+            .get(PACK_HEADER_SIZE..index_size)
+            .ok_or_else(|| format!("Pack index is truncated: {} of {} bytes", bytes.len(), index_size))?;
+
+        let entry_count = read_u32(bytes, 8).unwrap_or(0) as usize;
+        let payload_offset = read_u64(bytes, 16).unwrap_or(0);
+        let payload_size = read_u64(bytes, 24).unwrap_or(0);
+        if payload_offset < index_size as u64 {
+            return Err(format!("Pack payloads start at {}, inside the index", payload_offset));
+        }
+        // Every offset below fits in a usize once the end of the pack does
+        if payload_offset.checked_add(payload_size).and_then(|end| usize::try_from(end).ok()).is_none() {
+            return Err(format!("Pack of {} + {} bytes is too large", payload_offset, payload_size));
+        }
+
+        let mut entries = Vec::with_capacity(entry_count.min(toc.len() / TOC_ENTRY_SIZE));
+        let mut paths = HashSet::new();
+        let mut at = 0;
+        for _ in 0..entry_count {
+            let truncated = || "Pack table of contents is truncated".to_string();
+            let path_len = read_u16(toc, at).ok_or_else(truncated)? as usize;
+            let path = toc.get(at + 2..at + 2 + path_len).ok_or_else(truncated)?;
+            let path = std::str::from_utf8(path).map_err(|e| format!("Pack entry path is not UTF-8: {}", e))?;
+            let fields = at + 2 + path_len;
+            if toc.len() < fields + TOC_ENTRY_SIZE - 2 {
+                return Err(truncated());
+            }
+
+            let asset_type = AssetType::from_u8(toc[fields])
+                .ok_or_else(|| format!("Pack entry '{}' has invalid type {}", path, toc[fields]))?;
+            let offset = read_u64(toc, fields + 2).unwrap_or(0);
+            let size = read_u64(toc, fields + 10).unwrap_or(0);
+            let mut hash = [0; 32];
+            hash.copy_from_slice(&toc[fields + 18..fields + 50]);
+
+            if offset.checked_add(size).is_none_or(|end| end > payload_size) {
+                return Err(format!("Pack entry '{}' lies outside the payloads", path));
+            }
+            if !paths.insert(path) {
+                return Err(format!("Pack has two entries for '{}'", path));
+            }
+
+            entries.push(PackEntry {
+                path: path.to_string(),
+                asset_type,
+                offset,
+                size,
+                hash,
+            });
+            at = fields + TOC_ENTRY_SIZE - 2;
+        }
+
+        Ok(PackIndex {
+            entries,
+            payload_offset,
+            payload_size,
+        })
+    }
+
+    pub fn entry(&self, path: &str) -> Option<&PackEntry> {
+        self.entries.iter().find(|entry| entry.path == path)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Size of the whole pack file, None if it does not fit in memory
+    pub fn pack_size(&self) -> Option<usize> {
+        usize::try_from(self.payload_offset.checked_add(self.payload_size)?).ok()
+    }
+
+    // Payload section of a whole pack file
+    pub fn payloads<'a>(&self, pack: &'a [u8]) -> Option<&'a [u8]> {
+        pack.get(usize::try_from(self.payload_offset).ok()?..self.pack_size()?)
+    }
+
+    // Payload of an entry, given the payload section of its pack
+    pub fn payload<'a>(&self, entry: &PackEntry, payloads: &'a [u8]) -> Option<&'a [u8]> {
+        let start = usize::try_from(entry.offset).ok()?;
+        payloads.get(start..start.checked_add(usize::try_from(entry.size).ok()?)?)
+    }
+}
+
+// Lays assets out in the pack format
+#[derive(Default)]
+pub struct PackBuilder {
+    entries: Vec<(String, AssetType, Vec<u8>)>,
+}
+
+impl PackBuilder {
+    pub fn new() -> Self {
+        PackBuilder::default()
+    }
+
+    pub fn with_entry(mut self, path: &str, asset_type: AssetType, bytes: impl Into<Vec<u8>>) -> Self {
+        self.add(path, asset_type, bytes);
+        self
+    }
+
+    // Add an asset, replacing an earlier one with the same path
+    pub fn add(&mut self, path: &str, asset_type: AssetType, bytes: impl Into<Vec<u8>>) {
+        let bytes = bytes.into();
+        match self.entries.iter_mut().find(|(existing, _, _)| existing == path) {
+            Some(entry) => *entry = (path.to_string(), asset_type, bytes),
+            None => self.entries.push((path.to_string(), asset_type, bytes)),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn build(&self) -> Result<Vec<u8>, String> {
+        let entry_count = u32::try_from(self.entries.len()).map_err(|_| "Too many pack entries".to_string())?;
+
+        // Place every payload first, the table of contents needs the offsets
+        let mut toc = Vec::new();
+        let mut payload_size: usize = 0;
+        for (path, asset_type, bytes) in &self.entries {
+            let path_len = u16::try_from(path.len()).map_err(|_| format!("Pack entry path is too long: {}", path))?;
+            let offset = payload_size.next_multiple_of(PACK_ALIGNMENT);
+            payload_size = offset + bytes.len();
+
+            toc.extend_from_slice(&path_len.to_le_bytes());
+            toc.extend_from_slice(path.as_bytes());
+            toc.push(*asset_type as u8);
+            toc.push(0);
+            toc.extend_from_slice(&(offset as u64).to_le_bytes());
+            toc.extend_from_slice(&(bytes.len() as u64).to_le_bytes());
+            toc.extend_from_slice(&content_hash(bytes));
+        }
+
+        let toc_size = u32::try_from(toc.len()).map_err(|_| "Pack table of contents is too large".to_string())?;
+        let payload_offset = (PACK_HEADER_SIZE + toc.len()).next_multiple_of(PACK_ALIGNMENT);
+
+        let mut pack = Vec::with_capacity(payload_offset + payload_size);
+        pack.extend_from_slice(PACK_MAGIC);
+        pack.extend_from_slice(&PACK_VERSION.to_le_bytes());
+        pack.extend_from_slice(&entry_count.to_le_bytes());
+        pack.extend_from_slice(&toc_size.to_le_bytes());
+        pack.extend_from_slice(&(payload_offset as u64).to_le_bytes());
+        pack.extend_from_slice(&(payload_size as u64).to_le_bytes());
+        pack.extend_from_slice(&toc);
+
+        for (_, _, bytes) in &self.entries {
+            pack.resize(pack.len().next_multiple_of(PACK_ALIGNMENT), 0);
+            pack.extend_from_slice(bytes);
+        }
+        pack.resize(payload_offset + payload_size, 0);
+        Ok(pack)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pack() -> Vec<u8> {
+        PackBuilder::new()
+            .with_entry("a.txt", AssetType::Text, b"first entry".to_vec())
+            .with_entry("b.json", AssetType::Json, b"{\"second\": true}".to_vec())
+            .with_entry("empty.bin", AssetType::Image, Vec::new())
+            .build()
+            .unwrap()
+    }
+
+    fn position(pack: &[u8], bytes: &[u8]) -> usize {
+        pack.windows(bytes.len()).position(|window| window == bytes).unwrap()
+    }
+
+    #[test]
+    fn built_pack_parses_back() {
+        let pack = pack();
+        let index = PackIndex::parse(&pack).unwrap();
+        assert_eq!(index.len(), 3);
+        assert_eq!(index.pack_size(), Some(pack.len()));
+        assert_eq!(index.payload_offset as usize % PACK_ALIGNMENT, 0);
+
+        let payloads = index.payloads(&pack).unwrap();
+        for (path, asset_type, bytes) in [
+            ("a.txt", AssetType::Text, &b"first entry"[..]),
+            ("b.json", AssetType::Json, &b"{\"second\": true}"[..]),
+            ("empty.bin", AssetType::Image, &b""[..]),
+        ] {
+            let entry = index.entry(path).unwrap();
+            assert_eq!(entry.asset_type, asset_type);
+            assert_eq!(entry.offset as usize % PACK_ALIGNMENT, 0);
+            assert_eq!(entry.hash, content_hash(bytes));
+            let payload = index.payload(entry, payloads).unwrap();
+            assert_eq!(payload, bytes);
+            assert!(entry.verify(payload));
+        }
+        assert!(index.entry("missing").is_none());
+    }
+
+    #[test]
+    fn index_parses_from_the_prefix_alone() {
+        let pack = pack();
+        let index_size = PackIndex::index_size(&pack[..PACK_HEADER_SIZE]).unwrap();
+        assert!(index_size < pack.len());
+        assert_eq!(PackIndex::parse(&pack[..index_size]).unwrap(), PackIndex::parse(&pack).unwrap());
+
+        let error = PackIndex::parse(&pack[..index_size - 1]).unwrap_err();
+        assert!(error.starts_with("Pack index is truncated"), "{}", error);
+    }
+
+    #[test]
+    fn adding_a_path_again_replaces_it() {
+        let mut builder = PackBuilder::new().with_entry("a.txt", AssetType::Text, b"old".to_vec());
+        builder.add("a.txt", AssetType::Shader, b"new".to_vec());
+        assert_eq!(builder.len(), 1);
+
+        let pack = builder.build().unwrap();
+        let index = PackIndex::parse(&pack).unwrap();
+        let entry = index.entry("a.txt").unwrap();
+        assert_eq!(entry.asset_type, AssetType::Shader);
+        assert_eq!(index.payload(entry, index.payloads(&pack).unwrap()), Some(&b"new"[..]));
+    }
+
+    #[test]
+    fn corrupt_headers_are_rejected() {
+        let mut pack = pack();
+        assert_eq!(PackIndex::parse(&pack[..PACK_HEADER_SIZE - 1]).unwrap_err(), "Not a Layer-W pack");
+
+        pack[0] = b'X';
+        assert_eq!(PackIndex::parse(&pack).unwrap_err(), "Not a Layer-W pack");
+        pack[0] = b'L';
+
+        pack[4..8].copy_from_slice(&2u32.to_le_bytes());
+        assert_eq!(PackIndex::parse(&pack).unwrap_err(), "Unsupported pack version 2");
+        pack[4..8].copy_from_slice(&PACK_VERSION.to_le_bytes());
+
+        let payload_offset = pack[16..24].to_vec();
+        pack[16..24].copy_from_slice(&0u64.to_le_bytes());
+        assert_eq!(PackIndex::parse(&pack).unwrap_err(), "Pack payloads start at 0, inside the index");
+        pack[16..24].copy_from_slice(&payload_offset);
+
+        // The end of the pack has to fit in a u64 and a usize
+        pack[24..32].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert!(PackIndex::parse(&pack).unwrap_err().contains("is too large"));
+
+        let index = PackIndex {
+            entries: Vec::new(),
+            payload_offset: 64,
+            payload_size: u64::MAX,
+        };
+        assert_eq!(index.pack_size(), None);
+        assert_eq!(index.payloads(&pack), None);
+    }
+
+    #[test]
+    fn corrupt_tables_of_contents_are_rejected() {
+        let pack = pack();
+
+        let mut more_entries = pack.clone();
+        more_entries[8..12].copy_from_slice(&4u32.to_le_bytes());
+        assert_eq!(PackIndex::parse(&more_entries).unwrap_err(), "Pack table of contents is truncated");
+
+        let mut bad_type = pack.clone();
+        bad_type[position(&pack, b"a.txt") + 5] = 99;
+        assert_eq!(PackIndex::parse(&bad_type).unwrap_err(), "Pack entry 'a.txt' has invalid type 99");
+
+        let mut short_payloads = pack.clone();
+        short_payloads[24..32].copy_from_slice(&11u64.to_le_bytes());
+        assert_eq!(PackIndex::parse(&short_payloads).unwrap_err(), "Pack entry 'b.json' lies outside the payloads");
+
+        let mut overflow = pack.clone();
+        let size = position(&pack, b"a.txt") + 5 + 10;
+        overflow[size..size + 8].copy_from_slice(&u64::MAX.to_le_bytes());
+        assert_eq!(PackIndex::parse(&overflow).unwrap_err(), "Pack entry 'a.txt' lies outside the payloads");
+
+        // Same length paths, so one can be renamed to the other in place
+        let mut duplicate = PackBuilder::new()
+            .with_entry("a.txt", AssetType::Text, b"a".to_vec())
+            .with_entry("b.txt", AssetType::Text, b"b".to_vec())
+            .build()
+            .unwrap();
+        let second = position(&duplicate, b"b.txt");
+        duplicate[second] = b'a';
+        assert_eq!(PackIndex::parse(&duplicate).unwrap_err(), "Pack has two entries for 'a.txt'");
+    }
+
+    #[test]
+    fn entries_check_size_and_hash() {
+        let pack = pack();
+        let index = PackIndex::parse(&pack).unwrap();
+        let entry = index.entry("a.txt").unwrap();
+
+        assert!(matches!(
+            entry.check(b"first"),
+            Err(IntegrityError::SizeMismatch { expected: 11, actual: 5, .. })
+        ));
+        assert!(matches!(entry.check(b"first_entry"), Err(IntegrityError::HashMismatch { .. })));
+        assert!(!entry.verify(b"first_entry"));
+    }
+
+    #[test]
+    fn hashes_round_trip_through_hex() {
+        let hash = content_hash(b"abc");
+        let hex = hash_hex(&hash);
+        assert_eq!(hex, "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(hash_from_hex(&hex), Some(hash));
+        assert_eq!(hash_from_hex(&hex.to_uppercase()), Some(hash));
+        assert_eq!(hash_from_hex(&hex[1..]), None);
+        assert_eq!(hash_from_hex(&hex.replace('b', "g")), None);
+    }
+}
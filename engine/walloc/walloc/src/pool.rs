@@ -0,0 +1,317 @@
+// Fixed size object pools carved out of a tier.
+//
+// A Pool<T> takes one block of `capacity` slots from a tier (usually Entity)
+// and recycles them one by one, so particles, projectiles and actors can be
+// freed without resetting the rest of the tier. Free slots hold the index of
+// the next free slot, which makes alloc and free O(1) without any bookkeeping
+// memory beyond a generation per slot.
+//
+// Each slot's generation is odd while it holds a value and even while it is
+// free. A PoolHandle remembers the generation it was handed out with, so a
+// handle to a freed and reused slot no longer resolves. Like the typed
+// containers, a pool whose tier was reset or compacted away returns None
+// everywhere and never runs the destructors of the values it lost.
+
+use std::marker::PhantomData;
+use std::mem::ManuallyDrop;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use super::{AllocHandle, MemoryOwner, Tier};
+use super::typed::align_ptr;
+
+// Terminates the free list
+const NO_SLOT: u32 = u32::MAX;
+
+// Largest number of slots in a pool, NO_SLOT is reserved
+pub const MAX_POOL_SLOTS: usize = NO_SLOT as usize;
+
+#[repr(C)]
+pub(crate) union Slot<T> {
+    value: ManuallyDrop<T>,  // Live slot
+    next: u32,               // Free slot: next free slot or NO_SLOT
+}
+
+// Names a value in a pool as long as its slot has not been freed
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct PoolHandle {
+    index: u32,
+    generation: u32,
+}
+
+impl PoolHandle {
+    pub fn index(&self) -> usize {
+        self.index as usize
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+}
+
+// Shared between a pool and the allocator that made it, for memory_stats
+pub(crate) struct PoolCounters {
+    name: &'static str,
+    tier: Tier,
+    capacity: usize,
+    slot_size: usize,
+    live: AtomicUsize,
+}
+
+impl PoolCounters {
+    pub(crate) fn stats(&self) -> PoolStats {
+        PoolStats {
+            name: self.name,
+            tier: self.tier,
+            capacity: self.capacity,
+            live: self.live.load(Ordering::Relaxed),
+            slot_size: self.slot_size,
+        }
+    }
+}
+
+// Snapshot of a pool
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PoolStats {
+    pub name: &'static str,  // Type of the pooled values
+    pub tier: Tier,
+    pub capacity: usize,
+    pub live: usize,
+    pub slot_size: usize,
+}
+
+impl PoolStats {
+    // Share of the slots holding a value, 0.0 to 1.0
+    pub fn occupancy(&self) -> f32 {
+        if self.capacity == 0 {
+            0.0
+        } else {
+            self.live as f32 / self.capacity as f32
+        }
+    }
+}
+
+pub struct Pool<T> {
+    owner: MemoryOwner,  // One block holding every slot
+    generations: Vec<u32>,
+    free_head: u32,
+    fresh: u32,  // Slots from here on were never handed out and are not on the free list
+    counters: Arc<PoolCounters>,
+    _marker: PhantomData<T>,
+}
+
+impl<T> Pool<T> {
+    // `owner` holds one block with room for `capacity` Slot<T>-aligned slots
+    pub(crate) fn new(owner: MemoryOwner, capacity: usize) -> Self {
+        let counters = Arc::new(PoolCounters {
+            name: std::any::type_name::<T>(),
+            tier: owner.tier(),
+            capacity,
+            slot_size: std::mem::size_of::<Slot<T>>(),
+            live: AtomicUsize::new(0),
+        });
+
+        Pool {
+            owner,
+            generations: vec![0; capacity],
+            free_head: NO_SLOT,
+            fresh: 0,
+            counters,
+            _marker: PhantomData,
+        }
+    }
+
+    pub(crate) fn counters(&self) -> &Arc<PoolCounters> {
+        &self.counters
+    }
+
+    fn slots(&self) -> Option<*mut Slot<T>> {
+        let (ptr, _) = self.owner.resolve(0)?;
+        Some(align_ptr::<Slot<T>>(ptr))
+    }
+
+    // Slot of a live handle
+    fn slot(&self, handle: PoolHandle) -> Option<*mut Slot<T>> {
+        if self.generations.get(handle.index as usize) != Some(&handle.generation) {
+            return None;
+        }
+        Some(unsafe { self.slots()?.add(handle.index as usize) })
+    }
+
+    // Move a value into a free slot, handing it back if the pool is full
+    // or its tier was recycled
+    pub fn alloc(&mut self, value: T) -> Result<PoolHandle, T> {
+        let slots = match self.slots() {
+            Some(slots) => slots,
+            None => return Err(value),
+        };
+
+        let index = if self.free_head != NO_SLOT {
+            let index = self.free_head;
+            self.free_head = unsafe { (*slots.add(index as usize)).next };
+            index
+        } else if (self.fresh as usize) < self.generations.len() {
+            self.fresh += 1;
+            self.fresh - 1
+        } else {
+            return Err(value);
+        };
+
+        unsafe { slots.add(index as usize).write(Slot { value: ManuallyDrop::new(value) }) };
+        let generation = &mut self.generations[index as usize];
+        *generation = generation.wrapping_add(1);
+        self.counters.live.fetch_add(1, Ordering::Relaxed);
+
+        Ok(PoolHandle {
+            index,
+            generation: *generation,
+        })
+    }
+
+    // Move a value out and put its slot back on the free list
+    pub fn free(&mut self, handle: PoolHandle) -> Option<T> {
+        let slot = self.slot(handle)?;
+        let value = unsafe { ManuallyDrop::into_inner(slot.read().value) };
+
+        unsafe { slot.write(Slot { next: self.free_head }) };
+        self.free_head = handle.index;
+        self.generations[handle.index as usize] = handle.generation.wrapping_add(1);
+        self.counters.live.fetch_sub(1, Ordering::Relaxed);
+
+        Some(value)
+    }
+
+    // None once the value was freed or the tier recycled the pool's memory
+    pub fn get(&self, handle: PoolHandle) -> Option<&T> {
+        Some(unsafe { &(*self.slot(handle)?).value })
+    }
+
+    pub fn get_mut(&mut self, handle: PoolHandle) -> Option<&mut T> {
+        Some(unsafe { &mut (*self.slot(handle)?).value })
+    }
+
+    pub fn contains(&self, handle: PoolHandle) -> bool {
+        self.slot(handle).is_some()
+    }
+
+    // Live values in slot order. Empty once the tier recycled the pool's memory.
+    pub fn iter(&self) -> impl Iterator<Item = (PoolHandle, &T)> {
+        let slots = self.slots();
+        live_handles(&self.generations[..self.fresh as usize]).filter_map(move |handle| {
+            let slot = unsafe { slots?.add(handle.index as usize) };
+            Some((handle, unsafe { &*(*slot).value }))
+        })
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (PoolHandle, &mut T)> {
+        let slots = self.slots();
+        live_handles(&self.generations[..self.fresh as usize]).filter_map(move |handle| {
+            let slot = unsafe { slots?.add(handle.index as usize) };
+            Some((handle, unsafe { &mut *(*slot).value }))
+        })
+    }
+
+    // Free every live value, keeping the pool's memory
+    pub fn clear(&mut self) {
+        let handles: Vec<PoolHandle> = live_handles(&self.generations[..self.fresh as usize]).collect();
+        for handle in handles {
+            self.free(handle);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.counters.live.load(Ordering::Relaxed)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.generations.len()
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.len() == self.capacity()
+    }
+
+    // False once the tier has recycled the pool's memory
+    pub fn is_valid(&self) -> bool {
+        self.slots().is_some()
+    }
+
+    // Handle of the block holding every slot
+    pub fn handle(&self) -> Option<AllocHandle> {
+        self.owner.handle(0)
+    }
+
+    pub fn stats(&self) -> PoolStats {
+        self.counters.stats()
+    }
+}
+
+impl<T> Drop for Pool<T> {
+    // Values lost to a reset of the tier are gone already, their destructors never run
+    fn drop(&mut self) {
+        if self.is_valid() {
+            self.clear();
+        }
+    }
+}
+
+// Handles of the slots whose generation marks them live
+fn live_handles(generations: &[u32]) -> impl Iterator<Item = PoolHandle> + '_ {
+    generations
+        .iter()
+        .enumerate()
+        .filter(|(_, generation)| *generation % 2 == 1)
+        .map(|(index, &generation)| PoolHandle {
+            index: index as u32,
+            generation,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{NativeMemory, Tier, TieredAllocator};
+
+    fn allocator() -> TieredAllocator {
+        TieredAllocator::new(Box::new(NativeMemory::new(64)), 16)
+    }
+
+    #[test]
+    fn capacity_must_fit_the_slot_index() {
+        let mut allocator = allocator();
+        assert!(allocator.create_pool::<u32>(0, Tier::ENTITY).is_none());
+        #[cfg(target_pointer_width = "64")]
+        assert!(allocator.create_pool::<u32>(u32::MAX as usize + 1, Tier::ENTITY).is_none());
+        assert_eq!(allocator.create_pool::<u32>(8, Tier::ENTITY).unwrap().capacity(), 8);
+    }
+
+    #[test]
+    fn freed_slots_are_reused_with_a_new_generation() {
+        let mut allocator = allocator();
+        let mut pool = allocator.create_pool::<u64>(2, Tier::ENTITY).unwrap();
+        let a = pool.alloc(1).unwrap();
+        pool.alloc(2).unwrap();
+        assert_eq!(pool.alloc(3), Err(3));
+
+        assert_eq!(pool.free(a), Some(1));
+        assert_eq!(pool.free(a), None);
+        let b = pool.alloc(4).unwrap();
+        assert_eq!(b.index(), a.index());
+        assert_ne!(b.generation(), a.generation());
+        assert_eq!(pool.get(a), None);
+        assert_eq!(pool.get(b), Some(&4));
+    }
+
+    #[test]
+    fn reset_tier_invalidates_the_pool() {
+        let mut allocator = allocator();
+        let mut pool = allocator.create_pool::<u64>(4, Tier::ENTITY).unwrap();
+        let handle = pool.alloc(7).unwrap();
+        allocator.reset_tier(Tier::ENTITY);
+        assert!(!pool.is_valid());
+        assert_eq!(pool.get(handle), None);
+    }
+}
@@ -0,0 +1,346 @@
+// Memory pressure.
+//
+// Each tier can have a warning and a critical threshold, in bytes held by live
+// blocks. The allocator checks the tier's level after every allocation, reset
+// and compaction, and tells every pressure handler when it changes, so the
+// application can free or evict memory before the tier runs out.
+//
+// A tier runs out when an allocation fails and the tier cannot grow. Handlers
+// then hear a critical event marked exhausted and get one chance to make room
+// before the allocation is retried. If it still fails, the tier's exhaustion
+// policy decides what happens next.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::ThreadId;
+
+use super::{Tier, TieredAllocator};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub enum PressureLevel {
+    #[default]
+    Normal,
+    Warning,
+    Critical,
+}
+
+impl PressureLevel {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PressureLevel::Normal => "normal",
+            PressureLevel::Warning => "warning",
+            PressureLevel::Critical => "critical",
+        }
+    }
+}
+
+// Bytes in use at which a tier reaches each level, None to never reach it
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub struct PressureThresholds {
+    pub warning: Option<usize>,
+    pub critical: Option<usize>,
+}
+
+impl PressureThresholds {
+    pub fn new(warning: usize, critical: usize) -> Self {
+        PressureThresholds { warning: Some(warning), critical: Some(critical) }
+    }
+
+    pub fn level(&self, used: usize) -> PressureLevel {
+        if self.critical.is_some_and(|critical| used >= critical) {
+            PressureLevel::Critical
+        } else if self.warning.is_some_and(|warning| used >= warning) {
+            PressureLevel::Warning
+        } else {
+            PressureLevel::Normal
+        }
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        match (self.warning, self.critical) {
+            (Some(warning), Some(critical)) if warning > critical => Err(format!(
+                "Warning threshold {} is above the critical threshold {}", warning, critical
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+// What an allocation does once its tier is out of space, after the pressure
+// handlers had their chance
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum ExhaustionPolicy {
+    #[default]
+    Reset,  // Reset the whole tier and retry, every live block in it is recycled
+    Fail,   // The allocation fails and the tier is left alone
+}
+
+impl ExhaustionPolicy {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "reset" => Some(ExhaustionPolicy::Reset),
+            "fail" => Some(ExhaustionPolicy::Fail),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExhaustionPolicy::Reset => "reset",
+            ExhaustionPolicy::Fail => "fail",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct PressureEvent {
+    pub tier: Tier,
+    pub level: PressureLevel,
+    pub previous: PressureLevel,
+    pub used: usize,
+    pub capacity: usize,
+    pub requested: usize,  // Size of the allocation that failed, 0 unless exhausted
+    pub exhausted: bool,   // The tier is out of space and cannot grow
+}
+
+// Called with the allocator so it can free blocks, evict assets or reset tiers.
+// Allocations a handler makes never call the handlers again, the level they
+// leave the tier at is reported once the handlers are done.
+pub type PressureCallback = Arc<dyn Fn(&mut TieredAllocator, &PressureEvent) + Send + Sync>;
+
+// Registered handlers, shared by every clone of the allocator
+#[derive(Default)]
+pub(crate) struct PressureHandlers {
+    callbacks: Mutex<Vec<(usize, PressureCallback)>>,
+    next_id: AtomicUsize,
+    dispatching: Mutex<Vec<ThreadId>>,  // Threads calling the handlers right now
+}
+
+impl PressureHandlers {
+    pub fn add(&self, callback: PressureCallback) -> usize {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed) + 1;
+        if let Ok(mut callbacks) = self.callbacks.lock() {
+            callbacks.push((id, callback));
+        }
+        id
+    }
+
+    pub fn remove(&self, id: usize) -> bool {
+        match self.callbacks.lock() {
+            Ok(mut callbacks) => {
+                let count = callbacks.len();
+                callbacks.retain(|(other, _)| *other != id);
+                callbacks.len() != count
+            },
+            Err(_) => false,
+        }
+    }
+
+    // Handlers to call for an event, None while this thread is already calling
+    // them. Allocations on other threads still dispatch their own events.
+    pub fn begin_dispatch(&self) -> Option<Dispatch<'_>> {
+        let thread = std::thread::current().id();
+        let mut dispatching = self.dispatching.lock().ok()?;
+        if dispatching.contains(&thread) {
+            return None;
+        }
+        dispatching.push(thread);
+        drop(dispatching);
+
+        let callbacks = match self.callbacks.lock() {
+            Ok(callbacks) => callbacks.iter().map(|(_, callback)| Arc::clone(callback)).collect(),
+            Err(_) => Vec::new(),
+        };
+        Some(Dispatch { handlers: self, thread, callbacks })
+    }
+
+    pub fn is_dispatching(&self) -> bool {
+        let thread = std::thread::current().id();
+        self.dispatching.lock().map_or(true, |dispatching| dispatching.contains(&thread))
+    }
+}
+
+// A thread's turn calling the handlers. It ends when this is dropped, even if
+// a handler panicked.
+pub(crate) struct Dispatch<'a> {
+    handlers: &'a PressureHandlers,
+    thread: ThreadId,
+    pub callbacks: Vec<PressureCallback>,
+}
+
+impl Drop for Dispatch<'_> {
+    fn drop(&mut self) {
+        let mut dispatching = match self.handlers.dispatching.lock() {
+            Ok(dispatching) => dispatching,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        dispatching.retain(|thread| *thread != self.thread);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::sync::mpsc;
+
+    use super::*;
+    use crate::NativeMemory;
+
+    fn allocator() -> TieredAllocator {
+        TieredAllocator::new(Box::new(NativeMemory::new(64)), 16)
+    }
+
+    // Every event the handlers hear from now on
+    fn record(allocator: &TieredAllocator) -> Arc<Mutex<Vec<PressureEvent>>> {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&events);
+        allocator.on_pressure(move |_, event| recorded.lock().unwrap().push(*event));
+        events
+    }
+
+    fn levels(events: &Mutex<Vec<PressureEvent>>) -> Vec<(PressureLevel, PressureLevel)> {
+        events.lock().unwrap().drain(..).map(|event| (event.previous, event.level)).collect()
+    }
+
+    #[test]
+    fn thresholds_report_each_crossing_once() {
+        let mut allocator = allocator();
+        let events = record(&allocator);
+        allocator.set_pressure_thresholds(Tier::ENTITY, PressureThresholds::new(1024, 4096)).unwrap();
+
+        assert!(!allocator.allocate(512, Tier::ENTITY).is_null());
+        assert!(levels(&events).is_empty());
+        assert!(!allocator.allocate(1024, Tier::ENTITY).is_null());
+        assert_eq!(levels(&events), [(PressureLevel::Normal, PressureLevel::Warning)]);
+        assert!(!allocator.allocate(16, Tier::ENTITY).is_null());
+        assert!(levels(&events).is_empty());
+        assert!(!allocator.allocate(4096, Tier::ENTITY).is_null());
+        assert_eq!(levels(&events), [(PressureLevel::Warning, PressureLevel::Critical)]);
+        assert_eq!(allocator.pressure_level(Tier::ENTITY), PressureLevel::Critical);
+
+        allocator.reset_tier(Tier::ENTITY);
+        assert_eq!(levels(&events), [(PressureLevel::Critical, PressureLevel::Normal)]);
+
+        // Other tiers keep their own level
+        assert!(!allocator.allocate(8192, Tier::SCENE).is_null());
+        assert!(levels(&events).is_empty());
+    }
+
+    #[test]
+    fn invalid_thresholds_are_rejected() {
+        let mut allocator = allocator();
+        assert!(allocator.set_pressure_thresholds(Tier::ENTITY, PressureThresholds::new(4096, 1024)).is_err());
+        assert_eq!(allocator.pressure_thresholds(Tier::ENTITY), PressureThresholds::default());
+        assert_eq!(PressureThresholds { warning: None, critical: Some(10) }.level(100), PressureLevel::Critical);
+        assert_eq!(PressureThresholds::default().level(usize::MAX), PressureLevel::Normal);
+    }
+
+    #[test]
+    fn exhausted_handlers_make_room_before_the_retry() {
+        let mut allocator = allocator();
+        allocator.set_exhaustion_policy(Tier::SCENE, ExhaustionPolicy::Fail);
+        let kept = allocator.allocate_handle(2 << 20, Tier::SCENE).unwrap();
+
+        let requested = Arc::new(Mutex::new(Vec::new()));
+        let seen = Arc::clone(&requested);
+        allocator.on_pressure(move |allocator, event| {
+            if event.exhausted {
+                seen.lock().unwrap().push(event.requested);
+                allocator.reset_tier(event.tier);
+            }
+        });
+
+        // Does not fit beside the first block, does once the handler reset the tier
+        let handle = allocator.allocate_handle(2 << 20, Tier::SCENE);
+        assert_eq!(*requested.lock().unwrap(), [2 << 20]);
+        assert!(handle.is_some_and(|handle| allocator.is_handle_valid(&handle)));
+        assert!(!allocator.is_handle_valid(&kept));
+    }
+
+    #[test]
+    fn the_fail_policy_leaves_the_tier_alone() {
+        let mut allocator = allocator();
+        allocator.set_exhaustion_policy(Tier::SCENE, ExhaustionPolicy::Fail);
+        let kept = allocator.allocate_handle(2 << 20, Tier::SCENE).unwrap();
+        let events = record(&allocator);
+
+        assert!(allocator.allocate(2 << 20, Tier::SCENE).is_null());
+        assert!(allocator.is_handle_valid(&kept));
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 1);
+        assert!(events[0].exhausted && events[0].requested == 2 << 20);
+    }
+
+    #[test]
+    fn the_reset_policy_recycles_the_tier_and_retries() {
+        let mut allocator = allocator();
+        assert_eq!(allocator.exhaustion_policy(Tier::SCENE), ExhaustionPolicy::Reset);
+        let kept = allocator.allocate_handle((2 << 20) + 4096, Tier::SCENE).unwrap();
+
+        assert!(!allocator.allocate(2 << 20, Tier::SCENE).is_null());
+        assert!(!allocator.is_handle_valid(&kept));
+    }
+
+    #[test]
+    fn handler_allocations_are_reported_once_they_return() {
+        let mut allocator = allocator();
+        allocator.set_pressure_thresholds(Tier::ENTITY, PressureThresholds::new(1024, 4096)).unwrap();
+        let events = record(&allocator);
+        allocator.on_pressure(|allocator, event| {
+            if event.level == PressureLevel::Warning {
+                allocator.allocate(4096, Tier::ENTITY);
+            }
+        });
+
+        assert!(!allocator.allocate(1024, Tier::ENTITY).is_null());
+        assert_eq!(levels(&events), [
+            (PressureLevel::Normal, PressureLevel::Warning),
+            (PressureLevel::Warning, PressureLevel::Critical),
+        ]);
+    }
+
+    #[test]
+    fn a_panicking_handler_does_not_silence_the_others() {
+        let mut allocator = allocator();
+        allocator.set_pressure_thresholds(Tier::ENTITY, PressureThresholds::new(1024, 4096)).unwrap();
+        let panicking = allocator.on_pressure(|_, _| panic!("handler failed"));
+
+        let result = catch_unwind(AssertUnwindSafe(|| allocator.allocate(1024, Tier::ENTITY)));
+        assert!(result.is_err());
+        assert!(allocator.remove_pressure_handler(panicking));
+
+        let events = record(&allocator);
+        assert!(!allocator.allocate(4096, Tier::ENTITY).is_null());
+        assert_eq!(levels(&events), [(PressureLevel::Warning, PressureLevel::Critical)]);
+    }
+
+    #[test]
+    fn clones_on_other_threads_dispatch_while_a_handler_runs() {
+        let mut allocator = allocator();
+        allocator.set_pressure_thresholds(Tier::SCENE, PressureThresholds::new(1024, 1 << 20)).unwrap();
+        allocator.set_pressure_thresholds(Tier::ENTITY, PressureThresholds::new(1024, 1 << 20)).unwrap();
+        let events = record(&allocator);
+
+        // The scene handler holds its thread until the entity event was heard
+        let (started, wait_started) = mpsc::channel();
+        let (release, wait_release) = mpsc::channel::<()>();
+        let wait_release = Mutex::new(wait_release);
+        allocator.on_pressure(move |_, event| {
+            if event.tier == Tier::SCENE {
+                started.send(()).unwrap();
+                wait_release.lock().unwrap().recv().unwrap();
+            }
+        });
+
+        let mut other = allocator.clone();
+        let scene = std::thread::spawn(move || other.allocate(1024, Tier::SCENE).is_null());
+        wait_started.recv().unwrap();
+        assert!(!allocator.allocate(1024, Tier::ENTITY).is_null());
+        let heard: Vec<Tier> = events.lock().unwrap().iter().map(|event| event.tier).collect();
+        assert!(heard.contains(&Tier::ENTITY));
+
+        release.send(()).unwrap();
+        assert!(!scene.join().unwrap());
+        assert_eq!(allocator.pressure_level(Tier::SCENE), PressureLevel::Warning);
+    }
+}
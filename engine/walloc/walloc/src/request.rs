@@ -0,0 +1,233 @@
+// What to load and how.
+//
+// An AssetRequest names an asset and the type to decode it as, and can add
+// request headers and a credentials mode for the fetch, a byte range of the
+// file, a priority among queued loads, the tier to place it in and a SHA-256
+// it has to match. TieredAllocator::load runs one.
+//
+// A ranged request is cached under its own key, `path#bytes=start-end`, so
+// several ranges of one file and the whole file can be resident together.
+
+use wasm_bindgen::prelude::*;
+
+use super::{AssetType, Credentials, FetchOptions, IntegrityError, Tier, WallocError, content_hash, hash_from_hex};
+
+// Queued loads start highest priority first, in request order within one
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Debug, Default)]
+pub enum Priority {
+    Low,
+    #[default]
+    Normal,
+    High,
+    Critical,  // Needed for the next frame
+}
+
+impl Priority {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "low" => Some(Priority::Low),
+            "normal" => Some(Priority::Normal),
+            "high" => Some(Priority::High),
+            "critical" => Some(Priority::Critical),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Priority::Low => "low",
+            Priority::Normal => "normal",
+            Priority::High => "high",
+            Priority::Critical => "critical",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct AssetRequest {
+    pub path: String,
+    pub asset_type: AssetType,
+    pub headers: Vec<(String, String)>,
+    pub credentials: Credentials,
+    pub range: Option<(u64, u64)>,  // Start and length in bytes
+    pub priority: Priority,
+    pub tier: Option<Tier>,  // None picks by type, textures and meshes to the render tier
+    pub hash: Option<[u8; 32]>,  // SHA-256 of the fetched bytes
+}
+
+impl AssetRequest {
+    pub fn new(path: &str, asset_type: AssetType) -> Self {
+        AssetRequest {
+            path: path.to_string(),
+            asset_type,
+            headers: Vec::new(),
+            credentials: Credentials::default(),
+            range: None,
+            priority: Priority::default(),
+            tier: None,
+            hash: None,
+        }
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn with_credentials(mut self, credentials: Credentials) -> Self {
+        self.credentials = credentials;
+        self
+    }
+
+    // `len` bytes from `start` on, fewer at the end of the file
+    pub fn with_range(mut self, start: u64, len: u64) -> Self {
+        self.range = Some((start, len));
+        self
+    }
+
+    pub fn with_priority(mut self, priority: Priority) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_tier(mut self, tier: Tier) -> Self {
+        self.tier = Some(tier);
+        self
+    }
+
+    pub fn with_hash(mut self, hash: [u8; 32]) -> Self {
+        self.hash = Some(hash);
+        self
+    }
+
+    // Path the asset is cached and deduplicated under
+    pub fn key(&self) -> String {
+        match self.range {
+            Some((start, len)) => format!("{}#bytes={}-{}", self.path, start, start.saturating_add(len.max(1) - 1)),
+            None => self.path.clone(),
+        }
+    }
+
+    pub fn fetch_options(&self) -> FetchOptions {
+        FetchOptions {
+            headers: self.headers.clone(),
+            credentials: self.credentials,
+        }
+    }
+
+    // Check fetched bytes against the request's hash. Returns their hash,
+    // reusing `known` if something already hashed them.
+    pub(crate) fn verify(&self, bytes: &[u8], known: Option<[u8; 32]>) -> Result<Option<[u8; 32]>, IntegrityError> {
+        let expected = match self.hash {
+            Some(expected) => expected,
+            None => return Ok(known),
+        };
+        let actual = known.unwrap_or_else(|| content_hash(bytes));
+        if actual != expected {
+            return Err(IntegrityError::HashMismatch { path: self.key(), expected, actual });
+        }
+        Ok(Some(actual))
+    }
+}
+
+// From a JS object, with only path and type required:
+// {
+//   path: "textures/hero.png",
+//   type: 3,                          // number or name, e.g. "texture"
+//   headers: { Authorization: "Bearer ..." },
+//   credentials: "include",           // "omit", "same-origin" or "include"
+//   range: { start: 0, length: 4096 },
+//   priority: "high",                 // "low", "normal", "high" or "critical"
+//   tier: "render",                   // name or index
+//   sha256: "9f86d0...",
+// }
+impl AssetRequest {
+    pub fn from_js(value: &JsValue, tier_by_name: impl Fn(&str) -> Option<Tier>) -> Result<Self, WallocError> {
+        let invalid = |message: String| WallocError::InvalidArgument(message);
+        let get = |object: &JsValue, name: &str| {
+            js_sys::Reflect::get(object, &JsValue::from_str(name))
+                .map_err(|_| invalid("Asset request must be an object".to_string()))
+        };
+        let field = |name: &str| get(value, name);
+
+        let path = field("path")?
+            .as_string()
+            .ok_or_else(|| invalid("Asset request needs a 'path'".to_string()))?;
+        let asset_type = field("type")?;
+        let asset_type = match asset_type.as_string() {
+            Some(name) => AssetType::from_name(&name),
+            None => asset_type.as_f64().and_then(|value| AssetType::from_u8(value as u8)),
+        }
+        .ok_or_else(|| invalid(format!("Asset request for {} needs a valid 'type'", path)))?;
+        let mut request = AssetRequest::new(&path, asset_type);
+
+        let headers = field("headers")?;
+        if headers.is_object() {
+            for entry in js_sys::Object::entries(headers.unchecked_ref()).iter() {
+                let entry = js_sys::Array::from(&entry);
+                match (entry.get(0).as_string(), entry.get(1).as_string()) {
+                    (Some(name), Some(value)) => request = request.with_header(&name, &value),
+                    _ => return Err(invalid(format!("Headers of {} must be strings", path))),
+                }
+            }
+        }
+
+        if let Some(credentials) = field("credentials")?.as_string() {
+            let credentials = Credentials::from_name(&credentials)
+                .ok_or_else(|| invalid(format!("Unknown credentials mode '{}'", credentials)))?;
+            request = request.with_credentials(credentials);
+        }
+
+        let range = field("range")?;
+        if range.is_object() {
+            let start = get(&range, "start")?.as_f64().unwrap_or(0.0);
+            let len = get(&range, "length")?
+                .as_f64()
+                .ok_or_else(|| invalid(format!("Range of {} needs a 'length'", path)))?;
+            request = request.with_range(start.max(0.0) as u64, len.max(0.0) as u64);
+        }
+
+        if let Some(priority) = field("priority")?.as_string() {
+            let priority = Priority::from_name(&priority)
+                .ok_or_else(|| invalid(format!("Unknown priority '{}'", priority)))?;
+            request = request.with_priority(priority);
+        }
+
+        let tier = field("tier")?;
+        if let Some(name) = tier.as_string() {
+            let tier = tier_by_name(&name).ok_or_else(|| invalid(format!("Unknown tier '{}'", name)))?;
+            request = request.with_tier(tier);
+        } else if let Some(index) = tier.as_f64() {
+            request = request.with_tier(Tier::new(index as u8));
+        }
+
+        if let Some(hash) = field("sha256")?.as_string() {
+            let hash = hash_from_hex(&hash).ok_or_else(|| invalid(format!("Not a SHA-256 hash: {}", hash)))?;
+            request = request.with_hash(hash);
+        }
+        Ok(request)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_key_by_their_inclusive_end() {
+        let request = AssetRequest::new("a.bin", AssetType::Image);
+        assert_eq!(request.key(), "a.bin");
+        assert_eq!(request.clone().with_range(10, 20).key(), "a.bin#bytes=10-29");
+        assert_eq!(request.clone().with_range(10, 0).key(), "a.bin#bytes=10-10");
+        assert_eq!(request.with_range(u64::MAX - 1, 10).key(), format!("a.bin#bytes={}-{}", u64::MAX - 1, u64::MAX));
+    }
+
+    #[test]
+    fn priorities_round_trip_through_their_names() {
+        for priority in [Priority::Low, Priority::Normal, Priority::High, Priority::Critical] {
+            assert_eq!(Priority::from_name(priority.name()), Some(priority));
+        }
+        assert_eq!(Priority::from_name("urgent"), None);
+        assert!(Priority::Critical > Priority::High && Priority::Normal > Priority::Low);
+    }
+}
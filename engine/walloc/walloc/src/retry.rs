@@ -0,0 +1,498 @@
+// When a failed fetch is tried again, and how long any fetch may take.
+//
+// Fetches that fail with a timeout, a transport error or one of the
+// retryable HTTP statuses are tried again after an exponential backoff, up
+// to max_attempts in total. Everything else, such as a 404 or a hash
+// mismatch, fails right away. The timeout covers one attempt, for streaming
+// loads from the request until the last chunk.
+//
+// Waiting uses setTimeout in the browser and one shared timer thread
+// natively, so no particular async runtime is needed.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::collections::BTreeMap;
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::{Arc, Condvar, Mutex, OnceLock};
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+
+use wasm_bindgen::prelude::*;
+
+use super::WallocError;
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,  // Including the first, 1 never retries
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+    pub backoff_multiplier: f64,  // Growth of the backoff after every retry
+    pub timeout: Option<Duration>,  // Per attempt, None waits forever
+    pub retryable_statuses: Vec<u16>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(200),
+            max_backoff: Duration::from_secs(5),
+            backoff_multiplier: 2.0,
+            timeout: Some(Duration::from_secs(30)),
+            retryable_statuses: vec![408, 425, 429, 500, 502, 503, 504],
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        RetryPolicy::default()
+    }
+
+    // One attempt, no timeout
+    pub fn none() -> Self {
+        RetryPolicy::new().with_max_attempts(1).with_timeout(None)
+    }
+
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial_backoff = initial;
+        self.max_backoff = max.max(initial);
+        self
+    }
+
+    pub fn with_backoff_multiplier(mut self, multiplier: f64) -> Self {
+        self.backoff_multiplier = multiplier.max(1.0);
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn with_retryable_statuses(mut self, statuses: impl Into<Vec<u16>>) -> Self {
+        self.retryable_statuses = statuses.into();
+        self
+    }
+
+    // Whether an error is worth another attempt, regardless of attempts left
+    pub fn is_retryable(&self, error: &WallocError) -> bool {
+        match error {
+            WallocError::Http { status, .. } => self.retryable_statuses.contains(status),
+            WallocError::Fetch { .. } | WallocError::Timeout { .. } => true,
+            _ => false,
+        }
+    }
+
+    // Whether to try again after `attempt` attempts ended in `error`
+    pub fn should_retry(&self, error: &WallocError, attempt: u32) -> bool {
+        attempt < self.max_attempts && self.is_retryable(error)
+    }
+
+    // Wait before the attempt after `attempt`. Clamped before it becomes a
+    // Duration, the factor overflows one after enough attempts.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exponent = attempt.saturating_sub(1).min(i32::MAX as u32) as i32;
+        let factor = self.backoff_multiplier.powi(exponent);
+        Duration::try_from_secs_f64(self.initial_backoff.as_secs_f64() * factor)
+            .unwrap_or(self.max_backoff)
+            .min(self.max_backoff)
+    }
+}
+
+// From a JS object, every field optional and defaulting as above:
+// {
+//   maxAttempts: 3,
+//   initialBackoffMs: 200,
+//   maxBackoffMs: 5000,
+//   backoffMultiplier: 2,
+//   timeoutMs: 30000,         // 0 or null waits forever
+//   retryableStatuses: [408, 425, 429, 500, 502, 503, 504],
+// }
+impl RetryPolicy {
+    pub fn from_js(value: &JsValue) -> Result<Self, WallocError> {
+        let field = |name: &str| {
+            js_sys::Reflect::get(value, &JsValue::from_str(name))
+                .map_err(|_| WallocError::InvalidArgument("Retry policy must be an object".to_string()))
+        };
+        let millis = |millis: f64| Duration::from_millis(millis.max(0.0) as u64);
+
+        let mut policy = RetryPolicy::default();
+        if let Some(attempts) = field("maxAttempts")?.as_f64() {
+            policy = policy.with_max_attempts(attempts as u32);
+        }
+        let initial = field("initialBackoffMs")?.as_f64().map_or(policy.initial_backoff, millis);
+        let max = field("maxBackoffMs")?.as_f64().map_or(policy.max_backoff, millis);
+        policy = policy.with_backoff(initial, max);
+        if let Some(multiplier) = field("backoffMultiplier")?.as_f64() {
+            policy = policy.with_backoff_multiplier(multiplier);
+        }
+
+        let timeout = field("timeoutMs")?;
+        if let Some(timeout) = timeout.as_f64() {
+            policy = policy.with_timeout(Some(millis(timeout)).filter(|timeout| !timeout.is_zero()));
+        } else if timeout.is_null() {
+            policy = policy.with_timeout(None);
+        }
+
+        let statuses = field("retryableStatuses")?;
+        if js_sys::Array::is_array(&statuses) {
+            let statuses = js_sys::Array::from(&statuses)
+                .iter()
+                .map(|status| match status.as_f64() {
+                    Some(status) if (100.0..=599.0).contains(&status) => Ok(status as u16),
+                    _ => Err(WallocError::InvalidArgument(format!("Not an HTTP status: {:?}", status))),
+                })
+                .collect::<Result<Vec<u16>, WallocError>>()?;
+            policy = policy.with_retryable_statuses(statuses);
+        } else if !statuses.is_undefined() {
+            return Err(WallocError::InvalidArgument("retryableStatuses must be an array".to_string()));
+        }
+        Ok(policy)
+    }
+}
+
+// Run `attempt` until it succeeds, fails for good or runs out of attempts,
+// giving every attempt the policy's timeout
+pub(crate) async fn with_retry<T, F, Fut>(policy: &RetryPolicy, path: &str, mut attempt: F) -> Result<T, WallocError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, WallocError>>,
+{
+    let mut attempts = 0;
+    loop {
+        attempts += 1;
+        let result = Deadline::new(policy.timeout).run(path, attempt()).await;
+        match result {
+            Err(e) if policy.should_retry(&e, attempts) => sleep(policy.backoff(attempts)).await,
+            result => return result,
+        }
+    }
+}
+
+// The timeout of one attempt, shared by every step of it
+pub(crate) struct Deadline {
+    timer: Option<(Sleep, Duration)>,
+}
+
+impl Deadline {
+    // Starts counting now, None never runs out
+    pub fn new(timeout: Option<Duration>) -> Self {
+        Deadline {
+            timer: timeout.map(|timeout| (sleep(timeout), timeout)),
+        }
+    }
+
+    // Output of `future`, a Timeout error if the deadline passes first
+    pub async fn run<T>(&mut self, path: &str, future: impl Future<Output = Result<T, WallocError>>) -> Result<T, WallocError> {
+        match &mut self.timer {
+            Some((timer, after)) => race(future, timer)
+                .await
+                .unwrap_or_else(|| Err(WallocError::Timeout { path: path.to_string(), after: *after })),
+            None => future.await,
+        }
+    }
+}
+
+// Output of `future`, None if `timer` fires first
+async fn race<F: Future>(future: F, timer: &mut Sleep) -> Option<F::Output> {
+    let mut future = std::pin::pin!(future);
+    std::future::poll_fn(|cx: &mut Context<'_>| {
+        if let Poll::Ready(output) = future.as_mut().poll(cx) {
+            return Poll::Ready(Some(output));
+        }
+        match Pin::new(&mut *timer).poll(cx) {
+            Poll::Ready(()) => Poll::Ready(None),
+            Poll::Pending => Poll::Pending,
+        }
+    })
+    .await
+}
+
+// === Timer ===
+
+// Resolves once `duration` has passed
+#[cfg(target_arch = "wasm32")]
+pub(crate) struct Sleep {
+    timeout: wasm_bindgen_futures::JsFuture,
+}
+
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn sleep(duration: Duration) -> Sleep {
+    use wasm_bindgen::{JsCast, JsValue};
+
+    let millis = duration.as_millis().min(i32::MAX as u128) as i32;
+    let promise = js_sys::Promise::new(&mut |resolve, _reject| {
+        let global = js_sys::global();
+        let set_timeout = js_sys::Reflect::get(&global, &JsValue::from_str("setTimeout"))
+            .ok()
+            .and_then(|set_timeout| set_timeout.dyn_into::<js_sys::Function>().ok());
+        match set_timeout {
+            Some(set_timeout) => {
+                let _ = set_timeout.call2(&global, &resolve, &JsValue::from(millis));
+            },
+            None => {
+                let _ = resolve.call0(&JsValue::NULL);
+            },
+        }
+    });
+    Sleep { timeout: wasm_bindgen_futures::JsFuture::from(promise) }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        Pin::new(&mut self.timeout).poll(cx).map(|_| ())
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) struct Sleep {
+    state: Arc<Mutex<SleepState>>,
+    key: Option<TimerKey>,  // Where it waits in the timer queue, None if it never did
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+struct SleepState {
+    done: bool,
+    waker: Option<std::task::Waker>,
+}
+
+// When a timer fires, and a sequence number to tell timers with the same deadline apart
+#[cfg(not(target_arch = "wasm32"))]
+type TimerKey = (Instant, u64);
+
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+struct TimerQueue {
+    pending: BTreeMap<TimerKey, Arc<Mutex<SleepState>>>,  // In deadline order
+    next: u64,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+struct Timers {
+    queue: Mutex<TimerQueue>,
+    added: Condvar,  // Wakes the timer thread when a timer is added
+}
+
+// Timers, starting the thread that fires them on first use. One thread serves
+// every timeout and backoff, however many fetches are in flight.
+#[cfg(not(target_arch = "wasm32"))]
+fn timers() -> &'static Timers {
+    static TIMERS: OnceLock<Timers> = OnceLock::new();
+    TIMERS.get_or_init(|| {
+        std::thread::Builder::new()
+            .name("walloc-timer".to_string())
+            .spawn(run_timers)
+            .expect("failed to start the timer thread");
+        Timers { queue: Mutex::new(TimerQueue::default()), added: Condvar::new() }
+    })
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn run_timers() {
+    let timers = timers();
+    let mut queue = timers.queue.lock().unwrap_or_else(|e| e.into_inner());
+    loop {
+        let now = Instant::now();
+        let mut due = Vec::new();
+        while let Some(entry) = queue.pending.first_entry() {
+            if entry.key().0 > now {
+                break;
+            }
+            due.push(entry.remove());
+        }
+
+        // Wake outside the queue lock, a waker may start a new sleep right away
+        if !due.is_empty() {
+            drop(queue);
+            for state in due {
+                if let Ok(mut state) = state.lock() {
+                    state.done = true;
+                    if let Some(waker) = state.waker.take() {
+                        waker.wake();
+                    }
+                }
+            }
+            queue = timers.queue.lock().unwrap_or_else(|e| e.into_inner());
+            continue;
+        }
+
+        queue = match queue.pending.keys().next() {
+            Some(&(at, _)) => timers.added.wait_timeout(queue, at - now).unwrap_or_else(|e| e.into_inner()).0,
+            None => timers.added.wait(queue).unwrap_or_else(|e| e.into_inner()),
+        };
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn sleep(duration: Duration) -> Sleep {
+    let state = Arc::new(Mutex::new(SleepState { done: duration.is_zero(), waker: None }));
+    // Too far out to represent never fires, like a timeout that is not set
+    let at = match Instant::now().checked_add(duration) {
+        Some(at) if !duration.is_zero() => at,
+        _ => return Sleep { state, key: None },
+    };
+
+    let timers = timers();
+    let mut queue = timers.queue.lock().unwrap_or_else(|e| e.into_inner());
+    let key = (at, queue.next);
+    queue.next += 1;
+    queue.pending.insert(key, Arc::clone(&state));
+    timers.added.notify_one();
+    Sleep { state, key: Some(key) }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Drop for Sleep {
+    // A deadline that is no longer needed leaves the queue right away
+    fn drop(&mut self) {
+        if let Some(key) = self.key {
+            timers().queue.lock().unwrap_or_else(|e| e.into_inner()).pending.remove(&key);
+        }
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Future for Sleep {
+    type Output = ();
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        match self.state.lock() {
+            Ok(mut state) if !state.done => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            },
+            _ => Poll::Ready(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use futures_executor::block_on;
+
+    use super::*;
+
+    #[test]
+    fn backoff_grows_up_to_the_max() {
+        let policy = RetryPolicy::default();
+        assert_eq!(policy.backoff(1), Duration::from_millis(200));
+        assert_eq!(policy.backoff(2), Duration::from_millis(400));
+        assert_eq!(policy.backoff(3), Duration::from_millis(800));
+        assert_eq!(policy.backoff(10), policy.max_backoff);
+    }
+
+    #[test]
+    fn backoff_never_overflows() {
+        let policy = RetryPolicy::default().with_max_attempts(100);
+        for attempt in 1..=policy.max_attempts {
+            assert!(policy.backoff(attempt) <= policy.max_backoff);
+        }
+        assert_eq!(policy.backoff(u32::MAX), policy.max_backoff);
+
+        let policy = policy.with_backoff_multiplier(f64::MAX);
+        assert_eq!(policy.backoff(3), policy.max_backoff);
+    }
+
+    #[test]
+    fn builders_keep_the_policy_sane() {
+        let policy = RetryPolicy::new()
+            .with_max_attempts(0)
+            .with_backoff(Duration::from_secs(2), Duration::from_secs(1))
+            .with_backoff_multiplier(0.5);
+        assert_eq!(policy.max_attempts, 1);
+        assert_eq!(policy.max_backoff, Duration::from_secs(2));
+        assert_eq!(policy.backoff_multiplier, 1.0);
+    }
+
+    #[test]
+    fn only_retryable_errors_are_retried() {
+        let policy = RetryPolicy::default();
+        let unavailable = WallocError::Http { url: "a".to_string(), status: 503 };
+        let missing = WallocError::Http { url: "a".to_string(), status: 404 };
+        assert!(policy.should_retry(&unavailable, 1));
+        assert!(!policy.should_retry(&unavailable, 3));
+        assert!(!policy.should_retry(&missing, 1));
+    }
+
+    #[test]
+    fn with_retry_stops_at_success_or_max_attempts() {
+        let policy = RetryPolicy::none()
+            .with_max_attempts(3)
+            .with_backoff(Duration::ZERO, Duration::ZERO);
+        let attempts = Cell::new(0);
+        let failing = || {
+            attempts.set(attempts.get() + 1);
+            let attempt = attempts.get();
+            async move {
+                match attempt {
+                    1 => Err(WallocError::Fetch { url: "a".to_string(), message: "reset".to_string() }),
+                    _ => Ok(attempt),
+                }
+            }
+        };
+        assert_eq!(block_on(with_retry(&policy, "a", failing)).unwrap(), 2);
+
+        attempts.set(0);
+        let result: Result<(), WallocError> = block_on(with_retry(&policy, "a", || {
+            attempts.set(attempts.get() + 1);
+            async { Err(WallocError::Fetch { url: "a".to_string(), message: "reset".to_string() }) }
+        }));
+        assert!(matches!(result, Err(WallocError::Fetch { .. })));
+        assert_eq!(attempts.get(), 3);
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn is_queued(key: TimerKey) -> bool {
+        timers().queue.lock().unwrap().pending.contains_key(&key)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn sleeps_share_one_timer_thread() {
+        let started = Instant::now();
+        let sleeps: Vec<Sleep> = (0..50).map(|i| sleep(Duration::from_millis(50 - i))).collect();
+        for sleep in sleeps {
+            block_on(sleep);
+        }
+        assert!(started.elapsed() >= Duration::from_millis(50));
+        assert!(block_on(race(std::future::pending::<()>(), &mut sleep(Duration::from_millis(5)))).is_none());
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    #[test]
+    fn dropped_sleeps_leave_the_queue() {
+        let pending = sleep(Duration::from_secs(30));
+        let key = pending.key.unwrap();
+        assert!(is_queued(key));
+        drop(pending);
+        assert!(!is_queued(key));
+
+        assert!(sleep(Duration::ZERO).key.is_none());
+        assert!(sleep(Duration::MAX).key.is_none());
+    }
+
+    #[test]
+    fn deadline_times_out_a_stalled_fetch() {
+        let mut deadline = Deadline::new(Some(Duration::from_millis(10)));
+        let result: Result<(), WallocError> = block_on(deadline.run("slow", std::future::pending()));
+        assert!(matches!(result, Err(WallocError::Timeout { after, .. }) if after == Duration::from_millis(10)));
+
+        let mut deadline = Deadline::new(None);
+        assert_eq!(block_on(deadline.run("fast", async { Ok(1) })).unwrap(), 1);
+    }
+}
@@ -0,0 +1,777 @@
+// Where load_asset gets its bytes from.
+//
+// An AssetSource turns an asset path into bytes. HttpSource fetches
+// `base_url + path`, FileSource reads below a root directory (native and
+// WASI), InMemorySource serves bytes registered up front, and FallbackSource
+// tries a list of sources in order, e.g. a disk cache before HTTP.
+//
+// fetch_range reads part of an asset, which is how packs are read entry by
+// entry. Sources that cannot read ranges fetch the whole asset and slice it.
+//
+// fetch_stream hands an asset over chunk by chunk as it arrives, so
+// load_asset can copy it into its tier without buffering the whole body.
+// Sources that cannot stream fetch the whole asset and hand it over as one
+// chunk.
+//
+// fetch_conditional sends the ETag and Last-Modified of a copy the caller
+// already has, and hears back whether it is still current. That is how the
+// offline cache revalidates what it stored.
+//
+// The `_with` variants take FetchOptions, extra request headers and a
+// credentials mode from an AssetRequest. Only HttpSource sends them, the
+// other sources have no use for them and ignore them.
+//
+// Fetches return a boxed future without a Send bound, because browser fetches
+// are tied to the JS thread. Their errors say whether the server answered
+// (Http), the transfer failed (Fetch) or the asset does not exist
+// (NotFound), which is what the retry policy goes by.
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+
+use reqwest::Client;
+
+use super::{AssetEncoding, WallocError};
+
+pub type FetchFuture<'a> = Pin<Box<dyn Future<Output = Result<Vec<u8>, WallocError>> + 'a>>;
+pub type StreamFuture<'a> = Pin<Box<dyn Future<Output = Result<Box<dyn AssetStream + 'a>, WallocError>> + 'a>>;
+pub type ChunkFuture<'a> = Pin<Box<dyn Future<Output = Result<Option<Vec<u8>>, WallocError>> + 'a>>;
+pub type ConditionalFuture<'a> = Pin<Box<dyn Future<Output = Result<Revalidation, WallocError>> + 'a>>;
+
+// Whether a browser fetch sends cookies and HTTP auth, as in the Fetch API
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Credentials {
+    Omit,
+    #[default]
+    SameOrigin,
+    Include,  // Also to other origins, e.g. a CDN with cookie auth
+}
+
+impl Credentials {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "omit" => Some(Credentials::Omit),
+            "same-origin" => Some(Credentials::SameOrigin),
+            "include" => Some(Credentials::Include),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Credentials::Omit => "omit",
+            Credentials::SameOrigin => "same-origin",
+            Credentials::Include => "include",
+        }
+    }
+}
+
+// How to send a fetch
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct FetchOptions {
+    pub headers: Vec<(String, String)>,
+    pub credentials: Credentials,
+}
+
+pub(crate) static NO_OPTIONS: FetchOptions = FetchOptions {
+    headers: Vec::new(),
+    credentials: Credentials::SameOrigin,
+};
+
+// What a server said identifies a version of an asset
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct Validators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+}
+
+// Answer to a conditional fetch
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Revalidation {
+    NotModified,  // The copy the validators came from is current
+    Modified { bytes: Vec<u8>, validators: Validators },
+}
+
+// How much of a streaming load has arrived
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct LoadProgress {
+    pub received: u64,
+    pub total: Option<u64>,  // None if the source did not report a length
+}
+
+impl LoadProgress {
+    // 0.0 to 1.0, None without a total
+    pub fn fraction(&self) -> Option<f64> {
+        match self.total {
+            Some(0) => Some(1.0),
+            Some(total) => Some((self.received as f64 / total as f64).min(1.0)),
+            None => None,
+        }
+    }
+}
+
+// An asset arriving in chunks
+pub trait AssetStream {
+    // Size of the whole asset, if the source knows it up front
+    fn size_hint(&self) -> Option<u64>;
+
+    // Next chunk, None once the asset is complete
+    fn next_chunk(&mut self) -> ChunkFuture<'_>;
+
+    // Compression the chunks still carry, if the source says
+    fn content_encoding(&self) -> Option<AssetEncoding> {
+        None
+    }
+}
+
+// A whole asset handed over as one chunk
+pub(crate) struct BufferedStream {
+    bytes: Option<Vec<u8>>,
+    size: u64,
+}
+
+impl BufferedStream {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        BufferedStream { size: bytes.len() as u64, bytes: Some(bytes) }
+    }
+}
+
+impl AssetStream for BufferedStream {
+    fn size_hint(&self) -> Option<u64> {
+        Some(self.size)
+    }
+
+    fn next_chunk(&mut self) -> ChunkFuture<'_> {
+        Box::pin(std::future::ready(Ok(self.bytes.take())))
+    }
+}
+
+pub trait AssetSource: Send + Sync {
+    // Short description for logs and errors
+    fn describe(&self) -> String;
+
+    fn fetch<'a>(&'a self, path: &'a str) -> FetchFuture<'a>;
+
+    // Up to `len` bytes of an asset from `start` on, fewer at its end
+    fn fetch_range<'a>(&'a self, path: &'a str, start: u64, len: u64) -> FetchFuture<'a> {
+        Box::pin(async move {
+            let bytes = self.fetch(path).await?;
+            Ok(slice_range(&bytes, start, len).to_vec())
+        })
+    }
+
+    fn fetch_stream<'a>(&'a self, path: &'a str) -> StreamFuture<'a> {
+        Box::pin(async move {
+            let bytes = self.fetch(path).await?;
+            let stream: Box<dyn AssetStream + 'a> = Box::new(BufferedStream::new(bytes));
+            Ok(stream)
+        })
+    }
+
+    // Where `path` comes from, what the offline cache keys it by
+    fn url(&self, path: &str) -> String {
+        path.to_string()
+    }
+
+    // The asset unless it still matches `validators`. Sources without
+    // validators always fetch it and report none.
+    fn fetch_conditional<'a>(&'a self, path: &'a str, options: &'a FetchOptions, validators: &'a Validators) -> ConditionalFuture<'a> {
+        let _ = (options, validators);
+        Box::pin(async move {
+            let bytes = self.fetch(path).await?;
+            Ok(Revalidation::Modified { bytes, validators: Validators::default() })
+        })
+    }
+
+    // fetch_range and fetch_stream sent with `options`
+    fn fetch_range_with<'a>(&'a self, path: &'a str, start: u64, len: u64, options: &'a FetchOptions) -> FetchFuture<'a> {
+        let _ = options;
+        self.fetch_range(path, start, len)
+    }
+
+    fn fetch_stream_with<'a>(&'a self, path: &'a str, options: &'a FetchOptions) -> StreamFuture<'a> {
+        let _ = options;
+        self.fetch_stream(path)
+    }
+}
+
+fn slice_range(bytes: &[u8], start: u64, len: u64) -> &[u8] {
+    let start = (start as usize).min(bytes.len());
+    let end = start.saturating_add(len as usize).min(bytes.len());
+    &bytes[start..end]
+}
+
+// === HTTP ===
+
+pub struct HttpSource {
+    client: Client,
+    base_url: String,
+}
+
+impl HttpSource {
+    // Paths are appended to `base_url` as they are, so it usually ends in '/'
+    pub fn new(base_url: &str) -> Self {
+        HttpSource {
+            client: Client::new(),
+            base_url: base_url.to_string(),
+        }
+    }
+
+    pub fn with_client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+}
+
+impl AssetSource for HttpSource {
+    fn describe(&self) -> String {
+        format!("http {}", self.base_url)
+    }
+
+    fn fetch<'a>(&'a self, path: &'a str) -> FetchFuture<'a> {
+        Box::pin(async move {
+            let url = format!("{}{}", self.base_url, path);
+            let response = self.send(&url, &NO_OPTIONS).await?;
+
+            let bytes = response.bytes().await.map_err(|e| fetch_error(&url, e))?;
+            Ok(bytes.to_vec())
+        })
+    }
+
+    fn fetch_range<'a>(&'a self, path: &'a str, start: u64, len: u64) -> FetchFuture<'a> {
+        self.fetch_range_with(path, start, len, &NO_OPTIONS)
+    }
+
+    fn fetch_stream<'a>(&'a self, path: &'a str) -> StreamFuture<'a> {
+        self.fetch_stream_with(path, &NO_OPTIONS)
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    fn fetch_conditional<'a>(&'a self, path: &'a str, options: &'a FetchOptions, validators: &'a Validators) -> ConditionalFuture<'a> {
+        Box::pin(async move {
+            let url = self.url(path);
+            let mut request = self.request(&url, options);
+            if let Some(etag) = &validators.etag {
+                request = request.header("If-None-Match", etag.as_str());
+            }
+            if let Some(last_modified) = &validators.last_modified {
+                request = request.header("If-Modified-Since", last_modified.as_str());
+            }
+            let response = request.send().await.map_err(|e| fetch_error(&url, e))?;
+
+            let status = response.status();
+            if status == reqwest::StatusCode::NOT_MODIFIED && !validators.is_empty() {
+                return Ok(Revalidation::NotModified);
+            }
+            if !status.is_success() {
+                return Err(WallocError::Http { url, status: status.as_u16() });
+            }
+
+            let header = |name: reqwest::header::HeaderName| {
+                response.headers().get(name).and_then(|value| value.to_str().ok()).map(str::to_string)
+            };
+            let validators = Validators {
+                etag: header(reqwest::header::ETAG),
+                last_modified: header(reqwest::header::LAST_MODIFIED),
+            };
+            let bytes = response.bytes().await.map_err(|e| fetch_error(&url, e))?;
+            Ok(Revalidation::Modified { bytes: bytes.to_vec(), validators })
+        })
+    }
+
+    fn fetch_range_with<'a>(&'a self, path: &'a str, start: u64, len: u64, options: &'a FetchOptions) -> FetchFuture<'a> {
+        Box::pin(async move {
+            if len == 0 {
+                return Ok(Vec::new());
+            }
+            let url = format!("{}{}", self.base_url, path);
+            let response = self
+                .request(&url, options)
+                .header("Range", format!("bytes={}-{}", start, start + len - 1))
+                .send()
+                .await
+                .map_err(|e| fetch_error(&url, e))?;
+
+            let status = response.status();
+            if status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE {
+                return Ok(Vec::new());
+            }
+            if !status.is_success() {
+                return Err(WallocError::Http { url, status: status.as_u16() });
+            }
+
+            let bytes = response.bytes().await.map_err(|e| fetch_error(&url, e))?;
+
+            // Servers without range support answer 200 with the whole asset
+            if status == reqwest::StatusCode::PARTIAL_CONTENT {
+                Ok(slice_range(&bytes, 0, len).to_vec())
+            } else {
+                Ok(slice_range(&bytes, start, len).to_vec())
+            }
+        })
+    }
+
+    fn fetch_stream_with<'a>(&'a self, path: &'a str, options: &'a FetchOptions) -> StreamFuture<'a> {
+        Box::pin(async move {
+            let url = format!("{}{}", self.base_url, path);
+            let response = self.send(&url, options).await?;
+            let size = response.content_length();
+            // Browsers undo Content-Encoding before the body reaches us
+            #[cfg(not(target_arch = "wasm32"))]
+            let encoding = response
+                .headers()
+                .get(reqwest::header::CONTENT_ENCODING)
+                .and_then(|value| value.to_str().ok())
+                .and_then(AssetEncoding::from_name);
+            #[cfg(target_arch = "wasm32")]
+            let encoding = None;
+            let stream: Box<dyn AssetStream + 'a> = Box::new(HttpStream {
+                url,
+                body: Box::pin(response.bytes_stream()),
+                size,
+                encoding,
+            });
+            Ok(stream)
+        })
+    }
+}
+
+impl HttpSource {
+    // GET with the headers and credentials mode of `options`. Credentials only
+    // mean something to a browser fetch, native requests send no cookies.
+    fn request(&self, url: &str, options: &FetchOptions) -> reqwest::RequestBuilder {
+        let mut request = self.client.get(url);
+        for (name, value) in &options.headers {
+            request = request.header(name.as_str(), value.as_str());
+        }
+        #[cfg(target_arch = "wasm32")]
+        let request = match options.credentials {
+            Credentials::Omit => request.fetch_credentials_omit(),
+            Credentials::SameOrigin => request.fetch_credentials_same_origin(),
+            Credentials::Include => request.fetch_credentials_include(),
+        };
+        request
+    }
+
+    async fn send(&self, url: &str, options: &FetchOptions) -> Result<reqwest::Response, WallocError> {
+        let response = self
+            .request(url, options)
+            .send()
+            .await
+            .map_err(|e| fetch_error(url, e))?;
+
+        if !response.status().is_success() {
+            return Err(WallocError::Http { url: url.to_string(), status: response.status().as_u16() });
+        }
+        Ok(response)
+    }
+}
+
+fn fetch_error(url: &str, error: impl std::fmt::Display) -> WallocError {
+    WallocError::Fetch { url: url.to_string(), message: error.to_string() }
+}
+
+// Response body as it comes off the network
+struct HttpStream<S> {
+    url: String,
+    body: Pin<Box<S>>,
+    size: Option<u64>,
+    encoding: Option<AssetEncoding>,
+}
+
+impl<S, B> AssetStream for HttpStream<S>
+where
+    S: futures_core::Stream<Item = reqwest::Result<B>>,
+    B: AsRef<[u8]>,
+{
+    fn size_hint(&self) -> Option<u64> {
+        self.size
+    }
+
+    fn next_chunk(&mut self) -> ChunkFuture<'_> {
+        Box::pin(async move {
+            match std::future::poll_fn(|cx| self.body.as_mut().poll_next(cx)).await {
+                Some(Ok(chunk)) => Ok(Some(chunk.as_ref().to_vec())),
+                Some(Err(e)) => Err(fetch_error(&self.url, e)),
+                None => Ok(None),
+            }
+        })
+    }
+
+    fn content_encoding(&self) -> Option<AssetEncoding> {
+        self.encoding
+    }
+}
+
+// === Filesystem ===
+
+// Reads assets below a root directory. Paths may not be absolute or leave the root.
+#[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+pub struct FileSource {
+    root: std::path::PathBuf,
+}
+
+#[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+impl FileSource {
+    pub fn new(root: impl Into<std::path::PathBuf>) -> Self {
+        FileSource {
+            root: root.into(),
+        }
+    }
+
+    pub fn root(&self) -> &std::path::Path {
+        &self.root
+    }
+
+    fn resolve(&self, path: &str) -> Result<std::path::PathBuf, WallocError> {
+        let relative = std::path::Path::new(path);
+        let escapes = relative
+            .components()
+            .any(|component| !matches!(component, std::path::Component::Normal(_) | std::path::Component::CurDir));
+        if escapes {
+            return Err(WallocError::InvalidArgument(format!("Asset path '{}' leaves {}", path, self.root.display())));
+        }
+        Ok(self.root.join(relative))
+    }
+}
+
+#[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+impl AssetSource for FileSource {
+    fn describe(&self) -> String {
+        format!("file {}", self.root.display())
+    }
+
+    fn fetch<'a>(&'a self, path: &'a str) -> FetchFuture<'a> {
+        let result = self.resolve(path).and_then(|file| std::fs::read(&file).map_err(|e| file_error(path, &file, e)));
+        Box::pin(std::future::ready(result))
+    }
+
+    fn fetch_range<'a>(&'a self, path: &'a str, start: u64, len: u64) -> FetchFuture<'a> {
+        use std::io::{Read, Seek, SeekFrom};
+
+        let result = self.resolve(path).and_then(|file| {
+            let error = |e: std::io::Error| file_error(path, &file, e);
+            let mut reader = std::fs::File::open(&file).map_err(error)?;
+            reader.seek(SeekFrom::Start(start)).map_err(error)?;
+            let mut bytes = Vec::new();
+            reader.take(len).read_to_end(&mut bytes).map_err(error)?;
+            Ok(bytes)
+        });
+        Box::pin(std::future::ready(result))
+    }
+
+    fn fetch_stream<'a>(&'a self, path: &'a str) -> StreamFuture<'a> {
+        let result = self.resolve(path).and_then(|file| {
+            let error = |e: std::io::Error| file_error(path, &file, e);
+            let reader = std::fs::File::open(&file).map_err(error)?;
+            let size = reader.metadata().map_err(error)?.len();
+            let stream: Box<dyn AssetStream + 'a> = Box::new(FileStream { file, reader, size });
+            Ok(stream)
+        });
+        Box::pin(std::future::ready(result))
+    }
+}
+
+// A missing file is NotFound, anything else a failed fetch
+#[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+fn file_error(path: &str, file: &std::path::Path, error: std::io::Error) -> WallocError {
+    match error.kind() {
+        std::io::ErrorKind::NotFound => WallocError::NotFound { path: path.to_string() },
+        _ => WallocError::Fetch { url: file.display().to_string(), message: error.to_string() },
+    }
+}
+
+// Bytes read from a file per chunk
+#[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+const FILE_CHUNK_SIZE: usize = 64 * 1024;
+
+// A file read a chunk at a time
+#[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+struct FileStream {
+    file: std::path::PathBuf,
+    reader: std::fs::File,
+    size: u64,
+}
+
+#[cfg(any(not(target_arch = "wasm32"), target_os = "wasi"))]
+impl AssetStream for FileStream {
+    fn size_hint(&self) -> Option<u64> {
+        Some(self.size)
+    }
+
+    fn next_chunk(&mut self) -> ChunkFuture<'_> {
+        use std::io::Read;
+
+        let mut chunk = vec![0; FILE_CHUNK_SIZE];
+        let result = match self.reader.read(&mut chunk) {
+            Ok(0) => Ok(None),
+            Ok(read) => {
+                chunk.truncate(read);
+                Ok(Some(chunk))
+            },
+            Err(e) => Err(WallocError::Fetch { url: self.file.display().to_string(), message: e.to_string() }),
+        };
+        Box::pin(std::future::ready(result))
+    }
+}
+
+// === In memory ===
+
+// Serves bytes registered with insert, for tests and assets baked into the binary
+#[derive(Default)]
+pub struct InMemorySource {
+    assets: Mutex<HashMap<String, Vec<u8>>>,
+}
+
+impl InMemorySource {
+    pub fn new() -> Self {
+        InMemorySource::default()
+    }
+
+    pub fn with_asset(self, path: &str, bytes: impl Into<Vec<u8>>) -> Self {
+        self.insert(path, bytes);
+        self
+    }
+
+    pub fn insert(&self, path: &str, bytes: impl Into<Vec<u8>>) {
+        if let Ok(mut assets) = self.assets.lock() {
+            assets.insert(path.to_string(), bytes.into());
+        }
+    }
+
+    pub fn remove(&self, path: &str) -> bool {
+        match self.assets.lock() {
+            Ok(mut assets) => assets.remove(path).is_some(),
+            Err(_) => false,
+        }
+    }
+}
+
+impl AssetSource for InMemorySource {
+    fn describe(&self) -> String {
+        "memory".to_string()
+    }
+
+    fn fetch<'a>(&'a self, path: &'a str) -> FetchFuture<'a> {
+        let result = match self.assets.lock() {
+            Ok(assets) => assets
+                .get(path)
+                .cloned()
+                .ok_or_else(|| WallocError::NotFound { path: path.to_string() }),
+            Err(_) => Err(WallocError::Lock("in-memory assets")),
+        };
+        Box::pin(std::future::ready(result))
+    }
+
+    fn fetch_range<'a>(&'a self, path: &'a str, start: u64, len: u64) -> FetchFuture<'a> {
+        let result = match self.assets.lock() {
+            Ok(assets) => assets
+                .get(path)
+                .map(|bytes| slice_range(bytes, start, len).to_vec())
+                .ok_or_else(|| WallocError::NotFound { path: path.to_string() }),
+            Err(_) => Err(WallocError::Lock("in-memory assets")),
+        };
+        Box::pin(std::future::ready(result))
+    }
+}
+
+// === Fallback chain ===
+
+// Tries each source in order and returns the first success. If all fail, the
+// error is the last one that is more than NotFound, so a failed download
+// behind a cache miss is what the retry policy sees.
+#[derive(Default)]
+pub struct FallbackSource {
+    sources: Vec<Box<dyn AssetSource>>,
+}
+
+impl FallbackSource {
+    pub fn new() -> Self {
+        FallbackSource::default()
+    }
+
+    pub fn with_source(mut self, source: impl AssetSource + 'static) -> Self {
+        self.sources.push(Box::new(source));
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.sources.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sources.is_empty()
+    }
+}
+
+fn keep_error(last_error: &mut Option<WallocError>, error: WallocError) {
+    if last_error.is_none() || !matches!(error, WallocError::NotFound { .. }) {
+        *last_error = Some(error);
+    }
+}
+
+impl AssetSource for FallbackSource {
+    fn describe(&self) -> String {
+        let sources: Vec<String> = self.sources.iter().map(|source| source.describe()).collect();
+        format!("fallback [{}]", sources.join(", "))
+    }
+
+    fn fetch<'a>(&'a self, path: &'a str) -> FetchFuture<'a> {
+        Box::pin(async move {
+            let mut last_error = None;
+            for source in &self.sources {
+                match source.fetch(path).await {
+                    Ok(bytes) => return Ok(bytes),
+                    Err(e) => keep_error(&mut last_error, e),
+                }
+            }
+            Err(last_error.unwrap_or_else(|| WallocError::NotFound { path: path.to_string() }))
+        })
+    }
+
+    fn fetch_range<'a>(&'a self, path: &'a str, start: u64, len: u64) -> FetchFuture<'a> {
+        self.fetch_range_with(path, start, len, &NO_OPTIONS)
+    }
+
+    // The source of last resort, usually the network
+    fn url(&self, path: &str) -> String {
+        match self.sources.last() {
+            Some(source) => source.url(path),
+            None => path.to_string(),
+        }
+    }
+
+    fn fetch_conditional<'a>(&'a self, path: &'a str, options: &'a FetchOptions, validators: &'a Validators) -> ConditionalFuture<'a> {
+        Box::pin(async move {
+            let mut last_error = None;
+            for source in &self.sources {
+                match source.fetch_conditional(path, options, validators).await {
+                    Ok(revalidation) => return Ok(revalidation),
+                    Err(e) => keep_error(&mut last_error, e),
+                }
+            }
+            Err(last_error.unwrap_or_else(|| WallocError::NotFound { path: path.to_string() }))
+        })
+    }
+
+    fn fetch_stream<'a>(&'a self, path: &'a str) -> StreamFuture<'a> {
+        self.fetch_stream_with(path, &NO_OPTIONS)
+    }
+
+    fn fetch_range_with<'a>(&'a self, path: &'a str, start: u64, len: u64, options: &'a FetchOptions) -> FetchFuture<'a> {
+        Box::pin(async move {
+            let mut last_error = None;
+            for source in &self.sources {
+                match source.fetch_range_with(path, start, len, options).await {
+                    Ok(bytes) => return Ok(bytes),
+                    Err(e) => keep_error(&mut last_error, e),
+                }
+            }
+            Err(last_error.unwrap_or_else(|| WallocError::NotFound { path: path.to_string() }))
+        })
+    }
+
+    // Falls back only while opening the stream, not once chunks are arriving
+    fn fetch_stream_with<'a>(&'a self, path: &'a str, options: &'a FetchOptions) -> StreamFuture<'a> {
+        Box::pin(async move {
+            let mut last_error = None;
+            for source in &self.sources {
+                match source.fetch_stream_with(path, options).await {
+                    Ok(stream) => return Ok(stream),
+                    Err(e) => keep_error(&mut last_error, e),
+                }
+            }
+            Err(last_error.unwrap_or_else(|| WallocError::NotFound { path: path.to_string() }))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_executor::block_on;
+
+    use super::*;
+
+    // Fails every fetch with a server error
+    struct BrokenSource;
+
+    impl AssetSource for BrokenSource {
+        fn describe(&self) -> String {
+            "broken".to_string()
+        }
+
+        fn fetch<'a>(&'a self, path: &'a str) -> FetchFuture<'a> {
+            Box::pin(std::future::ready(Err(WallocError::Http { url: path.to_string(), status: 503 })))
+        }
+    }
+
+    fn read_stream(mut stream: Box<dyn AssetStream + '_>) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        while let Some(chunk) = block_on(stream.next_chunk()).unwrap() {
+            bytes.extend(chunk);
+        }
+        bytes
+    }
+
+    #[test]
+    fn in_memory_source_serves_what_was_inserted() {
+        let source = InMemorySource::new().with_asset("a.bin", b"abcdef".to_vec());
+        assert_eq!(block_on(source.fetch("a.bin")).unwrap(), b"abcdef");
+        assert_eq!(block_on(source.fetch_range("a.bin", 2, 3)).unwrap(), b"cde");
+        assert_eq!(block_on(source.fetch_range("a.bin", 4, 10)).unwrap(), b"ef");
+        assert_eq!(block_on(source.fetch_range("a.bin", 10, 1)).unwrap(), b"");
+
+        assert!(source.remove("a.bin"));
+        assert!(!source.remove("a.bin"));
+        assert!(matches!(block_on(source.fetch("a.bin")), Err(WallocError::NotFound { .. })));
+    }
+
+    #[test]
+    fn fallback_returns_the_first_success() {
+        let source = FallbackSource::new()
+            .with_source(InMemorySource::new().with_asset("a.bin", b"first".to_vec()))
+            .with_source(InMemorySource::new().with_asset("a.bin", b"second".to_vec()).with_asset("b.bin", b"only".to_vec()));
+        assert_eq!(source.len(), 2);
+        assert_eq!(block_on(source.fetch("a.bin")).unwrap(), b"first");
+        assert_eq!(block_on(source.fetch("b.bin")).unwrap(), b"only");
+        assert_eq!(block_on(source.fetch_range("b.bin", 1, 2)).unwrap(), b"nl");
+        assert_eq!(read_stream(block_on(source.fetch_stream("b.bin")).unwrap()), b"only");
+        assert_eq!(source.describe(), "fallback [memory, memory]");
+    }
+
+    #[test]
+    fn fallback_prefers_errors_over_not_found() {
+        let source = FallbackSource::new().with_source(BrokenSource).with_source(InMemorySource::new());
+        assert!(matches!(block_on(source.fetch("a.bin")), Err(WallocError::Http { status: 503, .. })));
+
+        let source = FallbackSource::new().with_source(InMemorySource::new()).with_source(InMemorySource::new());
+        assert!(matches!(block_on(source.fetch("a.bin")), Err(WallocError::NotFound { .. })));
+
+        let source = FallbackSource::new();
+        assert!(source.is_empty());
+        assert!(matches!(block_on(source.fetch("a.bin")), Err(WallocError::NotFound { .. })));
+    }
+
+    #[test]
+    fn progress_fraction() {
+        assert_eq!(LoadProgress { received: 50, total: Some(200) }.fraction(), Some(0.25));
+        assert_eq!(LoadProgress { received: 300, total: Some(200) }.fraction(), Some(1.0));
+        assert_eq!(LoadProgress { received: 0, total: Some(0) }.fraction(), Some(1.0));
+        assert_eq!(LoadProgress { received: 50, total: None }.fraction(), None);
+    }
+}
@@ -0,0 +1,1238 @@
+// Allocation strategies behind an Arena.
+//
+// Every segment of a tier runs its own strategy instance over the offsets
+// [0, capacity) of that segment. The arena hands strategies sizes that are
+// already a multiple of the tier alignment, and segment capacities are too, so
+// every offset a strategy produces stays aligned.
+//
+//   Bump     - the original pointer bump, freed blocks go on a header free list
+//   FreeList - segregated fit, one list of free ranges per power of two class
+//   Buddy    - power of two blocks that split on allocation and merge with
+//              their buddy on free
+//   TLSF     - two level segregated fit, bitmaps give an O(1) good fit lookup
+//
+// Bump suits Entity churn and per-frame data, the others reuse memory with
+// random lifetimes (long lived Scene data) without waiting for a tier reset.
+
+use std::collections::BTreeMap;
+
+use super::Tier;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum StrategyKind {
+    #[default]
+    Bump = 0,
+    FreeList = 1,
+    Buddy = 2,
+    Tlsf = 3,
+}
+
+impl StrategyKind {
+    pub fn from_u8(value: u8) -> Option<StrategyKind> {
+        match value {
+            0 => Some(StrategyKind::Bump),
+            1 => Some(StrategyKind::FreeList),
+            2 => Some(StrategyKind::Buddy),
+            3 => Some(StrategyKind::Tlsf),
+            _ => None,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<StrategyKind> {
+        [StrategyKind::Bump, StrategyKind::FreeList, StrategyKind::Buddy, StrategyKind::Tlsf]
+            .into_iter()
+            .find(|kind| kind.name() == name)
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            StrategyKind::Bump => "bump",
+            StrategyKind::FreeList => "free-list",
+            StrategyKind::Buddy => "buddy",
+            StrategyKind::Tlsf => "tlsf",
+        }
+    }
+
+    // Build a strategy over `capacity` bytes starting at `base`
+    pub fn build(self, tier: Tier, base: *mut u8, capacity: usize) -> Box<dyn AllocStrategy> {
+        match self {
+            StrategyKind::Bump => Box::new(BumpStrategy::new(tier, base, capacity)),
+            StrategyKind::FreeList => Box::new(FreeListStrategy::new(capacity)),
+            StrategyKind::Buddy => Box::new(BuddyStrategy::new(capacity)),
+            StrategyKind::Tlsf => Box::new(TlsfStrategy::new(capacity)),
+        }
+    }
+}
+
+pub trait AllocStrategy: Send {
+    fn kind(&self) -> StrategyKind;
+
+    // Allocate `size` bytes (a multiple of `align`) and return (offset, block size)
+    fn allocate(&mut self, size: usize, align: usize) -> Option<(usize, usize)>;
+
+    // Same as allocate, but only hands out memory at or above `floor`. While a
+    // marker is pushed this keeps everything allocated after it above the
+    // marker, so popping the marker rolls it all back.
+    fn allocate_from(&mut self, size: usize, align: usize, floor: usize) -> Option<(usize, usize)>;
+
+    // Give a block back. Returns false if it is not currently allocated.
+    fn free(&mut self, offset: usize, block_size: usize) -> bool;
+
+    // Forget every allocation
+    fn reset(&mut self);
+
+    // Recycle every allocation that ends past `keep` bytes
+    fn truncate(&mut self, keep: usize);
+
+    // Treat everything below `bytes` as allocated
+    fn reserve(&mut self, bytes: usize);
+
+    // End of the highest live allocation
+    fn extent(&self) -> usize;
+
+    // Bytes held by live allocations, including any rounding
+    fn used(&self) -> usize;
+
+    // Bytes that can still be handed out
+    fn available(&self) -> usize;
+
+    // Largest single allocation that could succeed right now
+    fn largest_available(&self) -> usize;
+
+    // 0.0 when all available memory is one block, approaching 1.0 as it splinters
+    fn fragmentation(&self) -> f32 {
+        let available = self.available();
+        if available == 0 {
+            return 0.0;
+        }
+        1.0 - (self.largest_available() as f32 / available as f32)
+    }
+}
+
+// === Bump ===
+
+// Written into the first bytes of every freed block, linking it into the
+// strategy's free list. Live blocks carry no header.
+#[repr(C)]
+struct BlockHeader {
+    size: usize,
+    next: *mut BlockHeader,
+    is_free: bool,
+    tier: u8,
+}
+
+// Every bump block must be able to hold a header once it is freed
+pub const MIN_BLOCK_SIZE: usize = std::mem::size_of::<BlockHeader>();
+
+pub struct BumpStrategy {
+    base: *mut u8,
+    capacity: usize,
+    current_offset: usize,
+    tier: Tier,
+
+    // Address ordered list of freed blocks, reused first fit before bumping
+    free_list: *mut BlockHeader,
+    free_bytes: usize,
+}
+
+// The segment behind `base` belongs to this strategy alone
+unsafe impl Send for BumpStrategy {}
+
+impl BumpStrategy {
+    pub fn new(tier: Tier, base: *mut u8, capacity: usize) -> Self {
+        BumpStrategy {
+            base,
+            capacity,
+            current_offset: 0,
+            tier,
+            free_list: std::ptr::null_mut(),
+            free_bytes: 0,
+        }
+    }
+
+    fn header_at(&self, offset: usize) -> *mut BlockHeader {
+        unsafe { self.base.add(offset) as *mut BlockHeader }
+    }
+
+    fn offset_of(&self, header: *mut BlockHeader) -> usize {
+        (header as usize) - (self.base as usize)
+    }
+
+    // First fit search of the free list at or above `floor`, splitting off the unused tail
+    fn allocate_from_free_list(&mut self, size: usize, align: usize, floor: usize) -> Option<(usize, usize)> {
+        let min_block = MIN_BLOCK_SIZE.next_multiple_of(align);
+        let mut prev: *mut BlockHeader = std::ptr::null_mut();
+        let mut current = self.free_list;
+
+        while !current.is_null() {
+            let header = unsafe { &mut *current };
+            debug_assert!(header.is_free && header.tier == self.tier.id());
+
+            if header.size >= size && self.offset_of(current) >= floor {
+                let remainder = header.size - size;
+                let (next, block_size) = if remainder >= min_block {
+                    // The tail stays on the free list as a smaller block
+                    let tail = unsafe { (current as *mut u8).add(size) } as *mut BlockHeader;
+                    unsafe {
+                        tail.write(BlockHeader {
+                            size: remainder,
+                            next: header.next,
+                            is_free: true,
+                            tier: self.tier.id(),
+                        });
+                    }
+                    (tail, size)
+                } else {
+                    (header.next, header.size)
+                };
+
+                if prev.is_null() {
+                    self.free_list = next;
+                } else {
+                    unsafe { (*prev).next = next };
+                }
+
+                header.is_free = false;
+                self.free_bytes -= block_size;
+                return Some((self.offset_of(current), block_size));
+            }
+
+            prev = current;
+            current = header.next;
+        }
+
+        None
+    }
+
+    // Keep lowering the bump pointer while a free block sits on top of it
+    fn absorb_free_top(&mut self) {
+        loop {
+            let mut prev: *mut BlockHeader = std::ptr::null_mut();
+            let mut current = self.free_list;
+            while !current.is_null() {
+                let size = unsafe { (*current).size };
+                if self.offset_of(current) + size == self.current_offset {
+                    break;
+                }
+                prev = current;
+                current = unsafe { (*current).next };
+            }
+
+            if current.is_null() {
+                return;
+            }
+
+            let (size, next) = unsafe { ((*current).size, (*current).next) };
+            if prev.is_null() {
+                self.free_list = next;
+            } else {
+                unsafe { (*prev).next = next };
+            }
+            self.current_offset -= size;
+            self.free_bytes -= size;
+        }
+    }
+
+    // Insert a block in address order, merging it with free neighbours.
+    // Returns false if the block overlaps one that is already free.
+    fn insert_free_block(&mut self, offset: usize, block_size: usize) -> bool {
+        let block = self.header_at(offset);
+
+        let mut prev: *mut BlockHeader = std::ptr::null_mut();
+        let mut next = self.free_list;
+        while !next.is_null() && (next as usize) < (block as usize) {
+            prev = next;
+            next = unsafe { (*next).next };
+        }
+
+        // A double free lands inside, or on top of, a block freed before
+        let overlaps_prev = !prev.is_null() && self.offset_of(prev) + unsafe { (*prev).size } > offset;
+        let overlaps_next = !next.is_null() && self.offset_of(next) < offset + block_size;
+        if overlaps_prev || overlaps_next {
+            return false;
+        }
+
+        unsafe {
+            block.write(BlockHeader {
+                size: block_size,
+                next,
+                is_free: true,
+                tier: self.tier.id(),
+            });
+
+            // Merge with the following block
+            if !next.is_null() && self.offset_of(next) == offset + block_size {
+                (*block).size += (*next).size;
+                (*block).next = (*next).next;
+            }
+
+            // Merge into the preceding block, or link after it
+            if !prev.is_null() && self.offset_of(prev) + (*prev).size == offset {
+                (*prev).size += (*block).size;
+                (*prev).next = (*block).next;
+            } else if prev.is_null() {
+                self.free_list = block;
+            } else {
+                (*prev).next = block;
+            }
+        }
+
+        self.free_bytes += block_size;
+        true
+    }
+
+    // Drop free blocks for which `keep(offset, size)` is false
+    fn retain_free_blocks(&mut self, keep: impl Fn(usize, usize) -> bool) {
+        let mut prev: *mut BlockHeader = std::ptr::null_mut();
+        let mut current = self.free_list;
+
+        while !current.is_null() {
+            let (size, next) = unsafe { ((*current).size, (*current).next) };
+            if keep(self.offset_of(current), size) {
+                prev = current;
+            } else {
+                if prev.is_null() {
+                    self.free_list = next;
+                } else {
+                    unsafe { (*prev).next = next };
+                }
+                self.free_bytes -= size;
+            }
+            current = next;
+        }
+    }
+
+    fn largest_free_block(&self) -> usize {
+        let mut largest = 0;
+        let mut current = self.free_list;
+        while !current.is_null() {
+            unsafe {
+                largest = largest.max((*current).size);
+                current = (*current).next;
+            }
+        }
+        largest
+    }
+}
+
+impl AllocStrategy for BumpStrategy {
+    fn kind(&self) -> StrategyKind {
+        StrategyKind::Bump
+    }
+
+    fn allocate(&mut self, size: usize, align: usize) -> Option<(usize, usize)> {
+        self.allocate_from(size, align, 0)
+    }
+
+    fn allocate_from(&mut self, size: usize, align: usize, floor: usize) -> Option<(usize, usize)> {
+        // Freed blocks are reused before bumping
+        if let Some(allocation) = self.allocate_from_free_list(size, align, floor) {
+            return Some(allocation);
+        }
+
+        // Bump from the floor, what lies between the bump pointer and the floor stays free
+        let floor = floor.min(self.capacity);
+        if self.current_offset < floor {
+            let gap = floor - self.current_offset;
+            let gap_start = self.current_offset;
+            self.current_offset = floor;
+            if gap >= MIN_BLOCK_SIZE {
+                self.insert_free_block(gap_start, gap);
+            }
+        }
+
+        let offset = self.current_offset.next_multiple_of(align);
+        if offset + size > self.capacity {
+            return None; // Not enough space
+        }
+
+        self.current_offset = offset + size;
+        Some((offset, size))
+    }
+
+    // Blocks at the top just lower the bump pointer, everything else goes on the free list
+    fn free(&mut self, offset: usize, block_size: usize) -> bool {
+        if offset + block_size > self.current_offset {
+            return false; // Already recycled by a reset or compaction
+        }
+
+        if offset + block_size == self.current_offset {
+            self.current_offset = offset;
+            self.absorb_free_top();
+            true
+        } else {
+            self.insert_free_block(offset, block_size)
+        }
+    }
+
+    fn reset(&mut self) {
+        self.current_offset = 0;
+        self.free_list = std::ptr::null_mut();
+        self.free_bytes = 0;
+    }
+
+    fn truncate(&mut self, keep: usize) {
+        self.current_offset = self.current_offset.min(keep);
+
+        // Free blocks cut off by the truncate are gone with the rest
+        let end = self.current_offset;
+        self.retain_free_blocks(|offset, size| offset + size <= end);
+    }
+
+    fn reserve(&mut self, bytes: usize) {
+        let bytes = bytes.min(self.capacity);
+        self.current_offset = self.current_offset.max(bytes);
+
+        // Reserved memory is no longer up for reuse
+        self.retain_free_blocks(|offset, _| offset >= bytes);
+    }
+
+    fn extent(&self) -> usize {
+        self.current_offset
+    }
+
+    fn used(&self) -> usize {
+        self.current_offset - self.free_bytes
+    }
+
+    fn available(&self) -> usize {
+        self.capacity - self.current_offset + self.free_bytes
+    }
+
+    fn largest_available(&self) -> usize {
+        (self.capacity - self.current_offset).max(self.largest_free_block())
+    }
+}
+
+// === Free range bookkeeping shared by FreeList and TLSF ===
+
+// Size lookup over the free ranges of a segment
+trait SizeIndex: Send {
+    fn insert(&mut self, offset: usize, size: usize);
+    fn remove(&mut self, offset: usize, size: usize);
+    // Offset of a free range of at least `size` bytes
+    fn find(&self, size: usize) -> Option<usize>;
+    fn clear(&mut self);
+}
+
+// Live and free ranges of a segment. Free ranges are kept coalesced, so two
+// free ranges are never adjacent.
+struct RangeAllocator<I: SizeIndex> {
+    capacity: usize,
+    live: BTreeMap<usize, usize>,  // offset -> block size
+    free: BTreeMap<usize, usize>,  // offset -> size
+    index: I,
+    used: usize,
+}
+
+impl<I: SizeIndex> RangeAllocator<I> {
+    fn new(capacity: usize, index: I) -> Self {
+        let mut allocator = RangeAllocator {
+            capacity,
+            live: BTreeMap::new(),
+            free: BTreeMap::new(),
+            index,
+            used: 0,
+        };
+        allocator.rebuild_free();
+        allocator
+    }
+
+    fn insert_free(&mut self, offset: usize, size: usize) {
+        self.free.insert(offset, size);
+        self.index.insert(offset, size);
+    }
+
+    fn remove_free(&mut self, offset: usize, size: usize) {
+        self.free.remove(&offset);
+        self.index.remove(offset, size);
+    }
+
+    // Free ranges are exactly the gaps between live allocations
+    fn rebuild_free(&mut self) {
+        self.free.clear();
+        self.index.clear();
+
+        let mut gaps = Vec::new();
+        let mut cursor = 0;
+        for (&offset, &size) in &self.live {
+            if offset > cursor {
+                gaps.push((cursor, offset - cursor));
+            }
+            cursor = offset + size;
+        }
+        if self.capacity > cursor {
+            gaps.push((cursor, self.capacity - cursor));
+        }
+
+        for (offset, size) in gaps {
+            self.insert_free(offset, size);
+        }
+    }
+
+    fn allocate(&mut self, size: usize, align: usize) -> Option<(usize, usize)> {
+        let offset = self.index.find(size)?;
+        let free_size = self.free[&offset];
+        debug_assert!(offset.is_multiple_of(align) && free_size >= size);
+
+        self.remove_free(offset, free_size);
+        if free_size > size {
+            // Neighbours of a free range are live, so the tail needs no merging
+            self.insert_free(offset + size, free_size - size);
+        }
+
+        self.live.insert(offset, size);
+        self.used += size;
+        Some((offset, size))
+    }
+
+    // First fit over the ranges reaching past `floor`, the part below it stays free
+    fn allocate_from(&mut self, size: usize, floor: usize) -> Option<(usize, usize)> {
+        let (offset, free_size, start) = self.free.iter().find_map(|(&offset, &free_size)| {
+            let start = offset.max(floor);
+            (start + size <= offset + free_size).then_some((offset, free_size, start))
+        })?;
+
+        self.remove_free(offset, free_size);
+        if start > offset {
+            self.insert_free(offset, start - offset);
+        }
+        if offset + free_size > start + size {
+            self.insert_free(start + size, offset + free_size - start - size);
+        }
+
+        self.live.insert(start, size);
+        self.used += size;
+        Some((start, size))
+    }
+
+    fn free(&mut self, offset: usize) -> bool {
+        let mut size = match self.live.remove(&offset) {
+            Some(size) => size,
+            None => return false,
+        };
+        self.used -= size;
+
+        let mut start = offset;
+
+        // Merge with the free range right after
+        if let Some(&next_size) = self.free.get(&(offset + size)) {
+            self.remove_free(offset + size, next_size);
+            size += next_size;
+        }
+
+        // Merge with the free range right before
+        if let Some((&prev_offset, &prev_size)) = self.free.range(..offset).next_back()
+            && prev_offset + prev_size == offset
+        {
+            self.remove_free(prev_offset, prev_size);
+            start = prev_offset;
+            size += prev_size;
+        }
+
+        self.insert_free(start, size);
+        true
+    }
+
+    fn reset(&mut self) {
+        self.live.clear();
+        self.used = 0;
+        self.rebuild_free();
+    }
+
+    fn truncate(&mut self, keep: usize) {
+        self.live.retain(|&offset, &mut size| offset + size <= keep);
+        self.used = self.live.values().sum();
+        self.rebuild_free();
+    }
+
+    fn reserve(&mut self, bytes: usize) {
+        let bytes = bytes.min(self.capacity);
+        let reserved: Vec<(usize, usize)> = self.free
+            .range(..bytes)
+            .map(|(&offset, &size)| (offset, size.min(bytes - offset)))
+            .collect();
+
+        for (offset, size) in reserved {
+            self.live.insert(offset, size);
+            self.used += size;
+        }
+        self.rebuild_free();
+    }
+
+    fn extent(&self) -> usize {
+        self.live
+            .iter()
+            .next_back()
+            .map(|(&offset, &size)| offset + size)
+            .unwrap_or(0)
+    }
+
+    fn largest_free(&self) -> usize {
+        self.free.values().copied().max().unwrap_or(0)
+    }
+}
+
+// === Segregated free-list ===
+
+// One class per power of two: class k holds free ranges of [2^k, 2^(k+1)) bytes
+const SIZE_CLASSES: usize = usize::BITS as usize;
+
+struct SegregatedIndex {
+    classes: Vec<BTreeMap<usize, usize>>,  // offset -> size, per class
+    non_empty: u64,                        // Bit k set when class k has ranges
+}
+
+impl SegregatedIndex {
+    fn new() -> Self {
+        SegregatedIndex {
+            classes: (0..SIZE_CLASSES).map(|_| BTreeMap::new()).collect(),
+            non_empty: 0,
+        }
+    }
+
+    fn class_of(size: usize) -> usize {
+        size.max(1).ilog2() as usize
+    }
+}
+
+impl SizeIndex for SegregatedIndex {
+    fn insert(&mut self, offset: usize, size: usize) {
+        let class = Self::class_of(size);
+        self.classes[class].insert(offset, size);
+        self.non_empty |= 1 << class;
+    }
+
+    fn remove(&mut self, offset: usize, size: usize) {
+        let class = Self::class_of(size);
+        self.classes[class].remove(&offset);
+        if self.classes[class].is_empty() {
+            self.non_empty &= !(1 << class);
+        }
+    }
+
+    fn find(&self, size: usize) -> Option<usize> {
+        // The size's own class may hold ranges that are too small, search it first fit
+        let class = Self::class_of(size);
+        if let Some((&offset, _)) = self.classes[class].iter().find(|&(_, &free)| free >= size) {
+            return Some(offset);
+        }
+
+        // Any range in a larger class fits, take the lowest address of the smallest class
+        let larger = self.non_empty & (u64::MAX << 1).checked_shl(class as u32).unwrap_or(0);
+        if larger == 0 {
+            return None;
+        }
+        let class = larger.trailing_zeros() as usize;
+        self.classes[class].keys().next().copied()
+    }
+
+    fn clear(&mut self) {
+        for class in &mut self.classes {
+            class.clear();
+        }
+        self.non_empty = 0;
+    }
+}
+
+pub struct FreeListStrategy {
+    ranges: RangeAllocator<SegregatedIndex>,
+}
+
+impl FreeListStrategy {
+    pub fn new(capacity: usize) -> Self {
+        FreeListStrategy {
+            ranges: RangeAllocator::new(capacity, SegregatedIndex::new()),
+        }
+    }
+}
+
+impl AllocStrategy for FreeListStrategy {
+    fn kind(&self) -> StrategyKind {
+        StrategyKind::FreeList
+    }
+
+    fn allocate(&mut self, size: usize, align: usize) -> Option<(usize, usize)> {
+        self.ranges.allocate(size, align)
+    }
+
+    fn allocate_from(&mut self, size: usize, _align: usize, floor: usize) -> Option<(usize, usize)> {
+        self.ranges.allocate_from(size, floor)
+    }
+
+    fn free(&mut self, offset: usize, _block_size: usize) -> bool {
+        self.ranges.free(offset)
+    }
+
+    fn reset(&mut self) {
+        self.ranges.reset();
+    }
+
+    fn truncate(&mut self, keep: usize) {
+        self.ranges.truncate(keep);
+    }
+
+    fn reserve(&mut self, bytes: usize) {
+        self.ranges.reserve(bytes);
+    }
+
+    fn extent(&self) -> usize {
+        self.ranges.extent()
+    }
+
+    fn used(&self) -> usize {
+        self.ranges.used
+    }
+
+    fn available(&self) -> usize {
+        self.ranges.capacity - self.ranges.used
+    }
+
+    fn largest_available(&self) -> usize {
+        self.ranges.largest_free()
+    }
+}
+
+// === TLSF ===
+
+// Each first level (power of two) is split into 2^SL_BITS linear second levels
+const SL_BITS: u32 = 4;
+const SL_COUNT: usize = 1 << SL_BITS;
+const SMALL_BLOCK: usize = 1 << SL_BITS;  // Sizes below this map linearly into level 0
+const FL_COUNT: usize = (usize::BITS - SL_BITS + 1) as usize;
+
+struct TlsfIndex {
+    buckets: Vec<BTreeMap<usize, usize>>,  // FL_COUNT * SL_COUNT buckets of offset -> size
+    fl_bitmap: u64,
+    sl_bitmaps: Vec<u32>,
+}
+
+impl TlsfIndex {
+    fn new() -> Self {
+        TlsfIndex {
+            buckets: (0..FL_COUNT * SL_COUNT).map(|_| BTreeMap::new()).collect(),
+            fl_bitmap: 0,
+            sl_bitmaps: vec![0; FL_COUNT],
+        }
+    }
+
+    // Bucket that a free range of `size` bytes is filed under
+    fn mapping_insert(size: usize) -> (usize, usize) {
+        if size < SMALL_BLOCK {
+            return (0, size);
+        }
+        let fl = size.ilog2();
+        let sl = (size >> (fl - SL_BITS)) ^ SL_COUNT;
+        ((fl - SL_BITS + 1) as usize, sl)
+    }
+
+    // Round the request up to the next bucket so anything found there fits
+    fn mapping_search(size: usize) -> (usize, usize) {
+        if size < SMALL_BLOCK {
+            return (0, size);
+        }
+        let round = (1usize << (size.ilog2() - SL_BITS)) - 1;
+        Self::mapping_insert(size.saturating_add(round))
+    }
+
+    fn bucket(&self, fl: usize, sl: usize) -> &BTreeMap<usize, usize> {
+        &self.buckets[fl * SL_COUNT + sl]
+    }
+
+    fn find_suitable(&self, fl: usize, sl: usize) -> Option<(usize, usize)> {
+        if fl >= FL_COUNT {
+            return None;
+        }
+
+        // Remaining second levels of this first level
+        let sl_map = self.sl_bitmaps[fl] & (u32::MAX << sl);
+        if sl_map != 0 {
+            return Some((fl, sl_map.trailing_zeros() as usize));
+        }
+
+        // Otherwise the smallest non-empty first level above
+        let fl_map = self.fl_bitmap & (u64::MAX << 1).checked_shl(fl as u32).unwrap_or(0);
+        if fl_map == 0 {
+            return None;
+        }
+        let fl = fl_map.trailing_zeros() as usize;
+        Some((fl, self.sl_bitmaps[fl].trailing_zeros() as usize))
+    }
+}
+
+impl SizeIndex for TlsfIndex {
+    fn insert(&mut self, offset: usize, size: usize) {
+        let (fl, sl) = Self::mapping_insert(size);
+        self.buckets[fl * SL_COUNT + sl].insert(offset, size);
+        self.fl_bitmap |= 1 << fl;
+        self.sl_bitmaps[fl] |= 1 << sl;
+    }
+
+    fn remove(&mut self, offset: usize, size: usize) {
+        let (fl, sl) = Self::mapping_insert(size);
+        let bucket = &mut self.buckets[fl * SL_COUNT + sl];
+        bucket.remove(&offset);
+        if bucket.is_empty() {
+            self.sl_bitmaps[fl] &= !(1 << sl);
+            if self.sl_bitmaps[fl] == 0 {
+                self.fl_bitmap &= !(1 << fl);
+            }
+        }
+    }
+
+    fn find(&self, size: usize) -> Option<usize> {
+        let (fl, sl) = Self::mapping_search(size);
+        if let Some((fl, sl)) = self.find_suitable(fl, sl) {
+            return self.bucket(fl, sl).keys().next().copied();
+        }
+
+        // Good fit missed - the request's own bucket may still hold a range that fits
+        let (fl, sl) = Self::mapping_insert(size);
+        self.bucket(fl, sl)
+            .iter()
+            .find(|&(_, &free)| free >= size)
+            .map(|(&offset, _)| offset)
+    }
+
+    fn clear(&mut self) {
+        for bucket in &mut self.buckets {
+            bucket.clear();
+        }
+        self.fl_bitmap = 0;
+        self.sl_bitmaps.iter_mut().for_each(|bitmap| *bitmap = 0);
+    }
+}
+
+pub struct TlsfStrategy {
+    ranges: RangeAllocator<TlsfIndex>,
+}
+
+impl TlsfStrategy {
+    pub fn new(capacity: usize) -> Self {
+        TlsfStrategy {
+            ranges: RangeAllocator::new(capacity, TlsfIndex::new()),
+        }
+    }
+}
+
+impl AllocStrategy for TlsfStrategy {
+    fn kind(&self) -> StrategyKind {
+        StrategyKind::Tlsf
+    }
+
+    fn allocate(&mut self, size: usize, align: usize) -> Option<(usize, usize)> {
+        self.ranges.allocate(size, align)
+    }
+
+    fn allocate_from(&mut self, size: usize, _align: usize, floor: usize) -> Option<(usize, usize)> {
+        self.ranges.allocate_from(size, floor)
+    }
+
+    fn free(&mut self, offset: usize, _block_size: usize) -> bool {
+        self.ranges.free(offset)
+    }
+
+    fn reset(&mut self) {
+        self.ranges.reset();
+    }
+
+    fn truncate(&mut self, keep: usize) {
+        self.ranges.truncate(keep);
+    }
+
+    fn reserve(&mut self, bytes: usize) {
+        self.ranges.reserve(bytes);
+    }
+
+    fn extent(&self) -> usize {
+        self.ranges.extent()
+    }
+
+    fn used(&self) -> usize {
+        self.ranges.used
+    }
+
+    fn available(&self) -> usize {
+        self.ranges.capacity - self.ranges.used
+    }
+
+    fn largest_available(&self) -> usize {
+        self.ranges.largest_free()
+    }
+}
+
+// === Buddy ===
+
+pub const MIN_BUDDY_BLOCK: usize = 64;
+
+pub struct BuddyStrategy {
+    capacity: usize,                     // Rounded down to whole minimum blocks
+    free_lists: Vec<BTreeMap<usize, ()>>, // Free block offsets per order
+    live: BTreeMap<usize, usize>,        // offset -> order
+    used: usize,
+    available: usize,
+}
+
+impl BuddyStrategy {
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity - capacity % MIN_BUDDY_BLOCK;
+        let orders = if capacity == 0 {
+            1
+        } else {
+            (capacity / MIN_BUDDY_BLOCK).ilog2() as usize + 1
+        };
+
+        let mut buddy = BuddyStrategy {
+            capacity,
+            free_lists: (0..orders).map(|_| BTreeMap::new()).collect(),
+            live: BTreeMap::new(),
+            used: 0,
+            available: 0,
+        };
+        buddy.rebuild_free();
+        buddy
+    }
+
+    fn block_size(order: usize) -> usize {
+        MIN_BUDDY_BLOCK << order
+    }
+
+    fn order_for(size: usize) -> usize {
+        (size.max(MIN_BUDDY_BLOCK).next_power_of_two() / MIN_BUDDY_BLOCK).ilog2() as usize
+    }
+
+    // Split a range into the largest naturally aligned power of two blocks
+    fn decompose(mut offset: usize, mut size: usize, max_order: usize) -> Vec<(usize, usize)> {
+        let mut blocks = Vec::new();
+        while size >= MIN_BUDDY_BLOCK {
+            let mut order = max_order;
+            while Self::block_size(order) > size || !offset.is_multiple_of(Self::block_size(order)) {
+                order -= 1;
+            }
+            blocks.push((offset, order));
+            offset += Self::block_size(order);
+            size -= Self::block_size(order);
+        }
+        blocks
+    }
+
+    // Free blocks are the gaps between live blocks, split into buddy blocks
+    fn rebuild_free(&mut self) {
+        for list in &mut self.free_lists {
+            list.clear();
+        }
+        self.available = 0;
+
+        let max_order = self.free_lists.len() - 1;
+        let mut gaps = Vec::new();
+        let mut cursor = 0;
+        for (&offset, &order) in &self.live {
+            if offset > cursor {
+                gaps.push((cursor, offset - cursor));
+            }
+            cursor = offset + Self::block_size(order);
+        }
+        if self.capacity > cursor {
+            gaps.push((cursor, self.capacity - cursor));
+        }
+
+        for (offset, size) in gaps {
+            for (block, order) in Self::decompose(offset, size, max_order) {
+                self.free_lists[order].insert(block, ());
+                self.available += Self::block_size(order);
+            }
+        }
+    }
+}
+
+impl AllocStrategy for BuddyStrategy {
+    fn kind(&self) -> StrategyKind {
+        StrategyKind::Buddy
+    }
+
+    fn allocate(&mut self, size: usize, align: usize) -> Option<(usize, usize)> {
+        let order = Self::order_for(size.max(align));
+        let mut found = (order..self.free_lists.len()).find(|&o| !self.free_lists[o].is_empty())?;
+        let (offset, _) = self.free_lists[found].pop_first()?;
+
+        // Split down to the requested order, freeing the upper halves
+        while found > order {
+            found -= 1;
+            self.free_lists[found].insert(offset + Self::block_size(found), ());
+        }
+
+        let block_size = Self::block_size(order);
+        self.live.insert(offset, order);
+        self.used += block_size;
+        self.available -= block_size;
+        Some((offset, block_size))
+    }
+
+    fn allocate_from(&mut self, size: usize, align: usize, floor: usize) -> Option<(usize, usize)> {
+        let order = Self::order_for(size.max(align));
+
+        // First block of the order at or above the floor inside a free block
+        let size = Self::block_size(order);
+        let start_in = |offset: usize| offset.max(floor.next_multiple_of(size));
+
+        // Smallest free block that holds one, lowest first
+        let (mut found, mut offset) = (order..self.free_lists.len()).find_map(|o| {
+            self.free_lists[o]
+                .keys()
+                .find(|&&offset| start_in(offset) + size <= offset + Self::block_size(o))
+                .map(|&offset| (o, offset))
+        })?;
+        self.free_lists[found].remove(&offset);
+
+        // Split down, keeping the half that holds the start and freeing the other
+        let start = start_in(offset);
+        while found > order {
+            found -= 1;
+            let upper = offset + Self::block_size(found);
+            if start >= upper {
+                self.free_lists[found].insert(offset, ());
+                offset = upper;
+            } else {
+                self.free_lists[found].insert(upper, ());
+            }
+        }
+
+        let block_size = Self::block_size(order);
+        self.live.insert(offset, order);
+        self.used += block_size;
+        self.available -= block_size;
+        Some((offset, block_size))
+    }
+
+    fn free(&mut self, offset: usize, _block_size: usize) -> bool {
+        let mut order = match self.live.remove(&offset) {
+            Some(order) => order,
+            None => return false,
+        };
+        self.used -= Self::block_size(order);
+        self.available += Self::block_size(order);
+
+        // Merge with the buddy for as long as it is free too
+        let mut offset = offset;
+        while order + 1 < self.free_lists.len() {
+            let buddy = offset ^ Self::block_size(order);
+            if self.free_lists[order].remove(&buddy).is_none() {
+                break;
+            }
+            offset = offset.min(buddy);
+            order += 1;
+        }
+
+        self.free_lists[order].insert(offset, ());
+        true
+    }
+
+    fn reset(&mut self) {
+        self.live.clear();
+        self.used = 0;
+        self.rebuild_free();
+    }
+
+    fn truncate(&mut self, keep: usize) {
+        self.live.retain(|&offset, &mut order| offset + Self::block_size(order) <= keep);
+        self.used = self.live.values().map(|&order| Self::block_size(order)).sum();
+        self.rebuild_free();
+    }
+
+    fn reserve(&mut self, bytes: usize) {
+        let bytes = bytes.min(self.capacity).next_multiple_of(MIN_BUDDY_BLOCK).min(self.capacity);
+        let max_order = self.free_lists.len() - 1;
+
+        // Cover every free range below `bytes` with live blocks
+        let mut reserved = Vec::new();
+        for (order, list) in self.free_lists.iter().enumerate() {
+            for &offset in list.keys() {
+                if offset < bytes {
+                    let size = Self::block_size(order).min(bytes - offset);
+                    reserved.extend(Self::decompose(offset, size, max_order));
+                }
+            }
+        }
+
+        for (offset, order) in reserved {
+            self.live.insert(offset, order);
+            self.used += Self::block_size(order);
+        }
+        self.rebuild_free();
+    }
+
+    fn extent(&self) -> usize {
+        self.live
+            .iter()
+            .next_back()
+            .map(|(&offset, &order)| offset + Self::block_size(order))
+            .unwrap_or(0)
+    }
+
+    fn used(&self) -> usize {
+        self.used
+    }
+
+    fn available(&self) -> usize {
+        self.available
+    }
+
+    fn largest_available(&self) -> usize {
+        (0..self.free_lists.len())
+            .rev()
+            .find(|&order| !self.free_lists[order].is_empty())
+            .map(Self::block_size)
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CAPACITY: usize = 4096;
+    const ALIGN: usize = 16;
+
+    const KINDS: [StrategyKind; 4] = [StrategyKind::Bump, StrategyKind::FreeList, StrategyKind::Buddy, StrategyKind::Tlsf];
+
+    // Run `test` against every strategy over a fresh segment. Bump keeps its
+    // free list in the segment, so it needs real memory behind it.
+    fn each_strategy(test: impl Fn(&mut dyn AllocStrategy, &str)) {
+        for kind in KINDS {
+            let mut memory = vec![0u128; CAPACITY / 16];
+            let mut strategy = kind.build(Tier::SCENE, memory.as_mut_ptr() as *mut u8, CAPACITY);
+            test(strategy.as_mut(), kind.name());
+        }
+    }
+
+    #[test]
+    fn freed_blocks_are_reused() {
+        each_strategy(|strategy, name| {
+            let (a, a_size) = strategy.allocate(64, ALIGN).unwrap();
+            let (b, b_size) = strategy.allocate(64, ALIGN).unwrap();
+            assert_ne!(a, b, "{}", name);
+            assert_eq!(strategy.used(), a_size + b_size, "{}", name);
+
+            assert!(strategy.free(a, a_size), "{}", name);
+            assert_eq!(strategy.used(), b_size, "{}", name);
+            assert_eq!(strategy.allocate(64, ALIGN), Some((a, a_size)), "{}", name);
+        });
+    }
+
+    #[test]
+    fn double_free_is_rejected() {
+        each_strategy(|strategy, name| {
+            let (a, a_size) = strategy.allocate(64, ALIGN).unwrap();
+            let (_, b_size) = strategy.allocate(64, ALIGN).unwrap();
+            assert!(strategy.free(a, a_size), "{}", name);
+            assert!(!strategy.free(a, a_size), "{}", name);
+            assert_eq!(strategy.used(), b_size, "{}", name);
+
+            // The block is handed out once, not twice
+            assert_eq!(strategy.allocate(64, ALIGN), Some((a, a_size)), "{}", name);
+            assert_ne!(strategy.allocate(64, ALIGN).map(|(offset, _)| offset), Some(a), "{}", name);
+        });
+    }
+
+    #[test]
+    fn freeing_unallocated_memory_is_rejected() {
+        each_strategy(|strategy, name| {
+            let (a, a_size) = strategy.allocate(256, ALIGN).unwrap();
+            strategy.allocate(64, ALIGN).unwrap();
+            assert!(!strategy.free(CAPACITY - 64, 64), "{}", name);
+
+            // Inside a block that is already free
+            assert!(strategy.free(a, a_size), "{}", name);
+            assert!(!strategy.free(a + 64, 64), "{}", name);
+        });
+    }
+
+    #[test]
+    fn fragmentation_follows_free_blocks() {
+        each_strategy(|strategy, name| {
+            let blocks: Vec<(usize, usize)> = (0..8).map(|_| strategy.allocate(256, ALIGN).unwrap()).collect();
+            assert_eq!(strategy.fragmentation(), 0.0, "{}", name);
+
+            for &(offset, size) in blocks.iter().step_by(2) {
+                assert!(strategy.free(offset, size), "{}", name);
+            }
+            assert!(strategy.fragmentation() > 0.0, "{}", name);
+            assert!(strategy.largest_available() < strategy.available(), "{}", name);
+
+            // Freeing the rest merges everything back into one block
+            for &(offset, size) in blocks.iter().skip(1).step_by(2) {
+                assert!(strategy.free(offset, size), "{}", name);
+            }
+            assert_eq!(strategy.used(), 0, "{}", name);
+            assert_eq!(strategy.available(), CAPACITY, "{}", name);
+            assert_eq!(strategy.fragmentation(), 0.0, "{}", name);
+        });
+    }
+
+    #[test]
+    fn allocate_from_stays_above_the_floor() {
+        each_strategy(|strategy, name| {
+            let (a, a_size) = strategy.allocate(64, ALIGN).unwrap();
+            strategy.allocate(64, ALIGN).unwrap();
+            assert!(strategy.free(a, a_size), "{}", name);
+
+            // The freed block sits below the floor and must not be handed out
+            let floor = 1000;
+            let (offset, size) = strategy.allocate_from(64, ALIGN, floor).unwrap();
+            assert!(offset >= floor, "{}: {} below {}", name, offset, floor);
+
+            // Memory below the floor is still there for later
+            assert!(strategy.free(offset, size), "{}", name);
+            assert_eq!(strategy.allocate(64, ALIGN), Some((a, a_size)), "{}", name);
+        });
+    }
+
+    #[test]
+    fn allocate_from_fails_when_nothing_fits_above_the_floor() {
+        each_strategy(|strategy, name| {
+            assert_eq!(strategy.allocate_from(512, ALIGN, CAPACITY - 256), None, "{}", name);
+            assert_eq!(strategy.used(), 0, "{}", name);
+        });
+    }
+
+    #[test]
+    fn truncate_recycles_blocks_past_keep() {
+        each_strategy(|strategy, name| {
+            let (a, a_size) = strategy.allocate(256, ALIGN).unwrap();
+            strategy.allocate(256, ALIGN).unwrap();
+            strategy.truncate(a + a_size);
+            assert_eq!(strategy.used(), a_size, "{}", name);
+            assert_eq!(strategy.extent(), a + a_size, "{}", name);
+
+            strategy.reset();
+            assert_eq!(strategy.used(), 0, "{}", name);
+            assert_eq!(strategy.extent(), 0, "{}", name);
+        });
+    }
+
+    #[test]
+    fn buddy_allocate_from_looks_past_a_block_the_floor_splits() {
+        let mut buddy = BuddyStrategy::new(CAPACITY);
+        // Leaves free blocks of 64 at 64, 128 at 128, 256 at 256 and up
+        assert_eq!(buddy.allocate(64, ALIGN), Some((0, 64)));
+
+        // The 128 byte block at 128 reaches past the floor, but not with 128 bytes
+        assert_eq!(buddy.allocate_from(128, ALIGN, 150), Some((256, 128)));
+        assert_eq!(buddy.allocate(128, ALIGN), Some((128, 128)));
+    }
+
+    #[test]
+    fn bump_free_lowers_the_top() {
+        let mut memory = vec![0u128; CAPACITY / 16];
+        let mut bump = BumpStrategy::new(Tier::SCENE, memory.as_mut_ptr() as *mut u8, CAPACITY);
+        let (a, a_size) = bump.allocate(64, ALIGN).unwrap();
+        let (b, b_size) = bump.allocate(64, ALIGN).unwrap();
+
+        // a goes on the free list, freeing b then takes the bump pointer below both
+        assert!(bump.free(a, a_size));
+        assert_eq!(bump.extent(), b + b_size);
+        assert!(bump.free(b, b_size));
+        assert_eq!(bump.extent(), 0);
+        assert_eq!(bump.available(), CAPACITY);
+    }
+}
@@ -0,0 +1,182 @@
+// Allocation tags.
+//
+// An allocation can carry a tag naming who made it, a subsystem such as
+// "physics" or a call site such as "shadows.rs:142". Each tier remembers the
+// tag of every live tagged block by its position (segments laid end to end),
+// forgets it when the block is freed or recycled, and keeps running totals per
+// tag. Untagged allocations cost nothing beyond that check.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use super::Tier;
+
+// What one tag holds in one tier
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct TagStats {
+    pub tag: String,
+    pub tier: Tier,
+    pub live_bytes: usize,        // Block sizes of its live allocations
+    pub live_allocations: usize,
+    pub total_bytes: u64,         // Everything it ever allocated, recycled or not
+    pub total_allocations: u64,
+}
+
+#[derive(Default)]
+pub(crate) struct TagTracker {
+    live: HashMap<usize, (Arc<str>, usize)>,  // Position to tag and block size
+    totals: HashMap<Arc<str>, (u64, u64)>,    // Tag to allocations and bytes
+}
+
+impl TagTracker {
+    pub fn allocated(&mut self, position: usize, block_size: usize, tag: &Arc<str>) {
+        self.live.insert(position, (Arc::clone(tag), block_size));
+        let totals = self.totals.entry(Arc::clone(tag)).or_default();
+        totals.0 += 1;
+        totals.1 += block_size as u64;
+    }
+
+    // Move a live block over to another tag, e.g. once a staging block becomes an asset
+    pub fn retag(&mut self, position: usize, tag: &Arc<str>) {
+        if let Some((old, _)) = self.live.get_mut(&position) {
+            *old = Arc::clone(tag);
+            self.totals.entry(Arc::clone(tag)).or_default();
+        }
+    }
+
+    pub fn freed(&mut self, position: usize) {
+        self.live.remove(&position);
+    }
+
+    // Forget every block that reaches past `preserved` bytes
+    pub fn truncate(&mut self, preserved: usize) {
+        self.live.retain(|&position, (_, block_size)| position + *block_size <= preserved);
+    }
+
+    pub fn clear(&mut self) {
+        self.live.clear();
+    }
+
+    // Tag of the live block at `position`
+    pub fn tag_at(&self, position: usize) -> Option<&Arc<str>> {
+        self.live.get(&position).map(|(tag, _)| tag)
+    }
+
+    // One entry per tag ever seen in the tier, most live bytes first
+    pub fn stats(&self, tier: Tier) -> Vec<TagStats> {
+        let mut stats: Vec<TagStats> = self.totals
+            .iter()
+            .map(|(tag, &(total_allocations, total_bytes))| TagStats {
+                tag: tag.to_string(),
+                tier,
+                live_bytes: 0,
+                live_allocations: 0,
+                total_bytes,
+                total_allocations,
+            })
+            .collect();
+        for (tag, block_size) in self.live.values() {
+            if let Some(entry) = stats.iter_mut().find(|entry| *entry.tag == **tag) {
+                entry.live_bytes += block_size;
+                entry.live_allocations += 1;
+            }
+        }
+        stats.sort_by(|a, b| b.live_bytes.cmp(&a.live_bytes).then_with(|| a.tag.cmp(&b.tag)));
+        stats
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{NativeMemory, TieredAllocator};
+
+    fn allocator() -> TieredAllocator {
+        TieredAllocator::new(Box::new(NativeMemory::new(64)), 16)
+    }
+
+    fn live(stats: &[TagStats], tag: &str) -> (usize, usize, u64) {
+        stats
+            .iter()
+            .find(|entry| entry.tag == tag)
+            .map_or((0, 0, 0), |entry| (entry.live_allocations, entry.live_bytes, entry.total_allocations))
+    }
+
+    #[test]
+    fn totals_outlive_the_blocks() {
+        let physics: Arc<str> = Arc::from("physics");
+        let audio: Arc<str> = Arc::from("audio");
+        let mut tags = TagTracker::default();
+        tags.allocated(0, 64, &physics);
+        tags.allocated(64, 32, &physics);
+        tags.allocated(96, 128, &audio);
+        tags.freed(64);
+
+        let stats = tags.stats(Tier::ENTITY);
+        assert_eq!(stats.iter().map(|entry| entry.tag.as_str()).collect::<Vec<_>>(), ["audio", "physics"]);
+        assert_eq!((stats[1].live_allocations, stats[1].live_bytes), (1, 64));
+        assert_eq!((stats[1].total_allocations, stats[1].total_bytes), (2, 96));
+        assert_eq!(stats[0].tier, Tier::ENTITY);
+
+        tags.clear();
+        let stats = tags.stats(Tier::ENTITY);
+        assert!(stats.iter().all(|entry| entry.live_allocations == 0));
+        assert_eq!(stats.iter().map(|entry| entry.total_allocations).sum::<u64>(), 3);
+    }
+
+    #[test]
+    fn retagging_moves_live_bytes_not_totals() {
+        let staging: Arc<str> = Arc::from("staging");
+        let asset: Arc<str> = Arc::from("asset");
+        let mut tags = TagTracker::default();
+        tags.allocated(0, 256, &staging);
+        tags.retag(0, &asset);
+        tags.retag(512, &asset);
+
+        assert_eq!(tags.tag_at(0), Some(&asset));
+        assert_eq!(tags.tag_at(512), None);
+        let stats = tags.stats(Tier::SCENE);
+        assert_eq!(live(&stats, "asset"), (1, 256, 0));
+        assert_eq!(live(&stats, "staging"), (0, 0, 1));
+    }
+
+    #[test]
+    fn truncating_forgets_blocks_past_the_cut() {
+        let tag: Arc<str> = Arc::from("tag");
+        let mut tags = TagTracker::default();
+        tags.allocated(0, 64, &tag);
+        tags.allocated(64, 64, &tag);
+        tags.allocated(128, 64, &tag);
+        tags.truncate(160);
+
+        assert!(tags.tag_at(64).is_some());
+        assert!(tags.tag_at(128).is_none());
+        assert_eq!(live(&tags.stats(Tier::ENTITY), "tag"), (2, 128, 3));
+    }
+
+    #[test]
+    fn resets_and_compactions_drop_live_tags() {
+        let mut allocator = allocator();
+        let kept = allocator.allocate_handle_tagged(256, Tier::ENTITY, "kept").unwrap();
+        let marker = allocator.push_marker(Tier::ENTITY, "level").unwrap();
+        let popped = allocator.allocate_handle_tagged(256, Tier::ENTITY, "popped").unwrap();
+        assert_eq!(allocator.tag_of(&popped).as_deref(), Some("popped"));
+
+        allocator.pop_to_marker(&marker).unwrap();
+        let stats = allocator.tag_stats(Tier::ENTITY);
+        assert_eq!(live(&stats, "popped"), (0, 0, 1));
+        assert_eq!(live(&stats, "kept").0, 1);
+        assert_eq!(allocator.tag_of(&kept).as_deref(), Some("kept"));
+
+        allocator.allocate_handle_tagged(256, Tier::ENTITY, "compacted").unwrap();
+        assert!(allocator.fast_compact_tier(Tier::ENTITY, 0));
+        assert_eq!(live(&allocator.tag_stats(Tier::ENTITY), "compacted"), (0, 0, 1));
+
+        allocator.allocate_handle_tagged(256, Tier::ENTITY, "reset").unwrap();
+        allocator.reset_tier(Tier::ENTITY);
+        let stats = allocator.tag_stats(Tier::ENTITY);
+        assert!(stats.iter().all(|entry| entry.live_allocations == 0));
+        assert_eq!(stats.len(), 4);
+        assert_eq!(allocator.tag_of(&kept), None);
+    }
+}
@@ -0,0 +1,333 @@
+// Allocation trace, built with the "trace" feature.
+//
+// Every tier records what happens to it in a ring buffer shared by the whole
+// allocator: allocations, frees, resets, compactions (popped markers included),
+// growth and asset evictions. Once the buffer is full the oldest events make
+// room. The log exports as plain JSON or in the Chrome Trace Event format,
+// which chrome://tracing and Perfetto open: one track per tier with an instant
+// event per operation, and a counter of the tier's usage.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use serde_json::{Value, json};
+
+use super::Tier;
+
+// Events kept until the oldest are dropped
+pub const DEFAULT_TRACE_CAPACITY: usize = 4096;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum TraceOp {
+    Alloc,
+    Free,
+    Reset,
+    Compact,
+    Grow,
+    Evict,
+}
+
+impl TraceOp {
+    pub fn name(&self) -> &'static str {
+        match self {
+            TraceOp::Alloc => "alloc",
+            TraceOp::Free => "free",
+            TraceOp::Reset => "reset",
+            TraceOp::Compact => "compact",
+            TraceOp::Grow => "grow",
+            TraceOp::Evict => "evict",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct TraceEvent {
+    pub op: TraceOp,
+    pub tier: Tier,
+    pub time: f64,                // Milliseconds since the log was created
+    pub position: Option<usize>,  // Where in the tier, segments laid end to end
+    pub size: usize,              // Block size, bytes grown, or bytes preserved by a compaction
+    pub used: usize,              // Tier usage once the operation was done
+    pub tag: Option<Arc<str>>,
+}
+
+struct TraceBuffer {
+    events: VecDeque<TraceEvent>,
+    capacity: usize,
+    dropped: u64,
+    enabled: bool,
+}
+
+pub struct TraceLog {
+    buffer: Mutex<TraceBuffer>,
+    #[cfg(not(target_arch = "wasm32"))]
+    start: std::time::Instant,
+    #[cfg(target_arch = "wasm32")]
+    start: f64,
+}
+
+impl TraceLog {
+    pub fn new(capacity: usize) -> Self {
+        TraceLog {
+            buffer: Mutex::new(TraceBuffer {
+                events: VecDeque::with_capacity(capacity.min(DEFAULT_TRACE_CAPACITY)),
+                capacity,
+                dropped: 0,
+                enabled: true,
+            }),
+            #[cfg(not(target_arch = "wasm32"))]
+            start: std::time::Instant::now(),
+            #[cfg(target_arch = "wasm32")]
+            start: now_millis(),
+        }
+    }
+
+    // Milliseconds since the log was created
+    pub fn elapsed(&self) -> f64 {
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            self.start.elapsed().as_secs_f64() * 1000.0
+        }
+        #[cfg(target_arch = "wasm32")]
+        {
+            now_millis() - self.start
+        }
+    }
+
+    pub fn record(&self, op: TraceOp, tier: Tier, position: Option<usize>, size: usize, used: usize, tag: Option<&Arc<str>>) {
+        let time = self.elapsed();
+        let mut buffer = match self.buffer.lock() {
+            Ok(buffer) => buffer,
+            Err(_) => return,
+        };
+        if !buffer.enabled || buffer.capacity == 0 {
+            return;
+        }
+        if buffer.events.len() == buffer.capacity {
+            buffer.events.pop_front();
+            buffer.dropped += 1;
+        }
+        buffer.events.push_back(TraceEvent { op, tier, time, position, size, used, tag: tag.cloned() });
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        if let Ok(mut buffer) = self.buffer.lock() {
+            buffer.enabled = enabled;
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.buffer.lock().is_ok_and(|buffer| buffer.enabled)
+    }
+
+    // Keep at most `capacity` events, dropping the oldest beyond that
+    pub fn set_capacity(&self, capacity: usize) {
+        if let Ok(mut buffer) = self.buffer.lock() {
+            while buffer.events.len() > capacity {
+                buffer.events.pop_front();
+                buffer.dropped += 1;
+            }
+            buffer.capacity = capacity;
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.buffer.lock().map_or(0, |buffer| buffer.capacity)
+    }
+
+    // Events dropped to make room since the log was last cleared
+    pub fn dropped(&self) -> u64 {
+        self.buffer.lock().map_or(0, |buffer| buffer.dropped)
+    }
+
+    pub fn events(&self) -> Vec<TraceEvent> {
+        self.buffer.lock().map_or(Vec::new(), |buffer| buffer.events.iter().cloned().collect())
+    }
+
+    pub fn clear(&self) {
+        if let Ok(mut buffer) = self.buffer.lock() {
+            buffer.events.clear();
+            buffer.dropped = 0;
+        }
+    }
+
+    // { "capacity": 4096, "dropped": 0, "events": [{ "op": "alloc", "tier": 0,
+    //   "time": 1.5, "position": 0, "size": 256, "used": 256, "tag": "physics" }] }
+    pub fn to_json(&self) -> String {
+        let events: Vec<Value> = self.events()
+            .iter()
+            .map(|event| {
+                let mut value = json!({
+                    "op": event.op.name(),
+                    "tier": event.tier.id(),
+                    "time": event.time,
+                    "size": event.size,
+                    "used": event.used,
+                });
+                if let Some(position) = event.position {
+                    value["position"] = json!(position);
+                }
+                if let Some(tag) = &event.tag {
+                    value["tag"] = json!(tag.as_ref());
+                }
+                value
+            })
+            .collect();
+        json!({ "capacity": self.capacity(), "dropped": self.dropped(), "events": events }).to_string()
+    }
+
+    // Chrome Trace Event format, a track per tier named from `tier_names`
+    pub fn to_chrome_trace(&self, tier_names: &[String]) -> String {
+        let tier_name = |tier: Tier| {
+            tier_names.get(tier.index()).cloned().unwrap_or_else(|| format!("Tier {}", tier.id()))
+        };
+
+        let mut trace: Vec<Value> = vec![json!({
+            "name": "process_name", "ph": "M", "pid": 1, "tid": 0,
+            "args": { "name": "walloc" },
+        })];
+        trace.extend(tier_names.iter().enumerate().map(|(index, name)| json!({
+            "name": "thread_name", "ph": "M", "pid": 1, "tid": index,
+            "args": { "name": name },
+        })));
+
+        for event in self.events() {
+            // Chrome counts in microseconds
+            let ts = event.time * 1000.0;
+            let mut args = json!({ "size": event.size, "used": event.used });
+            if let Some(position) = event.position {
+                args["position"] = json!(position);
+            }
+            if let Some(tag) = &event.tag {
+                args["tag"] = json!(tag.as_ref());
+            }
+            let name = match &event.tag {
+                Some(tag) => format!("{} {}", event.op.name(), tag),
+                None => event.op.name().to_string(),
+            };
+            trace.push(json!({
+                "name": name, "cat": event.op.name(), "ph": "i", "s": "t",
+                "ts": ts, "pid": 1, "tid": event.tier.id(), "args": args,
+            }));
+            trace.push(json!({
+                "name": format!("{} used", tier_name(event.tier)), "ph": "C",
+                "ts": ts, "pid": 1, "tid": event.tier.id(), "args": { "bytes": event.used },
+            }));
+        }
+        json!({ "traceEvents": trace, "displayTimeUnit": "ms" }).to_string()
+    }
+}
+
+impl Default for TraceLog {
+    fn default() -> Self {
+        TraceLog::new(DEFAULT_TRACE_CAPACITY)
+    }
+}
+
+// High resolution browser clock, the wall clock where there is none
+#[cfg(target_arch = "wasm32")]
+fn now_millis() -> f64 {
+    use wasm_bindgen::{JsCast, JsValue};
+
+    let performance = js_sys::Reflect::get(&js_sys::global(), &JsValue::from_str("performance")).ok();
+    let now = performance.as_ref().and_then(|performance| {
+        let now = js_sys::Reflect::get(performance, &JsValue::from_str("now")).ok()?;
+        now.dyn_into::<js_sys::Function>().ok()?.call0(performance).ok()?.as_f64()
+    });
+    now.unwrap_or_else(js_sys::Date::now)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sizes(log: &TraceLog) -> Vec<usize> {
+        log.events().iter().map(|event| event.size).collect()
+    }
+
+    #[test]
+    fn full_buffers_drop_the_oldest_events() {
+        let log = TraceLog::new(3);
+        for size in 1..=5 {
+            log.record(TraceOp::Alloc, Tier::ENTITY, Some(0), size, size, None);
+        }
+        assert_eq!(sizes(&log), [3, 4, 5]);
+        assert_eq!(log.dropped(), 2);
+
+        log.set_capacity(1);
+        assert_eq!(sizes(&log), [5]);
+        assert_eq!(log.dropped(), 4);
+
+        log.clear();
+        assert!(log.events().is_empty());
+        assert_eq!((log.dropped(), log.capacity()), (0, 1));
+    }
+
+    #[test]
+    fn disabled_and_empty_logs_record_nothing() {
+        let log = TraceLog::new(4);
+        log.set_enabled(false);
+        log.record(TraceOp::Reset, Tier::SCENE, None, 0, 0, None);
+        assert!(!log.is_enabled() && log.events().is_empty());
+
+        let log = TraceLog::new(0);
+        log.record(TraceOp::Reset, Tier::SCENE, None, 0, 0, None);
+        assert!(log.events().is_empty());
+        assert_eq!(log.dropped(), 0);
+    }
+
+    #[test]
+    fn json_lists_every_event() {
+        let log = TraceLog::new(8);
+        let tag: Arc<str> = Arc::from("physics");
+        log.record(TraceOp::Alloc, Tier::ENTITY, Some(64), 256, 320, Some(&tag));
+        log.record(TraceOp::Grow, Tier::ENTITY, None, 65536, 320, None);
+
+        let json: Value = serde_json::from_str(&log.to_json()).unwrap();
+        assert_eq!((json["capacity"].as_u64(), json["dropped"].as_u64()), (Some(8), Some(0)));
+        let events = json["events"].as_array().unwrap();
+        assert_eq!(events[0]["op"], "alloc");
+        assert_eq!(events[0]["tier"], 2);
+        assert_eq!(events[0]["position"], 64);
+        assert_eq!(events[0]["tag"], "physics");
+        assert_eq!(events[1]["op"], "grow");
+        assert!(events[1].get("position").is_none() && events[1].get("tag").is_none());
+    }
+
+    #[test]
+    fn chrome_traces_have_a_track_and_a_counter_per_tier() {
+        let log = TraceLog::new(8);
+        let tag: Arc<str> = Arc::from("physics");
+        log.record(TraceOp::Alloc, Tier::SCENE, Some(0), 128, 128, Some(&tag));
+        log.record(TraceOp::Free, Tier::SCENE, Some(0), 128, 0, None);
+        let names = ["render".to_string(), "scene".to_string(), "entity".to_string()];
+
+        let trace: Value = serde_json::from_str(&log.to_chrome_trace(&names)).unwrap();
+        assert_eq!(trace["displayTimeUnit"], "ms");
+        let events = trace["traceEvents"].as_array().unwrap();
+
+        // Process name, one thread name per tier, then an instant and a counter per event
+        assert_eq!(events.len(), 1 + names.len() + 4);
+        assert_eq!(events[0]["name"], "process_name");
+        for (index, name) in names.iter().enumerate() {
+            let metadata = &events[1 + index];
+            assert_eq!((metadata["ph"].as_str(), metadata["tid"].as_u64()), (Some("M"), Some(index as u64)));
+            assert_eq!(metadata["args"]["name"], name.as_str());
+        }
+
+        let (alloc, counter) = (&events[4], &events[5]);
+        assert_eq!((alloc["ph"].as_str(), alloc["s"].as_str()), (Some("i"), Some("t")));
+        assert_eq!(alloc["name"], "alloc physics");
+        assert_eq!(alloc["cat"], "alloc");
+        assert_eq!(alloc["tid"], 1);
+        assert_eq!(alloc["args"]["size"], 128);
+        assert_eq!(alloc["args"]["tag"], "physics");
+        assert_eq!(counter["ph"], "C");
+        assert_eq!(counter["name"], "scene used");
+        assert_eq!(counter["args"]["bytes"], 128);
+        assert_eq!(counter["ts"], alloc["ts"]);
+
+        assert_eq!(events[6]["name"], "free");
+        assert_eq!(events[7]["args"]["bytes"], 0);
+    }
+}
@@ -0,0 +1,491 @@
+// Typed containers living in a tier.
+//
+// ArenaBox, ArenaSlice and ArenaVec wrap a MemoryOwner, so their memory is
+// reached through a generational handle rather than a raw pointer. Every access
+// checks the handle first: once reset_tier or fast_compact_tier has recycled the
+// memory, with() returns None instead of handing out someone else's bytes.
+// with() and with_mut() hold the tier's lock while the closure runs, so the
+// reference cannot outlive the memory. The closure must not allocate in that
+// tier or reset it. get() and friends skip the lock and are unsafe for it.
+//
+// Slices and vectors are restricted to Pod types, which can be zero initialised
+// and copied byte for byte when a vector moves to a bigger block.
+
+use std::marker::PhantomData;
+
+use super::{grow_tier, AllocHandle, MemoryOwner};
+
+/// Plain old data: any bit pattern (including all zeroes) is a valid value, and
+/// copying the bytes copies the value.
+///
+/// # Safety
+/// Only implement this for types without padding, pointers, references or
+/// Drop glue, e.g. #[repr(C)] structs made of Pod fields.
+pub unsafe trait Pod: Copy + 'static {}
+
+macro_rules! impl_pod {
+    ($($t:ty),*) => {
+        $(unsafe impl Pod for $t {})*
+    };
+}
+
+impl_pod!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+
+unsafe impl<T: Pod, const N: usize> Pod for [T; N] {}
+
+// Bytes to ask a tier for so `bytes` of T-aligned data fit in any of its blocks
+pub(crate) fn padded_size<T>(bytes: usize, tier_alignment: usize) -> Option<usize> {
+    bytes.checked_add(std::mem::align_of::<T>().saturating_sub(tier_alignment))
+}
+
+// First T-aligned address of a block
+pub(crate) fn align_ptr<T>(ptr: *mut u8) -> *mut T {
+    unsafe { ptr.add(ptr.align_offset(std::mem::align_of::<T>())) as *mut T }
+}
+
+// === ArenaBox ===
+
+// A single value in a tier, dropped and freed together with the box
+pub struct ArenaBox<T> {
+    owner: MemoryOwner,
+    _marker: PhantomData<T>,
+}
+
+impl<T> ArenaBox<T> {
+    // `owner` holds one block with room for a T-aligned T, already initialised with `value`
+    pub(crate) fn new(owner: MemoryOwner, value: T) -> Option<Self> {
+        let arena_box: ArenaBox<T> = ArenaBox {
+            owner,
+            _marker: PhantomData,
+        };
+        unsafe { arena_box.ptr()?.write(value) };
+        Some(arena_box)
+    }
+
+    fn ptr(&self) -> Option<*mut T> {
+        let (ptr, _) = self.owner.resolve(0)?;
+        Some(align_ptr::<T>(ptr))
+    }
+
+    // Read the value. None once the tier has recycled its memory.
+    pub fn with<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        self.owner.locked(0, |ptr, _| f(unsafe { &*align_ptr::<T>(ptr) }))
+    }
+
+    // Write the value, like with()
+    pub fn with_mut<R>(&mut self, f: impl FnOnce(&mut T) -> R) -> Option<R> {
+        self.owner.locked(0, |ptr, _| f(unsafe { &mut *align_ptr::<T>(ptr) }))
+    }
+
+    /// None once the tier has recycled the value's memory
+    ///
+    /// # Safety
+    /// The tier must not be reset or compacted while the reference is alive.
+    pub unsafe fn get(&self) -> Option<&T> {
+        Some(unsafe { &*self.ptr()? })
+    }
+
+    /// # Safety
+    /// The tier must not be reset or compacted while the reference is alive.
+    pub unsafe fn get_mut(&mut self) -> Option<&mut T> {
+        Some(unsafe { &mut *self.ptr()? })
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.ptr().is_some()
+    }
+
+    pub fn handle(&self) -> Option<AllocHandle> {
+        self.owner.handle(0)
+    }
+
+    // Move the value out and free its memory
+    pub fn into_inner(self) -> Option<T> {
+        let value = self.ptr().map(|ptr| unsafe { ptr.read() });
+        let mut this = std::mem::ManuallyDrop::new(self);
+        unsafe { std::ptr::drop_in_place(&mut this.owner) };
+        value
+    }
+}
+
+impl<T> Drop for ArenaBox<T> {
+    // A value whose tier was reset is gone already, its destructor never runs
+    fn drop(&mut self) {
+        if let Some(ptr) = self.ptr() {
+            unsafe { std::ptr::drop_in_place(ptr) };
+        }
+    }
+}
+
+// === ArenaSlice ===
+
+// A fixed length run of Pod values in a tier
+pub struct ArenaSlice<T: Pod> {
+    owner: MemoryOwner,
+    len: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Pod> ArenaSlice<T> {
+    // `owner` holds one block with room for `len` T-aligned values, which are zeroed here
+    pub(crate) fn new_zeroed(owner: MemoryOwner, len: usize) -> Option<Self> {
+        let slice: ArenaSlice<T> = ArenaSlice {
+            owner,
+            len,
+            _marker: PhantomData,
+        };
+        unsafe { std::ptr::write_bytes(slice.ptr()?, 0, len) };
+        Some(slice)
+    }
+
+    fn ptr(&self) -> Option<*mut T> {
+        let (ptr, _) = self.owner.resolve(0)?;
+        Some(align_ptr::<T>(ptr))
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Read the values. None once the tier has recycled the slice's memory.
+    pub fn with<R>(&self, f: impl FnOnce(&[T]) -> R) -> Option<R> {
+        let len = self.len;
+        self.owner.locked(0, |ptr, _| f(unsafe { std::slice::from_raw_parts(align_ptr::<T>(ptr), len) }))
+    }
+
+    // Write the values, like with()
+    pub fn with_mut<R>(&mut self, f: impl FnOnce(&mut [T]) -> R) -> Option<R> {
+        let len = self.len;
+        self.owner.locked(0, |ptr, _| f(unsafe { std::slice::from_raw_parts_mut(align_ptr::<T>(ptr), len) }))
+    }
+
+    /// None once the tier has recycled the slice's memory
+    ///
+    /// # Safety
+    /// The tier must not be reset or compacted while the slice is alive.
+    pub unsafe fn get(&self) -> Option<&[T]> {
+        Some(unsafe { std::slice::from_raw_parts(self.ptr()?, self.len) })
+    }
+
+    /// # Safety
+    /// The tier must not be reset or compacted while the slice is alive.
+    pub unsafe fn get_mut(&mut self) -> Option<&mut [T]> {
+        Some(unsafe { std::slice::from_raw_parts_mut(self.ptr()?, self.len) })
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.ptr().is_some()
+    }
+
+    pub fn handle(&self) -> Option<AllocHandle> {
+        self.owner.handle(0)
+    }
+}
+
+// === ArenaVec ===
+
+// A growable vector of Pod values in a tier. Growing moves the values to a
+// bigger block of the same tier and frees the old one, growing the tier by its
+// growth policy if needed.
+pub struct ArenaVec<T: Pod> {
+    owner: MemoryOwner,  // Holds the current buffer, if any, at index 0
+    len: usize,
+    capacity: usize,
+    _marker: PhantomData<T>,
+}
+
+impl<T: Pod> ArenaVec<T> {
+    pub(crate) fn new(owner: MemoryOwner) -> Self {
+        ArenaVec {
+            owner,
+            len: 0,
+            capacity: 0,
+            _marker: PhantomData,
+        }
+    }
+
+    fn ptr(&self) -> Option<*mut T> {
+        if self.owner.is_empty() {
+            return Some(std::ptr::NonNull::dangling().as_ptr());
+        }
+        let (ptr, _) = self.owner.resolve(0)?;
+        Some(align_ptr::<T>(ptr))
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // False once the tier has recycled the vector's buffer
+    pub fn is_valid(&self) -> bool {
+        self.ptr().is_some()
+    }
+
+    // Make room for `additional` more values. Fails if the buffer was recycled
+    // or the tier cannot grow.
+    pub fn reserve(&mut self, additional: usize) -> bool {
+        let old = match self.ptr() {
+            Some(ptr) => ptr,
+            None => return false,
+        };
+
+        let needed = match self.len.checked_add(additional) {
+            Some(needed) => needed,
+            None => return false,
+        };
+        if needed <= self.capacity {
+            return true;
+        }
+
+        let element_size = std::mem::size_of::<T>().max(1);
+        let capacity = match self.capacity.checked_mul(2) {
+            Some(doubled) => needed.max(doubled).max(4),
+            None => return false,
+        };
+        let alignment = match self.owner.arena.lock() {
+            Ok(arena) => arena.alignment(),
+            Err(_) => return false,
+        };
+        let bytes = match capacity.checked_mul(element_size).and_then(|bytes| padded_size::<T>(bytes, alignment)) {
+            Some(bytes) => bytes,
+            None => return false,
+        };
+
+        // Try the tier as it is, then grow it once
+        let index = match self.owner.allocate(bytes) {
+            Some(index) => index,
+            None => {
+                if grow_tier(&self.owner.arena, bytes).is_null() {
+                    return false;
+                }
+                match self.owner.allocate(bytes) {
+                    Some(index) => index,
+                    None => return false,
+                }
+            },
+        };
+
+        let new = match self.owner.resolve(index) {
+            Some((ptr, _)) => align_ptr::<T>(ptr),
+            None => return false,
+        };
+        unsafe { std::ptr::copy_nonoverlapping(old, new, self.len) };
+
+        // The new buffer moves down to index 0
+        if index > 0 {
+            self.owner.free(0);
+        }
+        self.capacity = capacity;
+        true
+    }
+
+    // Append a value, handing it back if there is no room
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        if !self.reserve(1) {
+            return Err(value);
+        }
+        let ptr = match self.ptr() {
+            Some(ptr) => ptr,
+            None => return Err(value),
+        };
+        unsafe { ptr.add(self.len).write(value) };
+        self.len += 1;
+        Ok(())
+    }
+
+    pub fn pop(&mut self) -> Option<T> {
+        if self.len == 0 {
+            return None;
+        }
+        let ptr = self.ptr()?;
+        self.len -= 1;
+        Some(unsafe { ptr.add(self.len).read() })
+    }
+
+    pub fn extend_from_slice(&mut self, values: &[T]) -> bool {
+        if !self.reserve(values.len()) {
+            return false;
+        }
+        let ptr = match self.ptr() {
+            Some(ptr) => ptr,
+            None => return false,
+        };
+        unsafe { std::ptr::copy_nonoverlapping(values.as_ptr(), ptr.add(self.len), values.len()) };
+        self.len += values.len();
+        true
+    }
+
+    // Drop every value but keep the buffer
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+
+    // Read the values. None once the tier has recycled the vector's buffer.
+    pub fn with<R>(&self, f: impl FnOnce(&[T]) -> R) -> Option<R> {
+        if self.owner.is_empty() {
+            return Some(f(&[]));
+        }
+        let len = self.len;
+        self.owner.locked(0, |ptr, _| f(unsafe { std::slice::from_raw_parts(align_ptr::<T>(ptr), len) }))
+    }
+
+    // Write the values, like with()
+    pub fn with_mut<R>(&mut self, f: impl FnOnce(&mut [T]) -> R) -> Option<R> {
+        if self.owner.is_empty() {
+            return Some(f(&mut []));
+        }
+        let len = self.len;
+        self.owner.locked(0, |ptr, _| f(unsafe { std::slice::from_raw_parts_mut(align_ptr::<T>(ptr), len) }))
+    }
+
+    /// None once the tier has recycled the vector's buffer
+    ///
+    /// # Safety
+    /// The tier must not be reset or compacted while the slice is alive.
+    pub unsafe fn as_slice(&self) -> Option<&[T]> {
+        Some(unsafe { std::slice::from_raw_parts(self.ptr()?, self.len) })
+    }
+
+    /// # Safety
+    /// The tier must not be reset or compacted while the slice is alive.
+    pub unsafe fn as_mut_slice(&mut self) -> Option<&mut [T]> {
+        Some(unsafe { std::slice::from_raw_parts_mut(self.ptr()?, self.len) })
+    }
+
+    // Handle of the current buffer, if one has been allocated
+    pub fn handle(&self) -> Option<AllocHandle> {
+        self.owner.handle(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use crate::{NativeMemory, Tier, TieredAllocator};
+
+    fn allocator() -> TieredAllocator {
+        TieredAllocator::new(Box::new(NativeMemory::new(64)), 16)
+    }
+
+    #[test]
+    fn values_are_aligned_and_readable() {
+        let mut allocator = allocator();
+        let mut b = allocator.alloc_value(7u64, Tier::ENTITY).unwrap();
+        assert_eq!(b.with(|v| *v), Some(7));
+        b.with_mut(|v| *v += 1).unwrap();
+        assert_eq!(b.with(|v| *v), Some(8));
+
+        let aligned = allocator.alloc_value([0u8; 64], Tier::ENTITY).unwrap();
+        let wide = allocator.alloc_value(1u128, Tier::ENTITY).unwrap();
+        assert!(aligned.is_valid());
+        assert_eq!(wide.with(|v| v as *const u128 as usize % std::mem::align_of::<u128>()), Some(0));
+    }
+
+    #[test]
+    fn box_outlives_the_allocator() {
+        let mut allocator = allocator();
+        let b = allocator.alloc_value(7u64, Tier::ENTITY).unwrap();
+        drop(allocator);
+        assert_eq!(b.with(|v| *v), Some(7));
+    }
+
+    #[test]
+    fn reset_invalidates_containers() {
+        let mut allocator = allocator();
+        let b = allocator.alloc_value(7u64, Tier::ENTITY).unwrap();
+        let slice = allocator.alloc_slice_copy(&[1u32, 2, 3], Tier::ENTITY).unwrap();
+        let mut vec = allocator.alloc_vec::<u16>(4, Tier::ENTITY).unwrap();
+        vec.push(5).unwrap();
+
+        allocator.reset_tier(Tier::ENTITY);
+        let reused = allocator.alloc_value(99u64, Tier::ENTITY).unwrap();
+
+        assert_eq!(b.with(|v| *v), None);
+        assert!(!b.is_valid());
+        assert_eq!(slice.with(|values| values.to_vec()), None);
+        assert_eq!(vec.with(|values| values.len()), None);
+        assert_eq!(vec.push(6), Err(6));
+        assert_eq!(reused.with(|v| *v), Some(99));
+    }
+
+    #[test]
+    fn reset_skips_the_destructor() {
+        let mut allocator = allocator();
+        let counted = Rc::new(());
+        let kept = allocator.alloc_value(Rc::clone(&counted), Tier::ENTITY).unwrap();
+        let lost = allocator.alloc_value(Rc::clone(&counted), Tier::SCENE).unwrap();
+        assert_eq!(Rc::strong_count(&counted), 3);
+
+        drop(kept);
+        assert_eq!(Rc::strong_count(&counted), 2);
+
+        allocator.reset_tier(Tier::SCENE);
+        drop(lost);
+        assert_eq!(Rc::strong_count(&counted), 2);
+    }
+
+    #[test]
+    fn slices_start_zeroed() {
+        let mut allocator = allocator();
+        let mut slice = allocator.alloc_slice::<u32>(5, Tier::ENTITY).unwrap();
+        assert_eq!(slice.len(), 5);
+        assert_eq!(slice.with(|values| values.iter().all(|&v| v == 0)), Some(true));
+        slice.with_mut(|values| values[4] = 9).unwrap();
+        assert_eq!(slice.with(|values| values[4]), Some(9));
+
+        assert!(allocator.alloc_slice::<u64>(usize::MAX / 4, Tier::ENTITY).is_none());
+    }
+
+    #[test]
+    fn vec_grows_into_a_bigger_block() {
+        let mut allocator = allocator();
+        let mut vec = allocator.alloc_vec::<u32>(0, Tier::ENTITY).unwrap();
+        assert_eq!(vec.with(|values| values.len()), Some(0));
+        assert!(vec.handle().is_none());
+
+        for i in 0..100 {
+            vec.push(i).unwrap();
+        }
+        assert_eq!(vec.len(), 100);
+        assert!(vec.capacity() >= 100);
+        assert_eq!(vec.with(|values| values.iter().copied().eq(0..100)), Some(true));
+
+        // The old buffers went back to the tier
+        let used = allocator.tier_stats(Tier::ENTITY).used;
+        assert!(used < 2 * vec.capacity() * 4);
+
+        assert!(vec.extend_from_slice(&[100, 101]));
+        assert_eq!(vec.pop(), Some(101));
+        assert!(!vec.reserve(usize::MAX));
+        assert!(!vec.reserve(usize::MAX / 2));
+        assert_eq!(vec.len(), 101);
+    }
+
+    #[test]
+    fn into_inner_moves_the_value_out() {
+        let mut allocator = allocator();
+        let used = allocator.tier_stats(Tier::ENTITY).used;
+        let b = allocator.alloc_value(String::from("hero"), Tier::ENTITY).unwrap();
+        assert!(allocator.tier_stats(Tier::ENTITY).used > used);
+
+        assert_eq!(b.into_inner().as_deref(), Some("hero"));
+        assert_eq!(allocator.tier_stats(Tier::ENTITY).used, used);
+
+        let lost = allocator.alloc_value(String::from("lost"), Tier::ENTITY).unwrap();
+        allocator.reset_tier(Tier::ENTITY);
+        assert_eq!(lost.into_inner(), None);
+    }
+}
@@ -0,0 +1,248 @@
+// Zero-copy views of linear memory for JS.
+//
+// A view is a typed array over wasm memory's own ArrayBuffer, so reading it
+// copies nothing. It stays usable under two rules:
+//
+// - Growing linear memory detaches the old ArrayBuffer, and every view over it
+//   then reads as empty. Any allocation may grow memory, so views are taken
+//   again after allocating, or checked with `view.buffer === memory_buffer()`.
+// - A view of a handle shows whatever lives at that address. Once the handle's
+//   memory is recycled the view does not notice, is_handle_valid does.
+//
+// A SoaLayout lays out a struct of arrays in one block, one field array after
+// the other, each starting at a multiple of SOA_ALIGNMENT so the block can be
+// uploaded to one GPU buffer and the fields bound at their offsets.
+
+use wasm_bindgen::prelude::*;
+
+// WebGPU's minimum storage and uniform buffer offset alignment
+pub const SOA_ALIGNMENT: usize = 256;
+
+// Element type of a typed view
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum ViewKind {
+    Uint8,
+    Uint32,
+    Float32,
+}
+
+impl ViewKind {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "u8" | "uint8" => Some(ViewKind::Uint8),
+            "u32" | "uint32" => Some(ViewKind::Uint32),
+            "f32" | "float32" => Some(ViewKind::Float32),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ViewKind::Uint8 => "u8",
+            ViewKind::Uint32 => "u32",
+            ViewKind::Float32 => "f32",
+        }
+    }
+
+    // Bytes per element, also the alignment the view needs
+    pub fn size(&self) -> usize {
+        match self {
+            ViewKind::Uint8 => 1,
+            ViewKind::Uint32 => 4,
+            ViewKind::Float32 => 4,
+        }
+    }
+}
+
+// Typed array over `bytes` bytes at `ptr`, which has to be aligned for `kind`
+//
+// # Safety
+// `ptr..ptr + bytes` has to lie in wasm linear memory.
+pub(crate) unsafe fn typed_view(ptr: *mut u8, bytes: usize, kind: ViewKind) -> Result<JsValue, String> {
+    if !(ptr as usize).is_multiple_of(kind.size()) || !bytes.is_multiple_of(kind.size()) {
+        return Err(format!("A {} view needs a {}-byte aligned offset and length", kind.name(), kind.size()));
+    }
+    let len = bytes / kind.size();
+    let view: JsValue = unsafe {
+        match kind {
+            ViewKind::Uint8 => js_sys::Uint8Array::view_mut_raw(ptr, len).into(),
+            ViewKind::Uint32 => js_sys::Uint32Array::view_mut_raw(ptr as *mut u32, len).into(),
+            ViewKind::Float32 => js_sys::Float32Array::view_mut_raw(ptr as *mut f32, len).into(),
+        }
+    };
+    Ok(view)
+}
+
+// The ArrayBuffer every view is taken over, replaced whenever memory grows
+pub(crate) fn memory_buffer() -> JsValue {
+    wasm_bindgen::memory()
+        .unchecked_into::<js_sys::WebAssembly::Memory>()
+        .buffer()
+}
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SoaField {
+    pub name: String,
+    pub kind: ViewKind,
+    pub components: usize,  // Elements per item, e.g. 3 for a position
+    pub offset: usize,      // Bytes from the start of the block
+}
+
+impl SoaField {
+    // Elements in the field's array, None if that many do not fit in a usize
+    pub fn len(&self, count: usize) -> Option<usize> {
+        count.checked_mul(self.components)
+    }
+
+    pub fn size(&self, count: usize) -> Option<usize> {
+        self.len(count)?.checked_mul(self.kind.size())
+    }
+}
+
+// Struct of arrays for `count` items
+#[wasm_bindgen]
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct SoaLayout {
+    count: usize,
+    fields: Vec<SoaField>,
+    size: usize,  // Bytes of the block, the last field's end rounded up to SOA_ALIGNMENT
+}
+
+impl SoaLayout {
+    pub fn with_field(mut self, name: &str, kind: ViewKind, components: usize) -> Result<Self, String> {
+        self.push(name, kind, components)?;
+        Ok(self)
+    }
+
+    // Fails if the name is taken or the block would no longer fit in a usize
+    fn push(&mut self, name: &str, kind: ViewKind, components: usize) -> Result<(), String> {
+        if self.get(name).is_some() {
+            return Err(format!("Field '{}' is already in the layout", name));
+        }
+        let field = SoaField {
+            name: name.to_string(),
+            kind,
+            components,
+            offset: self.size,
+        };
+        let size = field
+            .size(self.count)
+            .and_then(|size| field.offset.checked_add(size))
+            .and_then(|end| end.checked_next_multiple_of(SOA_ALIGNMENT))
+            .ok_or_else(|| format!(
+                "Field '{}' of {} x {} {} does not fit after {} bytes", name, self.count, components, kind.name(), self.size
+            ))?;
+        self.fields.push(field);
+        self.size = size;
+        Ok(())
+    }
+
+    pub fn fields(&self) -> &[SoaField] {
+        &self.fields
+    }
+
+    pub fn get(&self, name: &str) -> Option<&SoaField> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+}
+
+#[wasm_bindgen]
+impl SoaLayout {
+    #[wasm_bindgen(constructor)]
+    pub fn new(count: usize) -> Self {
+        SoaLayout { count, fields: Vec::new(), size: 0 }
+    }
+
+    // Add a field array of `components` elements per item, kind "u8", "u32" or "f32"
+    #[wasm_bindgen]
+    pub fn field(&mut self, name: String, kind: &str, components: usize) -> Result<(), JsValue> {
+        let kind = ViewKind::from_name(kind)
+            .ok_or_else(|| JsValue::from_str(&format!("Unknown view type '{}'", kind)))?;
+        self.push(&name, kind, components).map_err(|e| JsValue::from_str(&e))
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    // Bytes of the whole block
+    #[wasm_bindgen(getter)]
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    // Byte offset of a field's array in the block
+    #[wasm_bindgen]
+    pub fn offset(&self, name: &str) -> Option<usize> {
+        self.get(name).map(|field| field.offset)
+    }
+
+    // Byte length of a field's array
+    #[wasm_bindgen]
+    pub fn field_size(&self, name: &str) -> Option<usize> {
+        self.get(name).and_then(|field| field.size(self.count))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fields_start_on_the_alignment() {
+        let layout = SoaLayout::new(100)
+            .with_field("position", ViewKind::Float32, 3).unwrap()
+            .with_field("color", ViewKind::Uint32, 1).unwrap()
+            .with_field("flags", ViewKind::Uint8, 1).unwrap();
+
+        assert_eq!(layout.offset("position"), Some(0));
+        assert_eq!(layout.field_size("position"), Some(1200));
+        assert_eq!(layout.offset("color"), Some(1280));
+        assert_eq!(layout.field_size("color"), Some(400));
+        assert_eq!(layout.offset("flags"), Some(1792));
+        assert_eq!(layout.field_size("flags"), Some(100));
+        assert_eq!(layout.size(), 2048);
+        assert_eq!(layout.offset("normal"), None);
+        for field in layout.fields() {
+            assert!(field.offset.is_multiple_of(SOA_ALIGNMENT));
+        }
+    }
+
+    #[test]
+    fn empty_layouts_take_no_space() {
+        assert_eq!(SoaLayout::new(100).size(), 0);
+        let layout = SoaLayout::new(0).with_field("position", ViewKind::Float32, 3).unwrap();
+        assert_eq!((layout.offset("position"), layout.size()), (Some(0), 0));
+    }
+
+    #[test]
+    fn duplicate_fields_are_rejected() {
+        let layout = SoaLayout::new(4).with_field("position", ViewKind::Float32, 3).unwrap();
+        assert!(layout.clone().with_field("position", ViewKind::Uint8, 1).is_err());
+        assert_eq!(layout.fields().len(), 1);
+    }
+
+    #[test]
+    fn fields_that_overflow_are_rejected() {
+        let count = usize::MAX / 2;
+        assert!(SoaLayout::new(count).with_field("position", ViewKind::Float32, 3).is_err());
+        assert!(SoaLayout::new(usize::MAX).with_field("flags", ViewKind::Uint8, 1).is_err());
+
+        // Fits on its own, not after another field
+        let mut layout = SoaLayout::new(usize::MAX / 8).with_field("a", ViewKind::Float32, 1).unwrap();
+        let size = layout.size();
+        assert!(layout.push("b", ViewKind::Float32, 1).is_err());
+        assert_eq!((layout.fields().len(), layout.size()), (1, size));
+    }
+
+    #[test]
+    fn views_need_aligned_offsets_and_lengths() {
+        let mut buffer = [0u32; 4];
+        let ptr = buffer.as_mut_ptr() as *mut u8;
+        unsafe {
+            assert!(typed_view(ptr.wrapping_add(2), 8, ViewKind::Float32).is_err());
+            assert!(typed_view(ptr, 6, ViewKind::Uint32).is_err());
+        }
+    }
+}